@@ -10,15 +10,11 @@ fn main() {
     let devices = manager.get_device_list();
     println!("{:?}", devices);
     'outer: loop {
-        if let Some(event) = manager.get_event() {
-            match event {
-                RawEvent::KeyboardEvent(_, KeyId::Escape, State::Pressed) => break 'outer,
-                _ => (),
-            }
-            println!("{:?}", event);
-        } else {
-            std::thread::sleep(std::time::Duration::from_millis(10));
+        let event = manager.wait_event();
+        if let RawEvent::KeyboardEvent(_, KeyId::Escape, State::Pressed) = event {
+            break 'outer;
         }
+        println!("{:?}", event);
     }
     println!("Finishing");
 }
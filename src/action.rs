@@ -0,0 +1,1169 @@
+//! Named gameplay actions bound to keys, buttons and axes of any device
+//!
+//! An `ActionMap` turns events into actions such as "jump" or "steer", so game
+//! code asks what the player wants to do rather than which inputs are down.
+//! Digital actions are pressed or not, analog actions have a value. Bindings
+//! are saved to and loaded from JSON files, so players can change them.
+//!
+//! ```
+//! # use multiinput::*;
+//! # use multiinput::action::{ActionKind, ActionMap, Binding, Input};
+//! let mut actions = ActionMap::new();
+//! actions
+//!     .add_action("jump", ActionKind::Digital)
+//!     .bind(Binding::new(Input::Key(KeyId::Space)))
+//!     .bind(Binding::new(Input::JoystickButton(0)));
+//! actions
+//!     .add_action("steer", ActionKind::Analog)
+//!     .bind(Binding::new(Input::JoystickAxis(Axis::X)).deadzone(0.25))
+//!     .bind(Binding::new(Input::Key(KeyId::Left)).scale(-1.0))
+//!     .bind(Binding::new(Input::Key(KeyId::Right)));
+//!
+//! // Once per iteration of the game loop
+//! actions.next_frame();
+//! for event in [
+//!     RawEvent::KeyboardEvent(0, KeyId::Space, State::Pressed),
+//!     RawEvent::JoystickAxisEvent(0, Axis::X, 0.625),
+//! ] {
+//!     actions.apply(&event);
+//! }
+//! assert!(actions.action_just_pressed("jump"));
+//! assert_eq!(actions.action_value("steer"), 0.5);
+//! ```
+
+use devices::{HatSwitch, HAT_SWITCHES};
+use event::{Axis, KeyId, MouseButton, RawEvent, State, JOYSTICK_AXES, KEY_IDS, MOUSE_BUTTONS};
+use gamepad::{GamepadAxis, GamepadButton, AXES, BUTTONS};
+use json::{field, json_array, name, named, Json};
+use manager::DeviceType;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::fs;
+use std::path::Path;
+
+/// Identifies action map files
+const ACTIONS_FORMAT: &str = "multiinput-actions";
+const ACTIONS_VERSION: u16 = 1;
+
+/// Digital actions are pressed while a binding's value is above this
+pub const PRESS_THRESHOLD: f64 = 0.5;
+
+format_error!(
+    /// Error reading or writing an action map
+    ActionMapError,
+    "Invalid action map"
+);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ActionKind {
+    /// Pressed or released, like a button
+    Digital,
+    /// A value, the sum of the values of its bindings
+    Analog,
+}
+
+/// Input read by a binding, from any device of its type
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Input {
+    Key(KeyId),
+    MouseButton(MouseButton),
+    /// Notches the wheel turned during the frame, positive away from the user
+    MouseWheel,
+    JoystickButton(usize),
+    JoystickAxis(Axis),
+    /// Pressed while the hat points in the direction, or a diagonal next to it
+    JoystickHat(HatSwitch),
+    GamepadButton(GamepadButton),
+    GamepadAxis(GamepadAxis),
+}
+
+/// Input of an action, with the adjustments made to its value
+///
+/// Buttons, keys and hat directions are worth 1 while pressed and 0 otherwise.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Binding {
+    pub input: Input,
+    /// Factor the value is multiplied by
+    pub scale: f64,
+    /// Negates the value
+    pub invert: bool,
+    /// Values closer to zero than this count as zero, and the rest is
+    /// stretched to start from zero
+    pub deadzone: f64,
+}
+
+impl Binding {
+    pub fn new(input: Input) -> Binding {
+        Binding {
+            input,
+            scale: 1.0,
+            invert: false,
+            deadzone: 0.0,
+        }
+    }
+
+    pub fn scale(mut self, scale: f64) -> Binding {
+        self.scale = scale;
+        self
+    }
+
+    pub fn invert(mut self) -> Binding {
+        self.invert = !self.invert;
+        self
+    }
+
+    pub fn deadzone(mut self, deadzone: f64) -> Binding {
+        self.deadzone = deadzone;
+        self
+    }
+
+    /// Binding for the input an event activates, if it does
+    ///
+    /// Keys and buttons are activated when pressed, hats when pushed, the wheel
+    /// when turned and axes when moved past `PRESS_THRESHOLD`; the bindings of the
+    /// wheel and axes are inverted when they move towards negative.
+    pub fn activated_by(event: &RawEvent) -> Option<Binding> {
+        fn directed(input: Input, value: f64) -> Option<Binding> {
+            match value {
+                value if value > PRESS_THRESHOLD => Some(Binding::new(input)),
+                value if value < -PRESS_THRESHOLD => Some(Binding::new(input).invert()),
+                _ => None,
+            }
+        }
+        match *event {
+            RawEvent::KeyboardEvent(_, ref key, State::Pressed) => {
+                Some(Binding::new(Input::Key(key.clone())))
+            }
+            RawEvent::MouseButtonEvent(_, ref button, State::Pressed) => {
+                Some(Binding::new(Input::MouseButton(button.clone())))
+            }
+            RawEvent::MouseWheelEvent(_, delta) if delta > 0.0 => {
+                Some(Binding::new(Input::MouseWheel))
+            }
+            RawEvent::MouseWheelEvent(_, delta) if delta < 0.0 => {
+                Some(Binding::new(Input::MouseWheel).invert())
+            }
+            RawEvent::JoystickButtonEvent(_, button, State::Pressed) => {
+                Some(Binding::new(Input::JoystickButton(button)))
+            }
+            RawEvent::JoystickAxisEvent(_, ref axis, value) => {
+                directed(Input::JoystickAxis(axis.clone()), value)
+            }
+            RawEvent::JoystickHatSwitchEvent(_, ref hatswitch)
+                if *hatswitch != HatSwitch::Center =>
+            {
+                Some(Binding::new(Input::JoystickHat(hatswitch.clone())))
+            }
+            RawEvent::GamepadButtonEvent(_, ref button, State::Pressed) => {
+                Some(Binding::new(Input::GamepadButton(button.clone())))
+            }
+            RawEvent::GamepadAxisEvent(_, ref axis, value) => {
+                directed(Input::GamepadAxis(axis.clone()), value)
+            }
+            _ => None,
+        }
+    }
+
+    /// Adjusted value for a value of the input
+    pub fn value(&self, input: f64) -> f64 {
+        let magnitude = input.abs();
+        let value = if magnitude <= self.deadzone || self.deadzone >= 1.0 {
+            0.0
+        } else {
+            input.signum() * (magnitude - self.deadzone) / (1.0 - self.deadzone)
+        };
+        if self.invert {
+            -value * self.scale
+        } else {
+            value * self.scale
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Action {
+    pub name: String,
+    pub kind: ActionKind,
+    pub bindings: Vec<Binding>,
+}
+
+impl Action {
+    pub fn bind(&mut self, binding: Binding) -> &mut Action {
+        self.bindings.push(binding);
+        self
+    }
+}
+
+/// Latest state of every input seen, by device id
+#[derive(Clone, Debug, Default)]
+struct Inputs {
+    keys: HashSet<(usize, KeyId)>,
+    mouse_buttons: HashSet<(usize, MouseButton)>,
+    /// Wheel movement of all mice during the frame
+    wheel: f64,
+    joystick_buttons: HashSet<(usize, usize)>,
+    joystick_axes: HashMap<(usize, Axis), f64>,
+    hats: HashMap<usize, HatSwitch>,
+    gamepad_buttons: HashSet<(usize, GamepadButton)>,
+    gamepad_axes: HashMap<(usize, GamepadAxis), f64>,
+}
+
+impl Inputs {
+    fn apply(&mut self, event: &RawEvent) {
+        fn update<T: Eq + ::std::hash::Hash>(held: &mut HashSet<T>, input: T, state: &State) {
+            match *state {
+                State::Pressed => held.insert(input),
+                State::Released => held.remove(&input),
+            };
+        }
+        match *event {
+            RawEvent::KeyboardEvent(id, ref key, ref state) => {
+                update(&mut self.keys, (id, key.clone()), state)
+            }
+            RawEvent::MouseButtonEvent(id, ref button, ref state) => {
+                update(&mut self.mouse_buttons, (id, button.clone()), state)
+            }
+            RawEvent::MouseWheelEvent(_, delta) => self.wheel += delta as f64,
+            RawEvent::MouseMoveEvent(..) => (),
+            RawEvent::JoystickButtonEvent(id, button, ref state) => {
+                update(&mut self.joystick_buttons, (id, button), state)
+            }
+            RawEvent::JoystickAxisEvent(id, ref axis, value) => {
+                self.joystick_axes.insert((id, axis.clone()), value);
+            }
+            RawEvent::JoystickHatSwitchEvent(id, ref hatswitch) => {
+                self.hats.insert(id, hatswitch.clone());
+            }
+            RawEvent::JoystickInitialStateEvent(id, ref state) => {
+                self.joystick_buttons.retain(|&(device, _)| device != id);
+                for (button, &pressed) in state.button_states.iter().enumerate() {
+                    if pressed {
+                        self.joystick_buttons.insert((id, button));
+                    }
+                }
+                let axes = &state.axis_states;
+                let values = [
+                    axes.x,
+                    axes.y,
+                    axes.z,
+                    axes.rx,
+                    axes.ry,
+                    axes.rz,
+                    axes.slider,
+                ];
+                for (axis, value) in JOYSTICK_AXES.iter().zip(values.iter()) {
+                    if let Some(value) = *value {
+                        self.joystick_axes.insert((id, axis.clone()), value);
+                    }
+                }
+                if let Some(ref hatswitch) = state.hatswitch {
+                    self.hats.insert(id, hatswitch.clone());
+                }
+            }
+            RawEvent::GamepadButtonEvent(id, ref button, ref state) => {
+                update(&mut self.gamepad_buttons, (id, button.clone()), state)
+            }
+            RawEvent::GamepadAxisEvent(id, ref axis, value) => {
+                self.gamepad_axes.insert((id, axis.clone()), value);
+            }
+        }
+    }
+
+    /// Value of an input on the device where it is furthest from zero
+    fn value(&self, input: &Input) -> f64 {
+        fn held<T: PartialEq>(held: &HashSet<(usize, T)>, input: &T) -> f64 {
+            if held.iter().any(|(_, value)| value == input) {
+                1.0
+            } else {
+                0.0
+            }
+        }
+        fn furthest<T: PartialEq>(values: &HashMap<(usize, T), f64>, input: &T) -> f64 {
+            values
+                .iter()
+                .filter(|&((_, axis), _)| axis == input)
+                .map(|(_, &value)| value)
+                .fold(0.0, |furthest, value| {
+                    if value.abs() > furthest.abs() {
+                        value
+                    } else {
+                        furthest
+                    }
+                })
+        }
+        match *input {
+            Input::Key(ref key) => held(&self.keys, key),
+            Input::MouseButton(ref button) => held(&self.mouse_buttons, button),
+            Input::MouseWheel => self.wheel,
+            Input::JoystickButton(ref button) => held(&self.joystick_buttons, button),
+            Input::JoystickAxis(ref axis) => furthest(&self.joystick_axes, axis),
+            Input::JoystickHat(ref direction) => {
+                if self
+                    .hats
+                    .values()
+                    .any(|hatswitch| hat_points(hatswitch, direction))
+                {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Input::GamepadButton(ref button) => held(&self.gamepad_buttons, button),
+            Input::GamepadAxis(ref axis) => furthest(&self.gamepad_axes, axis),
+        }
+    }
+}
+
+/// Horizontal and vertical components of a hat position, up and right positive
+fn hat_components(hatswitch: &HatSwitch) -> (i8, i8) {
+    match *hatswitch {
+        HatSwitch::Center => (0, 0),
+        HatSwitch::Up => (0, 1),
+        HatSwitch::UpRight => (1, 1),
+        HatSwitch::Right => (1, 0),
+        HatSwitch::DownRight => (1, -1),
+        HatSwitch::Down => (0, -1),
+        HatSwitch::DownLeft => (-1, -1),
+        HatSwitch::Left => (-1, 0),
+        HatSwitch::UpLeft => (-1, 1),
+    }
+}
+
+/// True if the hat points in the direction (`Up` includes `UpLeft` and `UpRight`)
+fn hat_points(hatswitch: &HatSwitch, direction: &HatSwitch) -> bool {
+    if *direction == HatSwitch::Center {
+        return *hatswitch == HatSwitch::Center;
+    }
+    let (x, y) = hat_components(hatswitch);
+    let (direction_x, direction_y) = hat_components(direction);
+    (direction_x == 0 || direction_x == x) && (direction_y == 0 || direction_y == y)
+}
+
+/// Devices whose input an `ActionMap` reads, e.g. a player's keyboard and mouse
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DeviceSet {
+    pub keyboards: Vec<usize>,
+    pub mice: Vec<usize>,
+    pub joysticks: Vec<usize>,
+}
+
+impl DeviceSet {
+    pub fn new() -> DeviceSet {
+        DeviceSet::default()
+    }
+
+    pub fn keyboard(mut self, id: usize) -> DeviceSet {
+        self.keyboards.push(id);
+        self
+    }
+
+    pub fn mouse(mut self, id: usize) -> DeviceSet {
+        self.mice.push(id);
+        self
+    }
+
+    pub fn joystick(mut self, id: usize) -> DeviceSet {
+        self.joysticks.push(id);
+        self
+    }
+
+    /// True if the event comes from one of the devices
+    pub fn contains(&self, event: &RawEvent) -> bool {
+        let ids = if event.is_from(&DeviceType::Keyboards) {
+            &self.keyboards
+        } else if event.is_from(&DeviceType::Mice) {
+            &self.mice
+        } else {
+            &self.joysticks
+        };
+        ids.contains(&event.device_id())
+    }
+}
+
+/// Change of an action caused by an event
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ActionEvent {
+    /// A digital action was pressed
+    Pressed(String),
+    /// A digital action was released
+    Released(String),
+    /// The value of an analog action changed
+    Changed(String, f64),
+    /// An input was bound to an action by `ActionMap::listen_for_binding`
+    Bound(String, Binding),
+}
+
+/// Binding being listened for, with the binding it replaces
+#[derive(Clone, Debug)]
+struct Listening {
+    action: String,
+    replace: Option<usize>,
+}
+
+/// Actions with their bindings, and the state of the inputs they are bound to
+///
+/// Feed it every event with `apply` and call `next_frame` once per iteration
+/// of the game loop, before applying that iteration's events. Presses and
+/// releases within a frame are reported by `action_just_pressed` and
+/// `action_just_released` even if the action has changed back since.
+///
+/// An action map reads every device unless it is limited to a `DeviceSet`, so
+/// each player can have their own (see `Players`).
+#[derive(Clone, Debug, Default)]
+pub struct ActionMap {
+    actions: Vec<Action>,
+    devices: Option<DeviceSet>,
+    inputs: Inputs,
+    pressed: HashSet<String>,
+    just_pressed: HashSet<String>,
+    just_released: HashSet<String>,
+    /// Latest values of the analog actions
+    values: HashMap<String, f64>,
+    listening: Option<Listening>,
+}
+
+impl ActionMap {
+    pub fn new() -> ActionMap {
+        ActionMap::default()
+    }
+
+    /// Devices the map reads, or None for all of them
+    pub fn devices(&self) -> Option<&DeviceSet> {
+        self.devices.as_ref()
+    }
+
+    /// Limits the map to some devices (None reads all), releasing every action
+    pub fn set_devices(&mut self, devices: Option<DeviceSet>) -> Vec<ActionEvent> {
+        self.devices = devices;
+        self.reset()
+    }
+
+    /// True if the map reads the device of the event
+    pub fn reads(&self, event: &RawEvent) -> bool {
+        self.devices
+            .as_ref()
+            .is_none_or(|devices| devices.contains(event))
+    }
+
+    /// Adds an action without bindings, replacing any action of the same name
+    pub fn add_action(&mut self, name: &str, kind: ActionKind) -> &mut Action {
+        self.remove_action(name);
+        self.actions.push(Action {
+            name: name.to_string(),
+            kind,
+            bindings: Vec::new(),
+        });
+        self.actions.last_mut().unwrap()
+    }
+
+    pub fn remove_action(&mut self, name: &str) -> Option<Action> {
+        let index = self.actions.iter().position(|action| action.name == name)?;
+        self.pressed.remove(name);
+        self.values.remove(name);
+        Some(self.actions.remove(index))
+    }
+
+    /// Replaces the bindings of an action, returning the changes this makes
+    /// (None if there is no such action)
+    pub fn rebind(&mut self, name: &str, bindings: Vec<Binding>) -> Option<Vec<ActionEvent>> {
+        self.action_mut(name)?.bindings = bindings;
+        Some(self.update())
+    }
+
+    /// Binds the next input activated on the map's devices to an action
+    ///
+    /// The input (see `Binding::activated_by`) replaces binding replace of the
+    /// action, or is added if that is None. The event activating the input is
+    /// reported as `ActionEvent::Bound` and does not otherwise affect the
+    /// actions. Returns false if there is no such action.
+    pub fn listen_for_binding(&mut self, name: &str, replace: Option<usize>) -> bool {
+        if self.action(name).is_none() {
+            return false;
+        }
+        self.listening = Some(Listening {
+            action: name.to_string(),
+            replace,
+        });
+        true
+    }
+
+    /// Action an input is being listened for
+    pub fn listening_for(&self) -> Option<&str> {
+        self.listening
+            .as_ref()
+            .map(|listening| listening.action.as_str())
+    }
+
+    pub fn stop_listening(&mut self) {
+        self.listening = None;
+    }
+
+    pub fn action(&self, name: &str) -> Option<&Action> {
+        self.actions.iter().find(|action| action.name == name)
+    }
+
+    /// Action whose bindings are to be changed
+    pub fn action_mut(&mut self, name: &str) -> Option<&mut Action> {
+        self.actions.iter_mut().find(|action| action.name == name)
+    }
+
+    pub fn actions(&self) -> &[Action] {
+        &self.actions
+    }
+
+    /// Updates the inputs and the actions bound to them, returning the changes
+    ///
+    /// Events of devices the map does not read are ignored.
+    pub fn apply(&mut self, event: &RawEvent) -> Vec<ActionEvent> {
+        if !self.reads(event) {
+            return Vec::new();
+        }
+        if self.listening.is_some() {
+            if let Some(binding) = Binding::activated_by(event) {
+                let listening = self.listening.take().unwrap();
+                let action = self.action_mut(&listening.action).unwrap();
+                match listening.replace {
+                    Some(index) if index < action.bindings.len() => {
+                        action.bindings[index] = binding.clone()
+                    }
+                    _ => action.bindings.push(binding.clone()),
+                }
+                let mut events = vec![ActionEvent::Bound(listening.action, binding)];
+                events.extend(self.update());
+                return events;
+            }
+        }
+        self.inputs.apply(event);
+        self.update()
+    }
+
+    /// Starts the following frame, forgetting its presses, releases and wheel movement
+    pub fn next_frame(&mut self) -> Vec<ActionEvent> {
+        self.just_pressed.clear();
+        self.just_released.clear();
+        self.inputs.wheel = 0.0;
+        self.update()
+    }
+
+    /// Forgets the state of every input, releasing all actions
+    pub fn reset(&mut self) -> Vec<ActionEvent> {
+        self.inputs = Inputs::default();
+        self.update()
+    }
+
+    /// True while a digital action is pressed (analog actions never are)
+    pub fn action_pressed(&self, name: &str) -> bool {
+        self.pressed.contains(name)
+    }
+
+    /// True if a digital action was pressed during the frame
+    pub fn action_just_pressed(&self, name: &str) -> bool {
+        self.just_pressed.contains(name)
+    }
+
+    /// True if a digital action was released during the frame
+    pub fn action_just_released(&self, name: &str) -> bool {
+        self.just_released.contains(name)
+    }
+
+    /// Value of an analog action, or 1 or 0 for a pressed or released digital action
+    pub fn action_value(&self, name: &str) -> f64 {
+        match self.action(name) {
+            Some(action) if action.kind == ActionKind::Digital && self.pressed.contains(name) => {
+                1.0
+            }
+            Some(action) if action.kind == ActionKind::Digital => 0.0,
+            Some(action) => self.analog_value(action),
+            None => 0.0,
+        }
+    }
+
+    fn analog_value(&self, action: &Action) -> f64 {
+        action
+            .bindings
+            .iter()
+            .map(|binding| binding.value(self.inputs.value(&binding.input)))
+            .fold(0.0, |sum, value| sum + value)
+    }
+
+    /// Brings the actions up to date with the inputs
+    fn update(&mut self) -> Vec<ActionEvent> {
+        let mut events = Vec::new();
+        for action in &self.actions {
+            let name = &action.name;
+            if action.kind == ActionKind::Analog {
+                let value = self.analog_value(action);
+                if self.values.get(name).cloned().unwrap_or(0.0) != value {
+                    self.values.insert(name.clone(), value);
+                    events.push(ActionEvent::Changed(name.clone(), value));
+                }
+                continue;
+            }
+            let pressed = action
+                .bindings
+                .iter()
+                .any(|binding| binding.value(self.inputs.value(&binding.input)) > PRESS_THRESHOLD);
+            if pressed && self.pressed.insert(name.clone()) {
+                self.just_pressed.insert(name.clone());
+                events.push(ActionEvent::Pressed(name.clone()));
+            } else if !pressed && self.pressed.remove(name) {
+                self.just_released.insert(name.clone());
+                events.push(ActionEvent::Released(name.clone()));
+            }
+        }
+        events
+    }
+
+    /// The actions and bindings, without the state of the inputs
+    pub fn to_json(&self) -> String {
+        let actions = self
+            .actions
+            .iter()
+            .map(|action| {
+                Json::Object(vec![
+                    field("name", action.name.as_str()),
+                    field("kind", format!("{:?}", action.kind)),
+                    (
+                        "bindings".to_string(),
+                        Json::Array(action.bindings.iter().map(binding_to_json).collect()),
+                    ),
+                ])
+            })
+            .collect();
+        Json::Object(vec![
+            field("format", ACTIONS_FORMAT),
+            field("version", ACTIONS_VERSION as f64),
+            ("actions".to_string(), Json::Array(actions)),
+        ])
+        .to_string()
+    }
+
+    pub fn parse(text: &str) -> Result<ActionMap, ActionMapError> {
+        let json = Json::parse(text).or_else(|error| invalid(&error))?;
+        if json.get("format").and_then(Json::as_str) != Some(ACTIONS_FORMAT) {
+            return invalid("not an action map");
+        }
+        match json.get("version").and_then(Json::as_f64) {
+            Some(version) if (1.0..=ACTIONS_VERSION as f64).contains(&version) => (),
+            _ => return invalid("unsupported version"),
+        }
+        let mut map = ActionMap::new();
+        for action in json_array(&json, "actions")? {
+            let name = match action.get("name").and_then(Json::as_str) {
+                Some(name) => name,
+                None => return invalid("bad action name"),
+            };
+            let kind = match action.get("kind").and_then(Json::as_str) {
+                Some("Digital") => ActionKind::Digital,
+                Some("Analog") => ActionKind::Analog,
+                _ => return invalid(&format!("bad kind of {}", name)),
+            };
+            let bindings = json_array(action, "bindings")?
+                .iter()
+                .map(binding_from_json)
+                .collect::<Result<Vec<Binding>, ActionMapError>>()
+                .map_err(|error| ActionMapError(format!("{} in {}", error, name)))?;
+            map.add_action(name, kind).bindings = bindings;
+        }
+        Ok(map)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ActionMapError> {
+        fs::write(path, self.to_json() + "\n")?;
+        Ok(())
+    }
+
+    /// Reads actions and bindings saved by `save`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ActionMap, ActionMapError> {
+        ActionMap::parse(&fs::read_to_string(path)?)
+    }
+}
+
+/// Change of an action of one player
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PlayerEvent {
+    pub player: usize,
+    pub event: ActionEvent,
+}
+
+/// Action maps of several players, each usually limited to the player's devices
+///
+/// Events are applied to every player reading their device, and the changes
+/// they cause are reported with the number of the player.
+///
+/// ```
+/// # use multiinput::*;
+/// # use multiinput::action::{ActionKind, ActionMap, Binding, DeviceSet, Input, Players};
+/// let mut actions = ActionMap::new();
+/// actions
+///     .add_action("jump", ActionKind::Digital)
+///     .bind(Binding::new(Input::Key(KeyId::Space)))
+///     .bind(Binding::new(Input::JoystickButton(0)));
+///
+/// let mut players = Players::new();
+/// for devices in vec![DeviceSet::new().keyboard(0).mouse(1), DeviceSet::new().joystick(0)] {
+///     let mut player = actions.clone();
+///     player.set_devices(Some(devices));
+///     players.add_player(player);
+/// }
+/// let events = players.apply(&RawEvent::JoystickButtonEvent(0, 0, State::Pressed));
+/// assert_eq!(events[0].player, 1);
+/// assert!(players.player(1).unwrap().action_pressed("jump"));
+/// assert!(!players.player(0).unwrap().action_pressed("jump"));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Players {
+    players: Vec<ActionMap>,
+}
+
+impl Players {
+    pub fn new() -> Players {
+        Players::default()
+    }
+
+    /// Adds a player, returning their number
+    pub fn add_player(&mut self, actions: ActionMap) -> usize {
+        self.players.push(actions);
+        self.players.len() - 1
+    }
+
+    /// Removes a player, moving the later players down by one
+    pub fn remove_player(&mut self, player: usize) -> Option<ActionMap> {
+        if player < self.players.len() {
+            Some(self.players.remove(player))
+        } else {
+            None
+        }
+    }
+
+    pub fn player(&self, player: usize) -> Option<&ActionMap> {
+        self.players.get(player)
+    }
+
+    /// Action map of a player, to change their devices or bindings
+    pub fn player_mut(&mut self, player: usize) -> Option<&mut ActionMap> {
+        self.players.get_mut(player)
+    }
+
+    pub fn len(&self) -> usize {
+        self.players.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.players.is_empty()
+    }
+
+    /// First player reading the device of an event
+    pub fn player_of(&self, event: &RawEvent) -> Option<usize> {
+        self.players.iter().position(|actions| actions.reads(event))
+    }
+
+    /// Applies an event to the players reading its device
+    pub fn apply(&mut self, event: &RawEvent) -> Vec<PlayerEvent> {
+        let mut events = Vec::new();
+        for (player, actions) in self.players.iter_mut().enumerate() {
+            events.extend(
+                actions
+                    .apply(event)
+                    .into_iter()
+                    .map(|event| PlayerEvent { player, event }),
+            );
+        }
+        events
+    }
+
+    /// Starts the following frame for every player
+    pub fn next_frame(&mut self) -> Vec<PlayerEvent> {
+        let mut events = Vec::new();
+        for (player, actions) in self.players.iter_mut().enumerate() {
+            events.extend(
+                actions
+                    .next_frame()
+                    .into_iter()
+                    .map(|event| PlayerEvent { player, event }),
+            );
+        }
+        events
+    }
+}
+
+/// Binding as a JSON object, leaving out adjustments that do nothing
+fn binding_to_json(binding: &Binding) -> Json {
+    let (input, value) = match binding.input {
+        Input::Key(ref key) => ("Key", Some(("key", name(key)))),
+        Input::MouseButton(ref button) => ("MouseButton", Some(("button", name(button)))),
+        Input::MouseWheel => ("MouseWheel", None),
+        Input::JoystickButton(button) => (
+            "JoystickButton",
+            Some(("button", Json::Number(button as f64))),
+        ),
+        Input::JoystickAxis(ref axis) => ("JoystickAxis", Some(("axis", name(axis)))),
+        Input::JoystickHat(ref direction) => ("JoystickHat", Some(("direction", name(direction)))),
+        Input::GamepadButton(ref button) => ("GamepadButton", Some(("button", name(button)))),
+        Input::GamepadAxis(ref axis) => ("GamepadAxis", Some(("axis", name(axis)))),
+    };
+    let mut fields = vec![field("input", input)];
+    if let Some((key, value)) = value {
+        fields.push((key.to_string(), value));
+    }
+    if binding.scale != 1.0 {
+        fields.push(field("scale", binding.scale));
+    }
+    if binding.invert {
+        fields.push(field("invert", true));
+    }
+    if binding.deadzone != 0.0 {
+        fields.push(field("deadzone", binding.deadzone));
+    }
+    Json::Object(fields)
+}
+
+fn binding_from_json(json: &Json) -> Result<Binding, ActionMapError> {
+    let input = match json.get("input").and_then(Json::as_str) {
+        Some("Key") => Input::Key(named(&KEY_IDS, json, "key")?),
+        Some("MouseButton") => Input::MouseButton(named(&MOUSE_BUTTONS, json, "button")?),
+        Some("MouseWheel") => Input::MouseWheel,
+        Some("JoystickButton") => match json.get("button").and_then(Json::as_f64) {
+            Some(button) if button >= 0.0 && button.fract() == 0.0 => {
+                Input::JoystickButton(button as usize)
+            }
+            _ => return invalid("bad button"),
+        },
+        Some("JoystickAxis") => Input::JoystickAxis(named(&JOYSTICK_AXES, json, "axis")?),
+        Some("JoystickHat") => Input::JoystickHat(named(&HAT_SWITCHES, json, "direction")?),
+        Some("GamepadButton") => Input::GamepadButton(named(&BUTTONS, json, "button")?),
+        Some("GamepadAxis") => Input::GamepadAxis(named(&AXES, json, "axis")?),
+        _ => return invalid("bad input"),
+    };
+    let number = |key: &str, default: f64| match json.get(key) {
+        None => Ok(default),
+        Some(value) => value
+            .as_f64()
+            .map_or_else(|| invalid(&format!("bad {}", key)), Ok),
+    };
+    Ok(Binding {
+        input,
+        scale: number("scale", 1.0)?,
+        invert: match json.get("invert") {
+            None => false,
+            Some(value) => value.as_bool().map_or_else(|| invalid("bad invert"), Ok)?,
+        },
+        deadzone: number("deadzone", 0.0)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    fn axis(value: f64) -> RawEvent {
+        RawEvent::JoystickAxisEvent(0, Axis::X, value)
+    }
+
+    fn hat(hatswitch: HatSwitch) -> RawEvent {
+        RawEvent::JoystickHatSwitchEvent(0, hatswitch)
+    }
+
+    #[test]
+    fn binding_values() {
+        let binding = Binding::new(Input::JoystickAxis(Axis::X));
+        assert_eq!(binding.value(-0.3), -0.3);
+        let deadzone = binding.clone().deadzone(0.2);
+        assert_eq!(deadzone.value(0.2), 0.0);
+        assert_eq!(deadzone.value(-0.1), 0.0);
+        assert!(close(deadzone.value(0.6), 0.5));
+        assert!(close(deadzone.value(-0.6), -0.5));
+        assert_eq!(deadzone.value(1.0), 1.0);
+        assert_eq!(binding.clone().deadzone(1.0).value(1.0), 0.0);
+        assert_eq!(binding.clone().scale(2.0).value(0.25), 0.5);
+        assert_eq!(binding.clone().invert().value(0.25), -0.25);
+        assert_eq!(binding.clone().invert().invert(), binding);
+        assert!(close(
+            binding.scale(-0.5).invert().deadzone(0.5).value(0.75),
+            0.25
+        ));
+    }
+
+    #[test]
+    fn digital_actions_press_past_the_threshold() {
+        let mut actions = ActionMap::new();
+        actions
+            .add_action("fire", ActionKind::Digital)
+            .bind(Binding::new(Input::JoystickAxis(Axis::X)));
+        assert!(actions.apply(&axis(PRESS_THRESHOLD)).is_empty());
+        assert!(!actions.action_pressed("fire"));
+        assert_eq!(
+            actions.apply(&axis(0.75)),
+            vec![ActionEvent::Pressed("fire".to_string())]
+        );
+        assert!(actions.action_just_pressed("fire"));
+        assert_eq!(actions.action_value("fire"), 1.0);
+        assert!(actions.next_frame().is_empty());
+        assert!(!actions.action_just_pressed("fire"));
+        assert!(actions.action_pressed("fire"));
+        assert_eq!(
+            actions.apply(&axis(-1.0)),
+            vec![ActionEvent::Released("fire".to_string())]
+        );
+        assert!(actions.action_just_released("fire"));
+        // Inverted bindings press towards negative
+        actions.rebind(
+            "fire",
+            vec![Binding::new(Input::JoystickAxis(Axis::X)).invert()],
+        );
+        assert!(actions.action_pressed("fire"));
+        assert_eq!(
+            actions.reset(),
+            vec![ActionEvent::Released("fire".to_string())]
+        );
+    }
+
+    #[test]
+    fn analog_actions_sum_their_bindings() {
+        let mut actions = ActionMap::new();
+        actions
+            .add_action("steer", ActionKind::Analog)
+            .bind(Binding::new(Input::JoystickAxis(Axis::X)).deadzone(0.5))
+            .bind(Binding::new(Input::Key(KeyId::Left)).scale(-1.0))
+            .bind(Binding::new(Input::Key(KeyId::Right)));
+        assert!(actions.apply(&axis(0.25)).is_empty());
+        assert_eq!(
+            actions.apply(&RawEvent::KeyboardEvent(0, KeyId::Left, State::Pressed)),
+            vec![ActionEvent::Changed("steer".to_string(), -1.0)]
+        );
+        actions.apply(&axis(1.0));
+        assert_eq!(actions.action_value("steer"), 0.0);
+        // The furthest value over all devices counts
+        actions.apply(&axis(0.75));
+        actions.apply(&RawEvent::JoystickAxisEvent(1, Axis::X, -1.0));
+        actions.apply(&RawEvent::KeyboardEvent(0, KeyId::Left, State::Released));
+        assert_eq!(actions.action_value("steer"), -1.0);
+        assert!(!actions.action_pressed("steer"));
+        assert_eq!(actions.action_value("missing"), 0.0);
+    }
+
+    #[test]
+    fn mouse_wheel_lasts_a_frame() {
+        let mut actions = ActionMap::new();
+        actions
+            .add_action("zoom", ActionKind::Analog)
+            .bind(Binding::new(Input::MouseWheel));
+        actions.apply(&RawEvent::MouseWheelEvent(0, 1.0));
+        actions.apply(&RawEvent::MouseWheelEvent(1, 0.5));
+        assert_eq!(actions.action_value("zoom"), 1.5);
+        assert_eq!(
+            actions.next_frame(),
+            vec![ActionEvent::Changed("zoom".to_string(), 0.0)]
+        );
+    }
+
+    #[test]
+    fn hat_directions_include_diagonals() {
+        let expected = [
+            (HatSwitch::Up, [true, false, false, false]),
+            (HatSwitch::UpRight, [true, true, false, false]),
+            (HatSwitch::Right, [false, true, false, false]),
+            (HatSwitch::DownRight, [false, true, true, false]),
+            (HatSwitch::Down, [false, false, true, false]),
+            (HatSwitch::DownLeft, [false, false, true, true]),
+            (HatSwitch::Left, [false, false, false, true]),
+            (HatSwitch::UpLeft, [true, false, false, true]),
+            (HatSwitch::Center, [false, false, false, false]),
+        ];
+        let directions = [
+            HatSwitch::Up,
+            HatSwitch::Right,
+            HatSwitch::Down,
+            HatSwitch::Left,
+        ];
+        for (hatswitch, pointing) in expected.iter() {
+            for (direction, &points) in directions.iter().zip(pointing.iter()) {
+                assert_eq!(
+                    hat_points(hatswitch, direction),
+                    points,
+                    "{:?} {:?}",
+                    hatswitch,
+                    direction
+                );
+            }
+            assert_eq!(
+                hat_points(hatswitch, &HatSwitch::Center),
+                *hatswitch == HatSwitch::Center
+            );
+        }
+        // Diagonal bindings need both directions
+        assert!(hat_points(&HatSwitch::UpLeft, &HatSwitch::UpLeft));
+        assert!(!hat_points(&HatSwitch::Up, &HatSwitch::UpLeft));
+        let mut actions = ActionMap::new();
+        actions
+            .add_action("up", ActionKind::Digital)
+            .bind(Binding::new(Input::JoystickHat(HatSwitch::Up)));
+        actions.apply(&hat(HatSwitch::UpRight));
+        assert!(actions.action_pressed("up"));
+        actions.apply(&hat(HatSwitch::Right));
+        assert!(!actions.action_pressed("up"));
+    }
+
+    #[test]
+    fn activated_bindings() {
+        let activated = |event: RawEvent| Binding::activated_by(&event);
+        assert_eq!(
+            activated(RawEvent::KeyboardEvent(0, KeyId::A, State::Pressed)),
+            Some(Binding::new(Input::Key(KeyId::A)))
+        );
+        assert_eq!(
+            activated(RawEvent::KeyboardEvent(0, KeyId::A, State::Released)),
+            None
+        );
+        assert_eq!(activated(axis(0.4)), None);
+        assert_eq!(
+            activated(axis(-0.9)),
+            Some(Binding::new(Input::JoystickAxis(Axis::X)).invert())
+        );
+        assert_eq!(
+            activated(RawEvent::MouseWheelEvent(0, -1.0)),
+            Some(Binding::new(Input::MouseWheel).invert())
+        );
+        assert_eq!(activated(hat(HatSwitch::Center)), None);
+        assert_eq!(
+            activated(hat(HatSwitch::DownLeft)),
+            Some(Binding::new(Input::JoystickHat(HatSwitch::DownLeft)))
+        );
+        assert_eq!(activated(RawEvent::MouseMoveEvent(0, 5, 5)), None);
+    }
+
+    #[test]
+    fn listening_replaces_a_binding() {
+        let mut actions = ActionMap::new();
+        actions
+            .add_action("jump", ActionKind::Digital)
+            .bind(Binding::new(Input::Key(KeyId::Space)));
+        assert!(!actions.listen_for_binding("missing", None));
+        assert!(actions.listen_for_binding("jump", Some(0)));
+        assert_eq!(actions.listening_for(), Some("jump"));
+        assert!(actions.apply(&axis(0.2)).is_empty());
+        let binding = Binding::new(Input::JoystickAxis(Axis::X));
+        assert_eq!(
+            actions.apply(&axis(0.9)),
+            vec![ActionEvent::Bound("jump".to_string(), binding.clone())]
+        );
+        assert_eq!(actions.listening_for(), None);
+        assert_eq!(actions.action("jump").unwrap().bindings, vec![binding]);
+        // The binding event did not reach the inputs
+        assert!(!actions.action_pressed("jump"));
+    }
+
+    #[test]
+    fn device_sets_limit_the_map() {
+        let mut actions = ActionMap::new();
+        actions
+            .add_action("jump", ActionKind::Digital)
+            .bind(Binding::new(Input::Key(KeyId::Space)));
+        actions.set_devices(Some(DeviceSet::new().keyboard(1).joystick(0)));
+        assert!(actions
+            .apply(&RawEvent::KeyboardEvent(0, KeyId::Space, State::Pressed))
+            .is_empty());
+        assert!(!actions.reads(&RawEvent::MouseMoveEvent(0, 1, 1)));
+        assert!(actions.reads(&axis(0.0)));
+        actions.apply(&RawEvent::KeyboardEvent(1, KeyId::Space, State::Pressed));
+        assert!(actions.action_pressed("jump"));
+        assert_eq!(
+            actions.set_devices(None),
+            vec![ActionEvent::Released("jump".to_string())]
+        );
+    }
+
+    fn every_input() -> ActionMap {
+        let mut actions = ActionMap::new();
+        actions
+            .add_action("digital", ActionKind::Digital)
+            .bind(Binding::new(Input::Key(KeyId::Escape)))
+            .bind(Binding::new(Input::MouseButton(MouseButton::Button5)))
+            .bind(Binding::new(Input::JoystickButton(7)))
+            .bind(Binding::new(Input::JoystickHat(HatSwitch::DownLeft)))
+            .bind(Binding::new(Input::GamepadButton(GamepadButton::North)));
+        actions
+            .add_action("analog \"quoted\"", ActionKind::Analog)
+            .bind(Binding::new(Input::MouseWheel).scale(0.5))
+            .bind(Binding::new(Input::JoystickAxis(Axis::SLIDER)).invert())
+            .bind(Binding::new(Input::GamepadAxis(GamepadAxis::RightTrigger)).deadzone(0.1));
+        actions
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let actions = every_input();
+        let json = actions.to_json();
+        assert!(json.starts_with(
+            r#"{"format":"multiinput-actions","version":1,"actions":[{"name":"digital","kind":"Digital","bindings":[{"input":"Key","key":"Escape"},"#
+        ));
+        assert!(json.contains(r#"{"input":"MouseWheel","scale":0.5}"#));
+        assert!(json.contains(r#"{"input":"JoystickAxis","axis":"SLIDER","invert":true}"#));
+        assert!(json.contains(r#"{"input":"JoystickButton","button":7}"#));
+        let parsed = ActionMap::parse(&json).unwrap();
+        assert_eq!(parsed.actions(), actions.actions());
+        assert_eq!(parsed.to_json(), json);
+        assert_eq!(
+            ActionMap::parse(&ActionMap::new().to_json())
+                .unwrap()
+                .actions(),
+            &[]
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |text: &str| ActionMap::parse(text).unwrap_err().0;
+        let valid = every_input().to_json();
+        assert_eq!(
+            error(&valid.replace(r#""version":1"#, r#""version":2"#)),
+            "Invalid action map: unsupported version"
+        );
+        assert_eq!(
+            error(&valid.replace(r#""version":1,"#, "")),
+            "Invalid action map: unsupported version"
+        );
+        assert_eq!(
+            error(&valid.replace("multiinput-actions", "multiinput-calibration")),
+            "Invalid action map: not an action map"
+        );
+        assert_eq!(
+            error(&valid.replace(r#""input":"MouseWheel""#, r#""input":"Pedal""#)),
+            "Invalid action map: bad input in analog \"quoted\""
+        );
+        assert_eq!(
+            error(&valid.replace("Escape", "Escapes")),
+            "Invalid action map: bad key in digital"
+        );
+        assert_eq!(
+            error(&valid.replace(r#""button":7"#, r#""button":-1"#)),
+            "Invalid action map: bad button in digital"
+        );
+        assert_eq!(
+            error(&valid.replace(r#""kind":"Digital""#, r#""kind":"Hybrid""#)),
+            "Invalid action map: bad kind of digital"
+        );
+        assert_eq!(
+            error(&valid.replace(r#""invert":true"#, r#""invert":1"#)),
+            "Invalid action map: bad invert in analog \"quoted\""
+        );
+        assert_eq!(error("[]"), "Invalid action map: not an action map");
+        assert_eq!(
+            error("{\"format\""),
+            "Invalid action map: Expected : at column 10"
+        );
+    }
+}
@@ -0,0 +1,544 @@
+//! "Press a button to join" assignment of devices to player slots
+//!
+//! A `PlayerAssignment` watches a `RawInputManager` for a join input on devices
+//! no player has, and gives each such device the first free slot. Players
+//! leave with a leave input, slots can be swapped, and more devices can be
+//! given to a slot (e.g. a mouse to go with a keyboard). Assigned devices are
+//! remembered by stable id, so an unplugged device keeps its slot and gets it
+//! back when it appears in the device list again, even under another id.
+//! Every change is reported as an `AssignmentEvent`.
+//!
+//! ```
+//! # use multiinput::*;
+//! # use multiinput::assignment::{AssignmentEvent, PlayerAssignment};
+//! # use multiinput::mock::MockBackend;
+//! let backend = MockBackend::new();
+//! let handle = backend.handle();
+//! let mut devices = DevicesDisplayInfo::default();
+//! devices.keyboards.push(KeyboardDisplayInfo::default());
+//! handle.set_devices(devices);
+//! let mut manager = RawInputManager::with_backend(
+//!     move || backend,
+//!     event_queue::DEFAULT_CAPACITY,
+//!     OverflowPolicy::DropOldest,
+//! );
+//! manager.register_devices(DeviceType::Keyboards);
+//!
+//! let mut lobby = PlayerAssignment::new(&manager, 4);
+//! handle.push_event(RawEvent::KeyboardEvent(0, KeyId::Return, State::Pressed));
+//! let mut events = Vec::new();
+//! while events.is_empty() {
+//!     std::thread::sleep(std::time::Duration::from_millis(10));
+//!     events = lobby.poll();
+//! }
+//! match events[0] {
+//!     AssignmentEvent::Joined(slot, _) => assert_eq!(slot, 0),
+//!     ref event => panic!("{:?}", event),
+//! }
+//! // The player's actions read the devices of their slot
+//! let devices = lobby.device_set(0);
+//! assert_eq!(devices.keyboards, vec![0]);
+//! ```
+
+use action::{Binding, DeviceSet, Input};
+use device_filter::StableDeviceId;
+use devices::DevicesDisplayInfo;
+use event::{EventKind, KeyId, MouseButton, RawEvent, EVENT_KINDS};
+use gamepad::GamepadButton;
+use manager::{DeviceListSource, DeviceType, RawInputManager};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use subscription::{EventFilter, EventSubscriber};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum DeviceKind {
+    Keyboard,
+    Mouse,
+    Joystick,
+}
+
+impl DeviceKind {
+    /// Kind of the device an event comes from
+    pub fn of(event: &RawEvent) -> DeviceKind {
+        if event.is_from(&DeviceType::Keyboards) {
+            DeviceKind::Keyboard
+        } else if event.is_from(&DeviceType::Mice) {
+            DeviceKind::Mouse
+        } else {
+            DeviceKind::Joystick
+        }
+    }
+}
+
+/// Device of a player
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AssignedDevice {
+    pub kind: DeviceKind,
+    /// Current device id, None while the device is disconnected
+    pub id: Option<usize>,
+    pub stable_id: StableDeviceId,
+}
+
+/// Change of the assignment of devices to slots
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum AssignmentEvent {
+    /// A device took a free slot
+    Joined(usize, AssignedDevice),
+    /// A device was given to an occupied slot by `assign`
+    Assigned(usize, AssignedDevice),
+    /// A join input was pressed while every slot was taken
+    Rejected(AssignedDevice),
+    /// A slot was freed, with the devices it had
+    Left(usize, Vec<AssignedDevice>),
+    /// The devices of two slots were exchanged
+    Swapped(usize, usize),
+    /// A device of a slot is gone from the device list, but keeps its slot
+    Disconnected(usize, AssignedDevice),
+    /// A disconnected device is back, or one of a slot has a new id
+    Reconnected(usize, AssignedDevice),
+}
+
+/// Slots of players and the devices given to them
+///
+/// By default players join by pressing Return or Space, clicking, pressing
+/// joystick button 0 or a gamepad's Start or South button, and leave by
+/// pressing Escape or a gamepad's Back button. The device list is fetched
+/// again when an event comes from a device missing from it. Device disconnects
+/// are noticed when `refresh_devices` is given a device list, e.g. after
+/// registering the devices again.
+pub struct PlayerAssignment {
+    subscriber: EventSubscriber,
+    source: DeviceListSource,
+    devices: DevicesDisplayInfo,
+    slots: Vec<Vec<AssignedDevice>>,
+    join_inputs: Vec<Input>,
+    leave_inputs: Vec<Input>,
+}
+
+impl PlayerAssignment {
+    /// Assignment of the manager's devices to the given number of slots, all free
+    pub fn new(manager: &RawInputManager, slots: usize) -> PlayerAssignment {
+        PlayerAssignment {
+            // Mouse motion never joins or leaves
+            subscriber: manager.subscribe(
+                EVENT_KINDS
+                    .iter()
+                    .filter(|&&kind| kind != EventKind::MouseMove)
+                    .fold(EventFilter::new(), |filter, &kind| filter.kind(kind)),
+            ),
+            source: manager.device_list_source(),
+            devices: manager.get_device_list(),
+            slots: vec![Vec::new(); slots],
+            join_inputs: vec![
+                Input::Key(KeyId::Return),
+                Input::Key(KeyId::Space),
+                Input::MouseButton(MouseButton::Left),
+                Input::JoystickButton(0),
+                Input::GamepadButton(GamepadButton::Start),
+                Input::GamepadButton(GamepadButton::South),
+            ],
+            leave_inputs: vec![
+                Input::Key(KeyId::Escape),
+                Input::GamepadButton(GamepadButton::Back),
+            ],
+        }
+    }
+
+    /// Inputs an unassigned device joins with (see `Binding::activated_by`)
+    pub fn set_join_inputs(&mut self, inputs: Vec<Input>) {
+        self.join_inputs = inputs;
+    }
+
+    /// Inputs an assigned device leaves its slot with
+    pub fn set_leave_inputs(&mut self, inputs: Vec<Input>) {
+        self.leave_inputs = inputs;
+    }
+
+    /// Handles the events received since the last call
+    pub fn poll(&mut self) -> Vec<AssignmentEvent> {
+        let mut events = Vec::new();
+        for event in self.subscriber.get_events() {
+            events.extend(self.apply(&event));
+        }
+        events
+    }
+
+    /// Handles one event (`poll` does this for the manager's events)
+    ///
+    /// An event of a device missing from the device list fetches the list
+    /// again first, so the changes it brings are reported before the event's.
+    pub fn apply(&mut self, event: &RawEvent) -> Vec<AssignmentEvent> {
+        let input = match Binding::activated_by(event) {
+            Some(binding) => binding.input,
+            None => return Vec::new(),
+        };
+        let kind = DeviceKind::of(event);
+        let id = event.device_id();
+        let mut events = Vec::new();
+        if stable_id(&self.devices, kind, id).is_none() {
+            if let Some(devices) = self.source.get() {
+                events = self.set_devices(devices);
+            }
+        }
+        match self.slot_of_device(kind, id) {
+            Some(slot) if self.leave_inputs.contains(&input) => events.extend(self.leave(slot)),
+            Some(_) => (),
+            None if self.join_inputs.contains(&input) => {
+                if let Some(device) = self.device(kind, id) {
+                    match self.slots.iter().position(Vec::is_empty) {
+                        Some(slot) => {
+                            self.slots[slot].push(device);
+                            events.push(AssignmentEvent::Joined(slot, device));
+                        }
+                        None => events.push(AssignmentEvent::Rejected(device)),
+                    }
+                }
+            }
+            None => (),
+        }
+        events
+    }
+
+    /// Takes a new device list, finding assigned devices by their stable ids
+    pub fn refresh_devices(&mut self, manager: &RawInputManager) -> Vec<AssignmentEvent> {
+        self.set_devices(manager.get_device_list())
+    }
+
+    /// Takes a new device list (see `refresh_devices`)
+    pub fn set_devices(&mut self, devices: DevicesDisplayInfo) -> Vec<AssignmentEvent> {
+        self.devices = devices;
+        let devices = &self.devices;
+        let kept = |device: &AssignedDevice| {
+            device
+                .id
+                .is_some_and(|id| stable_id(devices, device.kind, id) == Some(device.stable_id))
+        };
+        // Devices still under their id keep it, the others are looked for among the rest
+        let mut taken: HashSet<(DeviceKind, usize)> = self
+            .slots
+            .iter()
+            .flatten()
+            .filter(|device| kept(device))
+            .map(|device| (device.kind, device.id.unwrap()))
+            .collect();
+        let mut events = Vec::new();
+        for (slot, assigned) in self.slots.iter_mut().enumerate() {
+            for device in assigned.iter_mut() {
+                if kept(device) {
+                    continue;
+                }
+                let found = (0..device_count(devices, device.kind)).find(|&id| {
+                    !taken.contains(&(device.kind, id))
+                        && stable_id(devices, device.kind, id) == Some(device.stable_id)
+                });
+                match (device.id, found) {
+                    (_, Some(id)) => {
+                        taken.insert((device.kind, id));
+                        device.id = Some(id);
+                        events.push(AssignmentEvent::Reconnected(slot, *device));
+                    }
+                    (Some(_), None) => {
+                        device.id = None;
+                        events.push(AssignmentEvent::Disconnected(slot, *device));
+                    }
+                    (None, None) => (),
+                }
+            }
+        }
+        events
+    }
+
+    /// Gives a device to a slot, whether or not it is free
+    ///
+    /// Returns None if the slot or device does not exist or the device is
+    /// already assigned.
+    pub fn assign(&mut self, slot: usize, kind: DeviceKind, id: usize) -> Option<AssignmentEvent> {
+        if slot >= self.slots.len() || self.slot_of_device(kind, id).is_some() {
+            return None;
+        }
+        let device = self.device(kind, id)?;
+        let event = if self.slots[slot].is_empty() {
+            AssignmentEvent::Joined(slot, device)
+        } else {
+            AssignmentEvent::Assigned(slot, device)
+        };
+        self.slots[slot].push(device);
+        Some(event)
+    }
+
+    /// Frees a slot, returning None if it was free already
+    pub fn leave(&mut self, slot: usize) -> Option<AssignmentEvent> {
+        match self.slots.get_mut(slot) {
+            Some(devices) if !devices.is_empty() => {
+                Some(AssignmentEvent::Left(slot, ::std::mem::take(devices)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Exchanges the devices of two slots, free or not
+    pub fn swap(&mut self, first: usize, second: usize) -> Option<AssignmentEvent> {
+        if first == second || first >= self.slots.len() || second >= self.slots.len() {
+            return None;
+        }
+        self.slots.swap(first, second);
+        Some(AssignmentEvent::Swapped(first, second))
+    }
+
+    pub fn slot_count(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Devices of a slot, empty if it is free
+    pub fn devices(&self, slot: usize) -> &[AssignedDevice] {
+        self.slots.get(slot).map_or(&[], Vec::as_slice)
+    }
+
+    /// Slot of the device an event comes from
+    pub fn slot_of(&self, event: &RawEvent) -> Option<usize> {
+        self.slot_of_device(DeviceKind::of(event), event.device_id())
+    }
+
+    /// Connected devices of a slot, for `ActionMap::set_devices`
+    pub fn device_set(&self, slot: usize) -> DeviceSet {
+        let mut set = DeviceSet::new();
+        for device in self.devices(slot) {
+            if let Some(id) = device.id {
+                set = match device.kind {
+                    DeviceKind::Keyboard => set.keyboard(id),
+                    DeviceKind::Mouse => set.mouse(id),
+                    DeviceKind::Joystick => set.joystick(id),
+                };
+            }
+        }
+        set
+    }
+
+    fn slot_of_device(&self, kind: DeviceKind, id: usize) -> Option<usize> {
+        self.slots.iter().position(|devices| {
+            devices
+                .iter()
+                .any(|device| device.kind == kind && device.id == Some(id))
+        })
+    }
+
+    fn device(&self, kind: DeviceKind, id: usize) -> Option<AssignedDevice> {
+        stable_id(&self.devices, kind, id).map(|stable_id| AssignedDevice {
+            kind,
+            id: Some(id),
+            stable_id,
+        })
+    }
+}
+
+fn stable_id(devices: &DevicesDisplayInfo, kind: DeviceKind, id: usize) -> Option<StableDeviceId> {
+    match kind {
+        DeviceKind::Keyboard => devices.keyboards.get(id).map(|device| device.stable_id),
+        DeviceKind::Mouse => devices.mice.get(id).map(|device| device.stable_id),
+        DeviceKind::Joystick => devices.joysticks.get(id).map(|device| device.stable_id),
+    }
+}
+
+fn device_count(devices: &DevicesDisplayInfo, kind: DeviceKind) -> usize {
+    match kind {
+        DeviceKind::Keyboard => devices.keyboards.len(),
+        DeviceKind::Mouse => devices.mice.len(),
+        DeviceKind::Joystick => devices.joysticks.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use devices::{JoystickDisplayInfo, KeyboardDisplayInfo};
+    use event::State;
+    use event_queue::{OverflowPolicy, DEFAULT_CAPACITY};
+    use mock::{MockBackend, MockHandle};
+
+    fn devices(keyboards: &[&str], joysticks: &[&str]) -> DevicesDisplayInfo {
+        DevicesDisplayInfo {
+            mice: Vec::new(),
+            keyboards: keyboards
+                .iter()
+                .map(|name| KeyboardDisplayInfo {
+                    name: name.to_string(),
+                    stable_id: StableDeviceId::new(name, None, &Default::default()),
+                    ..Default::default()
+                })
+                .collect(),
+            joysticks: joysticks
+                .iter()
+                .map(|name| JoystickDisplayInfo {
+                    name: name.to_string(),
+                    stable_id: StableDeviceId::new(name, None, &Default::default()),
+                    ..Default::default()
+                })
+                .collect(),
+        }
+    }
+
+    fn lobby(
+        devices: DevicesDisplayInfo,
+        slots: usize,
+    ) -> (RawInputManager, MockHandle, PlayerAssignment) {
+        let backend = MockBackend::new();
+        let handle = backend.handle();
+        handle.set_devices(devices);
+        let manager = RawInputManager::with_backend(
+            move || backend,
+            DEFAULT_CAPACITY,
+            OverflowPolicy::DropOldest,
+        );
+        let lobby = PlayerAssignment::new(&manager, slots);
+        (manager, handle, lobby)
+    }
+
+    fn key(id: usize, key: KeyId) -> RawEvent {
+        RawEvent::KeyboardEvent(id, key, State::Pressed)
+    }
+
+    fn button(id: usize, button: usize) -> RawEvent {
+        RawEvent::JoystickButtonEvent(id, button, State::Pressed)
+    }
+
+    fn keyboard(id: usize, name: &str) -> AssignedDevice {
+        AssignedDevice {
+            kind: DeviceKind::Keyboard,
+            id: Some(id),
+            stable_id: StableDeviceId::new(name, None, &Default::default()),
+        }
+    }
+
+    fn joystick(id: Option<usize>, name: &str) -> AssignedDevice {
+        AssignedDevice {
+            kind: DeviceKind::Joystick,
+            id,
+            stable_id: StableDeviceId::new(name, None, &Default::default()),
+        }
+    }
+
+    #[test]
+    fn join_leave_and_swap() {
+        let (_manager, _handle, mut lobby) = lobby(devices(&["kb"], &["pad"]), 3);
+        assert_eq!(lobby.slot_count(), 3);
+        assert_eq!(
+            lobby.apply(&key(0, KeyId::Return)),
+            vec![AssignmentEvent::Joined(0, keyboard(0, "kb"))]
+        );
+        // Joined devices do not join again, and other inputs do nothing
+        assert!(lobby.apply(&key(0, KeyId::Space)).is_empty());
+        assert!(lobby.apply(&button(0, 1)).is_empty());
+        assert!(lobby
+            .apply(&RawEvent::KeyboardEvent(0, KeyId::Return, State::Released))
+            .is_empty());
+        assert_eq!(
+            lobby.apply(&button(0, 0)),
+            vec![AssignmentEvent::Joined(1, joystick(Some(0), "pad"))]
+        );
+        assert_eq!(lobby.slot_of(&button(0, 3)), Some(1));
+        assert_eq!(lobby.swap(0, 2), Some(AssignmentEvent::Swapped(0, 2)));
+        assert_eq!(lobby.swap(1, 1), None);
+        assert_eq!(lobby.swap(0, 3), None);
+        assert!(lobby.devices(0).is_empty());
+        assert_eq!(lobby.device_set(2), DeviceSet::new().keyboard(0));
+        assert_eq!(
+            lobby.apply(&key(0, KeyId::Escape)),
+            vec![AssignmentEvent::Left(2, vec![keyboard(0, "kb")])]
+        );
+        assert_eq!(lobby.leave(2), None);
+        assert_eq!(lobby.slot_of(&key(0, KeyId::A)), None);
+        // The freed keyboard takes the first free slot
+        assert_eq!(
+            lobby.apply(&key(0, KeyId::Space)),
+            vec![AssignmentEvent::Joined(0, keyboard(0, "kb"))]
+        );
+    }
+
+    #[test]
+    fn assign_adds_devices_to_a_slot() {
+        let (_manager, _handle, mut lobby) = lobby(devices(&["kb"], &["pad"]), 2);
+        assert_eq!(
+            lobby.assign(1, DeviceKind::Keyboard, 0),
+            Some(AssignmentEvent::Joined(1, keyboard(0, "kb")))
+        );
+        assert_eq!(
+            lobby.assign(1, DeviceKind::Joystick, 0),
+            Some(AssignmentEvent::Assigned(1, joystick(Some(0), "pad")))
+        );
+        assert_eq!(lobby.assign(0, DeviceKind::Keyboard, 0), None);
+        assert_eq!(lobby.assign(2, DeviceKind::Joystick, 0), None);
+        assert_eq!(lobby.assign(0, DeviceKind::Mouse, 0), None);
+        assert_eq!(
+            lobby.device_set(1),
+            DeviceSet::new().keyboard(0).joystick(0)
+        );
+    }
+
+    #[test]
+    fn rejects_when_full() {
+        let (_manager, _handle, mut lobby) = lobby(devices(&["one", "two"], &[]), 1);
+        assert_eq!(
+            lobby.apply(&key(0, KeyId::Return)),
+            vec![AssignmentEvent::Joined(0, keyboard(0, "one"))]
+        );
+        assert_eq!(
+            lobby.apply(&key(1, KeyId::Return)),
+            vec![AssignmentEvent::Rejected(keyboard(1, "two"))]
+        );
+        assert!(lobby.devices(0) == [keyboard(0, "one")]);
+    }
+
+    #[test]
+    fn reconnects_by_stable_id() {
+        let (_manager, _handle, mut lobby) = lobby(devices(&[], &["a", "b"]), 2);
+        lobby.apply(&button(0, 0));
+        lobby.apply(&button(1, 0));
+        // Reordered ids keep the slots
+        assert_eq!(
+            lobby.set_devices(devices(&[], &["b", "a"])),
+            vec![
+                AssignmentEvent::Reconnected(0, joystick(Some(1), "a")),
+                AssignmentEvent::Reconnected(1, joystick(Some(0), "b")),
+            ]
+        );
+        assert_eq!(lobby.slot_of(&button(1, 0)), Some(0));
+        assert_eq!(
+            lobby.set_devices(devices(&[], &["b"])),
+            vec![AssignmentEvent::Disconnected(0, joystick(None, "a"))]
+        );
+        assert_eq!(lobby.device_set(0), DeviceSet::new());
+        // Another joystick does not take the slot of a disconnected one
+        assert_eq!(lobby.set_devices(devices(&[], &["b", "c"])), vec![]);
+        assert_eq!(
+            lobby.set_devices(devices(&[], &["c", "b", "a"])),
+            vec![
+                AssignmentEvent::Reconnected(0, joystick(Some(2), "a")),
+                AssignmentEvent::Reconnected(1, joystick(Some(1), "b")),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_devices_refresh_the_device_list() {
+        let (manager, handle, mut lobby) = lobby(devices(&[], &["a"]), 2);
+        lobby.apply(&button(0, 0));
+        // "a" is plugged in again after "b"
+        handle.set_devices(devices(&[], &["b", "a"]));
+        assert_eq!(
+            lobby.apply(&button(1, 0)),
+            vec![AssignmentEvent::Reconnected(0, joystick(Some(1), "a"))]
+        );
+        assert_eq!(
+            lobby.apply(&button(0, 0)),
+            vec![AssignmentEvent::Joined(1, joystick(Some(0), "b"))]
+        );
+        // Devices missing after fetching the list are ignored
+        assert!(lobby.apply(&button(5, 0)).is_empty());
+        drop(manager);
+        assert!(lobby.apply(&button(6, 0)).is_empty());
+    }
+}
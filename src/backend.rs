@@ -0,0 +1,73 @@
+//! Sources of devices and input driven by the `RawInputManager`
+//!
+//! The manager thread owns a single backend: rawinput on windows, or a
+//! `MockBackend` (see the mock module) to drive the manager without hardware.
+
+use capture::HidReportSink;
+use devices::{DevicesDisplayInfo, JoystickState};
+use event::RawEvent;
+use force_feedback::{ForceFeedbackError, ForceFeedbackSink};
+use gamepad::GamepadMappingDb;
+use led::{LedError, LedSink};
+use manager::DeviceType;
+use std::collections::{HashSet, VecDeque};
+use std::time::Duration;
+
+/// Wakes the manager thread from `Backend::wait`
+pub type Waker = Box<dyn Fn() + Send>;
+
+/// Source of devices and input events
+///
+/// A backend is created on the manager thread and only used there.
+pub trait Backend {
+    /// Returns a function that makes a pending or later `wait` return
+    /// (used from other threads whenever a command is sent)
+    fn waker(&self) -> Waker;
+
+    fn register_devices(&mut self, device_type: DeviceType) -> Result<(), &'static str>;
+
+    /// Restricts events to the devices with the given names
+    fn filter_devices(&mut self, device_names: HashSet<String>);
+
+    fn unfilter_devices(&mut self);
+
+    /// Translates joystick input through the mappings (or stops if None)
+    fn set_gamepad_mappings(&mut self, mappings: Option<&GamepadMappingDb>);
+
+    /// Sends the descriptors and raw reports of joysticks to sink (or stops if None)
+    /// Descriptors are sent straight away and again whenever devices are registered.
+    /// Backends without raw reports ignore this.
+    fn record_hid_reports(&mut self, _sink: Option<HidReportSink>) {}
+
+    /// Appends all pending input to events
+    fn read_events(&mut self, events: &mut VecDeque<RawEvent>);
+
+    /// Blocks until input may be available, the waker is called or the timeout has passed
+    fn wait(&mut self, timeout: Option<Duration>);
+
+    fn joystick_state(&self, id: usize) -> Option<JoystickState>;
+
+    fn device_list(&self) -> DevicesDisplayInfo;
+
+    fn print_device_list(&self) {
+        let devices = self.device_list();
+        println!("Mice:");
+        for mouse in &devices.mice {
+            println!("{:?}", mouse);
+        }
+        println!("Keyboards:");
+        for keyboard in &devices.keyboards {
+            println!("{:?}", keyboard);
+        }
+        println!("Hids:");
+        for joystick in &devices.joysticks {
+            println!("{:?}", joystick);
+        }
+    }
+
+    /// Opens the rumble output of a joystick
+    fn open_force_feedback(&mut self, id: usize) -> Result<Box<dyn ForceFeedbackSink>, ForceFeedbackError>;
+
+    /// Opens the indicator LEDs of a keyboard
+    fn open_keyboard_leds(&mut self, id: usize) -> Result<Box<dyn LedSink>, LedError>;
+}
@@ -0,0 +1,384 @@
+//! Interactive joystick tester and calibration
+//!
+//! Run `joytest help` for usage.
+
+extern crate multiinput;
+
+use multiinput::calibration::{CalibrationProfile, CalibrationStep, Calibrator};
+use multiinput::replay::{ReplayBackend, ReplayMode};
+use multiinput::*;
+use std::collections::VecDeque;
+use std::env;
+use std::io::{stdin, stdout, BufRead, Write};
+use std::process;
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "Usage: joytest [--replay <capture>] [--speed <factor>]
+
+Shows the axes (normalised, raw and calibrated), buttons, hat and event rate of
+each joystick as they change, and calibrates them. Input comes from the devices,
+or from a capture file with --replay (faster or slower with --speed).
+
+Commands, typed at the prompt and followed by Enter:
+  n, p          Shows the next or previous joystick
+  c             Calibrates the shown joystick: leave the sticks at rest and press
+                Enter, then move every axis to both limits and press Enter again
+  s [<file>]    Saves the calibration (to calibration-<stable id>.json by default)
+  l <file>      Loads a calibration profile to compare against
+  x             Drops the calibration
+  q             Quits";
+
+/// Width of the axis bars, in characters
+const BAR_WIDTH: usize = 41;
+/// Buttons per row of the button grid
+const BUTTON_COLUMNS: usize = 16;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Err(error) = parse(&args).and_then(|(replay, speed)| run(replay, speed)) {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
+
+fn parse(args: &[String]) -> Result<(Option<String>, Option<f64>), String> {
+    let mut replay = None;
+    let mut speed = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("Missing value for {}", arg))
+        };
+        match arg.as_str() {
+            "--replay" => replay = Some(value()?.clone()),
+            "--speed" => {
+                let text = value()?;
+                match text.parse::<f64>() {
+                    Ok(factor) if factor > 0.0 => speed = Some(factor),
+                    _ => return Err(format!("Invalid value {} for {}", text, arg)),
+                }
+            }
+            "help" | "--help" | "-h" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            _ => return Err(format!("Unknown option {}\n\n{}", arg, USAGE)),
+        }
+    }
+    Ok((replay, speed))
+}
+
+fn open_manager(replay: Option<String>, speed: Option<f64>) -> Result<RawInputManager, String> {
+    let mut manager = match replay {
+        Some(path) => {
+            let mode = match speed {
+                Some(speed) => ReplayMode::Accelerated(speed),
+                None => ReplayMode::RealTime,
+            };
+            let backend =
+                ReplayBackend::load(&path, mode).map_err(|error| format!("{}: {}", path, error))?;
+            RawInputManager::with_backend(
+                move || backend,
+                event_queue::DEFAULT_CAPACITY,
+                OverflowPolicy::default(),
+            )
+        }
+        None => RawInputManager::new().map_err(str::to_string)?,
+    };
+    manager.register_devices(DeviceType::Joysticks(XInputInclude::True));
+    Ok(manager)
+}
+
+/// What the tester shows and the calibration in progress
+struct Tester {
+    joysticks: Vec<JoystickDisplayInfo>,
+    selected: usize,
+    calibrator: Option<Calibrator>,
+    profile: Option<CalibrationProfile>,
+    /// Times of the shown joystick's events during the last second
+    recent: VecDeque<Instant>,
+    message: String,
+}
+
+impl Tester {
+    fn joystick(&self) -> &JoystickDisplayInfo {
+        &self.joysticks[self.selected]
+    }
+
+    /// Profile of the shown joystick, if there is one
+    fn profile(&self) -> Option<&CalibrationProfile> {
+        self.profile
+            .as_ref()
+            .filter(|profile| profile.stable_id == self.joystick().stable_id)
+    }
+
+    fn select(&mut self, selected: usize) {
+        self.selected = selected;
+        self.calibrator = None;
+        self.recent.clear();
+        self.message = String::new();
+    }
+
+    /// Runs a command, returning false to quit
+    fn command(&mut self, line: &str) -> bool {
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next()) {
+            (None, _) => self.next_step(),
+            (Some("q"), _) => return false,
+            (Some("n"), _) => {
+                let next = (self.selected + 1) % self.joysticks.len();
+                self.select(next)
+            }
+            (Some("p"), _) => {
+                let previous = (self.selected + self.joysticks.len() - 1) % self.joysticks.len();
+                self.select(previous)
+            }
+            (Some("c"), _) => {
+                self.calibrator = Some(Calibrator::new());
+                self.message = "Leave the sticks at rest, then press Enter".to_string();
+            }
+            (Some("s"), path) => {
+                let path = path.map_or_else(
+                    || format!("calibration-{}.json", self.joystick().stable_id),
+                    str::to_string,
+                );
+                self.message = match self.profile() {
+                    Some(profile) => match profile.save(&path) {
+                        Ok(()) => format!("Saved the calibration to {}", path),
+                        Err(error) => format!("{}: {}", path, error),
+                    },
+                    None => "Nothing to save, calibrate with c first".to_string(),
+                };
+            }
+            (Some("l"), Some(path)) => {
+                self.message = match CalibrationProfile::load(path) {
+                    Ok(profile) => {
+                        let message = match self
+                            .joysticks
+                            .iter()
+                            .position(|joystick| joystick.stable_id == profile.stable_id)
+                        {
+                            Some(id) => {
+                                self.select(id);
+                                format!("Loaded {}", path)
+                            }
+                            None => format!("{} is for another joystick ({})", path, profile.name),
+                        };
+                        self.profile = Some(profile);
+                        message
+                    }
+                    Err(error) => format!("{}: {}", path, error),
+                };
+            }
+            (Some("x"), _) => {
+                self.calibrator = None;
+                self.profile = None;
+                self.message = "Dropped the calibration".to_string();
+            }
+            (Some(_), _) => {
+                self.message = "Unknown command, run joytest help for usage".to_string()
+            }
+        }
+        true
+    }
+
+    /// Moves the calibration on when Enter is pressed on its own
+    fn next_step(&mut self) {
+        let step = match self.calibrator {
+            Some(ref calibrator) => calibrator.step(),
+            None => return,
+        };
+        match step {
+            CalibrationStep::Center => {
+                self.calibrator.as_mut().unwrap().next_step();
+                self.message =
+                    "Move every axis to both of its limits, then press Enter".to_string();
+            }
+            CalibrationStep::Range => {
+                let calibrator = self.calibrator.take().unwrap();
+                let joystick = self.joystick();
+                let profile = calibrator.finish(joystick.stable_id, &joystick.name);
+                self.profile = Some(profile);
+                self.message = "Calibrated, s saves the profile".to_string();
+            }
+        }
+    }
+
+    fn add_event(&mut self, event: &RawEvent, now: Instant) {
+        if event.device_id() != self.selected
+            || !event.is_from(&DeviceType::Joysticks(XInputInclude::True))
+        {
+            return;
+        }
+        self.recent.push_back(now);
+        if let Some(ref mut calibrator) = self.calibrator {
+            calibrator.add_event(event);
+        }
+    }
+
+    /// Lines of the display for the latest state of the shown joystick
+    fn draw(&mut self, state: Option<&JoystickState>, now: Instant) -> Vec<String> {
+        while self
+            .recent
+            .front()
+            .is_some_and(|&time| now.duration_since(time) > Duration::from_secs(1))
+        {
+            self.recent.pop_front();
+        }
+        let mut lines = Vec::new();
+        for (id, joystick) in self.joysticks.iter().enumerate() {
+            let marker = if id == self.selected { '>' } else { ' ' };
+            let product = joystick.product.as_ref().unwrap_or(&joystick.name);
+            lines.push(format!(
+                "{} {:>2}  {}  {}",
+                marker, id, joystick.stable_id, product
+            ));
+        }
+        lines.push(String::new());
+        let state = match state {
+            Some(state) => state,
+            None => {
+                lines.push("No input from this joystick yet".to_string());
+                return lines;
+            }
+        };
+        lines.push(format!("Events  {}/s", self.recent.len()));
+        let profile = self.profile();
+        let axes = &state.axis_states;
+        let raw = &state.raw_axis_states;
+        let values = [
+            (Axis::X, axes.x, raw.x),
+            (Axis::Y, axes.y, raw.y),
+            (Axis::Z, axes.z, raw.z),
+            (Axis::RX, axes.rx, raw.rx),
+            (Axis::RY, axes.ry, raw.ry),
+            (Axis::RZ, axes.rz, raw.rz),
+            (Axis::SLIDER, axes.slider, raw.slider),
+        ];
+        for (axis, value, raw) in values.iter() {
+            let value = match *value {
+                Some(value) => value,
+                None => continue,
+            };
+            let mut line = format!(
+                "{:<7} {:>+7.3}  raw {:>6}  [{}]",
+                format!("{:?}", axis),
+                value,
+                raw,
+                bar(value)
+            );
+            if let Some(profile) = profile {
+                line += &format!("  calibrated {:>+7.3}", profile.axis(axis, value));
+            }
+            lines.push(line);
+        }
+        for (row, buttons) in state.button_states.chunks(BUTTON_COLUMNS).enumerate() {
+            let mut line = if row == 0 { "Buttons" } else { "" }.to_string();
+            line = format!("{:<7}", line);
+            for (column, &pressed) in buttons.iter().enumerate() {
+                let number = row * BUTTON_COLUMNS + column + 1;
+                if pressed {
+                    line += &format!(" \x1b[7m{:>3}\x1b[0m", number);
+                } else {
+                    line += &format!(" {:>3}", number);
+                }
+            }
+            lines.push(line);
+        }
+        if let Some(ref hatswitch) = state.hatswitch {
+            lines.push(format!("Hat     {:?}", hatswitch));
+        }
+        lines
+    }
+}
+
+/// Bar from the centre to the value of an axis in [-1, 1]
+fn bar(value: f64) -> String {
+    let center = BAR_WIDTH / 2;
+    let position = ((value.clamp(-1.0, 1.0) + 1.0) / 2.0 * (BAR_WIDTH - 1) as f64).round() as usize;
+    let (low, high) = (position.min(center), position.max(center));
+    (0..BAR_WIDTH)
+        .map(|column| match column {
+            column if column == position => 'O',
+            column if column == center => '|',
+            column if column > low && column < high => '=',
+            _ => '-',
+        })
+        .collect()
+}
+
+fn run(replay: Option<String>, speed: Option<f64>) -> Result<(), String> {
+    let mut manager = open_manager(replay, speed)?;
+    let joysticks = manager.get_device_list().joysticks;
+    if joysticks.is_empty() {
+        return Err("No joysticks found".to_string());
+    }
+    let mut tester = Tester {
+        joysticks,
+        selected: 0,
+        calibrator: None,
+        profile: None,
+        recent: VecDeque::new(),
+        message: "Type a command and press Enter (q quits, joytest help lists them)".to_string(),
+    };
+    let (sender, commands) = channel();
+    thread::spawn(move || {
+        for line in stdin().lock().lines().map_while(Result::ok) {
+            if sender.send(line).is_err() {
+                return;
+            }
+        }
+    });
+    let mut out = stdout();
+    // Rows above the prompt, grown to fit the tallest display so far
+    let mut height = 0;
+    write!(out, "\x1b[2J").unwrap();
+    loop {
+        let now = Instant::now();
+        let mut prompt = false;
+        match commands.recv_timeout(Duration::from_millis(50)) {
+            Ok(line) => {
+                if !tester.command(line.trim()) {
+                    break;
+                }
+                prompt = true;
+            }
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        for event in manager.get_events() {
+            tester.add_event(&event, now);
+        }
+        let state = manager.get_joystick_state(tester.selected);
+        if let (Some(calibrator), Some(state)) = (tester.calibrator.as_mut(), state.as_ref()) {
+            calibrator.add_state(state);
+        }
+        let mut lines = tester.draw(state.as_ref(), now);
+        lines.push(String::new());
+        lines.push(tester.message.clone());
+        if lines.len() > height {
+            height = lines.len();
+            prompt = true;
+        }
+        // Draws above the prompt, leaving the cursor where the command is typed
+        write!(out, "\x1b7\x1b[H").unwrap();
+        for row in 0..height {
+            write!(
+                out,
+                "{}\x1b[K\r\n",
+                lines.get(row).map_or("", String::as_str)
+            )
+            .unwrap();
+        }
+        write!(out, "\x1b8").unwrap();
+        if prompt {
+            write!(out, "\x1b[{};1H\x1b[J> ", height + 1).unwrap();
+        }
+        out.flush().unwrap();
+    }
+    writeln!(out).unwrap();
+    Ok(())
+}
@@ -0,0 +1,628 @@
+//! Command-line tools for multiinput
+//!
+//! Run without arguments for usage.
+
+extern crate multiinput;
+
+use multiinput::capture::{Capture, CaptureError, CaptureFormat, CapturedEvent};
+use multiinput::replay::{ReplayBackend, ReplayHandle, ReplayMode};
+use multiinput::*;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::File;
+use std::io::{stdin, stdout, Write};
+use std::path::Path;
+use std::process;
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "Usage: multiinput <command> [options]
+
+Commands:
+  list [--json]
+      Lists the devices with their ids, vendor and product ids and capabilities
+  monitor [--type <mice|keyboards|joysticks>]... [--device <id>]... [--kind <kind>]...
+      Prints events as they arrive, only from the given device types, device ids
+      and event kinds (e.g. Keyboard, JoystickAxis) when given
+  state [--joystick <id>]
+      Shows the state of every joystick (or one) as it changes
+  record <output> [--duration <seconds>] [--hid]
+      Records the devices and their events until Enter is pressed or the duration
+      has passed (JSON Lines if output ends in .jsonl, binary otherwise); --hid
+      also records the raw joystick reports for redecode
+  replay <capture> [--type ...] [--device ...] [--kind ...]
+      Prints the events of a capture at the times they were recorded, filtered
+      like monitor
+  convert <input> <output> [--from <seconds>] [--to <seconds>] [--type ...]
+          [--device ...] [--kind ...]
+      Writes a capture in the format of the output name, keeping only the time
+      range and the events passing the filters; times start again at --from
+  stats <capture>
+      Prints the duration of a capture and the number and rate of events of
+      each device and kind
+  redecode <capture> [<output>]
+      Runs the joystick reports of a capture written by start_hid_recording through
+      the decoder again (windows only) and prints the resulting events, or writes
+      them to a new capture (JSON Lines if output ends in .jsonl)
+
+Options of list, monitor, state and record:
+  --replay <capture>  Reads input from a capture file instead of the devices
+  --speed <factor>    Replays faster (or slower) than recorded (also for replay)";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("list") => Options::parse(&args[1..], 0).and_then(|options| list(&options)),
+        Some("monitor") => Options::parse(&args[1..], 0).and_then(|options| monitor(&options)),
+        Some("state") => Options::parse(&args[1..], 0).and_then(|options| state(&options)),
+        Some("record") => Options::parse(&args[1..], 1).and_then(|options| record(&options)),
+        Some("replay") => Options::parse(&args[1..], 1).and_then(|mut options| {
+            options.replay = Some(options.paths.remove(0));
+            monitor(&options)
+        }),
+        Some("convert") => Options::parse(&args[1..], 2).and_then(|options| convert(&options)),
+        Some("stats") => Options::parse(&args[1..], 1).and_then(|options| stats(&options)),
+        Some("redecode") => redecode(&args[1..]),
+        Some("help") | Some("--help") | Some("-h") => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => Err(USAGE.to_string()),
+    };
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
+
+/// Options shared by the commands reading input
+#[derive(Default)]
+struct Options {
+    json: bool,
+    replay: Option<String>,
+    speed: Option<f64>,
+    filter: EventFilter,
+    joystick: Option<usize>,
+    duration: Option<f64>,
+    hid: bool,
+    from: Option<f64>,
+    to: Option<f64>,
+    /// File arguments, in order
+    paths: Vec<String>,
+}
+
+impl Options {
+    /// Parses the options following a command which takes the given number of files
+    fn parse(args: &[String], paths: usize) -> Result<Options, String> {
+        let mut options = Options::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .map(String::as_str)
+                    .ok_or_else(|| format!("Missing value for {}", arg))
+            };
+            match arg.as_str() {
+                "--json" => options.json = true,
+                "--replay" => options.replay = Some(value()?.to_string()),
+                "--speed" => options.speed = Some(parse_speed(arg, value()?)?),
+                "--type" => {
+                    let device_type = parse_device_type(value()?)?;
+                    options.filter = options.filter.device_type(device_type);
+                }
+                "--device" => {
+                    let device = parse_number(arg, value()?)?;
+                    options.filter = options.filter.device(device);
+                }
+                "--kind" => {
+                    let name = value()?;
+                    match EVENT_KINDS
+                        .iter()
+                        .find(|kind| format!("{:?}", kind).eq_ignore_ascii_case(name))
+                    {
+                        Some(&kind) => options.filter = options.filter.kind(kind),
+                        None => return Err(format!("Unknown event kind {}", name)),
+                    }
+                }
+                "--joystick" => options.joystick = Some(parse_number(arg, value()?)?),
+                "--duration" => options.duration = Some(parse_seconds(arg, value()?)?),
+                "--hid" => options.hid = true,
+                "--from" => options.from = Some(parse_seconds(arg, value()?)?),
+                "--to" => options.to = Some(parse_seconds(arg, value()?)?),
+                _ if !arg.starts_with("--") => options.paths.push(arg.clone()),
+                _ => return Err(format!("Unknown option {}\n\n{}", arg, USAGE)),
+            }
+        }
+        if options.paths.len() != paths {
+            return Err(USAGE.to_string());
+        }
+        Ok(options)
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value {} for {}", value, option))
+}
+
+fn parse_seconds(option: &str, value: &str) -> Result<f64, String> {
+    match parse_number(option, value)? {
+        seconds if (0.0..1e9).contains(&seconds) => Ok(seconds),
+        _ => Err(format!("Invalid value {} for {}", value, option)),
+    }
+}
+
+fn parse_speed(option: &str, value: &str) -> Result<f64, String> {
+    match parse_number(option, value)? {
+        speed if speed > 0.0 => Ok(speed),
+        _ => Err(format!("Invalid value {} for {}", value, option)),
+    }
+}
+
+fn parse_device_type(name: &str) -> Result<DeviceType, String> {
+    match name {
+        "mice" | "mouse" => Ok(DeviceType::Mice),
+        "keyboards" | "keyboard" => Ok(DeviceType::Keyboards),
+        "joysticks" | "joystick" => Ok(DeviceType::Joysticks(XInputInclude::True)),
+        _ => Err(format!("Unknown device type {}", name)),
+    }
+}
+
+/// Opens a manager on the devices, or on the capture given by --replay, with all
+/// device types registered
+fn open_manager(options: &Options) -> Result<(RawInputManager, Option<ReplayHandle>), String> {
+    let (mut manager, handle) = match options.replay {
+        Some(ref path) => {
+            let mode = match options.speed {
+                Some(speed) => ReplayMode::Accelerated(speed),
+                None => ReplayMode::RealTime,
+            };
+            let backend =
+                ReplayBackend::load(path, mode).map_err(|error| format!("{}: {}", path, error))?;
+            let handle = backend.handle();
+            let manager = RawInputManager::with_backend(
+                move || backend,
+                event_queue::DEFAULT_CAPACITY,
+                OverflowPolicy::default(),
+            );
+            (manager, Some(handle))
+        }
+        None => (RawInputManager::new().map_err(str::to_string)?, None),
+    };
+    manager.register_devices(DeviceType::Mice);
+    manager.register_devices(DeviceType::Keyboards);
+    manager.register_devices(DeviceType::Joysticks(XInputInclude::True));
+    Ok((manager, handle))
+}
+
+/// Vendor and product id as shown by the tools
+fn vid_pid(ids: &DeviceIds) -> String {
+    match (ids.vendor_id, ids.product_id) {
+        (Some(vendor_id), Some(product_id)) => format!("{:04x}:{:04x}", vendor_id, product_id),
+        _ => "-".to_string(),
+    }
+}
+
+fn describe(name: &str, product: &Option<String>) -> String {
+    match *product {
+        Some(ref product) => format!("{} ({})", product, name),
+        None => name.to_string(),
+    }
+}
+
+fn list(options: &Options) -> Result<(), String> {
+    let (manager, _) = open_manager(options)?;
+    let devices = manager.get_device_list();
+    if options.json {
+        println!("{}", devices.to_json());
+        return Ok(());
+    }
+    let mut rows = Vec::new();
+    for (id, mouse) in devices.mice.iter().enumerate() {
+        rows.push((
+            "mouse",
+            id,
+            &mouse.ids,
+            mouse.stable_id,
+            mouse.status,
+            describe(&mouse.name, &mouse.product),
+        ));
+    }
+    for (id, keyboard) in devices.keyboards.iter().enumerate() {
+        rows.push((
+            "keyboard",
+            id,
+            &keyboard.ids,
+            keyboard.stable_id,
+            keyboard.status,
+            describe(&keyboard.name, &keyboard.product),
+        ));
+    }
+    for (id, joystick) in devices.joysticks.iter().enumerate() {
+        let capabilities = &joystick.capabilities;
+        let axes: Vec<String> = capabilities
+            .axes
+            .iter()
+            .map(|axis| format!("{:?}", axis))
+            .collect();
+        let description = format!(
+            "{} [{} buttons, axes {}{}]",
+            describe(&joystick.name, &joystick.product),
+            capabilities.buttons,
+            if axes.is_empty() {
+                "-".to_string()
+            } else {
+                axes.join(" ")
+            },
+            if capabilities.hatswitch { ", hat" } else { "" }
+        );
+        rows.push((
+            "joystick",
+            id,
+            &joystick.ids,
+            joystick.stable_id,
+            joystick.status,
+            description,
+        ));
+    }
+    println!(
+        "{:<9} {:>3}  {:<9}  {:<16}  {:<8}  Description",
+        "Type", "Id", "VID:PID", "Stable id", "Status"
+    );
+    for (device_type, id, ids, stable_id, status, description) in rows {
+        println!(
+            "{:<9} {:>3}  {:<9}  {}  {:<8}  {}",
+            device_type,
+            id,
+            vid_pid(ids),
+            stable_id,
+            format!("{:?}", status),
+            description
+        );
+    }
+    Ok(())
+}
+
+/// Type and id of the device an event came from
+fn device_of(event: &RawEvent) -> (&'static str, usize) {
+    let device_type = match event.kind() {
+        EventKind::MouseButton | EventKind::MouseMove | EventKind::MouseWheel => "mouse",
+        EventKind::Keyboard => "keyboard",
+        _ => "joystick",
+    };
+    (device_type, event.device_id())
+}
+
+/// Device an event came from, e.g. "keyboard 1"
+fn event_device(event: &RawEvent) -> String {
+    let (device_type, id) = device_of(event);
+    format!("{} {}", device_type, id)
+}
+
+fn format_event(event: &RawEvent) -> String {
+    match *event {
+        RawEvent::MouseButtonEvent(_, ref button, ref state) => {
+            format!("button {:?} {:?}", button, state)
+        }
+        RawEvent::MouseMoveEvent(_, x, y) => format!("move {:+} {:+}", x, y),
+        RawEvent::MouseWheelEvent(_, delta) => format!("wheel {:+}", delta),
+        RawEvent::KeyboardEvent(_, ref key, ref state) => format!("key {:?} {:?}", key, state),
+        RawEvent::JoystickButtonEvent(_, button, ref state) => {
+            format!("button {} {:?}", button, state)
+        }
+        RawEvent::JoystickAxisEvent(_, ref axis, value) => {
+            format!("axis {:?} {:+.4}", axis, value)
+        }
+        RawEvent::JoystickHatSwitchEvent(_, ref hatswitch) => format!("hat {:?}", hatswitch),
+        RawEvent::JoystickInitialStateEvent(_, ref state) => {
+            format!("initial state {}", format_state(state))
+        }
+        RawEvent::GamepadButtonEvent(_, ref button, ref state) => {
+            format!("gamepad button {:?} {:?}", button, state)
+        }
+        RawEvent::GamepadAxisEvent(_, ref axis, value) => {
+            format!("gamepad axis {:?} {:+.4}", axis, value)
+        }
+    }
+}
+
+/// Buttons as a row of 0s and 1s, then the axes and hat that are present
+fn format_state(state: &JoystickState) -> String {
+    let buttons: String = state
+        .button_states
+        .iter()
+        .map(|&pressed| if pressed { '1' } else { '0' })
+        .collect();
+    let axes = &state.axis_states;
+    let values = [
+        (Axis::X, axes.x),
+        (Axis::Y, axes.y),
+        (Axis::Z, axes.z),
+        (Axis::RX, axes.rx),
+        (Axis::RY, axes.ry),
+        (Axis::RZ, axes.rz),
+        (Axis::SLIDER, axes.slider),
+    ];
+    let mut text = format!(
+        "buttons {}",
+        if buttons.is_empty() { "-" } else { &buttons }
+    );
+    for (axis, value) in values.iter() {
+        if let Some(value) = *value {
+            text += &format!(" {:?} {:+.3}", axis, value);
+        }
+    }
+    if let Some(ref hatswitch) = state.hatswitch {
+        text += &format!(" hat {:?}", hatswitch);
+    }
+    text
+}
+
+/// True once a replay has emitted every event and they have all been taken
+fn replay_done(handle: &Option<ReplayHandle>) -> bool {
+    handle.as_ref().is_some_and(ReplayHandle::is_finished)
+}
+
+fn monitor(options: &Options) -> Result<(), String> {
+    let (mut manager, handle) = open_manager(options)?;
+    let start = Instant::now();
+    loop {
+        let event = match manager.wait_event_timeout(Duration::from_millis(200)) {
+            Some(event) => event,
+            None if replay_done(&handle) => return Ok(()),
+            None => continue,
+        };
+        if !options.filter.matches(&event) {
+            continue;
+        }
+        println!(
+            "{:>10.3}  {:<12} {}",
+            start.elapsed().as_secs_f64(),
+            event_device(&event),
+            format_event(&event)
+        );
+    }
+}
+
+fn state(options: &Options) -> Result<(), String> {
+    let (mut manager, handle) = open_manager(options)?;
+    let joysticks: Vec<usize> = match options.joystick {
+        Some(id) => vec![id],
+        None => (0..manager.get_device_list().joysticks.len()).collect(),
+    };
+    if joysticks.is_empty() {
+        return Err("No joysticks found".to_string());
+    }
+    let mut lines: Vec<String> = Vec::new();
+    loop {
+        let finished = replay_done(&handle);
+        manager.get_events();
+        let current: Vec<String> = joysticks
+            .iter()
+            .map(|&id| {
+                let state = manager
+                    .get_joystick_state(id)
+                    .map_or("no input yet".to_string(), |state| format_state(&state));
+                format!("joystick {}: {}", id, state)
+            })
+            .collect();
+        if current != lines {
+            let mut out = stdout();
+            if !lines.is_empty() {
+                // Redraw in place
+                write!(out, "\x1b[{}A", lines.len()).unwrap();
+            }
+            for line in &current {
+                writeln!(out, "\r{}\x1b[K", line).unwrap();
+            }
+            out.flush().unwrap();
+            lines = current;
+        }
+        if finished {
+            return Ok(());
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// Format of a capture file given its name
+fn format_of(path: &str) -> CaptureFormat {
+    match Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+    {
+        Some("jsonl") | Some("json") => CaptureFormat::JsonLines,
+        _ => CaptureFormat::Binary,
+    }
+}
+
+fn load(path: &str) -> Result<Capture, String> {
+    Capture::load(path).map_err(|error| format!("{}: {}", path, error))
+}
+
+fn record(options: &Options) -> Result<(), String> {
+    let output = &options.paths[0];
+    let (mut manager, handle) = open_manager(options)?;
+    let file = File::create(output).map_err(|error| format!("{}: {}", output, error))?;
+    let format = format_of(output);
+    let recording = if options.hid {
+        manager.start_hid_recording(file, format)
+    } else {
+        manager.start_recording(file, format)
+    }
+    .map_err(|error| format!("{}: {}", output, error))?;
+    let (sender, stop) = channel();
+    let deadline = match options.duration {
+        Some(seconds) => {
+            eprintln!("Recording to {} for {} s", output, seconds);
+            Some(Instant::now() + Duration::from_secs_f64(seconds))
+        }
+        None => {
+            eprintln!("Recording to {}, press Enter to stop", output);
+            thread::spawn(move || {
+                let _ = stdin().read_line(&mut String::new());
+                let _ = sender.send(());
+            });
+            None
+        }
+    };
+    while let Err(RecvTimeoutError::Timeout) = stop.recv_timeout(Duration::from_millis(50)) {
+        // Events are recorded by the manager thread, the queue only needs emptying
+        manager.get_events();
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) || replay_done(&handle) {
+            break;
+        }
+    }
+    recording
+        .stop()
+        .map_err(|error| format!("{}: {}", output, error))?;
+    Ok(())
+}
+
+fn convert(options: &Options) -> Result<(), String> {
+    let (input, output) = (&options.paths[0], &options.paths[1]);
+    let mut capture = load(input)?;
+    if options.from.is_some() || options.to.is_some() {
+        capture.trim(
+            Duration::from_secs_f64(options.from.unwrap_or(0.0)),
+            options.to.map(Duration::from_secs_f64),
+        );
+    }
+    capture.retain(&options.filter);
+    capture
+        .save(output, format_of(output))
+        .map_err(|error| format!("{}: {}", output, error))?;
+    eprintln!(
+        "Wrote {} events and {} reports",
+        capture.events.len(),
+        capture.hid_reports.len()
+    );
+    Ok(())
+}
+
+fn stats(options: &Options) -> Result<(), String> {
+    let capture = load(&options.paths[0])?;
+    let events = &capture.events;
+    let duration = events
+        .iter()
+        .map(|event| event.time)
+        .chain(capture.hid_reports.iter().map(|report| report.time))
+        .max()
+        .unwrap_or_default()
+        .as_secs_f64();
+    let rate = |count: usize| {
+        if duration > 0.0 {
+            count as f64 / duration
+        } else {
+            0.0
+        }
+    };
+    let devices = &capture.devices;
+    println!("Duration  {:.3} s", duration);
+    println!(
+        "Devices   {} mice, {} keyboards, {} joysticks",
+        devices.mice.len(),
+        devices.keyboards.len(),
+        devices.joysticks.len()
+    );
+    println!("Events    {} ({:.1}/s)", events.len(), rate(events.len()));
+    if !capture.hid_reports.is_empty() {
+        println!(
+            "Reports   {} ({:.1}/s)",
+            capture.hid_reports.len(),
+            rate(capture.hid_reports.len())
+        );
+    }
+    if events.is_empty() {
+        return Ok(());
+    }
+    let mut counts: BTreeMap<(&str, usize), (usize, &str)> = BTreeMap::new();
+    for captured in events {
+        let name = devices
+            .describe_event(&captured.event)
+            .map_or("?", |device| device.name);
+        counts
+            .entry(device_of(&captured.event))
+            .or_insert((0, name))
+            .0 += 1;
+    }
+    println!();
+    println!("{:<12} {:>8} {:>9}  Name", "Device", "Events", "Rate/s");
+    for ((device_type, id), (count, name)) in counts {
+        println!(
+            "{:<12} {:>8} {:>9.1}  {}",
+            format!("{} {}", device_type, id),
+            count,
+            rate(count),
+            name
+        );
+    }
+    println!();
+    println!("{:<21} {:>8} {:>9}", "Kind", "Events", "Rate/s");
+    for kind in EVENT_KINDS.iter() {
+        let count = events
+            .iter()
+            .filter(|captured| captured.event.kind() == *kind)
+            .count();
+        if count > 0 {
+            println!(
+                "{:<21} {:>8} {:>9.1}",
+                format!("{:?}", kind),
+                count,
+                rate(count)
+            );
+        }
+    }
+    Ok(())
+}
+
+fn redecode(args: &[String]) -> Result<(), String> {
+    let (input, output) = match *args {
+        [ref input] => (input, None),
+        [ref input, ref output] => (input, Some(output)),
+        _ => return Err(USAGE.to_string()),
+    };
+    let capture = load(input)?;
+    if capture.hid_descriptors.is_empty() {
+        return Err(format!("{}: no joystick reports were recorded", input));
+    }
+    let events = decode(&capture).map_err(|error| format!("{}: {}", input, error))?;
+    eprintln!(
+        "Decoded {} events from {} reports ({} recorded events)",
+        events.len(),
+        capture.hid_reports.len(),
+        capture.events.len()
+    );
+    match output {
+        Some(output) => {
+            let decoded = Capture {
+                devices: capture.devices,
+                events,
+                ..Default::default()
+            };
+            decoded
+                .save(output, format_of(output))
+                .map_err(|error| format!("{}: {}", output, error))
+        }
+        None => {
+            for event in events {
+                println!("{:>12.6} {:?}", event.time.as_secs_f64(), event.event);
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(windows)]
+fn decode(capture: &Capture) -> Result<Vec<CapturedEvent>, CaptureError> {
+    capture.decode_hid_reports()
+}
+
+#[cfg(not(windows))]
+fn decode(_capture: &Capture) -> Result<Vec<CapturedEvent>, CaptureError> {
+    Err(CaptureError(
+        "Reports can only be decoded on windows, where the HID parser is available".to_string(),
+    ))
+}
@@ -0,0 +1,570 @@
+//! Joystick calibration profiles, correcting drifting centres, short ranges and noise
+//!
+//! A `Calibrator` watches a joystick while its sticks rest and then while every
+//! axis is moved to its limits, and produces a `CalibrationProfile`. Profiles
+//! are saved as JSON and applied to events or states of the joystick they were
+//! made for. The `joytest` binary runs a calibration interactively.
+//!
+//! ```
+//! # use multiinput::*;
+//! # use multiinput::calibration::Calibrator;
+//! let mut calibrator = Calibrator::new();
+//! for &x in &[0.04, 0.05, 0.06] {
+//!     calibrator.add_event(&RawEvent::JoystickAxisEvent(0, Axis::X, x));
+//! }
+//! calibrator.next_step();
+//! for &x in &[-0.9, 0.95] {
+//!     calibrator.add_event(&RawEvent::JoystickAxisEvent(0, Axis::X, x));
+//! }
+//! let profile = calibrator.finish(StableDeviceId(0), "pad");
+//! assert_eq!(profile.axis(&Axis::X, 0.055), 0.0);
+//! assert_eq!(profile.axis(&Axis::X, 0.95), 1.0);
+//! ```
+
+use device_filter::StableDeviceId;
+use devices::{Axes, JoystickState};
+use event::{Axis, RawEvent, JOYSTICK_AXES};
+use json::{field, from_name, Json};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Identifies calibration profile files
+const PROFILE_FORMAT: &str = "multiinput-calibration";
+const PROFILE_VERSION: u16 = 1;
+
+/// Deadzones cover this many times the noise seen at rest
+const DEADZONE_MARGIN: f64 = 1.5;
+/// Largest deadzone a calibration produces
+const MAX_DEADZONE: f64 = 0.5;
+/// Sides of an axis moved less than this from the centre keep the full range
+const MIN_TRAVEL: f64 = 0.1;
+
+format_error!(
+    /// Error reading or writing a calibration profile
+    CalibrationError,
+    "Invalid calibration profile"
+);
+
+/// Correction of one axis, in the [-1, 1] range of joystick axis values
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AxisCalibration {
+    /// Value at the far negative end
+    pub min: f64,
+    /// Value at rest
+    pub center: f64,
+    /// Value at the far positive end
+    pub max: f64,
+    /// Corrected values closer to zero than this become zero
+    pub deadzone: f64,
+}
+
+impl Default for AxisCalibration {
+    /// Leaves values unchanged
+    fn default() -> AxisCalibration {
+        AxisCalibration {
+            min: -1.0,
+            center: 0.0,
+            max: 1.0,
+            deadzone: 0.0,
+        }
+    }
+}
+
+impl AxisCalibration {
+    /// Maps the range onto [-1, 1] with the centre at zero, then applies the deadzone
+    pub fn apply(&self, value: f64) -> f64 {
+        let offset = value - self.center;
+        let extent = if offset < 0.0 {
+            self.center - self.min
+        } else {
+            self.max - self.center
+        };
+        let scaled = if extent > 0.0 {
+            (offset / extent).clamp(-1.0, 1.0)
+        } else {
+            0.0
+        };
+        if scaled.abs() <= self.deadzone {
+            0.0
+        } else if self.deadzone < 1.0 {
+            scaled.signum() * (scaled.abs() - self.deadzone) / (1.0 - self.deadzone)
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Calibration of a joystick's axes (axes without one are left unchanged)
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CalibrationProfile {
+    /// Joystick the profile was made for
+    pub stable_id: StableDeviceId,
+    pub name: String,
+    pub axes: HashMap<Axis, AxisCalibration>,
+}
+
+impl CalibrationProfile {
+    /// Profile leaving every axis unchanged
+    pub fn new(stable_id: StableDeviceId, name: &str) -> CalibrationProfile {
+        CalibrationProfile {
+            stable_id,
+            name: name.to_string(),
+            axes: HashMap::new(),
+        }
+    }
+
+    /// Corrected value of an axis
+    pub fn axis(&self, axis: &Axis, value: f64) -> f64 {
+        match self.axes.get(axis) {
+            Some(calibration) => calibration.apply(value),
+            None => value,
+        }
+    }
+
+    /// Corrects the axes of a state of the joystick (raw values are left alone)
+    pub fn apply_state(&self, state: &mut JoystickState) {
+        for (axis, value) in axis_values(&mut state.axis_states) {
+            if let Some(ref mut value) = *value {
+                *value = self.axis(&axis, *value);
+            }
+        }
+    }
+
+    /// Corrects an axis or initial state event of the joystick
+    pub fn apply(&self, event: &mut RawEvent) {
+        match *event {
+            RawEvent::JoystickAxisEvent(_, ref axis, ref mut value) => {
+                *value = self.axis(axis, *value)
+            }
+            RawEvent::JoystickInitialStateEvent(_, ref mut state) => self.apply_state(state),
+            _ => (),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let axes = JOYSTICK_AXES
+            .iter()
+            .filter_map(|axis| {
+                self.axes.get(axis).map(|calibration| {
+                    let fields = vec![
+                        field("min", calibration.min),
+                        field("center", calibration.center),
+                        field("max", calibration.max),
+                        field("deadzone", calibration.deadzone),
+                    ];
+                    (format!("{:?}", axis), Json::Object(fields))
+                })
+            })
+            .collect();
+        Json::Object(vec![
+            field("format", PROFILE_FORMAT),
+            field("version", PROFILE_VERSION as f64),
+            field("stable_id", self.stable_id.to_string()),
+            field("name", self.name.as_str()),
+            ("axes".to_string(), Json::Object(axes)),
+        ])
+        .to_string()
+    }
+
+    pub fn parse(text: &str) -> Result<CalibrationProfile, CalibrationError> {
+        let json = Json::parse(text).or_else(|error| invalid(&error))?;
+        if json.get("format").and_then(Json::as_str) != Some(PROFILE_FORMAT) {
+            return invalid("not a calibration profile");
+        }
+        match json.get("version").and_then(Json::as_f64) {
+            Some(version) if (1.0..=PROFILE_VERSION as f64).contains(&version) => (),
+            _ => return invalid("unsupported version"),
+        }
+        let stable_id = match json.get("stable_id").and_then(Json::as_str) {
+            Some(id) => u64::from_str_radix(id, 16).or_else(|_| invalid("bad stable_id"))?,
+            None => return invalid("bad stable_id"),
+        };
+        let name = match json.get("name").and_then(Json::as_str) {
+            Some(name) => name.to_string(),
+            None => return invalid("bad name"),
+        };
+        let fields = match json.get("axes") {
+            Some(Json::Object(fields)) => fields,
+            _ => return invalid("bad axes"),
+        };
+        let mut axes = HashMap::new();
+        for (name, calibration) in fields {
+            let axis = match from_name(&JOYSTICK_AXES, name) {
+                Some(axis) => axis,
+                None => return invalid(&format!("unknown axis {}", name)),
+            };
+            let value = |key: &str| match calibration.get(key).and_then(Json::as_f64) {
+                Some(value) => Ok(value),
+                None => invalid(&format!("bad {} of {}", key, name)),
+            };
+            let calibration = AxisCalibration {
+                min: value("min")?,
+                center: value("center")?,
+                max: value("max")?,
+                deadzone: value("deadzone")?,
+            };
+            axes.insert(axis, calibration);
+        }
+        Ok(CalibrationProfile {
+            stable_id: StableDeviceId(stable_id),
+            name,
+            axes,
+        })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), CalibrationError> {
+        fs::write(path, self.to_json() + "\n")?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<CalibrationProfile, CalibrationError> {
+        CalibrationProfile::parse(&fs::read_to_string(path)?)
+    }
+}
+
+/// Stage of a calibration
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CalibrationStep {
+    /// The sticks are left at rest, measuring their centres and noise
+    Center,
+    /// Every axis is moved to both of its limits
+    Range,
+}
+
+#[derive(Clone, Copy)]
+struct Samples {
+    sum: f64,
+    count: usize,
+    min: f64,
+    max: f64,
+}
+
+/// Builds a `CalibrationProfile` from the values a joystick reports
+///
+/// Feed it the joystick's events or states while the sticks rest, call
+/// `next_step`, feed it more while every axis is moved to its limits, then
+/// call `finish`.
+pub struct Calibrator {
+    step: CalibrationStep,
+    center: HashMap<Axis, Samples>,
+    range: HashMap<Axis, Samples>,
+}
+
+impl Default for Calibrator {
+    fn default() -> Calibrator {
+        Calibrator::new()
+    }
+}
+
+impl Calibrator {
+    pub fn new() -> Calibrator {
+        Calibrator {
+            step: CalibrationStep::Center,
+            center: HashMap::new(),
+            range: HashMap::new(),
+        }
+    }
+
+    pub fn step(&self) -> CalibrationStep {
+        self.step
+    }
+
+    /// Moves from measuring the centres to measuring the ranges
+    pub fn next_step(&mut self) {
+        self.step = CalibrationStep::Range;
+    }
+
+    pub fn add_value(&mut self, axis: &Axis, value: f64) {
+        let samples = match self.step {
+            CalibrationStep::Center => &mut self.center,
+            CalibrationStep::Range => &mut self.range,
+        };
+        let entry = samples.entry(axis.clone()).or_insert(Samples {
+            sum: 0.0,
+            count: 0,
+            min: value,
+            max: value,
+        });
+        entry.sum += value;
+        entry.count += 1;
+        entry.min = entry.min.min(value);
+        entry.max = entry.max.max(value);
+    }
+
+    /// Adds the value of an axis event, or the axes of an initial state
+    pub fn add_event(&mut self, event: &RawEvent) {
+        match *event {
+            RawEvent::JoystickAxisEvent(_, ref axis, value) => self.add_value(axis, value),
+            RawEvent::JoystickInitialStateEvent(_, ref state) => self.add_state(state),
+            _ => (),
+        }
+    }
+
+    /// Adds the current value of every axis of a state
+    pub fn add_state(&mut self, state: &JoystickState) {
+        let mut axes = state.axis_states.clone();
+        for (axis, value) in axis_values(&mut axes) {
+            if let Some(value) = *value {
+                self.add_value(&axis, value);
+            }
+        }
+    }
+
+    /// Profile for the axes seen at rest (axes only seen moving are left out)
+    ///
+    /// Axes resting at one end, like throttles, are mapped onto the full range.
+    pub fn finish(&self, stable_id: StableDeviceId, name: &str) -> CalibrationProfile {
+        let mut profile = CalibrationProfile::new(stable_id, name);
+        for (axis, rest) in &self.center {
+            let center = rest.sum / rest.count as f64;
+            let (low, high) = match self.range.get(axis) {
+                Some(range) => (range.min.min(rest.min), range.max.max(rest.max)),
+                None => (rest.min, rest.max),
+            };
+            let calibration = if 1.0 - center.abs() < MIN_TRAVEL {
+                // Throttles and sliders rest at one end, so the centre is halfway
+                // through their travel and noise at rest is clamped away
+                let (min, max) = if high - low < MIN_TRAVEL {
+                    (-1.0, 1.0)
+                } else {
+                    (low, high)
+                };
+                AxisCalibration {
+                    min,
+                    center: (min + max) / 2.0,
+                    max,
+                    deadzone: 0.0,
+                }
+            } else {
+                let min = if center - low < MIN_TRAVEL { -1.0 } else { low };
+                let max = if high - center < MIN_TRAVEL {
+                    1.0
+                } else {
+                    high
+                };
+                let noise = (rest.max - center).max(center - rest.min);
+                let extent = (center - min).min(max - center);
+                let deadzone = if extent > 0.0 {
+                    (noise * DEADZONE_MARGIN / extent).min(MAX_DEADZONE)
+                } else {
+                    0.0
+                };
+                AxisCalibration {
+                    min,
+                    center,
+                    max,
+                    deadzone,
+                }
+            };
+            profile.axes.insert(axis.clone(), calibration);
+        }
+        profile
+    }
+}
+
+/// Every axis with its value in a state
+fn axis_values(axes: &mut Axes) -> [(Axis, &mut Option<f64>); 7] {
+    [
+        (Axis::X, &mut axes.x),
+        (Axis::Y, &mut axes.y),
+        (Axis::Z, &mut axes.z),
+        (Axis::RX, &mut axes.rx),
+        (Axis::RY, &mut axes.ry),
+        (Axis::RZ, &mut axes.rz),
+        (Axis::SLIDER, &mut axes.slider),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    #[test]
+    fn default_calibration_leaves_values_unchanged() {
+        let calibration = AxisCalibration::default();
+        for &value in [-1.0, -0.3, 0.0, 0.7, 1.0].iter() {
+            assert_eq!(calibration.apply(value), value);
+        }
+    }
+
+    #[test]
+    fn apply_maps_each_side_and_clamps() {
+        let calibration = AxisCalibration {
+            min: -0.5,
+            center: 0.1,
+            max: 0.9,
+            deadzone: 0.0,
+        };
+        assert_eq!(calibration.apply(0.1), 0.0);
+        assert_eq!(calibration.apply(-0.5), -1.0);
+        assert!((calibration.apply(-0.2) + 0.5).abs() < 1e-9);
+        assert_eq!(calibration.apply(0.9), 1.0);
+        assert!((calibration.apply(0.5) - 0.5).abs() < 1e-9);
+        assert_eq!(calibration.apply(-1.0), -1.0);
+        assert_eq!(calibration.apply(1.0), 1.0);
+        // A side without travel stays at zero
+        let flat = AxisCalibration {
+            min: 0.0,
+            ..AxisCalibration::default()
+        };
+        assert_eq!(flat.apply(-0.5), 0.0);
+    }
+
+    #[test]
+    fn apply_stretches_outside_the_deadzone() {
+        let calibration = AxisCalibration {
+            deadzone: 0.2,
+            ..AxisCalibration::default()
+        };
+        assert_eq!(calibration.apply(0.2), 0.0);
+        assert_eq!(calibration.apply(-0.1), 0.0);
+        assert!((calibration.apply(0.6) - 0.5).abs() < 1e-9);
+        assert!((calibration.apply(-0.6) + 0.5).abs() < 1e-9);
+        assert_eq!(calibration.apply(1.0), 1.0);
+        let everything = AxisCalibration {
+            deadzone: 1.0,
+            ..AxisCalibration::default()
+        };
+        assert_eq!(everything.apply(1.0), 0.0);
+    }
+
+    fn calibrate(rest: &[f64], range: &[f64]) -> AxisCalibration {
+        let mut calibrator = Calibrator::new();
+        for &value in rest {
+            calibrator.add_value(&Axis::Z, value);
+        }
+        calibrator.next_step();
+        assert_eq!(calibrator.step(), CalibrationStep::Range);
+        for &value in range {
+            calibrator.add_value(&Axis::Z, value);
+        }
+        calibrator.finish(StableDeviceId(0), "pad").axes[&Axis::Z]
+    }
+
+    #[test]
+    fn finish_measures_centre_range_and_noise() {
+        let calibration = calibrate(&[0.04, 0.06], &[-0.8, 0.9]);
+        assert!((calibration.center - 0.05).abs() < 1e-9);
+        assert_eq!(calibration.min, -0.8);
+        assert_eq!(calibration.max, 0.9);
+        assert!((calibration.deadzone - 0.01 * DEADZONE_MARGIN / 0.85).abs() < 1e-9);
+        // Sides that were not moved keep the full range
+        let calibration = calibrate(&[0.0], &[0.05, 0.9]);
+        assert_eq!(calibration.min, -1.0);
+        assert_eq!(calibration.max, 0.9);
+        // Noisy axes are capped
+        assert_eq!(calibrate(&[-0.5, 0.5], &[]).deadzone, MAX_DEADZONE);
+    }
+
+    #[test]
+    fn finish_keeps_one_sided_axes_symmetric() {
+        let throttle = calibrate(&[-1.0, -0.99], &[1.0]);
+        assert_eq!(throttle.deadzone, 0.0);
+        assert_eq!(throttle.apply(-1.0), -1.0);
+        assert!(throttle.apply(-0.99) < -0.98);
+        assert!(throttle.apply(0.0).abs() < 0.01);
+        assert_eq!(throttle.apply(1.0), 1.0);
+        let slider = calibrate(&[0.95], &[-0.9]);
+        assert_eq!(slider.apply(0.95), 1.0);
+        assert_eq!(slider.apply(-0.9), -1.0);
+        assert!((slider.apply(0.025)).abs() < 1e-9);
+        // Without a range the axis is left unchanged
+        let unmoved = calibrate(&[-1.0], &[]);
+        assert_eq!(unmoved.apply(-1.0), -1.0);
+        assert_eq!(unmoved.apply(0.5), 0.5);
+    }
+
+    fn profile() -> CalibrationProfile {
+        let mut profile = CalibrationProfile::new(StableDeviceId(0xabc), "stick \"one\"");
+        profile.axes.insert(
+            Axis::X,
+            AxisCalibration {
+                min: -0.9,
+                center: 0.05,
+                max: 0.95,
+                deadzone: 0.1,
+            },
+        );
+        profile
+            .axes
+            .insert(Axis::SLIDER, AxisCalibration::default());
+        profile
+    }
+
+    #[test]
+    fn profile_round_trip() {
+        let profile = profile();
+        let json = profile.to_json();
+        assert!(json.starts_with(
+            r#"{"format":"multiinput-calibration","version":1,"stable_id":"0000000000000abc","#
+        ));
+        assert!(json.contains(
+            r#""axes":{"X":{"min":-0.9,"center":0.05,"max":0.95,"deadzone":0.1},"SLIDER":"#
+        ));
+        assert_eq!(CalibrationProfile::parse(&json), Ok(profile.clone()));
+        let path = env::temp_dir().join(format!("multiinput-calibration-{}.json", process::id()));
+        profile.save(&path).unwrap();
+        let loaded = CalibrationProfile::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, Ok(profile));
+    }
+
+    #[test]
+    fn profile_applies_to_events_and_states() {
+        let profile = profile();
+        let mut event = RawEvent::JoystickAxisEvent(0, Axis::X, 0.05);
+        profile.apply(&mut event);
+        assert_eq!(event, RawEvent::JoystickAxisEvent(0, Axis::X, 0.0));
+        let mut state = JoystickState {
+            button_states: Vec::new(),
+            axis_states: Axes {
+                x: Some(0.95),
+                y: Some(0.3),
+                ..Axes::new()
+            },
+            hatswitch: None,
+            raw_axis_states: ::devices::RawAxes::new(),
+        };
+        profile.apply_state(&mut state);
+        assert_eq!(state.axis_states.x, Some(1.0));
+        assert_eq!(state.axis_states.y, Some(0.3));
+        assert_eq!(state.axis_states.z, None);
+    }
+
+    #[test]
+    fn profile_parse_errors() {
+        let error = |text: &str| CalibrationProfile::parse(text).unwrap_err().0;
+        let valid = profile().to_json();
+        assert_eq!(
+            error("{"),
+            "Invalid calibration profile: Expected \" at column 2"
+        );
+        assert_eq!(
+            error(&valid.replace("multiinput-calibration", "other")),
+            "Invalid calibration profile: not a calibration profile"
+        );
+        assert_eq!(
+            error(&valid.replace(r#""version":1"#, r#""version":2"#)),
+            "Invalid calibration profile: unsupported version"
+        );
+        assert_eq!(
+            error(&valid.replace("0000000000000abc", "xyz")),
+            "Invalid calibration profile: bad stable_id"
+        );
+        assert_eq!(
+            error(&valid.replace(r#""X":"#, r#""W":"#)),
+            "Invalid calibration profile: unknown axis W"
+        );
+        assert_eq!(
+            error(&valid.replace(r#""min":-0.9"#, r#""min":"low""#)),
+            "Invalid calibration profile: bad min of X"
+        );
+    }
+}
@@ -0,0 +1,1678 @@
+//! Capture files holding a device list and timestamped events
+//!
+//! Captures are written by `RawInputManager::start_recording` or a `CaptureWriter`
+//! and read back with a `CaptureReader`. They start with a header holding the
+//! format version and the devices (including joystick capabilities), followed by
+//! one record per event. Two encodings are available:
+//!
+//! * `CaptureFormat::Binary`: `MICP`, the version as a u16 and then little endian
+//!   fields, strings and lists prefixed by their u32 length
+//! * `CaptureFormat::JsonLines`: one JSON object per line, the header first, e.g.
+//!   `{"time_us":1500,"type":"KeyboardEvent","device":0,"key":"A","state":"Pressed"}`
+//!
+//! Readers detect the encoding, so both can be read by the same code.
+//!
+//! Captures written by `RawInputManager::start_hid_recording` also hold the
+//! descriptors and raw reports of joysticks (from version 2), so decoding can be
+//! repeated without the device by `Capture::decode_hid_reports` or
+//! `multiinput redecode`.
+
+use device_filter::StableDeviceId;
+use devices::{
+    Axes, DeviceIds, DevicesDisplayInfo, JoystickCapabilities, JoystickDisplayInfo, JoystickState,
+    KeyboardDisplayInfo, MouseDisplayInfo, RawAxes, HAT_SWITCHES,
+};
+use event::{EventKind, RawEvent, State, JOYSTICK_AXES, KEY_IDS, MOUSE_BUTTONS};
+use gamepad::{AXES, BUTTONS};
+use json::{field, from_name, json_array, name, named, Json};
+use manager::DeviceType;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use subscription::EventFilter;
+
+/// Version written to new captures (older versions can still be read)
+pub const CAPTURE_VERSION: u16 = 2;
+
+const BINARY_MAGIC: &[u8; 4] = b"MICP";
+const JSON_FORMAT_NAME: &str = "multiinput-capture";
+const EVENT_RECORD: u8 = 0;
+const HID_DESCRIPTOR_RECORD: u8 = 1;
+const HID_REPORT_RECORD: u8 = 2;
+
+/// Encoding of a capture file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum CaptureFormat {
+    /// Compact binary records
+    Binary,
+    /// One JSON object per line, readable and easy to edit
+    JsonLines,
+}
+
+format_error!(
+    /// Error reading or writing a capture
+    CaptureError,
+    "Invalid capture"
+);
+
+/// Event with the time it was recorded, measured from the start of the capture
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CapturedEvent {
+    pub time: Duration,
+    pub event: RawEvent,
+}
+
+/// Data needed to decode the reports of a joystick
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HidDescriptor {
+    pub time: Duration,
+    /// Joystick id
+    pub device: usize,
+    /// HIDP_PREPARSED_DATA of the joystick as returned by rawinput
+    pub preparsed_data: Vec<u8>,
+    pub is_360_controller: bool,
+}
+
+/// Input report of a joystick as received, measured from the start of the capture
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CapturedReport {
+    pub time: Duration,
+    /// Joystick id
+    pub device: usize,
+    pub report: Vec<u8>,
+}
+
+/// Single record of a capture
+#[derive(Clone, Debug, PartialEq)]
+pub enum CaptureRecord {
+    Event(CapturedEvent),
+    /// Sent before the reports of a joystick, and again when devices are registered
+    HidDescriptor(HidDescriptor),
+    HidReport(CapturedReport),
+}
+
+/// Complete contents of a capture file
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Capture {
+    pub devices: DevicesDisplayInfo,
+    pub events: Vec<CapturedEvent>,
+    pub hid_descriptors: Vec<HidDescriptor>,
+    pub hid_reports: Vec<CapturedReport>,
+}
+
+impl Capture {
+    pub fn read<R: BufRead>(reader: R) -> Result<Capture, CaptureError> {
+        let mut reader = CaptureReader::new(reader)?;
+        let mut events = Vec::new();
+        let mut hid_descriptors = Vec::new();
+        let mut hid_reports = Vec::new();
+        while let Some(record) = reader.read_record()? {
+            match record {
+                CaptureRecord::Event(event) => events.push(event),
+                CaptureRecord::HidDescriptor(descriptor) => hid_descriptors.push(descriptor),
+                CaptureRecord::HidReport(report) => hid_reports.push(report),
+            }
+        }
+        Ok(Capture {
+            devices: reader.devices,
+            events,
+            hid_descriptors,
+            hid_reports,
+        })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Capture, CaptureError> {
+        Capture::read(BufReader::new(File::open(path)?))
+    }
+
+    /// Writes the capture with records in time order (descriptors, then reports, then
+    /// events at equal times)
+    pub fn write<W: Write>(&self, writer: W, format: CaptureFormat) -> Result<(), CaptureError> {
+        let mut writer = CaptureWriter::new(writer, format, &self.devices)?;
+        let mut records: Vec<(Duration, u8, usize)> = Vec::new();
+        records.extend(self.hid_descriptors.iter().enumerate().map(|(index, descriptor)| (descriptor.time, 0, index)));
+        records.extend(self.hid_reports.iter().enumerate().map(|(index, report)| (report.time, 1, index)));
+        records.extend(self.events.iter().enumerate().map(|(index, event)| (event.time, 2, index)));
+        records.sort();
+        for (_, record, index) in records {
+            match record {
+                0 => writer.write_hid_descriptor(&self.hid_descriptors[index])?,
+                1 => writer.write_hid_report(&self.hid_reports[index])?,
+                _ => writer.write_event(&self.events[index])?,
+            }
+        }
+        writer.flush()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P, format: CaptureFormat) -> Result<(), CaptureError> {
+        self.write(BufWriter::new(File::create(path)?), format)
+    }
+
+    /// Keeps the records from `from` until (excluding) `to`, moving them to start at zero
+    ///
+    /// Joystick descriptors from before `from` are kept at zero, as the reports need them.
+    pub fn trim(&mut self, from: Duration, to: Option<Duration>) {
+        let within = |time: Duration| time >= from && to.is_none_or(|to| time < to);
+        self.events.retain(|event| within(event.time));
+        for event in self.events.iter_mut() {
+            event.time -= from;
+        }
+        self.hid_reports.retain(|report| within(report.time));
+        for report in self.hid_reports.iter_mut() {
+            report.time -= from;
+        }
+        self.hid_descriptors
+            .retain(|descriptor| to.is_none_or(|to| descriptor.time < to));
+        for descriptor in self.hid_descriptors.iter_mut() {
+            descriptor.time = descriptor.time.saturating_sub(from);
+        }
+    }
+
+    /// Keeps the events passing filter, and the joystick reports it would let through
+    ///
+    /// Devices keep their ids, so the device list is unchanged.
+    pub fn retain(&mut self, filter: &EventFilter) {
+        self.events.retain(|event| filter.matches(&event.event));
+        let joystick_passes = |device: usize| {
+            (filter.device_types.is_empty()
+                || filter
+                    .device_types
+                    .iter()
+                    .any(|device_type| matches!(*device_type, DeviceType::Joysticks(_))))
+                && (filter.devices.is_empty() || filter.devices.contains(&device))
+                && (filter.kinds.is_empty()
+                    || filter.kinds.iter().any(|kind| {
+                        matches!(
+                            *kind,
+                            EventKind::JoystickButton
+                                | EventKind::JoystickAxis
+                                | EventKind::JoystickHatSwitch
+                                | EventKind::JoystickInitialState
+                        )
+                    }))
+        };
+        self.hid_reports.retain(|report| joystick_passes(report.device));
+        self.hid_descriptors
+            .retain(|descriptor| joystick_passes(descriptor.device));
+    }
+
+    /// Runs the captured joystick reports through the decoder again, returning the
+    /// events it emits (gamepad mappings are not applied)
+    ///
+    /// Each joystick starts from the state it has after registration, so the
+    /// first report of a descriptor gives a `JoystickInitialStateEvent`.
+    #[cfg(windows)]
+    pub fn decode_hid_reports(&self) -> Result<Vec<CapturedEvent>, CaptureError> {
+        ::joystick::decode_hid_reports(&self.hid_descriptors, &self.hid_reports)
+    }
+}
+
+/// Writes a capture record by record
+pub struct CaptureWriter<W: Write> {
+    writer: W,
+    format: CaptureFormat,
+}
+
+impl<W: Write> CaptureWriter<W> {
+    /// Writes the header of a capture holding the given devices
+    pub fn new(
+        mut writer: W,
+        format: CaptureFormat,
+        devices: &DevicesDisplayInfo,
+    ) -> Result<CaptureWriter<W>, CaptureError> {
+        match format {
+            CaptureFormat::Binary => {
+                let mut buffer = BINARY_MAGIC.to_vec();
+                put_u16(&mut buffer, CAPTURE_VERSION);
+                put_devices(&mut buffer, devices);
+                writer.write_all(&buffer)?;
+            }
+            CaptureFormat::JsonLines => {
+                let header = Json::Object(vec![
+                    ("format".to_string(), JSON_FORMAT_NAME.into()),
+                    ("version".to_string(), f64::from(CAPTURE_VERSION).into()),
+                    ("devices".to_string(), devices_to_json(devices)),
+                ]);
+                writeln!(writer, "{}", header)?;
+            }
+        }
+        Ok(CaptureWriter { writer, format })
+    }
+
+    pub fn format(&self) -> CaptureFormat {
+        self.format
+    }
+
+    pub fn write_event(&mut self, event: &CapturedEvent) -> Result<(), CaptureError> {
+        match self.format {
+            CaptureFormat::Binary => {
+                let mut buffer = vec![EVENT_RECORD];
+                put_u64(&mut buffer, duration_micros(event.time));
+                put_event(&mut buffer, &event.event);
+                self.writer.write_all(&buffer)?;
+            }
+            CaptureFormat::JsonLines => {
+                let mut fields = vec![(
+                    "time_us".to_string(),
+                    (duration_micros(event.time) as f64).into(),
+                )];
+                fields.extend(event_to_json(&event.event));
+                writeln!(self.writer, "{}", Json::Object(fields))?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn write_hid_descriptor(&mut self, descriptor: &HidDescriptor) -> Result<(), CaptureError> {
+        match self.format {
+            CaptureFormat::Binary => {
+                let mut buffer = vec![HID_DESCRIPTOR_RECORD];
+                put_u64(&mut buffer, duration_micros(descriptor.time));
+                put_len(&mut buffer, descriptor.device);
+                put_u8(&mut buffer, descriptor.is_360_controller as u8);
+                put_bytes(&mut buffer, &descriptor.preparsed_data);
+                self.writer.write_all(&buffer)?;
+            }
+            CaptureFormat::JsonLines => {
+                let record = Json::Object(vec![
+                    field("time_us", duration_micros(descriptor.time) as f64),
+                    field("type", "HidDescriptor"),
+                    field("device", descriptor.device as f64),
+                    field("is_360_controller", descriptor.is_360_controller),
+                    field("preparsed_data", to_hex(&descriptor.preparsed_data)),
+                ]);
+                writeln!(self.writer, "{}", record)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn write_hid_report(&mut self, report: &CapturedReport) -> Result<(), CaptureError> {
+        match self.format {
+            CaptureFormat::Binary => {
+                let mut buffer = vec![HID_REPORT_RECORD];
+                put_u64(&mut buffer, duration_micros(report.time));
+                put_len(&mut buffer, report.device);
+                put_bytes(&mut buffer, &report.report);
+                self.writer.write_all(&buffer)?;
+            }
+            CaptureFormat::JsonLines => {
+                let record = Json::Object(vec![
+                    field("time_us", duration_micros(report.time) as f64),
+                    field("type", "HidReport"),
+                    field("device", report.device as f64),
+                    field("report", to_hex(&report.report)),
+                ]);
+                writeln!(self.writer, "{}", record)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn write_record(&mut self, record: &CaptureRecord) -> Result<(), CaptureError> {
+        match *record {
+            CaptureRecord::Event(ref event) => self.write_event(event),
+            CaptureRecord::HidDescriptor(ref descriptor) => self.write_hid_descriptor(descriptor),
+            CaptureRecord::HidReport(ref report) => self.write_hid_report(report),
+        }
+    }
+
+    pub fn flush(&mut self) -> Result<(), CaptureError> {
+        self.writer.flush().map_err(CaptureError::from)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Reads a capture record by record
+pub struct CaptureReader<R: BufRead> {
+    reader: R,
+    format: CaptureFormat,
+    version: u16,
+    devices: DevicesDisplayInfo,
+    line: String,
+}
+
+impl<R: BufRead> CaptureReader<R> {
+    /// Reads the header of a capture in either format
+    pub fn new(mut reader: R) -> Result<CaptureReader<R>, CaptureError> {
+        let binary = reader.fill_buf()?.first() == Some(&BINARY_MAGIC[0]);
+        if binary {
+            let mut magic = [0; 4];
+            reader.read_exact(&mut magic)?;
+            if &magic != BINARY_MAGIC {
+                return invalid("unknown format");
+            }
+            let version = get_u16(&mut reader)?;
+            check_version(version)?;
+            let devices = get_devices(&mut reader)?;
+            Ok(CaptureReader {
+                reader,
+                format: CaptureFormat::Binary,
+                version,
+                devices,
+                line: String::new(),
+            })
+        } else {
+            let mut line = String::new();
+            reader.read_line(&mut line)?;
+            let header = parse_line(&line)?;
+            if header.get("format").and_then(Json::as_str) != Some(JSON_FORMAT_NAME) {
+                return invalid("unknown format");
+            }
+            let version = json_u16(&header, "version")?;
+            check_version(version)?;
+            let devices = match header.get("devices") {
+                Some(devices) => devices_from_json(devices)?,
+                None => return invalid("missing devices"),
+            };
+            Ok(CaptureReader {
+                reader,
+                format: CaptureFormat::JsonLines,
+                version,
+                devices,
+                line,
+            })
+        }
+    }
+
+    pub fn format(&self) -> CaptureFormat {
+        self.format
+    }
+
+    /// Format version the capture was written with
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    pub fn devices(&self) -> &DevicesDisplayInfo {
+        &self.devices
+    }
+
+    /// Reads the next event, skipping other records, or None at the end of the capture
+    pub fn read_event(&mut self) -> Result<Option<CapturedEvent>, CaptureError> {
+        while let Some(record) = self.read_record()? {
+            if let CaptureRecord::Event(event) = record {
+                return Ok(Some(event));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Reads the next record, or None at the end of the capture
+    pub fn read_record(&mut self) -> Result<Option<CaptureRecord>, CaptureError> {
+        match self.format {
+            CaptureFormat::Binary => {
+                let mut record = [0];
+                if self.reader.read(&mut record)? == 0 {
+                    return Ok(None);
+                }
+                let reader = &mut self.reader;
+                let time = Duration::from_micros(get_u64(reader)?);
+                Ok(Some(match record[0] {
+                    EVENT_RECORD => CaptureRecord::Event(CapturedEvent {
+                        time,
+                        event: get_event(reader)?,
+                    }),
+                    HID_DESCRIPTOR_RECORD => CaptureRecord::HidDescriptor(HidDescriptor {
+                        time,
+                        device: get_len(reader)?,
+                        is_360_controller: get_bool(reader)?,
+                        preparsed_data: get_len(reader).and_then(|length| get_bytes(reader, length))?,
+                    }),
+                    HID_REPORT_RECORD => CaptureRecord::HidReport(CapturedReport {
+                        time,
+                        device: get_len(reader)?,
+                        report: get_len(reader).and_then(|length| get_bytes(reader, length))?,
+                    }),
+                    _ => return invalid("unknown record"),
+                }))
+            }
+            CaptureFormat::JsonLines => loop {
+                self.line.clear();
+                if self.reader.read_line(&mut self.line)? == 0 {
+                    return Ok(None);
+                }
+                if self.line.trim().is_empty() {
+                    continue;
+                }
+                let record = parse_line(&self.line)?;
+                let time = Duration::from_micros(json_u64(&record, "time_us")?);
+                return Ok(Some(match record.get("type").and_then(Json::as_str) {
+                    Some("HidDescriptor") => CaptureRecord::HidDescriptor(HidDescriptor {
+                        time,
+                        device: json_u64(&record, "device")? as usize,
+                        is_360_controller: record
+                            .get("is_360_controller")
+                            .and_then(Json::as_bool)
+                            .unwrap_or(false),
+                        preparsed_data: json_hex(&record, "preparsed_data")?,
+                    }),
+                    Some("HidReport") => CaptureRecord::HidReport(CapturedReport {
+                        time,
+                        device: json_u64(&record, "device")? as usize,
+                        report: json_hex(&record, "report")?,
+                    }),
+                    _ => CaptureRecord::Event(CapturedEvent {
+                        time,
+                        event: event_from_json(&record)?,
+                    }),
+                }));
+            },
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for CaptureReader<R> {
+    type Item = Result<CapturedEvent, CaptureError>;
+
+    fn next(&mut self) -> Option<Result<CapturedEvent, CaptureError>> {
+        self.read_event().transpose()
+    }
+}
+
+/// Records the events of a `RawInputManager` on a background thread
+/// (see `RawInputManager::start_recording` and `start_hid_recording`)
+///
+/// Events and joystick reports are timestamped by the manager thread when it
+/// reads them, so the times do not depend on how fast the capture is written.
+pub struct Recording<W: Write + Send + 'static> {
+    /// Ends the records sent through the `HidReportSink` with None
+    sender: Sender<Option<CaptureRecord>>,
+    joiner: Option<JoinHandle<Result<W, CaptureError>>>,
+}
+
+/// Where the manager thread sends the events it reads (and joystick descriptors
+/// and reports for HID recordings) while they are recorded
+///
+/// Sending fails once the recording has stopped, after which the sink should be dropped.
+#[derive(Clone)]
+pub struct HidReportSink {
+    sender: Sender<Option<CaptureRecord>>,
+    start: Instant,
+}
+
+impl HidReportSink {
+    pub fn send_descriptor(&self, device: usize, preparsed_data: &[u8], is_360_controller: bool) -> bool {
+        self.send(CaptureRecord::HidDescriptor(HidDescriptor {
+            time: self.start.elapsed(),
+            device,
+            preparsed_data: preparsed_data.to_vec(),
+            is_360_controller,
+        }))
+    }
+
+    pub fn send_report(&self, device: usize, report: &[u8]) -> bool {
+        self.send(CaptureRecord::HidReport(CapturedReport {
+            time: self.start.elapsed(),
+            device,
+            report: report.to_vec(),
+        }))
+    }
+
+    pub fn send_events<'a, I: IntoIterator<Item = &'a RawEvent>>(&self, events: I) -> bool {
+        let time = self.start.elapsed();
+        events.into_iter().all(|event| {
+            self.send(CaptureRecord::Event(CapturedEvent {
+                time,
+                event: event.clone(),
+            }))
+        })
+    }
+
+    fn send(&self, record: CaptureRecord) -> bool {
+        self.sender.send(Some(record)).is_ok()
+    }
+}
+
+impl<W: Write + Send + 'static> Recording<W> {
+    /// Starts writing a capture of the given devices and every record sent to the returned sink
+    /// Records are never missed, however far the writer falls behind.
+    pub fn start(
+        devices: &DevicesDisplayInfo,
+        writer: W,
+        format: CaptureFormat,
+    ) -> Result<(Recording<W>, HidReportSink), CaptureError> {
+        let mut writer = CaptureWriter::new(BufWriter::new(writer), format, devices)?;
+        writer.flush()?;
+        let (sender, receiver) = channel();
+        let sink = HidReportSink {
+            sender: sender.clone(),
+            start: Instant::now(),
+        };
+        let joiner = thread::spawn(move || {
+            record_records(&receiver, &mut writer)?;
+            into_writer(writer)
+        });
+        let recording = Recording {
+            sender,
+            joiner: Some(joiner),
+        };
+        Ok((recording, sink))
+    }
+
+    /// Writes the remaining events and returns the writer
+    pub fn stop(mut self) -> Result<W, CaptureError> {
+        self.finish().unwrap()
+    }
+
+    fn finish(&mut self) -> Option<Result<W, CaptureError>> {
+        let _ = self.sender.send(None);
+        self.joiner
+            .take()
+            .map(|joiner| joiner.join().expect("Recording thread panicked"))
+    }
+}
+
+impl<W: Write + Send + 'static> Drop for Recording<W> {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+fn record_records<W: Write>(
+    receiver: &Receiver<Option<CaptureRecord>>,
+    writer: &mut CaptureWriter<W>,
+) -> Result<(), CaptureError> {
+    while let Ok(Some(record)) = receiver.recv() {
+        writer.write_record(&record)?;
+        loop {
+            match receiver.try_recv() {
+                Ok(Some(record)) => writer.write_record(&record)?,
+                Ok(None) => return Ok(()),
+                Err(_) => break,
+            }
+        }
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+fn into_writer<W: Write>(writer: CaptureWriter<BufWriter<W>>) -> Result<W, CaptureError> {
+    writer
+        .into_inner()
+        .into_inner()
+        .map_err(|error| CaptureError::from(error.into_error()))
+}
+
+fn check_version(version: u16) -> Result<(), CaptureError> {
+    if version == 0 || version > CAPTURE_VERSION {
+        return Err(CaptureError(format!(
+            "Unsupported capture version {} (latest is {})",
+            version, CAPTURE_VERSION
+        )));
+    }
+    Ok(())
+}
+
+fn duration_micros(duration: Duration) -> u64 {
+    duration.as_micros().min(u128::from(u64::MAX)) as u64
+}
+
+fn state_index(state: &State) -> u8 {
+    match *state {
+        State::Pressed => 1,
+        State::Released => 0,
+    }
+}
+
+fn state_from_index(index: u8) -> Result<State, CaptureError> {
+    match index {
+        1 => Ok(State::Pressed),
+        0 => Ok(State::Released),
+        _ => invalid("unknown state"),
+    }
+}
+
+fn index_of<T: PartialEq>(values: &[T], value: &T) -> u8 {
+    values.iter().position(|v| v == value).unwrap() as u8
+}
+
+fn value_at<T: Clone>(values: &[T], index: u8) -> Result<T, CaptureError> {
+    match values.get(index as usize) {
+        Some(value) => Ok(value.clone()),
+        None => invalid("unknown value"),
+    }
+}
+
+fn axes_values(axes: &Axes) -> [Option<f64>; 7] {
+    [
+        axes.x,
+        axes.y,
+        axes.z,
+        axes.rx,
+        axes.ry,
+        axes.rz,
+        axes.slider,
+    ]
+}
+
+fn axes_from_values(values: [Option<f64>; 7]) -> Axes {
+    Axes {
+        x: values[0],
+        y: values[1],
+        z: values[2],
+        rx: values[3],
+        ry: values[4],
+        rz: values[5],
+        slider: values[6],
+    }
+}
+
+fn raw_axes_values(axes: &RawAxes) -> [u32; 7] {
+    [
+        axes.x,
+        axes.y,
+        axes.z,
+        axes.rx,
+        axes.ry,
+        axes.rz,
+        axes.slider,
+    ]
+}
+
+fn raw_axes_from_values(values: [u32; 7]) -> RawAxes {
+    RawAxes {
+        x: values[0],
+        y: values[1],
+        z: values[2],
+        rx: values[3],
+        ry: values[4],
+        rz: values[5],
+        slider: values[6],
+    }
+}
+
+/// Properties shared by all device types
+struct DeviceRecord {
+    name: String,
+    serial: Option<String>,
+    product: Option<String>,
+    manufacturer: Option<String>,
+    ids: DeviceIds,
+    stable_id: StableDeviceId,
+}
+
+fn mouse_record(mouse: &MouseDisplayInfo) -> DeviceRecord {
+    DeviceRecord {
+        name: mouse.name.clone(),
+        serial: mouse.serial.clone(),
+        product: mouse.product.clone(),
+        manufacturer: mouse.manufacturer.clone(),
+        ids: mouse.ids.clone(),
+        stable_id: mouse.stable_id,
+    }
+}
+
+fn keyboard_record(keyboard: &KeyboardDisplayInfo) -> DeviceRecord {
+    DeviceRecord {
+        name: keyboard.name.clone(),
+        serial: keyboard.serial.clone(),
+        product: keyboard.product.clone(),
+        manufacturer: keyboard.manufacturer.clone(),
+        ids: keyboard.ids.clone(),
+        stable_id: keyboard.stable_id,
+    }
+}
+
+fn joystick_record(joystick: &JoystickDisplayInfo) -> DeviceRecord {
+    DeviceRecord {
+        name: joystick.name.clone(),
+        serial: joystick.serial.clone(),
+        product: joystick.product.clone(),
+        manufacturer: joystick.manufacturer.clone(),
+        ids: joystick.ids.clone(),
+        stable_id: joystick.stable_id,
+    }
+}
+
+fn mouse_from_record(record: DeviceRecord) -> MouseDisplayInfo {
+    MouseDisplayInfo {
+        name: record.name,
+        serial: record.serial,
+        product: record.product,
+        manufacturer: record.manufacturer,
+        ids: record.ids,
+        stable_id: record.stable_id,
+        ..Default::default()
+    }
+}
+
+fn keyboard_from_record(record: DeviceRecord) -> KeyboardDisplayInfo {
+    KeyboardDisplayInfo {
+        name: record.name,
+        serial: record.serial,
+        product: record.product,
+        manufacturer: record.manufacturer,
+        ids: record.ids,
+        stable_id: record.stable_id,
+        ..Default::default()
+    }
+}
+
+fn joystick_from_record(
+    record: DeviceRecord,
+    capabilities: JoystickCapabilities,
+) -> JoystickDisplayInfo {
+    JoystickDisplayInfo {
+        name: record.name,
+        serial: record.serial,
+        product: record.product,
+        manufacturer: record.manufacturer,
+        ids: record.ids,
+        stable_id: record.stable_id,
+        capabilities,
+        ..Default::default()
+    }
+}
+
+impl DevicesDisplayInfo {
+    /// Devices as a JSON object, in the form used by the header of JSON Lines captures
+    pub fn to_json(&self) -> String {
+        devices_to_json(self).to_string()
+    }
+}
+
+// Binary encoding
+
+fn put_u8(buffer: &mut Vec<u8>, value: u8) {
+    buffer.push(value);
+}
+
+fn put_u16(buffer: &mut Vec<u8>, value: u16) {
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(buffer: &mut Vec<u8>, value: u32) {
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u64(buffer: &mut Vec<u8>, value: u64) {
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_f64(buffer: &mut Vec<u8>, value: f64) {
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_len(buffer: &mut Vec<u8>, length: usize) {
+    put_u32(buffer, length as u32);
+}
+
+fn put_string(buffer: &mut Vec<u8>, string: &str) {
+    put_len(buffer, string.len());
+    buffer.extend_from_slice(string.as_bytes());
+}
+
+fn put_bytes(buffer: &mut Vec<u8>, bytes: &[u8]) {
+    put_len(buffer, bytes.len());
+    buffer.extend_from_slice(bytes);
+}
+
+fn put_option<T, F: FnOnce(&mut Vec<u8>, T)>(buffer: &mut Vec<u8>, value: Option<T>, put: F) {
+    match value {
+        Some(value) => {
+            put_u8(buffer, 1);
+            put(buffer, value);
+        }
+        None => put_u8(buffer, 0),
+    }
+}
+
+fn put_device(buffer: &mut Vec<u8>, device: &DeviceRecord) {
+    put_string(buffer, &device.name);
+    put_option(buffer, device.serial.as_deref(), put_string);
+    put_option(buffer, device.product.as_deref(), put_string);
+    put_option(buffer, device.manufacturer.as_deref(), put_string);
+    put_option(buffer, device.ids.vendor_id, put_u16);
+    put_option(buffer, device.ids.product_id, put_u16);
+    put_option(buffer, device.ids.version_number, put_u16);
+    put_u16(buffer, device.ids.usage_page);
+    put_u16(buffer, device.ids.usage);
+    put_u64(buffer, device.stable_id.0);
+}
+
+fn put_devices(buffer: &mut Vec<u8>, devices: &DevicesDisplayInfo) {
+    put_len(buffer, devices.mice.len());
+    for mouse in &devices.mice {
+        put_device(buffer, &mouse_record(mouse));
+    }
+    put_len(buffer, devices.keyboards.len());
+    for keyboard in &devices.keyboards {
+        put_device(buffer, &keyboard_record(keyboard));
+    }
+    put_len(buffer, devices.joysticks.len());
+    for joystick in &devices.joysticks {
+        put_device(buffer, &joystick_record(joystick));
+        let capabilities = &joystick.capabilities;
+        put_len(buffer, capabilities.buttons);
+        put_len(buffer, capabilities.axes.len());
+        for axis in &capabilities.axes {
+            put_u8(buffer, index_of(&JOYSTICK_AXES, axis));
+        }
+        put_u8(buffer, capabilities.hatswitch as u8);
+    }
+}
+
+fn put_joystick_state(buffer: &mut Vec<u8>, state: &JoystickState) {
+    put_len(buffer, state.button_states.len());
+    for &pressed in &state.button_states {
+        put_u8(buffer, pressed as u8);
+    }
+    for &value in axes_values(&state.axis_states).iter() {
+        put_option(buffer, value, put_f64);
+    }
+    put_option(buffer, state.hatswitch.as_ref(), |buffer, hatswitch| {
+        put_u8(buffer, index_of(&HAT_SWITCHES, hatswitch))
+    });
+    for &value in raw_axes_values(&state.raw_axis_states).iter() {
+        put_u32(buffer, value);
+    }
+}
+
+fn put_event(buffer: &mut Vec<u8>, event: &RawEvent) {
+    match *event {
+        RawEvent::MouseButtonEvent(id, ref button, ref state) => {
+            put_u8(buffer, 0);
+            put_len(buffer, id);
+            put_u8(buffer, index_of(&MOUSE_BUTTONS, button));
+            put_u8(buffer, state_index(state));
+        }
+        RawEvent::MouseMoveEvent(id, x, y) => {
+            put_u8(buffer, 1);
+            put_len(buffer, id);
+            put_u32(buffer, x as u32);
+            put_u32(buffer, y as u32);
+        }
+        RawEvent::MouseWheelEvent(id, delta) => {
+            put_u8(buffer, 2);
+            put_len(buffer, id);
+            put_u32(buffer, delta.to_bits());
+        }
+        RawEvent::KeyboardEvent(id, ref key, ref state) => {
+            put_u8(buffer, 3);
+            put_len(buffer, id);
+            put_u8(buffer, index_of(&KEY_IDS, key));
+            put_u8(buffer, state_index(state));
+        }
+        RawEvent::JoystickButtonEvent(id, button, ref state) => {
+            put_u8(buffer, 4);
+            put_len(buffer, id);
+            put_len(buffer, button);
+            put_u8(buffer, state_index(state));
+        }
+        RawEvent::JoystickAxisEvent(id, ref axis, value) => {
+            put_u8(buffer, 5);
+            put_len(buffer, id);
+            put_u8(buffer, index_of(&JOYSTICK_AXES, axis));
+            put_f64(buffer, value);
+        }
+        RawEvent::JoystickHatSwitchEvent(id, ref hatswitch) => {
+            put_u8(buffer, 6);
+            put_len(buffer, id);
+            put_u8(buffer, index_of(&HAT_SWITCHES, hatswitch));
+        }
+        RawEvent::JoystickInitialStateEvent(id, ref state) => {
+            put_u8(buffer, 7);
+            put_len(buffer, id);
+            put_joystick_state(buffer, state);
+        }
+        RawEvent::GamepadButtonEvent(id, ref button, ref state) => {
+            put_u8(buffer, 8);
+            put_len(buffer, id);
+            put_u8(buffer, index_of(&BUTTONS, button));
+            put_u8(buffer, state_index(state));
+        }
+        RawEvent::GamepadAxisEvent(id, ref axis, value) => {
+            put_u8(buffer, 9);
+            put_len(buffer, id);
+            put_u8(buffer, index_of(&AXES, axis));
+            put_f64(buffer, value);
+        }
+    }
+}
+
+fn get_bytes<R: Read>(reader: &mut R, length: usize) -> Result<Vec<u8>, CaptureError> {
+    let mut bytes = Vec::new();
+    reader.take(length as u64).read_to_end(&mut bytes)?;
+    if bytes.len() < length {
+        return invalid("unexpected end");
+    }
+    Ok(bytes)
+}
+
+fn get_array<R: Read, A: AsMut<[u8]> + Default>(reader: &mut R) -> Result<A, CaptureError> {
+    let mut bytes = A::default();
+    match reader.read_exact(bytes.as_mut()) {
+        Ok(()) => Ok(bytes),
+        Err(ref error) if error.kind() == io::ErrorKind::UnexpectedEof => invalid("unexpected end"),
+        Err(error) => Err(error.into()),
+    }
+}
+
+fn get_u8<R: Read>(reader: &mut R) -> Result<u8, CaptureError> {
+    get_array::<R, [u8; 1]>(reader).map(|bytes| bytes[0])
+}
+
+fn get_u16<R: Read>(reader: &mut R) -> Result<u16, CaptureError> {
+    get_array(reader).map(u16::from_le_bytes)
+}
+
+fn get_u32<R: Read>(reader: &mut R) -> Result<u32, CaptureError> {
+    get_array(reader).map(u32::from_le_bytes)
+}
+
+fn get_u64<R: Read>(reader: &mut R) -> Result<u64, CaptureError> {
+    get_array(reader).map(u64::from_le_bytes)
+}
+
+fn get_f64<R: Read>(reader: &mut R) -> Result<f64, CaptureError> {
+    get_array(reader).map(f64::from_le_bytes)
+}
+
+fn get_len<R: Read>(reader: &mut R) -> Result<usize, CaptureError> {
+    get_u32(reader).map(|length| length as usize)
+}
+
+fn get_bool<R: Read>(reader: &mut R) -> Result<bool, CaptureError> {
+    match get_u8(reader)? {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => invalid("bad flag"),
+    }
+}
+
+fn get_string<R: Read>(reader: &mut R) -> Result<String, CaptureError> {
+    let length = get_len(reader)?;
+    String::from_utf8(get_bytes(reader, length)?).or_else(|_| invalid("bad string"))
+}
+
+fn get_option<R: Read, T, F>(reader: &mut R, get: F) -> Result<Option<T>, CaptureError>
+where
+    F: FnOnce(&mut R) -> Result<T, CaptureError>,
+{
+    if get_bool(reader)? {
+        get(reader).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+fn get_device<R: Read>(reader: &mut R) -> Result<DeviceRecord, CaptureError> {
+    Ok(DeviceRecord {
+        name: get_string(reader)?,
+        serial: get_option(reader, get_string)?,
+        product: get_option(reader, get_string)?,
+        manufacturer: get_option(reader, get_string)?,
+        ids: DeviceIds {
+            vendor_id: get_option(reader, get_u16)?,
+            product_id: get_option(reader, get_u16)?,
+            version_number: get_option(reader, get_u16)?,
+            usage_page: get_u16(reader)?,
+            usage: get_u16(reader)?,
+        },
+        stable_id: StableDeviceId(get_u64(reader)?),
+    })
+}
+
+fn get_devices<R: Read>(reader: &mut R) -> Result<DevicesDisplayInfo, CaptureError> {
+    let mut devices = DevicesDisplayInfo::default();
+    for _ in 0..get_len(reader)? {
+        devices.mice.push(mouse_from_record(get_device(reader)?));
+    }
+    for _ in 0..get_len(reader)? {
+        devices
+            .keyboards
+            .push(keyboard_from_record(get_device(reader)?));
+    }
+    for _ in 0..get_len(reader)? {
+        let record = get_device(reader)?;
+        let buttons = get_len(reader)?;
+        let mut axes = Vec::new();
+        for _ in 0..get_len(reader)? {
+            axes.push(value_at(&JOYSTICK_AXES, get_u8(reader)?)?);
+        }
+        let capabilities = JoystickCapabilities {
+            buttons,
+            axes,
+            hatswitch: get_bool(reader)?,
+        };
+        devices
+            .joysticks
+            .push(joystick_from_record(record, capabilities));
+    }
+    Ok(devices)
+}
+
+fn get_joystick_state<R: Read>(reader: &mut R) -> Result<JoystickState, CaptureError> {
+    let mut button_states = Vec::new();
+    for _ in 0..get_len(reader)? {
+        button_states.push(get_bool(reader)?);
+    }
+    let mut axes = [None; 7];
+    for value in axes.iter_mut() {
+        *value = get_option(reader, get_f64)?;
+    }
+    let hatswitch = get_option(reader, |reader| value_at(&HAT_SWITCHES, get_u8(reader)?))?;
+    let mut raw_axes = [0; 7];
+    for value in raw_axes.iter_mut() {
+        *value = get_u32(reader)?;
+    }
+    Ok(JoystickState {
+        button_states,
+        axis_states: axes_from_values(axes),
+        hatswitch,
+        raw_axis_states: raw_axes_from_values(raw_axes),
+    })
+}
+
+fn get_event<R: Read>(reader: &mut R) -> Result<RawEvent, CaptureError> {
+    let variant = get_u8(reader)?;
+    let id = get_len(reader)?;
+    Ok(match variant {
+        0 => RawEvent::MouseButtonEvent(
+            id,
+            value_at(&MOUSE_BUTTONS, get_u8(reader)?)?,
+            state_from_index(get_u8(reader)?)?,
+        ),
+        1 => RawEvent::MouseMoveEvent(id, get_u32(reader)? as i32, get_u32(reader)? as i32),
+        2 => RawEvent::MouseWheelEvent(id, f32::from_bits(get_u32(reader)?)),
+        3 => RawEvent::KeyboardEvent(
+            id,
+            value_at(&KEY_IDS, get_u8(reader)?)?,
+            state_from_index(get_u8(reader)?)?,
+        ),
+        4 => {
+            RawEvent::JoystickButtonEvent(id, get_len(reader)?, state_from_index(get_u8(reader)?)?)
+        }
+        5 => RawEvent::JoystickAxisEvent(
+            id,
+            value_at(&JOYSTICK_AXES, get_u8(reader)?)?,
+            get_f64(reader)?,
+        ),
+        6 => RawEvent::JoystickHatSwitchEvent(id, value_at(&HAT_SWITCHES, get_u8(reader)?)?),
+        7 => RawEvent::JoystickInitialStateEvent(id, get_joystick_state(reader)?),
+        8 => RawEvent::GamepadButtonEvent(
+            id,
+            value_at(&BUTTONS, get_u8(reader)?)?,
+            state_from_index(get_u8(reader)?)?,
+        ),
+        9 => RawEvent::GamepadAxisEvent(id, value_at(&AXES, get_u8(reader)?)?, get_f64(reader)?),
+        _ => return invalid("unknown event"),
+    })
+}
+
+// JSON Lines encoding (enum values are written by name)
+
+fn parse_line(line: &str) -> Result<Json, CaptureError> {
+    Json::parse(line.trim()).or_else(|error| invalid(&error))
+}
+
+fn json_u64(json: &Json, key: &str) -> Result<u64, CaptureError> {
+    match json.get(key).and_then(Json::as_f64) {
+        Some(number) if number >= 0.0 && number.fract() == 0.0 => Ok(number as u64),
+        _ => invalid(&format!("bad {}", key)),
+    }
+}
+
+fn json_u16(json: &Json, key: &str) -> Result<u16, CaptureError> {
+    match json_u64(json, key)? {
+        number if number <= u16::MAX as u64 => Ok(number as u16),
+        _ => invalid(&format!("bad {}", key)),
+    }
+}
+
+fn json_f64(json: &Json, key: &str) -> Result<f64, CaptureError> {
+    match json.get(key) {
+        Some(&Json::Number(number)) => Ok(number),
+        // Non finite numbers are written as null
+        Some(&Json::Null) => Ok(f64::NAN),
+        _ => invalid(&format!("bad {}", key)),
+    }
+}
+
+fn json_string(json: &Json, key: &str) -> Result<Option<String>, CaptureError> {
+    match json.get(key) {
+        None | Some(&Json::Null) => Ok(None),
+        Some(Json::String(string)) => Ok(Some(string.clone())),
+        Some(_) => invalid(&format!("bad {}", key)),
+    }
+}
+
+fn json_id(json: &Json, key: &str) -> Result<Option<u16>, CaptureError> {
+    match json.get(key) {
+        None | Some(&Json::Null) => Ok(None),
+        Some(_) => json_u16(json, key).map(Some),
+    }
+}
+
+fn json_hex(json: &Json, key: &str) -> Result<Vec<u8>, CaptureError> {
+    json.get(key)
+        .and_then(Json::as_str)
+        .and_then(from_hex)
+        .map_or_else(|| invalid(&format!("bad {}", key)), Ok)
+}
+
+/// Byte strings are written in hex
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&hex[index..index + 2], 16).ok())
+        .collect()
+}
+
+fn device_to_json(device: &DeviceRecord) -> Vec<(String, Json)> {
+    vec![
+        field("name", device.name.as_str()),
+        field("serial", device.serial.clone()),
+        field("product", device.product.clone()),
+        field("manufacturer", device.manufacturer.clone()),
+        field("vendor_id", device.ids.vendor_id.map(f64::from)),
+        field("product_id", device.ids.product_id.map(f64::from)),
+        field("version_number", device.ids.version_number.map(f64::from)),
+        field("usage_page", f64::from(device.ids.usage_page)),
+        field("usage", f64::from(device.ids.usage)),
+        field("stable_id", device.stable_id.to_string()),
+    ]
+}
+
+fn devices_to_json(devices: &DevicesDisplayInfo) -> Json {
+    let joysticks = devices
+        .joysticks
+        .iter()
+        .map(|joystick| {
+            let capabilities = &joystick.capabilities;
+            let mut fields = device_to_json(&joystick_record(joystick));
+            fields.push((
+                "capabilities".to_string(),
+                Json::Object(vec![
+                    field("buttons", capabilities.buttons as f64),
+                    (
+                        "axes".to_string(),
+                        Json::Array(capabilities.axes.iter().map(name).collect()),
+                    ),
+                    field("hatswitch", capabilities.hatswitch),
+                ]),
+            ));
+            Json::Object(fields)
+        })
+        .collect();
+    Json::Object(vec![
+        (
+            "mice".to_string(),
+            Json::Array(
+                devices
+                    .mice
+                    .iter()
+                    .map(|mouse| Json::Object(device_to_json(&mouse_record(mouse))))
+                    .collect(),
+            ),
+        ),
+        (
+            "keyboards".to_string(),
+            Json::Array(
+                devices
+                    .keyboards
+                    .iter()
+                    .map(|keyboard| Json::Object(device_to_json(&keyboard_record(keyboard))))
+                    .collect(),
+            ),
+        ),
+        ("joysticks".to_string(), Json::Array(joysticks)),
+    ])
+}
+
+fn device_from_json(json: &Json) -> Result<DeviceRecord, CaptureError> {
+    let stable_id = match json.get("stable_id").and_then(Json::as_str) {
+        Some(id) => u64::from_str_radix(id, 16).or_else(|_| invalid("bad stable_id"))?,
+        None => return invalid("bad stable_id"),
+    };
+    Ok(DeviceRecord {
+        name: json_string(json, "name")?.unwrap_or_default(),
+        serial: json_string(json, "serial")?,
+        product: json_string(json, "product")?,
+        manufacturer: json_string(json, "manufacturer")?,
+        ids: DeviceIds {
+            vendor_id: json_id(json, "vendor_id")?,
+            product_id: json_id(json, "product_id")?,
+            version_number: json_id(json, "version_number")?,
+            usage_page: json_u16(json, "usage_page")?,
+            usage: json_u16(json, "usage")?,
+        },
+        stable_id: StableDeviceId(stable_id),
+    })
+}
+
+fn devices_from_json(json: &Json) -> Result<DevicesDisplayInfo, CaptureError> {
+    let mut devices = DevicesDisplayInfo::default();
+    for mouse in json_array(json, "mice")? {
+        devices
+            .mice
+            .push(mouse_from_record(device_from_json(mouse)?));
+    }
+    for keyboard in json_array(json, "keyboards")? {
+        devices
+            .keyboards
+            .push(keyboard_from_record(device_from_json(keyboard)?));
+    }
+    for joystick in json_array(json, "joysticks")? {
+        let capabilities = match joystick.get("capabilities") {
+            Some(capabilities) => JoystickCapabilities {
+                buttons: json_u64(capabilities, "buttons")? as usize,
+                axes: json_array(capabilities, "axes")?
+                    .iter()
+                    .map(|axis| {
+                        axis.as_str()
+                            .and_then(|axis| from_name(&JOYSTICK_AXES, axis))
+                    })
+                    .collect::<Option<_>>()
+                    .map_or_else(|| invalid("bad axes"), Ok)?,
+                hatswitch: capabilities
+                    .get("hatswitch")
+                    .and_then(Json::as_bool)
+                    .unwrap_or(false),
+            },
+            None => JoystickCapabilities::default(),
+        };
+        devices.joysticks.push(joystick_from_record(
+            device_from_json(joystick)?,
+            capabilities,
+        ));
+    }
+    Ok(devices)
+}
+
+fn joystick_state_to_json(state: &JoystickState) -> Json {
+    let axes = JOYSTICK_AXES
+        .iter()
+        .zip(axes_values(&state.axis_states).iter())
+        .filter_map(|(axis, value)| value.map(|value| (format!("{:?}", axis), Json::Number(value))))
+        .collect();
+    let raw_axes = JOYSTICK_AXES
+        .iter()
+        .zip(raw_axes_values(&state.raw_axis_states).iter())
+        .map(|(axis, &value)| (format!("{:?}", axis), Json::Number(f64::from(value))))
+        .collect();
+    Json::Object(vec![
+        (
+            "buttons".to_string(),
+            Json::Array(
+                state
+                    .button_states
+                    .iter()
+                    .map(|&pressed| Json::Bool(pressed))
+                    .collect(),
+            ),
+        ),
+        ("axes".to_string(), Json::Object(axes)),
+        (
+            "hatswitch".to_string(),
+            state.hatswitch.as_ref().map_or(Json::Null, name),
+        ),
+        ("raw_axes".to_string(), Json::Object(raw_axes)),
+    ])
+}
+
+fn joystick_state_from_json(json: &Json) -> Result<JoystickState, CaptureError> {
+    let mut button_states = Vec::new();
+    for pressed in json_array(json, "buttons")? {
+        match pressed.as_bool() {
+            Some(pressed) => button_states.push(pressed),
+            None => return invalid("bad buttons"),
+        }
+    }
+    let mut axes = [None; 7];
+    let mut raw_axes = [0; 7];
+    for (index, axis) in JOYSTICK_AXES.iter().enumerate() {
+        let axis = format!("{:?}", axis);
+        if let Some(values) = json.get("axes") {
+            if values.get(&axis).is_some() {
+                axes[index] = Some(json_f64(values, &axis)?);
+            }
+        }
+        if let Some(values) = json.get("raw_axes") {
+            if values.get(&axis).is_some() {
+                raw_axes[index] = json_u64(values, &axis)? as u32;
+            }
+        }
+    }
+    let hatswitch = match json.get("hatswitch") {
+        None | Some(&Json::Null) => None,
+        Some(_) => Some(named(&HAT_SWITCHES, json, "hatswitch")?),
+    };
+    Ok(JoystickState {
+        button_states,
+        axis_states: axes_from_values(axes),
+        hatswitch,
+        raw_axis_states: raw_axes_from_values(raw_axes),
+    })
+}
+
+fn event_to_json(event: &RawEvent) -> Vec<(String, Json)> {
+    let mut fields = vec![
+        field("type", format!("{:?}Event", event.kind())),
+        field("device", event.device_id() as f64),
+    ];
+    match *event {
+        RawEvent::MouseButtonEvent(_, ref button, ref state) => {
+            fields.push(("button".to_string(), name(button)));
+            fields.push(("state".to_string(), name(state)));
+        }
+        RawEvent::MouseMoveEvent(_, x, y) => {
+            fields.push(field("x", f64::from(x)));
+            fields.push(field("y", f64::from(y)));
+        }
+        RawEvent::MouseWheelEvent(_, delta) => fields.push(field("delta", f64::from(delta))),
+        RawEvent::KeyboardEvent(_, ref key, ref state) => {
+            fields.push(("key".to_string(), name(key)));
+            fields.push(("state".to_string(), name(state)));
+        }
+        RawEvent::JoystickButtonEvent(_, button, ref state) => {
+            fields.push(field("button", button as f64));
+            fields.push(("state".to_string(), name(state)));
+        }
+        RawEvent::JoystickAxisEvent(_, ref axis, value) => {
+            fields.push(("axis".to_string(), name(axis)));
+            fields.push(field("value", value));
+        }
+        RawEvent::JoystickHatSwitchEvent(_, ref hatswitch) => {
+            fields.push(("hatswitch".to_string(), name(hatswitch)))
+        }
+        RawEvent::JoystickInitialStateEvent(_, ref state) => {
+            fields.push(("state".to_string(), joystick_state_to_json(state)))
+        }
+        RawEvent::GamepadButtonEvent(_, ref button, ref state) => {
+            fields.push(("button".to_string(), name(button)));
+            fields.push(("state".to_string(), name(state)));
+        }
+        RawEvent::GamepadAxisEvent(_, ref axis, value) => {
+            fields.push(("axis".to_string(), name(axis)));
+            fields.push(field("value", value));
+        }
+    }
+    fields
+}
+
+fn event_from_json(json: &Json) -> Result<RawEvent, CaptureError> {
+    const STATES: [State; 2] = [State::Pressed, State::Released];
+    let id = json_u64(json, "device")? as usize;
+    let state = || named(&STATES, json, "state");
+    Ok(match json.get("type").and_then(Json::as_str) {
+        Some("MouseButtonEvent") => {
+            RawEvent::MouseButtonEvent(id, named(&MOUSE_BUTTONS, json, "button")?, state()?)
+        }
+        Some("MouseMoveEvent") => {
+            RawEvent::MouseMoveEvent(id, json_f64(json, "x")? as i32, json_f64(json, "y")? as i32)
+        }
+        Some("MouseWheelEvent") => RawEvent::MouseWheelEvent(id, json_f64(json, "delta")? as f32),
+        Some("KeyboardEvent") => {
+            RawEvent::KeyboardEvent(id, named(&KEY_IDS, json, "key")?, state()?)
+        }
+        Some("JoystickButtonEvent") => {
+            RawEvent::JoystickButtonEvent(id, json_u64(json, "button")? as usize, state()?)
+        }
+        Some("JoystickAxisEvent") => RawEvent::JoystickAxisEvent(
+            id,
+            named(&JOYSTICK_AXES, json, "axis")?,
+            json_f64(json, "value")?,
+        ),
+        Some("JoystickHatSwitchEvent") => {
+            RawEvent::JoystickHatSwitchEvent(id, named(&HAT_SWITCHES, json, "hatswitch")?)
+        }
+        Some("JoystickInitialStateEvent") => match json.get("state") {
+            Some(state) => {
+                RawEvent::JoystickInitialStateEvent(id, joystick_state_from_json(state)?)
+            }
+            None => return invalid("missing state"),
+        },
+        Some("GamepadButtonEvent") => {
+            RawEvent::GamepadButtonEvent(id, named(&BUTTONS, json, "button")?, state()?)
+        }
+        Some("GamepadAxisEvent") => {
+            RawEvent::GamepadAxisEvent(id, named(&AXES, json, "axis")?, json_f64(json, "value")?)
+        }
+        _ => return invalid("unknown event"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use devices::HatSwitch;
+    use event::{Axis, KeyId, MouseButton};
+    use event_queue::{OverflowPolicy, DEFAULT_CAPACITY};
+    use gamepad::{GamepadAxis, GamepadButton};
+    use manager::RawInputManager;
+    use mock::MockBackend;
+
+    fn ids(vendor_id: u16) -> DeviceIds {
+        DeviceIds {
+            vendor_id: Some(vendor_id),
+            product_id: Some(0x0002),
+            version_number: None,
+            usage_page: 1,
+            usage: 4,
+        }
+    }
+
+    fn devices() -> DevicesDisplayInfo {
+        DevicesDisplayInfo {
+            mice: vec![MouseDisplayInfo {
+                name: "mouse".to_string(),
+                serial: Some("1234".to_string()),
+                ids: ids(0x046d),
+                stable_id: StableDeviceId(1),
+                ..Default::default()
+            }],
+            keyboards: vec![KeyboardDisplayInfo {
+                name: "keyboard \"quoted\"".to_string(),
+                manufacturer: Some("Maker".to_string()),
+                ..Default::default()
+            }],
+            joysticks: vec![JoystickDisplayInfo {
+                name: "joystick".to_string(),
+                product: Some("Stick".to_string()),
+                ids: ids(0x045e),
+                stable_id: StableDeviceId(u64::MAX),
+                capabilities: JoystickCapabilities {
+                    buttons: 2,
+                    axes: vec![Axis::X, Axis::SLIDER],
+                    hatswitch: true,
+                },
+                ..Default::default()
+            }],
+        }
+    }
+
+    fn sample() -> Capture {
+        let state = JoystickState {
+            button_states: vec![true, false],
+            axis_states: Axes {
+                x: Some(-0.25),
+                slider: Some(1.0),
+                ..Axes::new()
+            },
+            hatswitch: Some(HatSwitch::Center),
+            raw_axis_states: RawAxes {
+                x: 96,
+                slider: 255,
+                ..RawAxes::new()
+            },
+        };
+        let events = vec![
+            RawEvent::JoystickInitialStateEvent(0, state),
+            RawEvent::MouseButtonEvent(0, MouseButton::Right, State::Pressed),
+            RawEvent::MouseMoveEvent(0, -3, 7),
+            RawEvent::MouseWheelEvent(0, -120.0),
+            RawEvent::KeyboardEvent(0, KeyId::A, State::Released),
+            RawEvent::JoystickButtonEvent(0, 1, State::Pressed),
+            RawEvent::JoystickAxisEvent(0, Axis::SLIDER, 0.123456789),
+            RawEvent::JoystickHatSwitchEvent(0, HatSwitch::DownLeft),
+            RawEvent::GamepadButtonEvent(0, GamepadButton::South, State::Pressed),
+            RawEvent::GamepadAxisEvent(0, GamepadAxis::LeftY, -1.0),
+        ];
+        Capture {
+            devices: devices(),
+            events: events
+                .into_iter()
+                .enumerate()
+                .map(|(index, event)| CapturedEvent {
+                    time: Duration::from_micros(1500 * index as u64),
+                    event,
+                })
+                .collect(),
+            hid_descriptors: vec![HidDescriptor {
+                time: Duration::from_micros(0),
+                device: 0,
+                preparsed_data: vec![0x48, 0x69, 0x64, 0x50, 0x00, 0xff],
+                is_360_controller: true,
+            }],
+            hid_reports: vec![CapturedReport {
+                time: Duration::from_micros(2000),
+                device: 0,
+                report: vec![0x01, 0x80, 0x7f],
+            }],
+        }
+    }
+
+    fn round_trip(format: CaptureFormat) {
+        let capture = sample();
+        let mut bytes = Vec::new();
+        capture.write(&mut bytes, format).unwrap();
+        let reader = CaptureReader::new(&bytes[..]).unwrap();
+        assert_eq!(reader.format(), format);
+        assert_eq!(reader.version(), CAPTURE_VERSION);
+        assert_eq!(reader.devices(), &capture.devices);
+        assert_eq!(Capture::read(&bytes[..]).unwrap(), capture);
+        // Records come out in time order, events after reports at equal times
+        let events: Vec<CapturedEvent> = CaptureReader::new(&bytes[..])
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(events, capture.events);
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        round_trip(CaptureFormat::Binary);
+    }
+
+    #[test]
+    fn json_lines_round_trip() {
+        round_trip(CaptureFormat::JsonLines);
+    }
+
+    #[test]
+    fn json_lines_records() {
+        let mut bytes = Vec::new();
+        let mut writer =
+            CaptureWriter::new(&mut bytes, CaptureFormat::JsonLines, &Default::default()).unwrap();
+        writer
+            .write_event(&CapturedEvent {
+                time: Duration::from_micros(1500),
+                event: RawEvent::KeyboardEvent(0, KeyId::A, State::Pressed),
+            })
+            .unwrap();
+        writer.flush().unwrap();
+        let text = String::from_utf8(bytes).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[1],
+            r#"{"time_us":1500,"type":"KeyboardEvent","device":0,"key":"A","state":"Pressed"}"#
+        );
+    }
+
+    #[test]
+    fn unsupported_captures() {
+        let mut bytes = Vec::new();
+        CaptureWriter::new(&mut bytes, CaptureFormat::Binary, &devices()).unwrap();
+        bytes[4] = CAPTURE_VERSION as u8 + 1;
+        assert_eq!(
+            CaptureReader::new(&bytes[..]).err(),
+            Some(CaptureError(format!(
+                "Unsupported capture version {} (latest is {})",
+                CAPTURE_VERSION + 1,
+                CAPTURE_VERSION
+            )))
+        );
+        assert_eq!(
+            CaptureReader::new(&b"MICX"[..]).err(),
+            Some(CaptureError("Invalid capture: unknown format".to_string()))
+        );
+        assert_eq!(
+            CaptureReader::new(&b"{\"format\":\"other\"}\n"[..]).err(),
+            Some(CaptureError("Invalid capture: unknown format".to_string()))
+        );
+        assert!(Capture::read(&b"{\"format\":"[..]).is_err());
+    }
+
+    #[test]
+    fn out_of_range_header_values() {
+        let mut bytes = Vec::new();
+        CaptureWriter::new(&mut bytes, CaptureFormat::JsonLines, &devices()).unwrap();
+        let header = String::from_utf8(bytes).unwrap();
+        let replacements = [
+            ("\"version\":2", "\"version\":65538", "version"),
+            ("\"vendor_id\":1133", "\"vendor_id\":66669", "vendor_id"),
+            ("\"usage_page\":1,", "\"usage_page\":65537,", "usage_page"),
+            ("\"usage\":4,", "\"usage\":65540,", "usage"),
+        ];
+        for &(value, out_of_range, key) in replacements.iter() {
+            assert!(header.contains(value));
+            let header = header.replacen(value, out_of_range, 1);
+            assert_eq!(
+                CaptureReader::new(header.as_bytes()).err(),
+                Some(CaptureError(format!("Invalid capture: bad {}", key)))
+            );
+        }
+    }
+
+    #[test]
+    fn recording_timestamps_events_when_read() {
+        let backend = MockBackend::new();
+        let handle = backend.handle();
+        handle.set_devices(devices());
+        let mut manager = RawInputManager::with_backend(
+            move || backend,
+            DEFAULT_CAPACITY,
+            OverflowPolicy::DropOldest,
+        );
+        let recording = manager
+            .start_recording(Vec::new(), CaptureFormat::Binary)
+            .unwrap();
+        let first = RawEvent::KeyboardEvent(0, KeyId::A, State::Pressed);
+        let second = RawEvent::KeyboardEvent(0, KeyId::A, State::Released);
+        handle.push_event(first.clone());
+        assert!(manager.wait_event_timeout(Duration::from_secs(5)).is_some());
+        thread::sleep(Duration::from_millis(20));
+        handle.push_event(second.clone());
+        assert!(manager.wait_event_timeout(Duration::from_secs(5)).is_some());
+        let devices = manager.get_device_list();
+        let capture = Capture::read(&recording.stop().unwrap()[..]).unwrap();
+        assert_eq!(capture.devices, devices);
+        assert_eq!(capture.events.len(), 2);
+        assert_eq!(capture.events[0].event, first);
+        assert_eq!(capture.events[1].event, second);
+        assert!(capture.events[1].time - capture.events[0].time >= Duration::from_millis(20));
+    }
+}
@@ -0,0 +1,508 @@
+//! Filter expressions selecting which devices and events are reported
+//!
+//! ```
+//! # use multiinput::*;
+//! // Events from every keyboard but the built-in one, and button presses from Xbox pads
+//! let filter = DeviceFilter::DeviceType(DeviceType::Keyboards)
+//!     .and(!DeviceFilter::NameContains("internal".to_string()))
+//!     .or(DeviceFilter::VendorId(0x045E).and(DeviceFilter::EventKind(EventKind::JoystickButton)));
+//! ```
+//!
+//! Filters are applied by `RawInputManager::set_device_filter` on top of `filter_devices`.
+
+use devices::{DeviceIds, DevicesDisplayInfo};
+use event::{EventKind, RawEvent};
+use manager::{DeviceType, XInputInclude};
+#[cfg(feature = "regex")]
+use regex::Regex;
+#[cfg(feature = "serde")]
+use serde::de::Error;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashSet;
+use std::fmt;
+use std::ops::Not;
+
+/// Identifier of a device that stays the same across registrations and runs
+///
+/// When the device has a serial it is derived from the vendor id, product id,
+/// serial number, usage and the interface and collection in its name (the
+/// `MI_xx` and `COLxx` parts of the device path), so each top level collection
+/// of a composite device gets its own id. Otherwise it is derived from the name.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StableDeviceId(pub u64);
+
+impl StableDeviceId {
+    pub fn new(name: &str, serial: Option<&str>, ids: &DeviceIds) -> StableDeviceId {
+        let key = match serial {
+            Some(serial) if !serial.is_empty() => format!(
+                "{:04x}:{:04x}:{}:{:04x}:{:04x}:{}",
+                ids.vendor_id.unwrap_or(0),
+                ids.product_id.unwrap_or(0),
+                serial,
+                ids.usage_page,
+                ids.usage,
+                path_interface(name)
+            ),
+            _ => name.to_uppercase(),
+        };
+        // FNV-1a, fixed so ids can be stored
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for byte in key.bytes() {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+        StableDeviceId(hash)
+    }
+}
+
+/// Interface and collection parts of a device path, e.g. `MI_01&COL02`
+fn path_interface(path: &str) -> String {
+    path.to_uppercase()
+        .split(['#', '&', '\\'])
+        .filter(|part| {
+            part.starts_with("MI_")
+                || (part.starts_with("COL")
+                    && part.len() > 3
+                    && part[3..].chars().all(|c| c.is_ascii_hexdigit()))
+        })
+        .collect::<Vec<&str>>()
+        .join("&")
+}
+
+impl fmt::Display for StableDeviceId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// Serialized as its hex string, like in captures
+#[cfg(feature = "serde")]
+impl Serialize for StableDeviceId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for StableDeviceId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<StableDeviceId, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        u64::from_str_radix(&hex, 16)
+            .map(StableDeviceId)
+            .map_err(|_| D::Error::custom(format!("invalid stable device id {}", hex)))
+    }
+}
+
+/// Whether the events of a device are reported
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum DeviceStatus {
+    #[default]
+    Active,
+    /// Excluded by `filter_devices` or the device filter
+    Filtered,
+}
+
+/// Device properties a `DeviceFilter` is evaluated against
+#[derive(Clone, Debug)]
+pub struct DeviceDescription<'a> {
+    pub device_type: DeviceType,
+    pub name: &'a str,
+    pub serial: Option<&'a str>,
+    pub ids: &'a DeviceIds,
+    pub stable_id: StableDeviceId,
+}
+
+/// Filter expression over devices and event kinds
+///
+/// Joysticks match `DeviceType(DeviceType::Joysticks(_))` whatever their
+/// XInputInclude setting.
+#[derive(Clone, Debug)]
+pub enum DeviceFilter {
+    All,
+    DeviceType(DeviceType),
+    VendorId(u16),
+    ProductId(u16),
+    /// Exact device name
+    Name(String),
+    /// Case insensitive part of the device name
+    NameContains(String),
+    /// Regular expression matched against the device name
+    #[cfg(feature = "regex")]
+    NameRegex(Regex),
+    Serial(String),
+    StableId(StableDeviceId),
+    EventKind(EventKind),
+    And(Vec<DeviceFilter>),
+    Or(Vec<DeviceFilter>),
+    Not(Box<DeviceFilter>),
+}
+
+fn same_device_type(a: &DeviceType, b: &DeviceType) -> bool {
+    match (a, b) {
+        (&DeviceType::Joysticks(_), &DeviceType::Joysticks(_)) => true,
+        _ => a == b,
+    }
+}
+
+fn device_type_kinds(device_type: &DeviceType) -> &'static [EventKind] {
+    match *device_type {
+        DeviceType::Mice => &[
+            EventKind::MouseButton,
+            EventKind::MouseMove,
+            EventKind::MouseWheel,
+        ],
+        DeviceType::Keyboards => &[EventKind::Keyboard],
+        DeviceType::Joysticks(_) => &[
+            EventKind::JoystickButton,
+            EventKind::JoystickAxis,
+            EventKind::JoystickHatSwitch,
+            EventKind::JoystickInitialState,
+            EventKind::GamepadButton,
+            EventKind::GamepadAxis,
+        ],
+    }
+}
+
+impl DeviceFilter {
+    /// Matches device names against a regular expression
+    #[cfg(feature = "regex")]
+    pub fn name_regex(pattern: &str) -> Result<DeviceFilter, ::regex::Error> {
+        Regex::new(pattern).map(DeviceFilter::NameRegex)
+    }
+
+    pub fn and(self, other: DeviceFilter) -> DeviceFilter {
+        match self {
+            DeviceFilter::And(mut filters) => {
+                filters.push(other);
+                DeviceFilter::And(filters)
+            }
+            filter => DeviceFilter::And(vec![filter, other]),
+        }
+    }
+
+    pub fn or(self, other: DeviceFilter) -> DeviceFilter {
+        match self {
+            DeviceFilter::Or(mut filters) => {
+                filters.push(other);
+                DeviceFilter::Or(filters)
+            }
+            filter => DeviceFilter::Or(vec![filter, other]),
+        }
+    }
+
+    /// True if events of the given kind from the device pass the filter
+    pub fn matches(&self, device: &DeviceDescription, kind: EventKind) -> bool {
+        match *self {
+            DeviceFilter::All => true,
+            DeviceFilter::DeviceType(ref device_type) => {
+                same_device_type(device_type, &device.device_type)
+            }
+            DeviceFilter::VendorId(id) => device.ids.vendor_id == Some(id),
+            DeviceFilter::ProductId(id) => device.ids.product_id == Some(id),
+            DeviceFilter::Name(ref name) => device.name == name,
+            DeviceFilter::NameContains(ref part) => {
+                device.name.to_lowercase().contains(&part.to_lowercase())
+            }
+            #[cfg(feature = "regex")]
+            DeviceFilter::NameRegex(ref regex) => regex.is_match(device.name),
+            DeviceFilter::Serial(ref serial) => device.serial == Some(serial.as_str()),
+            DeviceFilter::StableId(id) => device.stable_id == id,
+            DeviceFilter::EventKind(event_kind) => event_kind == kind,
+            DeviceFilter::And(ref filters) => {
+                filters.iter().all(|filter| filter.matches(device, kind))
+            }
+            DeviceFilter::Or(ref filters) => {
+                filters.iter().any(|filter| filter.matches(device, kind))
+            }
+            DeviceFilter::Not(ref filter) => !filter.matches(device, kind),
+        }
+    }
+
+    /// True if any events of the device pass the filter
+    pub fn matches_device(&self, device: &DeviceDescription) -> bool {
+        device_type_kinds(&device.device_type)
+            .iter()
+            .any(|&kind| self.matches(device, kind))
+    }
+}
+
+impl Not for DeviceFilter {
+    type Output = DeviceFilter;
+
+    fn not(self) -> DeviceFilter {
+        match self {
+            DeviceFilter::Not(filter) => *filter,
+            filter => DeviceFilter::Not(Box::new(filter)),
+        }
+    }
+}
+
+impl DevicesDisplayInfo {
+    /// Describes the device of the given type and id
+    pub fn describe(&self, device_type: &DeviceType, id: usize) -> Option<DeviceDescription<'_>> {
+        let (name, serial, ids, stable_id) = match *device_type {
+            DeviceType::Mice => self
+                .mice
+                .get(id)
+                .map(|mouse| (&mouse.name, &mouse.serial, &mouse.ids, mouse.stable_id))?,
+            DeviceType::Keyboards => self.keyboards.get(id).map(|keyboard| {
+                (
+                    &keyboard.name,
+                    &keyboard.serial,
+                    &keyboard.ids,
+                    keyboard.stable_id,
+                )
+            })?,
+            DeviceType::Joysticks(_) => self.joysticks.get(id).map(|joystick| {
+                (
+                    &joystick.name,
+                    &joystick.serial,
+                    &joystick.ids,
+                    joystick.stable_id,
+                )
+            })?,
+        };
+        Some(DeviceDescription {
+            device_type: device_type.clone(),
+            name,
+            serial: serial.as_deref(),
+            ids,
+            stable_id,
+        })
+    }
+
+    /// Describes the device that sent an event
+    pub fn describe_event(&self, event: &RawEvent) -> Option<DeviceDescription<'_>> {
+        let device_type = [
+            DeviceType::Mice,
+            DeviceType::Keyboards,
+            DeviceType::Joysticks(XInputInclude::True),
+        ]
+        .iter()
+        .find(|device_type| event.is_from(device_type))?
+        .clone();
+        self.describe(&device_type, event.device_id())
+    }
+
+    /// Fills in the stable ids and the status of all devices
+    pub fn apply_filters(
+        &mut self,
+        names: Option<&HashSet<String>>,
+        filter: Option<&DeviceFilter>,
+    ) {
+        for mouse in self.mice.iter_mut() {
+            mouse.stable_id = StableDeviceId::new(&mouse.name, mouse.serial.as_deref(), &mouse.ids);
+        }
+        for keyboard in self.keyboards.iter_mut() {
+            keyboard.stable_id =
+                StableDeviceId::new(&keyboard.name, keyboard.serial.as_deref(), &keyboard.ids);
+        }
+        for joystick in self.joysticks.iter_mut() {
+            joystick.stable_id =
+                StableDeviceId::new(&joystick.name, joystick.serial.as_deref(), &joystick.ids);
+        }
+        let status = |device: Option<DeviceDescription>| {
+            let active = device.is_some_and(|device| {
+                names.is_none_or(|names| names.contains(device.name))
+                    && filter.is_none_or(|filter| filter.matches_device(&device))
+            });
+            if active {
+                DeviceStatus::Active
+            } else {
+                DeviceStatus::Filtered
+            }
+        };
+        for id in 0..self.mice.len() {
+            self.mice[id].status = status(self.describe(&DeviceType::Mice, id));
+        }
+        for id in 0..self.keyboards.len() {
+            self.keyboards[id].status = status(self.describe(&DeviceType::Keyboards, id));
+        }
+        for id in 0..self.joysticks.len() {
+            self.joysticks[id].status =
+                status(self.describe(&DeviceType::Joysticks(XInputInclude::True), id));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use devices::{JoystickDisplayInfo, KeyboardDisplayInfo, MouseDisplayInfo};
+    use event::{KeyId, State};
+
+    const PAD_PATH: &str = r"\\?\HID#VID_045E&PID_0B13&MI_01&Col02#8&1a2b3c&0&0001#{4d1e55b2-f16f-11cf-88cb-001111000030}";
+
+    fn ids(usage: u16) -> DeviceIds {
+        DeviceIds {
+            vendor_id: Some(0x045E),
+            product_id: Some(0x0B13),
+            version_number: None,
+            usage_page: 0x01,
+            usage,
+        }
+    }
+
+    fn devices() -> DevicesDisplayInfo {
+        let mut devices = DevicesDisplayInfo::default();
+        devices.mice.push(MouseDisplayInfo {
+            name: "Internal Touchpad".to_string(),
+            ..Default::default()
+        });
+        devices.keyboards.push(KeyboardDisplayInfo {
+            name: "USB Keyboard".to_string(),
+            serial: Some("KB1".to_string()),
+            ..Default::default()
+        });
+        devices.joysticks.push(JoystickDisplayInfo {
+            name: PAD_PATH.to_string(),
+            serial: Some("PAD1".to_string()),
+            ids: ids(0x05),
+            ..Default::default()
+        });
+        devices
+    }
+
+    #[test]
+    fn interface_and_collection_are_read_from_the_path() {
+        assert_eq!(path_interface(PAD_PATH), "MI_01&COL02");
+        assert_eq!(path_interface(r"\\?\HID#VID_046D&PID_C52B#7&0001"), "");
+        // Bluetooth style
+        assert_eq!(
+            path_interface(
+                r"\\?\HID#{00001124-0000-1000-8000-00805f9b34fb}_VID&0002045e_PID&0b13&Col01#9&0000"
+            ),
+            "COL01"
+        );
+    }
+
+    #[test]
+    fn serial_ids_survive_path_changes() {
+        let moved = PAD_PATH.replace("8&1a2b3c&0&0001", "8&99ffee&0&0002");
+        assert_eq!(
+            StableDeviceId::new(PAD_PATH, Some("PAD1"), &ids(0x05)),
+            StableDeviceId::new(&moved, Some("PAD1"), &ids(0x05))
+        );
+    }
+
+    #[test]
+    fn collections_of_a_serial_device_get_their_own_ids() {
+        let id = StableDeviceId::new(PAD_PATH, Some("PAD1"), &ids(0x05));
+        assert_ne!(id, StableDeviceId::new(PAD_PATH, Some("PAD1"), &ids(0x04)));
+        assert_ne!(
+            id,
+            StableDeviceId::new(
+                &PAD_PATH.replace("Col02", "Col01"),
+                Some("PAD1"),
+                &ids(0x05)
+            )
+        );
+        assert_ne!(
+            id,
+            StableDeviceId::new(
+                &PAD_PATH.replace("MI_01", "MI_00"),
+                Some("PAD1"),
+                &ids(0x05)
+            )
+        );
+        assert_ne!(id, StableDeviceId::new(PAD_PATH, Some("PAD2"), &ids(0x05)));
+    }
+
+    #[test]
+    fn ids_without_serial_come_from_the_name() {
+        let id = StableDeviceId::new("mouse path", None, &ids(0x02));
+        assert_eq!(
+            id,
+            StableDeviceId::new("MOUSE PATH", Some(""), &DeviceIds::default())
+        );
+        assert_ne!(id, StableDeviceId::new("other path", None, &ids(0x02)));
+        assert_eq!(StableDeviceId(0x1f).to_string(), "000000000000001f");
+    }
+
+    #[test]
+    fn filters_match_device_properties() {
+        let mut devices = devices();
+        devices.apply_filters(None, None);
+        let pad = devices
+            .describe(&DeviceType::Joysticks(XInputInclude::False), 0)
+            .unwrap();
+        let kind = EventKind::JoystickButton;
+        assert!(DeviceFilter::All.matches(&pad, kind));
+        assert!(
+            DeviceFilter::DeviceType(DeviceType::Joysticks(XInputInclude::True))
+                .matches(&pad, kind)
+        );
+        assert!(!DeviceFilter::DeviceType(DeviceType::Mice).matches(&pad, kind));
+        assert!(DeviceFilter::VendorId(0x045E).matches(&pad, kind));
+        assert!(!DeviceFilter::ProductId(0x028E).matches(&pad, kind));
+        assert!(DeviceFilter::Name(PAD_PATH.to_string()).matches(&pad, kind));
+        assert!(DeviceFilter::NameContains("vid_045e".to_string()).matches(&pad, kind));
+        assert!(DeviceFilter::Serial("PAD1".to_string()).matches(&pad, kind));
+        assert!(DeviceFilter::StableId(devices.joysticks[0].stable_id).matches(&pad, kind));
+        assert!(DeviceFilter::EventKind(kind).matches(&pad, kind));
+        assert!(!DeviceFilter::EventKind(kind).matches(&pad, EventKind::JoystickAxis));
+    }
+
+    #[test]
+    fn filters_combine() {
+        let devices = devices();
+        let mouse = devices.describe(&DeviceType::Mice, 0).unwrap();
+        let keyboard = devices.describe(&DeviceType::Keyboards, 0).unwrap();
+        let filter = DeviceFilter::DeviceType(DeviceType::Keyboards)
+            .or(DeviceFilter::DeviceType(DeviceType::Mice)
+                .and(!DeviceFilter::NameContains("internal".to_string())));
+        assert!(filter.matches(&keyboard, EventKind::Keyboard));
+        assert!(!filter.matches(&mouse, EventKind::MouseMove));
+        // Double negation is removed
+        assert!(matches!(!!DeviceFilter::All, DeviceFilter::All));
+        // Devices match if any of their event kinds can pass
+        let moves = DeviceFilter::EventKind(EventKind::MouseMove);
+        assert!(moves.matches_device(&mouse));
+        assert!(!moves.matches_device(&keyboard));
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn filters_match_name_regexes() {
+        let devices = devices();
+        let keyboard = devices.describe(&DeviceType::Keyboards, 0).unwrap();
+        assert!(DeviceFilter::name_regex("^USB")
+            .unwrap()
+            .matches(&keyboard, EventKind::Keyboard));
+        assert!(DeviceFilter::name_regex("(").is_err());
+    }
+
+    #[test]
+    fn applying_filters_sets_ids_and_status() {
+        let mut devices = devices();
+        let names: HashSet<String> = ["USB Keyboard".to_string(), PAD_PATH.to_string()]
+            .iter()
+            .cloned()
+            .collect();
+        let filter = !DeviceFilter::DeviceType(DeviceType::Keyboards);
+        devices.apply_filters(Some(&names), Some(&filter));
+        // Filtered by name
+        assert_eq!(devices.mice[0].status, DeviceStatus::Filtered);
+        // Filtered by the filter
+        assert_eq!(devices.keyboards[0].status, DeviceStatus::Filtered);
+        assert_eq!(devices.joysticks[0].status, DeviceStatus::Active);
+        assert_eq!(
+            devices.keyboards[0].stable_id,
+            StableDeviceId::new("USB Keyboard", Some("KB1"), &DeviceIds::default())
+        );
+        devices.apply_filters(None, None);
+        assert_eq!(devices.mice[0].status, DeviceStatus::Active);
+        assert_eq!(devices.keyboards[0].status, DeviceStatus::Active);
+    }
+
+    #[test]
+    fn events_are_described_by_their_device() {
+        let devices = devices();
+        let event = RawEvent::KeyboardEvent(0, KeyId::A, State::Pressed);
+        assert_eq!(devices.describe_event(&event).unwrap().name, "USB Keyboard");
+        let event = RawEvent::KeyboardEvent(1, KeyId::A, State::Pressed);
+        assert!(devices.describe_event(&event).is_none());
+    }
+}
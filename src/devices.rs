@@ -1,81 +1,193 @@
+use device_filter::{DeviceStatus, StableDeviceId};
+use event::{Axis, JOYSTICK_AXES};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(windows)]
 use std::collections::HashSet;
+#[cfg(windows)]
 use std::collections::HashMap;
+#[cfg(windows)]
 use std::fmt;
+#[cfg(windows)]
+use gamepad::{GamepadMappingDb, GamepadMapping, GamepadState};
+#[cfg(windows)]
+use xinput::XINPUT_GAMEPAD_MAPPING;
+#[cfg(windows)]
 use winapi::shared::hidpi::{HIDP_BUTTON_CAPS, HIDP_CAPS, HIDP_VALUE_CAPS};
+#[cfg(windows)]
 use winapi::um::winnt::HANDLE;
+#[cfg(windows)]
 use winapi::um::winuser::RID_DEVICE_INFO;
 
+/// Hardware identifiers of a device
+///
+/// Vendor and product ids come from the HID attributes when available and are
+/// otherwise parsed from the device path (see `parse_device_path_ids`).
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DeviceIds {
+    pub vendor_id: Option<u16>,
+    pub product_id: Option<u16>,
+    pub version_number: Option<u16>,
+    pub usage_page: u16,
+    pub usage: u16,
+}
+
+/// Extracts the vendor and product ids embedded in a device path
+///
+/// Handles USB style paths (`\\?\HID#VID_045E&PID_028E&IG_00#...`) and
+/// Bluetooth style paths (`...}_VID&0002045e_PID&0b13&Col01#...`).
+pub fn parse_device_path_ids(path: &str) -> (Option<u16>, Option<u16>) {
+    let path = path.to_uppercase();
+    (find_path_id(&path, "VID"), find_path_id(&path, "PID"))
+}
+
+fn find_path_id(path: &str, key: &str) -> Option<u16> {
+    let mut search = path;
+    while let Some(pos) = search.find(key) {
+        let rest = &search[pos + key.len()..];
+        let digits = match rest.chars().next() {
+            Some('_') => hex_prefix(&rest[1..], 4),
+            // Bluetooth ids are prefixed by the 16 bit id source
+            Some('&') => hex_prefix(&rest[1..], 8).map(|d| &d[d.len() - 4..]),
+            _ => None,
+        };
+        if let Some(id) = digits.and_then(|d| u16::from_str_radix(d, 16).ok()) {
+            return Some(id);
+        }
+        search = rest;
+    }
+    None
+}
+
+fn hex_prefix(string: &str, max_length: usize) -> Option<&str> {
+    let length = string
+        .chars()
+        .take(max_length)
+        .take_while(|c| c.is_ascii_hexdigit())
+        .count();
+    if length >= 4 {
+        Some(&string[..length])
+    } else {
+        None
+    }
+}
+
+#[cfg(windows)]
 #[derive(Clone)]
 pub struct MouseInfo {
     pub name: String,
     pub handle: HANDLE,
     pub serial: Option<String>,
+    pub product: Option<String>,
+    pub manufacturer: Option<String>,
+    pub ids: DeviceIds,
     pub info: RID_DEVICE_INFO,
 }
 
+#[cfg(windows)]
 impl fmt::Debug for MouseInfo {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Mouse Info")
             .field("name", &self.name)
             .field("handle", &self.handle)
             .field("serial", &self.serial)
+            .field("product", &self.product)
+            .field("manufacturer", &self.manufacturer)
+            .field("ids", &self.ids)
             .finish()
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct MouseDisplayInfo {
     pub name: String,
     pub serial: Option<String>,
+    pub product: Option<String>,
+    pub manufacturer: Option<String>,
+    pub ids: DeviceIds,
+    pub stable_id: StableDeviceId,
+    /// Filled in by `RawInputManager::get_device_list`
+    pub status: DeviceStatus,
 }
 
+#[cfg(windows)]
 impl From<MouseInfo> for MouseDisplayInfo {
     fn from(mouse: MouseInfo) -> Self {
         Self {
             name: mouse.name,
-            serial: mouse.serial
+            serial: mouse.serial,
+            product: mouse.product,
+            manufacturer: mouse.manufacturer,
+            ids: mouse.ids,
+            ..Default::default()
         }
     }
 }
 
+#[cfg(windows)]
 #[derive(Clone)]
 pub struct KeyboardInfo {
     pub name: String,
     pub handle: HANDLE,
     pub serial: Option<String>,
+    pub product: Option<String>,
+    pub manufacturer: Option<String>,
+    pub ids: DeviceIds,
     pub info: RID_DEVICE_INFO,
 }
 
+#[cfg(windows)]
 impl fmt::Debug for KeyboardInfo {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Keyboard Info")
             .field("name", &self.name)
             .field("handle", &self.handle)
             .field("serial", &self.serial)
+            .field("product", &self.product)
+            .field("manufacturer", &self.manufacturer)
+            .field("ids", &self.ids)
             .finish()
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct KeyboardDisplayInfo {
     pub name: String,
     pub serial: Option<String>,
+    pub product: Option<String>,
+    pub manufacturer: Option<String>,
+    pub ids: DeviceIds,
+    pub stable_id: StableDeviceId,
+    /// Filled in by `RawInputManager::get_device_list`
+    pub status: DeviceStatus,
 }
 
+#[cfg(windows)]
 impl From<KeyboardInfo> for KeyboardDisplayInfo {
     fn from(keyboard: KeyboardInfo) -> Self {
         Self {
             name: keyboard.name,
-            serial: keyboard.serial
+            serial: keyboard.serial,
+            product: keyboard.product,
+            manufacturer: keyboard.manufacturer,
+            ids: keyboard.ids,
+            ..Default::default()
         }
     }
 }
 
+#[cfg(windows)]
 #[derive(Clone)]
 pub struct JoystickInfo {
     pub name: String,
     pub handle: HANDLE,
     pub serial: Option<String>,
+    pub product: Option<String>,
+    pub manufacturer: Option<String>,
+    pub ids: DeviceIds,
     pub info: RID_DEVICE_INFO,
     pub caps: HIDP_CAPS,
     pub button_caps: Vec<HIDP_BUTTON_CAPS>,
@@ -83,34 +195,89 @@ pub struct JoystickInfo {
     pub preparsed_data: Vec<u8>,
     pub state: JoystickState,
     pub is_360_controller: bool,
+    /// Set once the first report has been received and the initial state sent
+    pub has_reported: bool,
+    /// Mapping to the standard gamepad layout, if gamepad mappings are in use
+    pub gamepad_mapping: Option<GamepadMapping>,
+    pub gamepad_state: GamepadState,
+    /// XInput user slot for controllers read through the XInput API
+    /// (their rawinput specific fields are zeroed)
+    pub xinput_index: Option<u32>,
 }
 
+#[cfg(windows)]
 impl fmt::Debug for JoystickInfo {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Joystick Info")
             .field("name", &self.name)
             .field("handle", &self.handle)
             .field("serial", &self.serial)
+            .field("product", &self.product)
+            .field("manufacturer", &self.manufacturer)
+            .field("ids", &self.ids)
             .field("360 Controller?", &self.is_360_controller)
+            .field("xinput_index", &self.xinput_index)
             .finish()
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct JoystickDisplayInfo {
     pub name: String,
     pub serial: Option<String>,
+    pub product: Option<String>,
+    pub manufacturer: Option<String>,
+    pub ids: DeviceIds,
+    pub stable_id: StableDeviceId,
+    /// Filled in by `RawInputManager::get_device_list`
+    pub status: DeviceStatus,
+    pub capabilities: JoystickCapabilities,
 }
 
+/// Controls reported by a joystick
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct JoystickCapabilities {
+    pub buttons: usize,
+    pub axes: Vec<Axis>,
+    pub hatswitch: bool,
+}
+
+impl JoystickCapabilities {
+    /// Controls present in a joystick state
+    pub fn from_state(state: &JoystickState) -> JoystickCapabilities {
+        let axes = &state.axis_states;
+        let values = [axes.x, axes.y, axes.z, axes.rx, axes.ry, axes.rz, axes.slider];
+        JoystickCapabilities {
+            buttons: state.button_states.len(),
+            axes: JOYSTICK_AXES
+                .iter()
+                .zip(values.iter())
+                .filter(|&(_, value)| value.is_some())
+                .map(|(axis, _)| axis.clone())
+                .collect(),
+            hatswitch: state.hatswitch.is_some(),
+        }
+    }
+}
+
+#[cfg(windows)]
 impl From<JoystickInfo> for JoystickDisplayInfo {
     fn from(joystick: JoystickInfo) -> Self {
         Self {
+            capabilities: JoystickCapabilities::from_state(&joystick.state),
             name: joystick.name,
-            serial: joystick.serial
+            serial: joystick.serial,
+            product: joystick.product,
+            manufacturer: joystick.manufacturer,
+            ids: joystick.ids,
+            ..Default::default()
         }
     }
 }
 
+#[cfg(windows)]
 #[derive(Clone, Debug)]
 pub enum DeviceInfo {
     Mouse(MouseInfo),
@@ -118,6 +285,7 @@ pub enum DeviceInfo {
     Joystick(JoystickInfo),
 }
 
+#[cfg(windows)]
 /// Stores Names to All Raw Input Devices
 #[derive(Clone)]
 pub struct Devices {
@@ -126,8 +294,12 @@ pub struct Devices {
     pub joysticks: Vec<JoystickInfo>,
     pub device_map: HashMap<HANDLE, usize>,
     pub original_device_map: HashMap<HANDLE, usize>,
+    /// Maps XInput user slots to joystick positions
+    pub xinput_map: HashMap<u32, usize>,
+    pub original_xinput_map: HashMap<u32, usize>,
 }
 
+#[cfg(windows)]
 impl Devices {
     pub fn new() -> Devices {
         Devices {
@@ -136,13 +308,17 @@ impl Devices {
             joysticks: Vec::new(),
             device_map: HashMap::new(),
             original_device_map: HashMap::new(),
+            xinput_map: HashMap::new(),
+            original_xinput_map: HashMap::new(),
         }
     }
 }
 
+#[cfg(windows)]
 impl Devices {
     pub fn filter_device_map(&mut self, device_filter: HashSet<String>) {
         self.device_map = HashMap::new();
+        self.xinput_map = HashMap::new();
 
         for (pos, mouse) in self.mice.iter().enumerate() {
             if device_filter.contains(&mouse.name) {
@@ -156,24 +332,50 @@ impl Devices {
         }
         for (pos, joystick) in self.joysticks.iter().enumerate() {
             if device_filter.contains(&joystick.name) {
-                self.device_map.insert(joystick.handle, pos);
+                match joystick.xinput_index {
+                    Some(user_index) => self.xinput_map.insert(user_index, pos),
+                    None => self.device_map.insert(joystick.handle, pos),
+                };
             }
         }
     }
 
     pub fn reset_device_map(&mut self) {
         self.device_map = self.original_device_map.clone();
+        self.xinput_map = self.original_xinput_map.clone();
+    }
+
+    /// Assigns each joystick its gamepad mapping (or the fallback mapping)
+    pub fn apply_gamepad_mappings(&mut self, mappings: &GamepadMappingDb) {
+        for joystick in self.joysticks.iter_mut() {
+            let mapping = match (joystick.ids.vendor_id, joystick.ids.product_id) {
+                _ if joystick.xinput_index.is_some() => {
+                    GamepadMapping::parse(XINPUT_GAMEPAD_MAPPING).unwrap()
+                }
+                (Some(vendor_id), Some(product_id)) => {
+                    mappings.find_or_fallback(vendor_id, product_id).clone()
+                }
+                _ => GamepadMapping::fallback(),
+            };
+            // Before the first report the mapping is evaluated along with it
+            if joystick.has_reported {
+                joystick.gamepad_state = mapping.evaluate(&joystick.state);
+            }
+            joystick.gamepad_mapping = Some(mapping);
+        }
     }
 }
 
 /// Striped down version of devices fit for sharing across threads
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct DevicesDisplayInfo {
     pub mice: Vec<MouseDisplayInfo>,
     pub keyboards: Vec<KeyboardDisplayInfo>,
     pub joysticks: Vec<JoystickDisplayInfo>,
 }
 
+#[cfg(windows)]
 impl From<Devices> for DevicesDisplayInfo {
     fn from(devices: Devices) -> Self {
         Self {
@@ -184,7 +386,8 @@ impl From<Devices> for DevicesDisplayInfo {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct JoystickState {
     pub button_states: Vec<bool>,
     pub axis_states: Axes,
@@ -193,6 +396,7 @@ pub struct JoystickState {
 }
 
 impl JoystickState {
+    #[cfg(windows)]
     pub fn new(
         p_button_caps: Vec<HIDP_BUTTON_CAPS>,
         p_value_caps: Vec<HIDP_VALUE_CAPS>,
@@ -245,7 +449,8 @@ impl JoystickState {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Axes {
     pub x: Option<f64>,
     pub y: Option<f64>,
@@ -270,7 +475,8 @@ impl Axes {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct RawAxes {
     pub x: u32,
     pub y: u32,
@@ -296,6 +502,7 @@ impl RawAxes {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum HatSwitch {
     Center,
     Up,
@@ -307,3 +514,74 @@ pub enum HatSwitch {
     Left,
     UpLeft,
 }
+
+/// All hatswitch positions, in declaration order
+pub const HAT_SWITCHES: [HatSwitch; 9] = [
+    HatSwitch::Center,
+    HatSwitch::Up,
+    HatSwitch::UpRight,
+    HatSwitch::Right,
+    HatSwitch::DownRight,
+    HatSwitch::Down,
+    HatSwitch::DownLeft,
+    HatSwitch::Left,
+    HatSwitch::UpLeft,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn usb_path_ids() {
+        assert_eq!(
+            parse_device_path_ids(
+                r"\\?\HID#VID_045E&PID_028E&IG_00#3&1b9b6b8f&0&0000#{4d1e55b2-f16f-11cf-88cb-001111000030}"
+            ),
+            (Some(0x045e), Some(0x028e))
+        );
+    }
+
+    #[test]
+    fn bluetooth_path_ids() {
+        assert_eq!(
+            parse_device_path_ids(
+                r"\\?\HID#{00001124-0000-1000-8000-00805f9b34fb}_VID&0002045e_PID&0b13&Col01#8&2a6b4f0e&0&0000#{4d1e55b2-f16f-11cf-88cb-001111000030}"
+            ),
+            (Some(0x045e), Some(0x0b13))
+        );
+    }
+
+    #[test]
+    fn lowercase_path_ids() {
+        assert_eq!(
+            parse_device_path_ids(r"\\?\hid#vid_046d&pid_c52b&mi_01#7&1f2c3d4e&0&0000"),
+            (Some(0x046d), Some(0xc52b))
+        );
+    }
+
+    #[test]
+    fn paths_without_ids() {
+        assert_eq!(
+            parse_device_path_ids(
+                r"\\?\ACPI#PNP0303#4&1d401fb5&0#{884b96c3-56ef-11d1-bc8c-00a0c91405dd}"
+            ),
+            (None, None)
+        );
+        assert_eq!(parse_device_path_ids(""), (None, None));
+    }
+
+    #[test]
+    fn truncated_path_ids() {
+        assert_eq!(parse_device_path_ids(r"\\?\HID#VID_04"), (None, None));
+        assert_eq!(
+            parse_device_path_ids(r"\\?\HID#VID_04&PID_028E"),
+            (None, Some(0x028e))
+        );
+        // A truncated id is skipped for a later complete one
+        assert_eq!(
+            parse_device_path_ids(r"\\?\HID#VID_04#VID_045E"),
+            (Some(0x045e), None)
+        );
+    }
+}
@@ -0,0 +1,58 @@
+//! Writing events to Linux event nodes (shared by the LED and force feedback sinks)
+
+use libc;
+use std::io;
+use std::io::Write;
+use std::mem;
+
+/// struct input_event from linux/input.h
+#[repr(C)]
+pub(crate) struct InputEvent {
+    time: libc::timeval,
+    event_type: u16,
+    code: u16,
+    value: i32,
+}
+
+/// Writes a single event (with a zero timestamp, which the kernel fills in)
+pub(crate) fn write_input_event(
+    writer: &mut impl Write,
+    event_type: u16,
+    code: u16,
+    value: i32,
+) -> io::Result<()> {
+    let event = InputEvent {
+        time: libc::timeval {
+            tv_sec: 0,
+            tv_usec: 0,
+        },
+        event_type,
+        code,
+        value,
+    };
+    let bytes = unsafe {
+        ::std::slice::from_raw_parts(
+            &event as *const InputEvent as *const u8,
+            mem::size_of::<InputEvent>(),
+        )
+    };
+    writer.write_all(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_the_event_after_the_timestamp() {
+        let mut bytes = Vec::new();
+        write_input_event(&mut bytes, 0x15, 0x50, -2).unwrap();
+        let size = mem::size_of::<InputEvent>();
+        assert_eq!(bytes.len(), size);
+        let time_size = mem::size_of::<libc::timeval>();
+        assert!(bytes[..time_size].iter().all(|&byte| byte == 0));
+        assert_eq!(bytes[size - 8..size - 6], 0x15u16.to_ne_bytes());
+        assert_eq!(bytes[size - 6..size - 4], 0x50u16.to_ne_bytes());
+        assert_eq!(bytes[size - 4..], (-2i32).to_ne_bytes());
+    }
+}
@@ -1,7 +1,12 @@
 use devices::{HatSwitch, JoystickState};
+use gamepad::{GamepadAxis, GamepadButton};
+use manager::DeviceType;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 /// State of a Key or Button
 #[derive(Eq, PartialEq, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum State {
     Pressed,
     Released,
@@ -9,6 +14,7 @@ pub enum State {
 
 /// Key Identifier (UK Keyboard Layout)
 #[derive(Eq, PartialEq, Hash, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum KeyId {
     Escape,
     Return,
@@ -104,6 +110,7 @@ pub enum KeyId {
 
 /// Mouse Buttons
 #[derive(Eq, PartialEq, Hash, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum MouseButton {
     Left,
     Right,
@@ -113,6 +120,7 @@ pub enum MouseButton {
 }
 
 #[derive(Eq, PartialEq, Hash, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Axis {
     X,
     Y,
@@ -123,11 +131,126 @@ pub enum Axis {
     SLIDER,
 }
 
+/// All keys, in declaration order
+pub const KEY_IDS: [KeyId; 90] = [
+    KeyId::Escape,
+    KeyId::Return,
+    KeyId::Backspace,
+    KeyId::Left,
+    KeyId::Right,
+    KeyId::Up,
+    KeyId::Down,
+    KeyId::Space,
+    KeyId::A,
+    KeyId::B,
+    KeyId::C,
+    KeyId::D,
+    KeyId::E,
+    KeyId::F,
+    KeyId::G,
+    KeyId::H,
+    KeyId::I,
+    KeyId::J,
+    KeyId::K,
+    KeyId::L,
+    KeyId::M,
+    KeyId::N,
+    KeyId::O,
+    KeyId::P,
+    KeyId::Q,
+    KeyId::R,
+    KeyId::S,
+    KeyId::T,
+    KeyId::U,
+    KeyId::V,
+    KeyId::W,
+    KeyId::X,
+    KeyId::Y,
+    KeyId::Z,
+    KeyId::F1,
+    KeyId::F2,
+    KeyId::F3,
+    KeyId::F4,
+    KeyId::F5,
+    KeyId::F6,
+    KeyId::F7,
+    KeyId::F8,
+    KeyId::F9,
+    KeyId::F10,
+    KeyId::F11,
+    KeyId::F12,
+    KeyId::Zero,
+    KeyId::One,
+    KeyId::Two,
+    KeyId::Three,
+    KeyId::Four,
+    KeyId::Five,
+    KeyId::Six,
+    KeyId::Seven,
+    KeyId::Eight,
+    KeyId::Nine,
+    KeyId::Shift,
+    KeyId::LeftCtrl,
+    KeyId::RightCtrl,
+    KeyId::LeftAlt,
+    KeyId::RightAlt,
+    KeyId::CapsLock,
+    KeyId::Pause,
+    KeyId::PageUp,
+    KeyId::PageDown,
+    KeyId::PrintScreen,
+    KeyId::Insert,
+    KeyId::End,
+    KeyId::Home,
+    KeyId::Delete,
+    KeyId::Add,
+    KeyId::Subtract,
+    KeyId::Multiply,
+    KeyId::Separator,
+    KeyId::Decimal,
+    KeyId::Divide,
+    KeyId::BackTick,
+    KeyId::BackSlash,
+    KeyId::ForwardSlash,
+    KeyId::Plus,
+    KeyId::Minus,
+    KeyId::FullStop,
+    KeyId::Comma,
+    KeyId::Tab,
+    KeyId::Numlock,
+    KeyId::LeftSquareBracket,
+    KeyId::RightSquareBracket,
+    KeyId::SemiColon,
+    KeyId::Apostrophe,
+    KeyId::Hash,
+];
+
+/// All mouse buttons, in declaration order
+pub const MOUSE_BUTTONS: [MouseButton; 5] = [
+    MouseButton::Left,
+    MouseButton::Right,
+    MouseButton::Middle,
+    MouseButton::Button4,
+    MouseButton::Button5,
+];
+
+/// All joystick axes, in declaration order
+pub const JOYSTICK_AXES: [Axis; 7] = [
+    Axis::X,
+    Axis::Y,
+    Axis::Z,
+    Axis::RX,
+    Axis::RY,
+    Axis::RZ,
+    Axis::SLIDER,
+];
+
 /// Event types
 ///
 /// The usize entry acts as a device ID unique to each DeviceType (Mouse, Keyboard, Hid).
 /// Keyboard press events repeat when a key is held down.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum RawEvent {
     MouseButtonEvent(usize, MouseButton, State),
     MouseMoveEvent(usize, i32, i32),
@@ -136,9 +259,110 @@ pub enum RawEvent {
     JoystickButtonEvent(usize, usize, State),
     JoystickAxisEvent(usize, Axis, f64),
     JoystickHatSwitchEvent(usize, HatSwitch),
+    /// Complete state of a joystick, sent once per joystick after registration
+    /// using its first report. Later changes are sent as individual events.
+    JoystickInitialStateEvent(usize, JoystickState),
+    /// Joystick input translated through its gamepad mapping (see `RawInputManager::set_gamepad_mappings`)
+    GamepadButtonEvent(usize, GamepadButton, State),
+    GamepadAxisEvent(usize, GamepadAxis, f64),
+}
+
+/// Kind of a RawEvent, one per variant
+#[derive(Eq, PartialEq, Hash, Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum EventKind {
+    MouseButton,
+    MouseMove,
+    MouseWheel,
+    Keyboard,
+    JoystickButton,
+    JoystickAxis,
+    JoystickHatSwitch,
+    JoystickInitialState,
+    GamepadButton,
+    GamepadAxis,
+}
+
+/// All event kinds, in declaration order
+pub const EVENT_KINDS: [EventKind; 10] = [
+    EventKind::MouseButton,
+    EventKind::MouseMove,
+    EventKind::MouseWheel,
+    EventKind::Keyboard,
+    EventKind::JoystickButton,
+    EventKind::JoystickAxis,
+    EventKind::JoystickHatSwitch,
+    EventKind::JoystickInitialState,
+    EventKind::GamepadButton,
+    EventKind::GamepadAxis,
+];
+
+impl RawEvent {
+    pub fn kind(&self) -> EventKind {
+        match *self {
+            RawEvent::MouseButtonEvent(..) => EventKind::MouseButton,
+            RawEvent::MouseMoveEvent(..) => EventKind::MouseMove,
+            RawEvent::MouseWheelEvent(..) => EventKind::MouseWheel,
+            RawEvent::KeyboardEvent(..) => EventKind::Keyboard,
+            RawEvent::JoystickButtonEvent(..) => EventKind::JoystickButton,
+            RawEvent::JoystickAxisEvent(..) => EventKind::JoystickAxis,
+            RawEvent::JoystickHatSwitchEvent(..) => EventKind::JoystickHatSwitch,
+            RawEvent::JoystickInitialStateEvent(..) => EventKind::JoystickInitialState,
+            RawEvent::GamepadButtonEvent(..) => EventKind::GamepadButton,
+            RawEvent::GamepadAxisEvent(..) => EventKind::GamepadAxis,
+        }
+    }
+
+    /// ID of the device that sent the event
+    pub fn device_id(&self) -> usize {
+        match *self {
+            RawEvent::MouseButtonEvent(id, ..)
+            | RawEvent::MouseMoveEvent(id, ..)
+            | RawEvent::MouseWheelEvent(id, ..)
+            | RawEvent::KeyboardEvent(id, ..)
+            | RawEvent::JoystickButtonEvent(id, ..)
+            | RawEvent::JoystickAxisEvent(id, ..)
+            | RawEvent::JoystickHatSwitchEvent(id, ..)
+            | RawEvent::JoystickInitialStateEvent(id, ..)
+            | RawEvent::GamepadButtonEvent(id, ..)
+            | RawEvent::GamepadAxisEvent(id, ..) => id,
+        }
+    }
+
+    /// True if the event was sent by a device of the given type
+    /// (joysticks match regardless of their XInputInclude setting)
+    pub fn is_from(&self, device_type: &DeviceType) -> bool {
+        match (self.kind(), device_type) {
+            (EventKind::MouseButton, &DeviceType::Mice)
+            | (EventKind::MouseMove, &DeviceType::Mice)
+            | (EventKind::MouseWheel, &DeviceType::Mice)
+            | (EventKind::Keyboard, &DeviceType::Keyboards) => true,
+            (EventKind::MouseButton, _)
+            | (EventKind::MouseMove, _)
+            | (EventKind::MouseWheel, _)
+            | (EventKind::Keyboard, _) => false,
+            (_, &DeviceType::Joysticks(_)) => true,
+            _ => false,
+        }
+    }
 }
 
 impl JoystickState {
+    /// Produces the events for a newly decoded state, given the previous state
+    ///
+    /// The first report of a joystick (without a previous state) sends the whole
+    /// state as a `JoystickInitialStateEvent`, later reports only what changed.
+    pub fn report_events(previous: Option<&JoystickState>, state: &JoystickState, id: usize) -> Vec<RawEvent> {
+        match previous {
+            Some(previous) => previous.compare_states(state.clone(), id),
+            None => vec![RawEvent::JoystickInitialStateEvent(id, state.clone())],
+        }
+    }
+
+    /// Produces the events describing the change from this state to other_state
+    ///
+    /// Axes and the hatswitch are compared by their decoded values, so only
+    /// genuine changes are reported.
     pub fn compare_states(&self, other_state: JoystickState, id: usize) -> Vec<RawEvent> {
         let mut output: Vec<RawEvent> = Vec::new();
         for (index, (&press_state, _)) in self
@@ -158,48 +382,154 @@ impl JoystickState {
                 },
             ));
         }
-        if self.raw_axis_states.x != other_state.raw_axis_states.x {
+        if self.axis_states.x != other_state.axis_states.x {
             if let Some(value) = other_state.axis_states.x {
                 output.push(RawEvent::JoystickAxisEvent(id, Axis::X, value));
             }
         }
-        if self.raw_axis_states.y != other_state.raw_axis_states.y {
+        if self.axis_states.y != other_state.axis_states.y {
             if let Some(value) = other_state.axis_states.y {
                 output.push(RawEvent::JoystickAxisEvent(id, Axis::Y, value));
             }
         }
-        if self.raw_axis_states.z != other_state.raw_axis_states.z {
+        if self.axis_states.z != other_state.axis_states.z {
             if let Some(value) = other_state.axis_states.z {
                 output.push(RawEvent::JoystickAxisEvent(id, Axis::Z, value));
             }
         }
-        if self.raw_axis_states.rx != other_state.raw_axis_states.rx {
+        if self.axis_states.rx != other_state.axis_states.rx {
             if let Some(value) = other_state.axis_states.rx {
                 output.push(RawEvent::JoystickAxisEvent(id, Axis::RX, value));
             }
         }
-        if self.raw_axis_states.ry != other_state.raw_axis_states.ry {
+        if self.axis_states.ry != other_state.axis_states.ry {
             if let Some(value) = other_state.axis_states.ry {
                 output.push(RawEvent::JoystickAxisEvent(id, Axis::RY, value));
             }
         }
-        if self.raw_axis_states.rz != other_state.raw_axis_states.rz {
+        if self.axis_states.rz != other_state.axis_states.rz {
             if let Some(value) = other_state.axis_states.rz {
                 output.push(RawEvent::JoystickAxisEvent(id, Axis::RZ, value));
             }
         }
-        if self.raw_axis_states.slider != other_state.raw_axis_states.slider {
+        if self.axis_states.slider != other_state.axis_states.slider {
             if let Some(value) = other_state.axis_states.slider {
                 output.push(RawEvent::JoystickAxisEvent(id, Axis::SLIDER, value));
             }
         }
-        if let Some(value_other) = other_state.hatswitch {
-            if let Some(value_self) = self.hatswitch.clone() {
-                if value_self != value_other {
-                    output.push(RawEvent::JoystickHatSwitchEvent(id, value_other));
-                }
+        if self.hatswitch != other_state.hatswitch {
+            if let Some(value) = other_state.hatswitch {
+                output.push(RawEvent::JoystickHatSwitchEvent(id, value));
             }
         }
         output
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use devices::{Axes, RawAxes};
+
+    fn centred(buttons: usize) -> JoystickState {
+        JoystickState {
+            button_states: vec![false; buttons],
+            axis_states: Axes {
+                x: Some(0.0),
+                y: Some(0.0),
+                ..Axes::new()
+            },
+            hatswitch: Some(HatSwitch::Center),
+            raw_axis_states: RawAxes {
+                x: 32768,
+                y: 32768,
+                ..RawAxes::new()
+            },
+        }
+    }
+
+    #[test]
+    fn first_report_sends_initial_state() {
+        let state = centred(4);
+        let events = JoystickState::report_events(None, &state, 2);
+        assert_eq!(events.len(), 1);
+        match events[0] {
+            RawEvent::JoystickInitialStateEvent(2, ref initial) => {
+                assert_eq!(initial.button_states, state.button_states);
+                assert_eq!(initial.axis_states.x, Some(0.0));
+                assert_eq!(initial.hatswitch, Some(HatSwitch::Center));
+            }
+            ref event => panic!("{:?}", event),
+        }
+    }
+
+    #[test]
+    fn centred_report_sends_no_axis_events() {
+        let previous = centred(4);
+        let mut state = centred(4);
+        // Raw values jitter while the decoded values stay the same
+        state.raw_axis_states.x = 32769;
+        assert!(JoystickState::report_events(Some(&previous), &state, 0).is_empty());
+    }
+
+    #[test]
+    fn axis_changes_send_decoded_values() {
+        let previous = centred(0);
+        let mut state = centred(0);
+        state.axis_states.y = Some(-0.5);
+        // Axes the joystick does not have are never reported
+        state.axis_states.rz = None;
+        let events = JoystickState::report_events(Some(&previous), &state, 1);
+        assert_eq!(events.len(), 1);
+        match events[0] {
+            RawEvent::JoystickAxisEvent(1, Axis::Y, value) => assert_eq!(value, -0.5),
+            ref event => panic!("{:?}", event),
+        }
+    }
+
+    #[test]
+    fn hatswitch_changes() {
+        let mut previous = centred(0);
+        let mut state = centred(0);
+        state.hatswitch = Some(HatSwitch::UpLeft);
+        assert_eq!(
+            JoystickState::report_events(Some(&previous), &state, 0),
+            vec![RawEvent::JoystickHatSwitchEvent(0, HatSwitch::UpLeft)]
+        );
+        // Holding the hat sends nothing, returning it sends Center
+        assert!(JoystickState::report_events(Some(&state), &state, 0).is_empty());
+        assert_eq!(
+            JoystickState::report_events(Some(&state), &previous, 0),
+            vec![RawEvent::JoystickHatSwitchEvent(0, HatSwitch::Center)]
+        );
+        // A joystick without a hat never reports one
+        previous.hatswitch = None;
+        state.hatswitch = None;
+        assert!(JoystickState::report_events(Some(&previous), &state, 0).is_empty());
+    }
+
+    #[test]
+    fn button_presses_and_releases() {
+        let mut previous = centred(4);
+        previous.button_states[1] = true;
+        let mut state = centred(4);
+        state.button_states[0] = true;
+        state.button_states[3] = true;
+        assert_eq!(
+            JoystickState::report_events(Some(&previous), &state, 0),
+            vec![
+                RawEvent::JoystickButtonEvent(0, 0, State::Pressed),
+                RawEvent::JoystickButtonEvent(0, 1, State::Released),
+                RawEvent::JoystickButtonEvent(0, 3, State::Pressed),
+            ]
+        );
+    }
+
+    #[test]
+    fn buttons_missing_from_either_state_are_ignored() {
+        let previous = centred(2);
+        let mut state = centred(3);
+        state.button_states[2] = true;
+        assert!(JoystickState::report_events(Some(&previous), &state, 0).is_empty());
+    }
+}
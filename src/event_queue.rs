@@ -0,0 +1,378 @@
+//! Bounded queue of events shared between the input thread and the caller
+//!
+//! The input thread pushes events as soon as they are read, the caller pops them
+//! directly. When the queue is full the `OverflowPolicy` decides what is lost.
+
+use event::RawEvent;
+#[cfg(feature = "async")]
+use futures::Stream;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+#[cfg(feature = "async")]
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex};
+#[cfg(feature = "async")]
+use std::task::{Context, Poll, Waker};
+use std::time::Instant;
+
+/// Default number of events held before the overflow policy applies
+pub const DEFAULT_CAPACITY: usize = 4096;
+
+/// What to do with events arriving while the queue is full
+#[derive(Eq, PartialEq, Hash, Clone, Copy, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum OverflowPolicy {
+    /// Discard the oldest queued event to make room
+    #[default]
+    DropOldest,
+    /// Discard the arriving event
+    DropNewest,
+    /// Merge motion into the newest queued event if it is motion of the same
+    /// control: mouse movements are summed and axis events replace the queued
+    /// value, so events are never reordered. Other events make room by discarding
+    /// the oldest queued motion, or the oldest event if there is none.
+    CoalesceMotion,
+}
+
+struct QueueState {
+    events: VecDeque<RawEvent>,
+    dropped: usize,
+    closed: bool,
+    /// Tasks polling an `EventStream`, woken on the next push or close
+    #[cfg(feature = "async")]
+    wakers: Vec<Waker>,
+}
+
+impl QueueState {
+    #[cfg(feature = "async")]
+    fn wake_streams(&mut self) {
+        for waker in self.wakers.drain(..) {
+            waker.wake();
+        }
+    }
+
+    #[cfg(not(feature = "async"))]
+    fn wake_streams(&mut self) {}
+}
+
+/// Bounded multi-threaded event queue
+#[derive(Clone)]
+pub struct EventQueue {
+    shared: Arc<(Mutex<QueueState>, Condvar)>,
+    capacity: usize,
+    policy: OverflowPolicy,
+}
+
+fn is_motion(event: &RawEvent) -> bool {
+    matches!(
+        *event,
+        RawEvent::MouseMoveEvent(..) | RawEvent::JoystickAxisEvent(..) | RawEvent::GamepadAxisEvent(..)
+    )
+}
+
+/// Merges event into queued if both are motion of the same control, returning true on success
+fn merge_motion(queued: &mut RawEvent, event: &RawEvent) -> bool {
+    match (queued, event) {
+        (&mut RawEvent::MouseMoveEvent(id, ref mut x, ref mut y), &RawEvent::MouseMoveEvent(new_id, dx, dy))
+            if id == new_id =>
+        {
+            *x = x.saturating_add(dx);
+            *y = y.saturating_add(dy);
+            true
+        }
+        (
+            &mut RawEvent::JoystickAxisEvent(id, ref axis, ref mut value),
+            &RawEvent::JoystickAxisEvent(new_id, ref new_axis, new_value),
+        ) if id == new_id && axis == new_axis => {
+            *value = new_value;
+            true
+        }
+        (
+            &mut RawEvent::GamepadAxisEvent(id, ref axis, ref mut value),
+            &RawEvent::GamepadAxisEvent(new_id, ref new_axis, new_value),
+        ) if id == new_id && axis == new_axis => {
+            *value = new_value;
+            true
+        }
+        _ => false,
+    }
+}
+
+impl EventQueue {
+    pub fn new(capacity: usize, policy: OverflowPolicy) -> EventQueue {
+        EventQueue {
+            shared: Arc::new((
+                Mutex::new(QueueState {
+                    events: VecDeque::new(),
+                    dropped: 0,
+                    closed: false,
+                    #[cfg(feature = "async")]
+                    wakers: Vec::new(),
+                }),
+                Condvar::new(),
+            )),
+            capacity: capacity.max(1),
+            policy,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn policy(&self) -> OverflowPolicy {
+        self.policy
+    }
+
+    /// Adds an event, applying the overflow policy if the queue is full
+    pub fn push(&self, event: RawEvent) {
+        let (lock, condvar) = &*self.shared;
+        let mut state = lock.lock().unwrap();
+        if state.events.len() >= self.capacity {
+            match self.policy {
+                OverflowPolicy::DropOldest => {
+                    state.events.pop_front();
+                    state.dropped += 1;
+                }
+                OverflowPolicy::DropNewest => {
+                    state.dropped += 1;
+                    return;
+                }
+                OverflowPolicy::CoalesceMotion => {
+                    if is_motion(&event) {
+                        let merged = state
+                            .events
+                            .back_mut()
+                            .is_some_and(|queued| merge_motion(queued, &event));
+                        if merged {
+                            return;
+                        }
+                    }
+                    match state.events.iter().position(is_motion) {
+                        Some(index) => {
+                            state.events.remove(index);
+                        }
+                        None => {
+                            state.events.pop_front();
+                        }
+                    }
+                    state.dropped += 1;
+                }
+            }
+        }
+        state.events.push_back(event);
+        state.wake_streams();
+        condvar.notify_all();
+    }
+
+    /// Adds several events
+    pub fn extend<I: IntoIterator<Item = RawEvent>>(&self, events: I) {
+        for event in events {
+            self.push(event);
+        }
+    }
+
+    /// Takes the oldest event without blocking
+    pub fn pop(&self) -> Option<RawEvent> {
+        self.shared.0.lock().unwrap().events.pop_front()
+    }
+
+    /// Takes the oldest event, blocking until one arrives, the deadline passes
+    /// (if given) or the queue is closed
+    pub fn pop_until(&self, deadline: Option<Instant>) -> Option<RawEvent> {
+        let (lock, condvar) = &*self.shared;
+        let mut state = lock.lock().unwrap();
+        loop {
+            if let Some(event) = state.events.pop_front() {
+                return Some(event);
+            }
+            if state.closed {
+                return None;
+            }
+            state = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return None;
+                    }
+                    condvar.wait_timeout(state, deadline - now).unwrap().0
+                }
+                None => condvar.wait(state).unwrap(),
+            };
+        }
+    }
+
+    /// Takes all queued events
+    pub fn drain(&self) -> Vec<RawEvent> {
+        self.shared.0.lock().unwrap().events.drain(..).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.shared.0.lock().unwrap().events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of events lost to the overflow policy so far
+    pub fn dropped(&self) -> usize {
+        self.shared.0.lock().unwrap().dropped
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.shared.0.lock().unwrap().closed
+    }
+
+    /// Wakes all blocked callers, which return None once the queue is empty
+    pub fn close(&self) {
+        let (lock, condvar) = &*self.shared;
+        let mut state = lock.lock().unwrap();
+        state.closed = true;
+        state.wake_streams();
+        condvar.notify_all();
+    }
+
+    /// Stream taking events from this queue
+    #[cfg(feature = "async")]
+    pub fn stream(&self) -> EventStream {
+        EventStream { queue: self.clone() }
+    }
+}
+
+/// Stream of events taken from an `EventQueue`, ending once the queue is closed
+#[cfg(feature = "async")]
+#[derive(Clone)]
+pub struct EventStream {
+    queue: EventQueue,
+}
+
+#[cfg(feature = "async")]
+impl Stream for EventStream {
+    type Item = RawEvent;
+
+    fn poll_next(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Option<RawEvent>> {
+        let mut state = self.queue.shared.0.lock().unwrap();
+        if let Some(event) = state.events.pop_front() {
+            return Poll::Ready(Some(event));
+        }
+        if state.closed {
+            return Poll::Ready(None);
+        }
+        if !state.wakers.iter().any(|waker| waker.will_wake(context.waker())) {
+            state.wakers.push(context.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use event::{Axis, KeyId, State};
+
+    fn key(id: usize) -> RawEvent {
+        RawEvent::KeyboardEvent(id, KeyId::A, State::Pressed)
+    }
+
+    fn axis(axis: Axis, value: f64) -> RawEvent {
+        RawEvent::JoystickAxisEvent(0, axis, value)
+    }
+
+    #[test]
+    fn queue_keeps_order_below_capacity() {
+        for &policy in &[
+            OverflowPolicy::DropOldest,
+            OverflowPolicy::DropNewest,
+            OverflowPolicy::CoalesceMotion,
+        ] {
+            let queue = EventQueue::new(4, policy);
+            queue.extend(vec![
+                key(0),
+                RawEvent::MouseMoveEvent(0, 1, 1),
+                RawEvent::MouseMoveEvent(0, 2, 2),
+            ]);
+            assert_eq!(queue.len(), 3);
+            assert_eq!(queue.pop(), Some(key(0)));
+            assert_eq!(queue.drain().len(), 2);
+            assert_eq!(queue.dropped(), 0);
+        }
+    }
+
+    #[test]
+    fn drop_oldest() {
+        let queue = EventQueue::new(2, OverflowPolicy::DropOldest);
+        queue.extend((0..4).map(key));
+        assert_eq!(queue.drain(), vec![key(2), key(3)]);
+        assert_eq!(queue.dropped(), 2);
+    }
+
+    #[test]
+    fn drop_newest() {
+        let queue = EventQueue::new(2, OverflowPolicy::DropNewest);
+        queue.extend((0..4).map(key));
+        assert_eq!(queue.drain(), vec![key(0), key(1)]);
+        assert_eq!(queue.dropped(), 2);
+    }
+
+    #[test]
+    fn coalesce_merges_motion_into_the_tail() {
+        let queue = EventQueue::new(2, OverflowPolicy::CoalesceMotion);
+        queue.push(key(0));
+        queue.push(RawEvent::MouseMoveEvent(1, 1, -1));
+        queue.push(RawEvent::MouseMoveEvent(1, 2, 5));
+        queue.push(RawEvent::MouseMoveEvent(1, 3, 0));
+        assert_eq!(
+            queue.drain(),
+            vec![key(0), RawEvent::MouseMoveEvent(1, 6, 4)]
+        );
+        assert_eq!(queue.dropped(), 0);
+
+        queue.push(key(0));
+        queue.push(axis(Axis::X, 0.25));
+        queue.push(axis(Axis::X, -0.5));
+        assert_eq!(queue.drain(), vec![key(0), axis(Axis::X, -0.5)]);
+    }
+
+    #[test]
+    fn coalesce_does_not_reorder_motion() {
+        let queue = EventQueue::new(3, OverflowPolicy::CoalesceMotion);
+        queue.push(axis(Axis::X, 0.25));
+        queue.push(axis(Axis::Y, 0.5));
+        queue.push(key(0));
+        // The queued X motion is not the tail, so it is dropped instead of merged
+        queue.push(axis(Axis::X, 1.0));
+        assert_eq!(
+            queue.drain(),
+            vec![axis(Axis::Y, 0.5), key(0), axis(Axis::X, 1.0)]
+        );
+        assert_eq!(queue.dropped(), 1);
+    }
+
+    #[test]
+    fn coalesce_makes_room_from_motion_before_other_events() {
+        let queue = EventQueue::new(3, OverflowPolicy::CoalesceMotion);
+        queue.push(key(0));
+        queue.push(RawEvent::MouseMoveEvent(0, 1, 1));
+        queue.push(key(1));
+        queue.push(key(2));
+        assert_eq!(queue.drain(), vec![key(0), key(1), key(2)]);
+        assert_eq!(queue.dropped(), 1);
+
+        // Without queued motion the oldest event goes
+        queue.extend((0..4).map(key));
+        assert_eq!(queue.drain(), vec![key(1), key(2), key(3)]);
+        assert_eq!(queue.dropped(), 2);
+    }
+
+    #[test]
+    fn blocked_pops_end_when_closed() {
+        let queue = EventQueue::new(1, OverflowPolicy::DropOldest);
+        queue.push(key(0));
+        queue.close();
+        assert!(queue.is_closed());
+        assert_eq!(queue.pop_until(None), Some(key(0)));
+        assert_eq!(queue.pop_until(None), None);
+    }
+}
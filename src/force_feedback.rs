@@ -0,0 +1,1007 @@
+//! Rumble and force feedback effects
+//!
+//! Effects are scheduled per joystick by an `EffectScheduler`, which sends them
+//! to a `ForceFeedbackSink`:
+//!
+//! * `Rumble` effects are combined into a pair of motor strengths, sent whenever
+//!   they change
+//! * `Constant`, `Periodic`, `Spring` and `Damper` are played by the device
+//!   itself, so they need a sink that implements `play_effect` (e.g. the evdev
+//!   sink on Linux); rumble only devices refuse them
+//!
+//! The scheduler stops effects when their duration is over and scales every
+//! effect by its gain. Strengths and levels lie in [0, 1] (or [-1, 1] for signed
+//! levels).
+
+use event::Axis;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Minimum time between two motor updates of a scheduler
+pub const UPDATE_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Identifies an effect started on a joystick
+#[derive(Eq, PartialEq, Hash, Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct EffectId(pub usize);
+
+/// Shapes of periodic effects
+#[derive(Eq, PartialEq, Hash, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Waveform {
+    Sine,
+    Square,
+    Triangle,
+    SawtoothUp,
+    SawtoothDown,
+}
+
+/// Force feedback effects
+#[derive(PartialEq, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ForceFeedbackEffect {
+    /// Low and high frequency motor strengths
+    Rumble { low: f64, high: f64 },
+    /// Constant force with a signed level, positive pushing towards positive X
+    Constant { level: f64 },
+    /// Force oscillating around offset
+    Periodic {
+        waveform: Waveform,
+        magnitude: f64,
+        period: Duration,
+        offset: f64,
+    },
+    /// Force pulling the axis back towards center, proportional to its distance
+    Spring {
+        axis: Axis,
+        center: f64,
+        coefficient: f64,
+        deadband: f64,
+    },
+    /// Force resisting movement of the axis, proportional to its velocity
+    Damper { axis: Axis, coefficient: f64 },
+}
+
+impl ForceFeedbackEffect {
+    /// The effect with its strength multiplied by gain
+    pub fn scaled(&self, gain: f64) -> ForceFeedbackEffect {
+        let mut effect = self.clone();
+        match effect {
+            ForceFeedbackEffect::Rumble {
+                ref mut low,
+                ref mut high,
+            } => {
+                *low *= gain;
+                *high *= gain;
+            }
+            ForceFeedbackEffect::Constant { ref mut level } => *level *= gain,
+            ForceFeedbackEffect::Periodic {
+                ref mut magnitude,
+                ref mut offset,
+                ..
+            } => {
+                *magnitude *= gain;
+                *offset *= gain;
+            }
+            ForceFeedbackEffect::Spring {
+                ref mut coefficient,
+                ..
+            }
+            | ForceFeedbackEffect::Damper {
+                ref mut coefficient,
+                ..
+            } => *coefficient *= gain,
+        }
+        effect
+    }
+
+    fn is_rumble(&self) -> bool {
+        matches!(*self, ForceFeedbackEffect::Rumble { .. })
+    }
+}
+
+/// Error reported by a force feedback sink
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ForceFeedbackError(pub String);
+
+impl fmt::Display for ForceFeedbackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Force feedback failed: {}", self.0)
+    }
+}
+
+impl std::error::Error for ForceFeedbackError {}
+
+/// Output of force feedback to a device
+pub trait ForceFeedbackSink {
+    /// Sets the strength of the low and high frequency motors (both in [0, 1])
+    fn set_rumble(&mut self, low: f64, high: f64) -> Result<(), ForceFeedbackError>;
+
+    /// Starts playing an effect other than rumble on the device, or updates it
+    /// if an effect with the same id is playing
+    /// Rumble only devices keep this default, which refuses the effect.
+    fn play_effect(
+        &mut self,
+        _id: EffectId,
+        _effect: &ForceFeedbackEffect,
+    ) -> Result<(), ForceFeedbackError> {
+        Err(ForceFeedbackError(
+            "Device only supports rumble".to_string(),
+        ))
+    }
+
+    /// Stops an effect started with play_effect
+    fn stop_effect(&mut self, _id: EffectId) -> Result<(), ForceFeedbackError> {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+struct ScheduledEffect {
+    id: EffectId,
+    effect: ForceFeedbackEffect,
+    end: Option<Instant>,
+}
+
+/// Plays the effects of one joystick on its sink
+#[derive(Clone, Debug)]
+pub struct EffectScheduler {
+    effects: Vec<ScheduledEffect>,
+    gain: f64,
+    last_output: (f64, f64),
+    last_update: Option<Instant>,
+}
+
+impl EffectScheduler {
+    pub fn new() -> EffectScheduler {
+        EffectScheduler {
+            effects: Vec::new(),
+            gain: 1f64,
+            last_output: (0f64, 0f64),
+            last_update: None,
+        }
+    }
+
+    /// Starts an effect, replacing any effect with the same id
+    /// Effects without a duration play until stopped. Rumble is sent on the next update.
+    pub fn start(
+        &mut self,
+        id: EffectId,
+        effect: ForceFeedbackEffect,
+        duration: Option<Duration>,
+        now: Instant,
+        sink: &mut dyn ForceFeedbackSink,
+    ) -> Result<(), ForceFeedbackError> {
+        self.stop(id, sink)?;
+        if !effect.is_rumble() {
+            sink.play_effect(id, &effect.scaled(self.gain))?;
+        }
+        self.effects.push(ScheduledEffect {
+            id,
+            effect,
+            end: duration.map(|duration| now + duration),
+        });
+        self.last_update = None;
+        Ok(())
+    }
+
+    pub fn stop(
+        &mut self,
+        id: EffectId,
+        sink: &mut dyn ForceFeedbackSink,
+    ) -> Result<(), ForceFeedbackError> {
+        self.last_update = None;
+        match self.effects.iter().position(|effect| effect.id == id) {
+            Some(index) => stop_scheduled(self.effects.remove(index), sink),
+            None => Ok(()),
+        }
+    }
+
+    pub fn stop_all(&mut self, sink: &mut dyn ForceFeedbackSink) -> Result<(), ForceFeedbackError> {
+        self.last_update = None;
+        let mut result = Ok(());
+        for scheduled in self.effects.drain(..) {
+            result = result.and(stop_scheduled(scheduled, sink));
+        }
+        result
+    }
+
+    /// Scales all effects by gain (in [0, 1], 1 by default)
+    /// Effects played by the device are updated straight away.
+    pub fn set_gain(
+        &mut self,
+        gain: f64,
+        sink: &mut dyn ForceFeedbackSink,
+    ) -> Result<(), ForceFeedbackError> {
+        self.gain = gain.clamp(0f64, 1f64);
+        self.last_update = None;
+        for scheduled in &self.effects {
+            if !scheduled.effect.is_rumble() {
+                sink.play_effect(scheduled.id, &scheduled.effect.scaled(self.gain))?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn gain(&self) -> f64 {
+        self.gain
+    }
+
+    /// True while any effect is playing or the motors have not yet been stopped
+    pub fn is_active(&self) -> bool {
+        !self.effects.is_empty() || self.last_output != (0f64, 0f64)
+    }
+
+    /// Motor strengths of the rumble effects playing at the given time
+    pub fn output(&self, now: Instant) -> (f64, f64) {
+        let mut low = 0f64;
+        let mut high = 0f64;
+        for scheduled in &self.effects {
+            if scheduled.end.is_some_and(|end| now >= end) {
+                continue;
+            }
+            if let ForceFeedbackEffect::Rumble {
+                low: effect_low,
+                high: effect_high,
+            } = scheduled.effect
+            {
+                low += effect_low;
+                high += effect_high;
+            }
+        }
+        (
+            (low * self.gain).clamp(0f64, 1f64),
+            (high * self.gain).clamp(0f64, 1f64),
+        )
+    }
+
+    /// Stops finished effects and sends the current motor strengths to the sink if they changed
+    /// Updates closer together than UPDATE_INTERVAL are skipped.
+    pub fn update(
+        &mut self,
+        now: Instant,
+        sink: &mut dyn ForceFeedbackSink,
+    ) -> Result<(), ForceFeedbackError> {
+        if let Some(last_update) = self.last_update {
+            if now.duration_since(last_update) < UPDATE_INTERVAL {
+                return Ok(());
+            }
+        }
+        let (finished, playing) = self
+            .effects
+            .drain(..)
+            .partition(|effect| effect.end.is_some_and(|end| now >= end));
+        self.effects = playing;
+        let mut result = Ok(());
+        for scheduled in finished {
+            result = result.and(stop_scheduled(scheduled, sink));
+        }
+        let output = self.output(now);
+        self.last_update = Some(now);
+        if output != self.last_output {
+            sink.set_rumble(output.0, output.1)?;
+            self.last_output = output;
+        }
+        result
+    }
+}
+
+impl Default for EffectScheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn stop_scheduled(
+    scheduled: ScheduledEffect,
+    sink: &mut dyn ForceFeedbackSink,
+) -> Result<(), ForceFeedbackError> {
+    if scheduled.effect.is_rumble() {
+        Ok(())
+    } else {
+        sink.stop_effect(scheduled.id)
+    }
+}
+
+struct EffectPlayer {
+    scheduler: EffectScheduler,
+    sink: Box<dyn ForceFeedbackSink>,
+    // Set through set_output rather than opened from the backend
+    external: bool,
+}
+
+impl EffectPlayer {
+    fn new(sink: Box<dyn ForceFeedbackSink>, external: bool) -> EffectPlayer {
+        EffectPlayer {
+            scheduler: EffectScheduler::new(),
+            sink,
+            external,
+        }
+    }
+
+    fn silence(&mut self) {
+        let _ = self.scheduler.stop_all(self.sink.as_mut());
+        let _ = self.sink.set_rumble(0f64, 0f64);
+    }
+}
+
+/// Effect schedulers and outputs of every joystick with force feedback in use
+#[derive(Default)]
+pub struct EffectPlayers {
+    players: HashMap<usize, EffectPlayer>,
+}
+
+impl EffectPlayers {
+    pub fn new() -> EffectPlayers {
+        Self::default()
+    }
+
+    fn player<F>(
+        &mut self,
+        joystick: usize,
+        open_sink: F,
+    ) -> Result<&mut EffectPlayer, ForceFeedbackError>
+    where
+        F: FnOnce() -> Result<Box<dyn ForceFeedbackSink>, ForceFeedbackError>,
+    {
+        Ok(match self.players.entry(joystick) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(EffectPlayer::new(open_sink()?, false)),
+        })
+    }
+
+    /// Sends the effects of a joystick to sink instead of the output opened by the backend
+    /// Effects playing on the previous output are stopped.
+    pub fn set_output(&mut self, joystick: usize, sink: Box<dyn ForceFeedbackSink>) {
+        if let Some(mut player) = self.players.insert(joystick, EffectPlayer::new(sink, true)) {
+            player.silence();
+        }
+    }
+
+    /// Starts an effect on a joystick, opening its output with open_sink on first use
+    pub fn start<F>(
+        &mut self,
+        joystick: usize,
+        open_sink: F,
+        id: EffectId,
+        effect: ForceFeedbackEffect,
+        duration: Option<Duration>,
+    ) -> Result<(), ForceFeedbackError>
+    where
+        F: FnOnce() -> Result<Box<dyn ForceFeedbackSink>, ForceFeedbackError>,
+    {
+        let player = self.player(joystick, open_sink)?;
+        player
+            .scheduler
+            .start(id, effect, duration, Instant::now(), player.sink.as_mut())
+    }
+
+    /// Sets the gain of a joystick, opening its output with open_sink on first use
+    pub fn set_gain<F>(
+        &mut self,
+        joystick: usize,
+        open_sink: F,
+        gain: f64,
+    ) -> Result<(), ForceFeedbackError>
+    where
+        F: FnOnce() -> Result<Box<dyn ForceFeedbackSink>, ForceFeedbackError>,
+    {
+        let player = self.player(joystick, open_sink)?;
+        player.scheduler.set_gain(gain, player.sink.as_mut())
+    }
+
+    pub fn stop(&mut self, joystick: usize, id: EffectId) {
+        if let Some(player) = self.players.get_mut(&joystick) {
+            let _ = player.scheduler.stop(id, player.sink.as_mut());
+        }
+    }
+
+    pub fn stop_all(&mut self, joystick: usize) {
+        if let Some(player) = self.players.get_mut(&joystick) {
+            let _ = player.scheduler.stop_all(player.sink.as_mut());
+        }
+    }
+
+    /// True if any joystick still needs updates
+    pub fn is_active(&self) -> bool {
+        self.players
+            .values()
+            .any(|player| player.scheduler.is_active())
+    }
+
+    /// Updates the motors of every joystick
+    /// Effects on a joystick whose output fails are stopped.
+    pub fn update(&mut self, now: Instant) {
+        for player in self.players.values_mut() {
+            if player.scheduler.update(now, player.sink.as_mut()).is_err() {
+                let _ = player.scheduler.stop_all(player.sink.as_mut());
+            }
+        }
+    }
+
+    /// Stops all effects and closes the outputs opened by the backend (e.g. when
+    /// devices are re-registered)
+    pub fn clear(&mut self) {
+        for player in self.players.values_mut() {
+            player.silence();
+        }
+        self.players.retain(|_, player| player.external);
+        for player in self.players.values_mut() {
+            player.scheduler = EffectScheduler::new();
+        }
+    }
+}
+
+/// Force feedback through a Linux evdev event node
+///
+/// Rumble is played by a single FF_RUMBLE effect that is updated in place
+/// whenever the motor strengths change. Other effects are uploaded as the
+/// matching evdev effect (spring and damper only act on the X and Y axes).
+#[cfg(target_os = "linux")]
+pub mod evdev {
+    use super::{EffectId, ForceFeedbackEffect, ForceFeedbackError, ForceFeedbackSink, Waveform};
+    use event::Axis;
+    use evdev::write_input_event;
+    use libc;
+    use std::collections::HashMap;
+    use std::fs::File;
+    use std::mem;
+    use std::os::unix::io::AsRawFd;
+
+    const EV_FF: u16 = 0x15;
+    const FF_RUMBLE: u16 = 0x50;
+    const FF_PERIODIC: u16 = 0x51;
+    const FF_CONSTANT: u16 = 0x52;
+    const FF_SPRING: u16 = 0x53;
+    const FF_DAMPER: u16 = 0x55;
+    const FF_SQUARE: u16 = 0x58;
+    const FF_TRIANGLE: u16 = 0x59;
+    const FF_SINE: u16 = 0x5a;
+    const FF_SAW_UP: u16 = 0x5b;
+    const FF_SAW_DOWN: u16 = 0x5c;
+    /// Direction of forces pushing towards positive X
+    const DIRECTION_POSITIVE_X: u16 = 0x4000;
+
+    #[repr(C)]
+    struct FfEffect {
+        effect_type: u16,
+        id: i16,
+        direction: u16,
+        trigger: [u16; 2],
+        replay: [u16; 2],
+        // Start of the 8 byte aligned union of effect parameters
+        _align: [u64; 0],
+        parameters: [u16; 16],
+    }
+
+    // _IOW('E', 0x80, struct ff_effect)
+    const EVIOCSFF: libc::c_ulong =
+        (1 << 30) | ((mem::size_of::<FfEffect>() as libc::c_ulong) << 16) | (0x45 << 8) | 0x80;
+    // _IOW('E', 0x81, int)
+    const EVIOCRMFF: libc::c_ulong =
+        (1 << 30) | ((mem::size_of::<libc::c_int>() as libc::c_ulong) << 16) | (0x45 << 8) | 0x81;
+
+    fn signed_level(value: f64) -> u16 {
+        (value.clamp(-1f64, 1f64) * 32767f64) as i16 as u16
+    }
+
+    fn magnitude(value: f64) -> u16 {
+        (value.clamp(0f64, 1f64) * 65535f64) as u16
+    }
+
+    /// Type, direction and parameters (the ff_effect union) of an effect
+    fn effect_parameters(
+        effect: &ForceFeedbackEffect,
+    ) -> Result<(u16, u16, [u16; 16]), ForceFeedbackError> {
+        let mut parameters = [0u16; 16];
+        let effect_type = match *effect {
+            ForceFeedbackEffect::Rumble { low, high } => {
+                // ff_rumble_effect: strong_magnitude, weak_magnitude
+                parameters[0] = magnitude(low);
+                parameters[1] = magnitude(high);
+                FF_RUMBLE
+            }
+            ForceFeedbackEffect::Constant { level } => {
+                // ff_constant_effect: level, envelope
+                parameters[0] = signed_level(level);
+                FF_CONSTANT
+            }
+            ForceFeedbackEffect::Periodic {
+                ref waveform,
+                magnitude,
+                period,
+                offset,
+            } => {
+                // ff_periodic_effect: waveform, period, magnitude, offset, phase, envelope, custom data
+                parameters[0] = match *waveform {
+                    Waveform::Sine => FF_SINE,
+                    Waveform::Square => FF_SQUARE,
+                    Waveform::Triangle => FF_TRIANGLE,
+                    Waveform::SawtoothUp => FF_SAW_UP,
+                    Waveform::SawtoothDown => FF_SAW_DOWN,
+                };
+                parameters[1] = period.as_millis().min(u16::MAX as u128) as u16;
+                parameters[2] = signed_level(magnitude);
+                parameters[3] = signed_level(offset);
+                FF_PERIODIC
+            }
+            ForceFeedbackEffect::Spring {
+                ref axis,
+                center,
+                coefficient,
+                deadband,
+            } => {
+                set_condition(&mut parameters, axis, coefficient, deadband, center)?;
+                FF_SPRING
+            }
+            ForceFeedbackEffect::Damper {
+                ref axis,
+                coefficient,
+            } => {
+                set_condition(&mut parameters, axis, coefficient, 0f64, 0f64)?;
+                FF_DAMPER
+            }
+        };
+        Ok((effect_type, DIRECTION_POSITIVE_X, parameters))
+    }
+
+    /// Fills the ff_condition_effect of the axis (one per X and Y axis): saturations,
+    /// coefficients, deadband and center
+    fn set_condition(
+        parameters: &mut [u16; 16],
+        axis: &Axis,
+        coefficient: f64,
+        deadband: f64,
+        center: f64,
+    ) -> Result<(), ForceFeedbackError> {
+        let start = match *axis {
+            Axis::X => 0,
+            Axis::Y => 6,
+            ref axis => {
+                return Err(ForceFeedbackError(format!(
+                    "Conditions on the {:?} axis are not supported",
+                    axis
+                )))
+            }
+        };
+        parameters[start..start + 6].copy_from_slice(&[
+            u16::MAX,
+            u16::MAX,
+            signed_level(coefficient),
+            signed_level(coefficient),
+            magnitude(deadband),
+            signed_level(center),
+        ]);
+        Ok(())
+    }
+
+    pub struct EvdevForceFeedbackSink {
+        device: File,
+        rumble_id: i16,
+        effect_ids: HashMap<EffectId, i16>,
+    }
+
+    impl EvdevForceFeedbackSink {
+        /// Uses an event node opened for writing (e.g. /dev/input/event5)
+        pub fn new(device: File) -> EvdevForceFeedbackSink {
+            EvdevForceFeedbackSink {
+                device,
+                rumble_id: -1,
+                effect_ids: HashMap::new(),
+            }
+        }
+
+        /// Uploads an effect (replacing the effect with the given id if it is not -1)
+        /// and returns its id on the device
+        fn upload(
+            &mut self,
+            id: i16,
+            effect: &ForceFeedbackEffect,
+        ) -> Result<i16, ForceFeedbackError> {
+            let (effect_type, direction, parameters) = effect_parameters(effect)?;
+            let mut effect = FfEffect {
+                effect_type,
+                id,
+                direction,
+                trigger: [0, 0],
+                replay: [0, 0],
+                _align: [],
+                parameters,
+            };
+            let result = unsafe {
+                libc::ioctl(
+                    self.device.as_raw_fd(),
+                    EVIOCSFF as _,
+                    &mut effect as *mut FfEffect,
+                )
+            };
+            if result < 0 {
+                return Err(ForceFeedbackError("Could not upload effect".to_string()));
+            }
+            Ok(effect.id)
+        }
+
+        fn write_event(&mut self, code: u16, value: i32) -> Result<(), ForceFeedbackError> {
+            write_input_event(&mut self.device, EV_FF, code, value)
+                .map_err(|error| ForceFeedbackError(error.to_string()))
+        }
+    }
+
+    impl ForceFeedbackSink for EvdevForceFeedbackSink {
+        fn set_rumble(&mut self, low: f64, high: f64) -> Result<(), ForceFeedbackError> {
+            if magnitude(low) == 0 && magnitude(high) == 0 {
+                if self.rumble_id >= 0 {
+                    let rumble_id = self.rumble_id as u16;
+                    return self.write_event(rumble_id, 0);
+                }
+                return Ok(());
+            }
+            let rumble_id = self.rumble_id;
+            self.rumble_id = self.upload(rumble_id, &ForceFeedbackEffect::Rumble { low, high })?;
+            let rumble_id = self.rumble_id as u16;
+            self.write_event(rumble_id, 1)
+        }
+
+        fn play_effect(
+            &mut self,
+            id: EffectId,
+            effect: &ForceFeedbackEffect,
+        ) -> Result<(), ForceFeedbackError> {
+            match self.effect_ids.get(&id) {
+                Some(&device_id) => self.upload(device_id, effect).map(|_| ()),
+                None => {
+                    let device_id = self.upload(-1, effect)?;
+                    self.effect_ids.insert(id, device_id);
+                    self.write_event(device_id as u16, 1)
+                }
+            }
+        }
+
+        fn stop_effect(&mut self, id: EffectId) -> Result<(), ForceFeedbackError> {
+            let device_id = match self.effect_ids.remove(&id) {
+                Some(device_id) => device_id,
+                None => return Ok(()),
+            };
+            self.write_event(device_id as u16, 0)?;
+            let result = unsafe {
+                libc::ioctl(
+                    self.device.as_raw_fd(),
+                    EVIOCRMFF as _,
+                    device_id as libc::c_int,
+                )
+            };
+            if result < 0 {
+                return Err(ForceFeedbackError("Could not remove effect".to_string()));
+            }
+            Ok(())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::time::Duration;
+
+        #[test]
+        fn effect_struct_matches_the_kernel_layout() {
+            assert_eq!(mem::size_of::<FfEffect>(), 48);
+        }
+
+        #[test]
+        fn conditions_fill_the_axis_slot() {
+            let effect = ForceFeedbackEffect::Spring {
+                axis: Axis::Y,
+                center: 0.5,
+                coefficient: -1.0,
+                deadband: 1.0,
+            };
+            let (effect_type, _, parameters) = effect_parameters(&effect).unwrap();
+            assert_eq!(effect_type, FF_SPRING);
+            assert_eq!(parameters[..6], [0; 6]);
+            assert_eq!(
+                parameters[6..12],
+                [
+                    u16::MAX,
+                    u16::MAX,
+                    (-32767i16) as u16,
+                    (-32767i16) as u16,
+                    u16::MAX,
+                    16383
+                ]
+            );
+            let effect = ForceFeedbackEffect::Damper {
+                axis: Axis::Z,
+                coefficient: 1.0,
+            };
+            assert!(effect_parameters(&effect).is_err());
+        }
+
+        #[test]
+        fn periodic_parameters() {
+            let effect = ForceFeedbackEffect::Periodic {
+                waveform: Waveform::Triangle,
+                magnitude: 2.0,
+                period: Duration::from_millis(250),
+                offset: -0.5,
+            };
+            let (effect_type, direction, parameters) = effect_parameters(&effect).unwrap();
+            assert_eq!(effect_type, FF_PERIODIC);
+            assert_eq!(direction, DIRECTION_POSITIVE_X);
+            assert_eq!(
+                parameters[..4],
+                [FF_TRIANGLE, 250, 32767, (-16383i16) as u16]
+            );
+            assert_eq!(parameters[4..], [0; 12]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(PartialEq, Debug)]
+    enum Call {
+        Rumble(f64, f64),
+        Play(EffectId, ForceFeedbackEffect),
+        Stop(EffectId),
+    }
+
+    #[derive(Default)]
+    struct FakeSink {
+        calls: Vec<Call>,
+        rumble_only: bool,
+    }
+
+    impl ForceFeedbackSink for FakeSink {
+        fn set_rumble(&mut self, low: f64, high: f64) -> Result<(), ForceFeedbackError> {
+            self.calls.push(Call::Rumble(low, high));
+            Ok(())
+        }
+
+        fn play_effect(
+            &mut self,
+            id: EffectId,
+            effect: &ForceFeedbackEffect,
+        ) -> Result<(), ForceFeedbackError> {
+            if self.rumble_only {
+                return Err(ForceFeedbackError("rumble only".to_string()));
+            }
+            self.calls.push(Call::Play(id, effect.clone()));
+            Ok(())
+        }
+
+        fn stop_effect(&mut self, id: EffectId) -> Result<(), ForceFeedbackError> {
+            self.calls.push(Call::Stop(id));
+            Ok(())
+        }
+    }
+
+    fn ms(milliseconds: u64) -> Duration {
+        Duration::from_millis(milliseconds)
+    }
+
+    const CONSTANT: ForceFeedbackEffect = ForceFeedbackEffect::Constant { level: -0.5 };
+
+    #[test]
+    fn rumble_is_combined_and_sent_on_update() {
+        let mut scheduler = EffectScheduler::new();
+        let mut sink = FakeSink::default();
+        let now = Instant::now();
+        let rumble = ForceFeedbackEffect::Rumble {
+            low: 0.5,
+            high: 0.25,
+        };
+        scheduler
+            .start(EffectId(0), rumble.clone(), None, now, &mut sink)
+            .unwrap();
+        scheduler
+            .start(EffectId(1), rumble, None, now, &mut sink)
+            .unwrap();
+        assert!(sink.calls.is_empty());
+        scheduler.update(now, &mut sink).unwrap();
+        assert_eq!(sink.calls, vec![Call::Rumble(1.0, 0.5)]);
+        // Unchanged output is not sent again
+        scheduler.update(now + ms(10), &mut sink).unwrap();
+        assert_eq!(sink.calls.len(), 1);
+    }
+
+    #[test]
+    fn stopping_rumble_silences_the_motors() {
+        let mut scheduler = EffectScheduler::new();
+        let mut sink = FakeSink::default();
+        let now = Instant::now();
+        let rumble = ForceFeedbackEffect::Rumble {
+            low: 1.0,
+            high: 1.0,
+        };
+        scheduler
+            .start(EffectId(0), rumble, None, now, &mut sink)
+            .unwrap();
+        scheduler.update(now, &mut sink).unwrap();
+        scheduler.stop(EffectId(0), &mut sink).unwrap();
+        assert!(scheduler.is_active());
+        scheduler.update(now + ms(1), &mut sink).unwrap();
+        assert_eq!(
+            sink.calls,
+            vec![Call::Rumble(1.0, 1.0), Call::Rumble(0.0, 0.0)]
+        );
+        assert!(!scheduler.is_active());
+    }
+
+    #[test]
+    fn device_effects_are_played_and_stopped_by_the_sink() {
+        let mut scheduler = EffectScheduler::new();
+        let mut sink = FakeSink::default();
+        let now = Instant::now();
+        scheduler
+            .start(EffectId(3), CONSTANT, None, now, &mut sink)
+            .unwrap();
+        assert_eq!(sink.calls, vec![Call::Play(EffectId(3), CONSTANT)]);
+        scheduler.update(now, &mut sink).unwrap();
+        // Device effects leave the motors alone
+        assert_eq!(sink.calls.len(), 1);
+        scheduler.stop(EffectId(3), &mut sink).unwrap();
+        assert_eq!(sink.calls[1], Call::Stop(EffectId(3)));
+        // Stopping twice does nothing
+        scheduler.stop(EffectId(3), &mut sink).unwrap();
+        assert_eq!(sink.calls.len(), 2);
+    }
+
+    #[test]
+    fn restarting_an_id_replaces_the_effect() {
+        let mut scheduler = EffectScheduler::new();
+        let mut sink = FakeSink::default();
+        let now = Instant::now();
+        let damper = ForceFeedbackEffect::Damper {
+            axis: Axis::X,
+            coefficient: 0.5,
+        };
+        scheduler
+            .start(EffectId(0), CONSTANT, None, now, &mut sink)
+            .unwrap();
+        scheduler
+            .start(EffectId(0), damper.clone(), None, now, &mut sink)
+            .unwrap();
+        assert_eq!(
+            sink.calls,
+            vec![
+                Call::Play(EffectId(0), CONSTANT),
+                Call::Stop(EffectId(0)),
+                Call::Play(EffectId(0), damper),
+            ]
+        );
+        scheduler.stop_all(&mut sink).unwrap();
+        assert_eq!(sink.calls[3], Call::Stop(EffectId(0)));
+    }
+
+    #[test]
+    fn rumble_only_sinks_refuse_device_effects() {
+        let mut scheduler = EffectScheduler::new();
+        let mut sink = FakeSink {
+            rumble_only: true,
+            ..FakeSink::default()
+        };
+        let result = scheduler.start(EffectId(0), CONSTANT, None, Instant::now(), &mut sink);
+        assert!(result.is_err());
+        assert!(!scheduler.is_active());
+    }
+
+    #[test]
+    fn effects_end_after_their_duration() {
+        let mut scheduler = EffectScheduler::new();
+        let mut sink = FakeSink::default();
+        let now = Instant::now();
+        let rumble = ForceFeedbackEffect::Rumble {
+            low: 0.5,
+            high: 0.0,
+        };
+        scheduler
+            .start(EffectId(0), rumble, Some(ms(100)), now, &mut sink)
+            .unwrap();
+        scheduler
+            .start(EffectId(1), CONSTANT, Some(ms(50)), now, &mut sink)
+            .unwrap();
+        scheduler.update(now, &mut sink).unwrap();
+        scheduler.update(now + ms(45), &mut sink).unwrap();
+        assert_eq!(sink.calls.len(), 2);
+        scheduler.update(now + ms(50), &mut sink).unwrap();
+        assert_eq!(sink.calls[2], Call::Stop(EffectId(1)));
+        assert_eq!(scheduler.output(now + ms(99)), (0.5, 0.0));
+        scheduler.update(now + ms(100), &mut sink).unwrap();
+        assert_eq!(sink.calls[3], Call::Rumble(0.0, 0.0));
+        assert!(!scheduler.is_active());
+    }
+
+    #[test]
+    fn updates_are_throttled() {
+        let mut scheduler = EffectScheduler::new();
+        let mut sink = FakeSink::default();
+        let now = Instant::now();
+        let rumble = ForceFeedbackEffect::Rumble {
+            low: 0.5,
+            high: 0.0,
+        };
+        scheduler
+            .start(EffectId(0), rumble, Some(ms(2)), now, &mut sink)
+            .unwrap();
+        scheduler.update(now, &mut sink).unwrap();
+        // The effect has ended, but the motors are only stopped after UPDATE_INTERVAL
+        scheduler.update(now + ms(3), &mut sink).unwrap();
+        assert_eq!(sink.calls.len(), 1);
+        scheduler.update(now + UPDATE_INTERVAL, &mut sink).unwrap();
+        assert_eq!(sink.calls[1], Call::Rumble(0.0, 0.0));
+    }
+
+    #[test]
+    fn gain_scales_rumble_and_device_effects() {
+        let mut scheduler = EffectScheduler::new();
+        let mut sink = FakeSink::default();
+        let now = Instant::now();
+        let rumble = ForceFeedbackEffect::Rumble {
+            low: 1.0,
+            high: 0.5,
+        };
+        scheduler
+            .start(EffectId(0), rumble, None, now, &mut sink)
+            .unwrap();
+        scheduler
+            .start(EffectId(1), CONSTANT, None, now, &mut sink)
+            .unwrap();
+        scheduler.update(now, &mut sink).unwrap();
+        scheduler.set_gain(0.5, &mut sink).unwrap();
+        // Device effects are updated straight away, rumble on the next update
+        assert_eq!(
+            sink.calls[2],
+            Call::Play(EffectId(1), ForceFeedbackEffect::Constant { level: -0.25 })
+        );
+        scheduler.update(now + ms(1), &mut sink).unwrap();
+        assert_eq!(sink.calls[3], Call::Rumble(0.5, 0.25));
+        let periodic = ForceFeedbackEffect::Periodic {
+            waveform: Waveform::Sine,
+            magnitude: 1.0,
+            period: ms(100),
+            offset: 0.5,
+        };
+        scheduler
+            .start(EffectId(2), periodic.clone(), None, now, &mut sink)
+            .unwrap();
+        assert_eq!(sink.calls[4], Call::Play(EffectId(2), periodic.scaled(0.5)));
+        // Gain is limited to [0, 1]
+        scheduler.set_gain(2.0, &mut sink).unwrap();
+        assert_eq!(scheduler.gain(), 1.0);
+    }
+
+    #[test]
+    fn players_keep_external_outputs_when_cleared() {
+        let mut players = EffectPlayers::new();
+        players.set_output(0, Box::new(FakeSink::default()));
+        players
+            .start(
+                1,
+                || Ok(Box::new(FakeSink::default())),
+                EffectId(0),
+                CONSTANT,
+                None,
+            )
+            .unwrap();
+        players.clear();
+        assert_eq!(players.players.len(), 1);
+        assert!(!players.is_active());
+        // The external output is used without opening one
+        players
+            .start(
+                0,
+                || Err(ForceFeedbackError("not opened".to_string())),
+                EffectId(1),
+                CONSTANT,
+                None,
+            )
+            .unwrap();
+        assert!(players.is_active());
+    }
+}
@@ -0,0 +1,360 @@
+//! Per-frame snapshots of input for game loops (see `RawInputManager::begin_frame`)
+
+use devices::{HatSwitch, JoystickState};
+use event::{Axis, KeyId, MouseButton, RawEvent, State};
+use gamepad::{GamepadAxis, GamepadButton};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+/// Keys or buttons of a device during a frame
+///
+/// A button pressed and released within the frame is in both `pressed` and
+/// `released` but not in `held`. Key repeats do not count as presses.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ButtonSet<T: Eq + Hash> {
+    /// Pressed during the frame
+    pub pressed: HashSet<T>,
+    /// Released during the frame
+    pub released: HashSet<T>,
+    /// Down at the end of the frame
+    pub held: HashSet<T>,
+}
+
+impl<T: Eq + Hash> Default for ButtonSet<T> {
+    fn default() -> ButtonSet<T> {
+        ButtonSet {
+            pressed: HashSet::new(),
+            released: HashSet::new(),
+            held: HashSet::new(),
+        }
+    }
+}
+
+impl<T: Eq + Hash + Clone> ButtonSet<T> {
+    pub fn just_pressed(&self, button: &T) -> bool {
+        self.pressed.contains(button)
+    }
+
+    pub fn just_released(&self, button: &T) -> bool {
+        self.released.contains(button)
+    }
+
+    pub fn held(&self, button: &T) -> bool {
+        self.held.contains(button)
+    }
+
+    fn update(&mut self, button: &T, state: &State) {
+        match *state {
+            State::Pressed => {
+                if self.held.insert(button.clone()) {
+                    self.pressed.insert(button.clone());
+                }
+            }
+            State::Released => {
+                if self.held.remove(button) {
+                    self.released.insert(button.clone());
+                }
+            }
+        }
+    }
+
+    /// Keeps the held buttons for the next frame
+    fn next_frame(&mut self) {
+        self.pressed.clear();
+        self.released.clear();
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct KeyboardFrame {
+    pub keys: ButtonSet<KeyId>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MouseFrame {
+    pub buttons: ButtonSet<MouseButton>,
+    /// Motion accumulated over the frame
+    pub motion: (i32, i32),
+    /// Wheel movement accumulated over the frame
+    pub wheel: f32,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct JoystickFrame {
+    pub buttons: ButtonSet<usize>,
+    /// Latest value of each axis reported so far
+    pub axes: HashMap<Axis, f64>,
+    pub hatswitch: Option<HatSwitch>,
+    pub gamepad_buttons: ButtonSet<GamepadButton>,
+    pub gamepad_axes: HashMap<GamepadAxis, f64>,
+}
+
+/// Input of all devices during a frame, indexed by device id
+///
+/// Devices appear once they have sent an event.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct InputFrame {
+    pub keyboards: HashMap<usize, KeyboardFrame>,
+    pub mice: HashMap<usize, MouseFrame>,
+    pub joysticks: HashMap<usize, JoystickFrame>,
+}
+
+impl InputFrame {
+    /// True if the key of the keyboard was pressed during the frame
+    pub fn just_pressed(&self, keyboard: usize, key: KeyId) -> bool {
+        self.keyboards
+            .get(&keyboard)
+            .is_some_and(|frame| frame.keys.just_pressed(&key))
+    }
+
+    /// True if the key of the keyboard was released during the frame
+    pub fn just_released(&self, keyboard: usize, key: KeyId) -> bool {
+        self.keyboards
+            .get(&keyboard)
+            .is_some_and(|frame| frame.keys.just_released(&key))
+    }
+
+    /// True if the key of the keyboard is down at the end of the frame
+    pub fn held(&self, keyboard: usize, key: KeyId) -> bool {
+        self.keyboards
+            .get(&keyboard)
+            .is_some_and(|frame| frame.keys.held(&key))
+    }
+
+    pub fn mouse_motion(&self, mouse: usize) -> (i32, i32) {
+        self.mice.get(&mouse).map_or((0, 0), |frame| frame.motion)
+    }
+
+    pub fn mouse_wheel(&self, mouse: usize) -> f32 {
+        self.mice.get(&mouse).map_or(0.0, |frame| frame.wheel)
+    }
+
+    pub fn joystick_axis(&self, joystick: usize, axis: Axis) -> Option<f64> {
+        self.joysticks
+            .get(&joystick)
+            .and_then(|frame| frame.axes.get(&axis).cloned())
+    }
+
+    /// Adds an event to the frame
+    pub fn apply(&mut self, event: &RawEvent) {
+        match *event {
+            RawEvent::KeyboardEvent(id, ref key, ref state) => self
+                .keyboards
+                .entry(id)
+                .or_default()
+                .keys
+                .update(key, state),
+            RawEvent::MouseButtonEvent(id, ref button, ref state) => self
+                .mice
+                .entry(id)
+                .or_default()
+                .buttons
+                .update(button, state),
+            RawEvent::MouseMoveEvent(id, x, y) => {
+                let motion = &mut self.mice.entry(id).or_default().motion;
+                motion.0 = motion.0.saturating_add(x);
+                motion.1 = motion.1.saturating_add(y);
+            }
+            RawEvent::MouseWheelEvent(id, delta) => self.mice.entry(id).or_default().wheel += delta,
+            RawEvent::JoystickButtonEvent(id, button, ref state) => self
+                .joysticks
+                .entry(id)
+                .or_default()
+                .buttons
+                .update(&button, state),
+            RawEvent::JoystickAxisEvent(id, ref axis, value) => {
+                self.joysticks
+                    .entry(id)
+                    .or_default()
+                    .axes
+                    .insert(axis.clone(), value);
+            }
+            RawEvent::JoystickHatSwitchEvent(id, ref hatswitch) => {
+                self.joysticks.entry(id).or_default().hatswitch = Some(hatswitch.clone());
+            }
+            RawEvent::JoystickInitialStateEvent(id, ref state) => {
+                self.joysticks.entry(id).or_default().set_state(state)
+            }
+            RawEvent::GamepadButtonEvent(id, ref button, ref state) => self
+                .joysticks
+                .entry(id)
+                .or_default()
+                .gamepad_buttons
+                .update(button, state),
+            RawEvent::GamepadAxisEvent(id, ref axis, value) => {
+                self.joysticks
+                    .entry(id)
+                    .or_default()
+                    .gamepad_axes
+                    .insert(axis.clone(), value);
+            }
+        }
+    }
+
+    /// Starts the following frame, keeping held buttons and axis values
+    pub fn next_frame(&mut self) {
+        for frame in self.keyboards.values_mut() {
+            frame.keys.next_frame();
+        }
+        for frame in self.mice.values_mut() {
+            frame.buttons.next_frame();
+            frame.motion = (0, 0);
+            frame.wheel = 0.0;
+        }
+        for frame in self.joysticks.values_mut() {
+            frame.buttons.next_frame();
+            frame.gamepad_buttons.next_frame();
+        }
+    }
+}
+
+impl JoystickFrame {
+    /// Takes over a complete joystick state without reporting presses
+    fn set_state(&mut self, state: &JoystickState) {
+        self.buttons.held = state
+            .button_states
+            .iter()
+            .enumerate()
+            .filter(|&(_, &pressed)| pressed)
+            .map(|(button, _)| button)
+            .collect();
+        let axes = &state.axis_states;
+        let values = [
+            (Axis::X, axes.x),
+            (Axis::Y, axes.y),
+            (Axis::Z, axes.z),
+            (Axis::RX, axes.rx),
+            (Axis::RY, axes.ry),
+            (Axis::RZ, axes.rz),
+            (Axis::SLIDER, axes.slider),
+        ];
+        for (axis, value) in values.iter().cloned() {
+            if let Some(value) = value {
+                self.axes.insert(axis, value);
+            }
+        }
+        self.hatswitch = state.hatswitch.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use devices::{Axes, DevicesDisplayInfo, KeyboardDisplayInfo, RawAxes};
+    use event_queue::{OverflowPolicy, DEFAULT_CAPACITY};
+    use manager::RawInputManager;
+    use mock::MockBackend;
+    use std::time::Duration;
+
+    fn key(state: State) -> RawEvent {
+        RawEvent::KeyboardEvent(0, KeyId::A, state)
+    }
+
+    #[test]
+    fn press_and_release_within_a_frame() {
+        let mut frame = InputFrame::default();
+        frame.apply(&key(State::Pressed));
+        frame.apply(&key(State::Released));
+        assert!(frame.just_pressed(0, KeyId::A));
+        assert!(frame.just_released(0, KeyId::A));
+        assert!(!frame.held(0, KeyId::A));
+        frame.next_frame();
+        assert!(!frame.just_pressed(0, KeyId::A));
+        assert!(!frame.just_released(0, KeyId::A));
+    }
+
+    #[test]
+    fn held_buttons_carry_over() {
+        let mut frame = InputFrame::default();
+        frame.apply(&key(State::Pressed));
+        frame.next_frame();
+        // Key repeat
+        frame.apply(&key(State::Pressed));
+        assert!(frame.held(0, KeyId::A));
+        assert!(!frame.just_pressed(0, KeyId::A));
+        frame.next_frame();
+        frame.apply(&key(State::Released));
+        assert!(frame.just_released(0, KeyId::A));
+        assert!(!frame.held(0, KeyId::A));
+        // Releases of buttons that were not held are ignored
+        frame.apply(&RawEvent::MouseButtonEvent(
+            1,
+            MouseButton::Left,
+            State::Released,
+        ));
+        assert!(frame.mice[&1].buttons.released.is_empty());
+    }
+
+    #[test]
+    fn motion_and_wheel_accumulate_per_frame() {
+        let mut frame = InputFrame::default();
+        frame.apply(&RawEvent::MouseMoveEvent(0, 3, -1));
+        frame.apply(&RawEvent::MouseMoveEvent(0, 2, -4));
+        frame.apply(&RawEvent::MouseMoveEvent(1, 1, 1));
+        frame.apply(&RawEvent::MouseWheelEvent(0, 1.0));
+        frame.apply(&RawEvent::MouseWheelEvent(0, -0.5));
+        assert_eq!(frame.mouse_motion(0), (5, -5));
+        assert_eq!(frame.mouse_motion(1), (1, 1));
+        assert_eq!(frame.mouse_wheel(0), 0.5);
+        frame.next_frame();
+        assert_eq!(frame.mouse_motion(0), (0, 0));
+        assert_eq!(frame.mouse_wheel(0), 0.0);
+        assert_eq!(frame.mouse_motion(2), (0, 0));
+    }
+
+    #[test]
+    fn joystick_values_carry_over() {
+        let mut frame = InputFrame::default();
+        let state = JoystickState {
+            button_states: vec![false, true],
+            axis_states: Axes {
+                x: Some(0.5),
+                ..Default::default()
+            },
+            hatswitch: Some(HatSwitch::Up),
+            raw_axis_states: RawAxes::new(),
+        };
+        frame.apply(&RawEvent::JoystickInitialStateEvent(0, state));
+        // The initial state is held without being pressed
+        assert!(frame.joysticks[&0].buttons.held(&1));
+        assert!(frame.joysticks[&0].buttons.pressed.is_empty());
+        frame.apply(&RawEvent::JoystickAxisEvent(0, Axis::Y, -1.0));
+        frame.next_frame();
+        assert_eq!(frame.joystick_axis(0, Axis::X), Some(0.5));
+        assert_eq!(frame.joystick_axis(0, Axis::Y), Some(-1.0));
+        assert_eq!(frame.joystick_axis(0, Axis::Z), None);
+        assert_eq!(frame.joysticks[&0].hatswitch, Some(HatSwitch::Up));
+        frame.apply(&RawEvent::JoystickButtonEvent(0, 1, State::Released));
+        assert!(frame.joysticks[&0].buttons.just_released(&1));
+    }
+
+    #[test]
+    fn first_frame_has_input_from_before_it() {
+        let backend = MockBackend::new();
+        let handle = backend.handle();
+        let mut devices = DevicesDisplayInfo::default();
+        devices.keyboards.push(KeyboardDisplayInfo::default());
+        handle.set_devices(devices);
+        let mut manager = RawInputManager::with_backend(
+            move || backend,
+            DEFAULT_CAPACITY,
+            OverflowPolicy::DropOldest,
+        );
+        handle.push_event(key(State::Pressed));
+        // The event has been read once get_event returns it
+        assert!(manager.wait_event_timeout(Duration::from_secs(5)).is_some());
+        let frame = manager.begin_frame();
+        assert!(frame.just_pressed(0, KeyId::A));
+        assert!(frame.held(0, KeyId::A));
+        let frame = manager.begin_frame();
+        assert!(!frame.just_pressed(0, KeyId::A));
+        assert!(frame.held(0, KeyId::A));
+    }
+}
@@ -0,0 +1,747 @@
+//! Standard gamepad layout driven by SDL2 GameControllerDB mappings
+//!
+//! Raw joystick buttons and axes are numbered differently by every vendor. A
+//! `GamepadMapping` (one line of SDL2's `gamecontrollerdb.txt`) describes how
+//! a controller's raw inputs correspond to a standard layout, which is then
+//! reported through `RawEvent::GamepadButtonEvent` and `RawEvent::GamepadAxisEvent`.
+//!
+//! Stick axes follow the same convention as joystick axes: values lie in
+//! [-1, 1] and up is positive. Triggers lie in [0, 1].
+
+use devices::JoystickState;
+use event::{RawEvent, State};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Standard Gamepad Buttons (named by position for the face buttons)
+#[derive(Eq, PartialEq, Hash, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum GamepadButton {
+    South,
+    East,
+    West,
+    North,
+    Back,
+    Guide,
+    Start,
+    LeftStick,
+    RightStick,
+    LeftShoulder,
+    RightShoulder,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+    Misc1,
+    Paddle1,
+    Paddle2,
+    Paddle3,
+    Paddle4,
+    Touchpad,
+}
+
+/// Standard Gamepad Axes
+#[derive(Eq, PartialEq, Hash, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum GamepadAxis {
+    LeftX,
+    LeftY,
+    RightX,
+    RightY,
+    LeftTrigger,
+    RightTrigger,
+}
+
+/// All gamepad buttons, in declaration order
+pub const BUTTONS: [GamepadButton; 21] = [
+    GamepadButton::South,
+    GamepadButton::East,
+    GamepadButton::West,
+    GamepadButton::North,
+    GamepadButton::Back,
+    GamepadButton::Guide,
+    GamepadButton::Start,
+    GamepadButton::LeftStick,
+    GamepadButton::RightStick,
+    GamepadButton::LeftShoulder,
+    GamepadButton::RightShoulder,
+    GamepadButton::DPadUp,
+    GamepadButton::DPadDown,
+    GamepadButton::DPadLeft,
+    GamepadButton::DPadRight,
+    GamepadButton::Misc1,
+    GamepadButton::Paddle1,
+    GamepadButton::Paddle2,
+    GamepadButton::Paddle3,
+    GamepadButton::Paddle4,
+    GamepadButton::Touchpad,
+];
+
+/// All gamepad axes, in declaration order
+pub const AXES: [GamepadAxis; 6] = [
+    GamepadAxis::LeftX,
+    GamepadAxis::LeftY,
+    GamepadAxis::RightX,
+    GamepadAxis::RightY,
+    GamepadAxis::LeftTrigger,
+    GamepadAxis::RightTrigger,
+];
+
+/// Mapping used for devices not found in the database
+///
+/// This follows the layout of most generic DirectInput pads and is only a best guess.
+pub const FALLBACK_MAPPING: &str = "00000000000000000000000000000000,Generic Gamepad,\
+a:b1,b:b2,x:b0,y:b3,leftshoulder:b4,rightshoulder:b5,lefttrigger:b6,righttrigger:b7,\
+back:b8,start:b9,leftstick:b10,rightstick:b11,leftx:a0,lefty:a1,rightx:a2,righty:a3,\
+dpup:h0.1,dpright:h0.2,dpdown:h0.4,dpleft:h0.8,";
+
+impl GamepadButton {
+    fn from_sdl_name(name: &str) -> Option<GamepadButton> {
+        match name {
+            "a" => Some(GamepadButton::South),
+            "b" => Some(GamepadButton::East),
+            "x" => Some(GamepadButton::West),
+            "y" => Some(GamepadButton::North),
+            "back" => Some(GamepadButton::Back),
+            "guide" => Some(GamepadButton::Guide),
+            "start" => Some(GamepadButton::Start),
+            "leftstick" => Some(GamepadButton::LeftStick),
+            "rightstick" => Some(GamepadButton::RightStick),
+            "leftshoulder" => Some(GamepadButton::LeftShoulder),
+            "rightshoulder" => Some(GamepadButton::RightShoulder),
+            "dpup" => Some(GamepadButton::DPadUp),
+            "dpdown" => Some(GamepadButton::DPadDown),
+            "dpleft" => Some(GamepadButton::DPadLeft),
+            "dpright" => Some(GamepadButton::DPadRight),
+            "misc1" => Some(GamepadButton::Misc1),
+            "paddle1" => Some(GamepadButton::Paddle1),
+            "paddle2" => Some(GamepadButton::Paddle2),
+            "paddle3" => Some(GamepadButton::Paddle3),
+            "paddle4" => Some(GamepadButton::Paddle4),
+            "touchpad" => Some(GamepadButton::Touchpad),
+            _ => None,
+        }
+    }
+
+    fn index(&self) -> usize {
+        BUTTONS.iter().position(|b| b == self).unwrap()
+    }
+}
+
+impl GamepadAxis {
+    fn from_sdl_name(name: &str) -> Option<GamepadAxis> {
+        match name {
+            "leftx" => Some(GamepadAxis::LeftX),
+            "lefty" => Some(GamepadAxis::LeftY),
+            "rightx" => Some(GamepadAxis::RightX),
+            "righty" => Some(GamepadAxis::RightY),
+            "lefttrigger" => Some(GamepadAxis::LeftTrigger),
+            "righttrigger" => Some(GamepadAxis::RightTrigger),
+            _ => None,
+        }
+    }
+
+    fn index(&self) -> usize {
+        AXES.iter().position(|a| a == self).unwrap()
+    }
+
+    fn is_trigger(&self) -> bool {
+        *self == GamepadAxis::LeftTrigger || *self == GamepadAxis::RightTrigger
+    }
+}
+
+/// Error produced when a mapping line cannot be parsed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GamepadMappingError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for GamepadMappingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid gamepad mapping on line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for GamepadMappingError {}
+
+#[derive(Clone, Debug, PartialEq)]
+enum AxisRange {
+    Full,
+    Positive,
+    Negative,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum MappingInput {
+    Button(usize),
+    Axis {
+        index: usize,
+        range: AxisRange,
+        invert: bool,
+    },
+    Hat {
+        hat: usize,
+        mask: u8,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum MappingOutput {
+    Button(GamepadButton),
+    Axis(GamepadAxis, AxisRange),
+}
+
+/// A single SDL2 GameControllerDB entry
+#[derive(Clone, Debug, PartialEq)]
+pub struct GamepadMapping {
+    pub guid: String,
+    pub name: String,
+    pub platform: Option<String>,
+    bindings: Vec<(MappingInput, MappingOutput)>,
+}
+
+impl GamepadMapping {
+    /// Parses a single line of gamecontrollerdb.txt
+    pub fn parse(line: &str) -> Result<GamepadMapping, String> {
+        let mut fields = line.trim().split(',');
+        let guid = fields.next().unwrap_or("").trim().to_lowercase();
+        if guid.len() != 32 || !guid.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("\"{}\" is not a valid GUID", guid));
+        }
+        let name = match fields.next() {
+            Some(name) => name.trim().to_string(),
+            None => return Err("Missing controller name".to_string()),
+        };
+        let mut platform = None;
+        let mut bindings = Vec::new();
+        for field in fields {
+            let field = field.trim();
+            if field.is_empty() {
+                continue;
+            }
+            let (target, source) = match field.find(':') {
+                Some(pos) => (&field[..pos], &field[pos + 1..]),
+                None => return Err(format!("\"{}\" is not of the form target:source", field)),
+            };
+            if target == "platform" {
+                platform = Some(source.to_string());
+                continue;
+            }
+            if target == "crc" || target == "hint" || target == "sdk>=" || target == "sdk<=" {
+                continue;
+            }
+            // Elements added to SDL after this was written are skipped
+            let output = match parse_output(target)? {
+                Some(output) => output,
+                None => continue,
+            };
+            let input = parse_input(source)?;
+            bindings.push((input, output));
+        }
+        Ok(GamepadMapping {
+            guid,
+            name,
+            platform,
+            bindings,
+        })
+    }
+
+    /// Mapping used when a device has no database entry
+    pub fn fallback() -> GamepadMapping {
+        GamepadMapping::parse(FALLBACK_MAPPING).unwrap()
+    }
+
+    /// Checks whether this mapping was written for the given vendor and product ids
+    ///
+    /// Both the current SDL GUID layout (bus, crc, vendor, product, version) and the
+    /// older DirectInput layout ending in "PIDVID" are recognised. The crc and version
+    /// fields are ignored.
+    pub fn matches(&self, vendor_id: u16, product_id: u16) -> bool {
+        let bytes = match guid_bytes(&self.guid) {
+            Some(bytes) => bytes,
+            None => return false,
+        };
+        let word = |pos: usize| bytes[pos] as u16 | (bytes[pos + 1] as u16) << 8;
+        if &bytes[10..16] == b"PIDVID" {
+            return word(0) == vendor_id && word(2) == product_id;
+        }
+        word(4) == vendor_id && word(6) == 0 && word(8) == product_id && word(10) == 0
+    }
+
+    /// Computes the gamepad state corresponding to a raw joystick state
+    pub fn evaluate(&self, joystick: &JoystickState) -> GamepadState {
+        let axes = present_axes(joystick);
+        let mut state = GamepadState::new();
+        let mut axis_values = [0f64; 6];
+        for (input, output) in &self.bindings {
+            let value = match input_value(input, joystick, &axes) {
+                Some(value) => value,
+                None => continue,
+            };
+            match *output {
+                MappingOutput::Button(ref button) => {
+                    if value > 0.5 {
+                        state.buttons[button.index()] = true;
+                    }
+                }
+                MappingOutput::Axis(ref axis, ref range) => {
+                    let value = match (range, input) {
+                        (&AxisRange::Positive, _) => value.abs(),
+                        (&AxisRange::Negative, _) => -value.abs(),
+                        (&AxisRange::Full, &MappingInput::Axis { range: AxisRange::Full, .. })
+                            if axis.is_trigger() =>
+                        {
+                            (value + 1f64) / 2f64
+                        }
+                        (&AxisRange::Full, _) => value,
+                    };
+                    axis_values[axis.index()] += value;
+                }
+            }
+        }
+        for (index, axis) in AXES.iter().enumerate() {
+            let value = if axis.is_trigger() {
+                axis_values[index].clamp(0f64, 1f64)
+            } else {
+                axis_values[index].clamp(-1f64, 1f64)
+            };
+            // SDL sticks are down positive, joystick axes here are up positive
+            state.axes[index] = match *axis {
+                GamepadAxis::LeftY | GamepadAxis::RightY => -value,
+                _ => value,
+            };
+        }
+        state
+    }
+}
+
+/// Parses a gamepad element, None if it is not one of the known elements
+fn parse_output(target: &str) -> Result<Option<MappingOutput>, String> {
+    let (range, name) = split_range(target);
+    if let Some(button) = GamepadButton::from_sdl_name(name) {
+        if range != AxisRange::Full {
+            return Err(format!("Button \"{}\" cannot take an axis range", name));
+        }
+        return Ok(Some(MappingOutput::Button(button)));
+    }
+    if let Some(axis) = GamepadAxis::from_sdl_name(name) {
+        return Ok(Some(MappingOutput::Axis(axis, range)));
+    }
+    Ok(None)
+}
+
+fn parse_input(source: &str) -> Result<MappingInput, String> {
+    let invalid = || format!("Invalid input \"{}\"", source);
+    let (range, rest) = split_range(source);
+    let (rest, invert) = match rest.strip_suffix('~') {
+        Some(rest) => (rest, true),
+        None => (rest, false),
+    };
+    if let Some(index) = rest.strip_prefix('a') {
+        let index = index.parse().map_err(|_| invalid())?;
+        return Ok(MappingInput::Axis {
+            index,
+            range,
+            invert,
+        });
+    }
+    if range != AxisRange::Full || invert {
+        return Err(invalid());
+    }
+    if let Some(index) = rest.strip_prefix('b') {
+        let index = index.parse().map_err(|_| invalid())?;
+        return Ok(MappingInput::Button(index));
+    }
+    if let Some(hat) = rest.strip_prefix('h') {
+        let mut parts = hat.split('.');
+        let hat = parts.next().and_then(|s| s.parse().ok()).ok_or_else(invalid)?;
+        let mask = parts.next().and_then(|s| s.parse().ok()).ok_or_else(invalid)?;
+        return Ok(MappingInput::Hat { hat, mask });
+    }
+    Err(invalid())
+}
+
+fn split_range(element: &str) -> (AxisRange, &str) {
+    if let Some(rest) = element.strip_prefix('+') {
+        (AxisRange::Positive, rest)
+    } else if let Some(rest) = element.strip_prefix('-') {
+        (AxisRange::Negative, rest)
+    } else {
+        (AxisRange::Full, element)
+    }
+}
+
+fn guid_bytes(guid: &str) -> Option<[u8; 16]> {
+    let mut bytes = [0u8; 16];
+    if guid.len() != 32 {
+        return None;
+    }
+    for (pos, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(guid.get(2 * pos..2 * pos + 2)?, 16).ok()?;
+    }
+    Some(bytes)
+}
+
+/// Axes in the order SDL numbers them (usage order, skipping absent axes).
+/// Values are given in device orientation (down positive).
+fn present_axes(joystick: &JoystickState) -> Vec<f64> {
+    let axes = &joystick.axis_states;
+    vec![
+        axes.x,
+        axes.y.map(|y| -y),
+        axes.z.map(|z| -z),
+        axes.rx,
+        axes.ry,
+        axes.rz,
+        axes.slider,
+    ]
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+fn input_value(input: &MappingInput, joystick: &JoystickState, axes: &[f64]) -> Option<f64> {
+    match *input {
+        MappingInput::Button(index) => joystick
+            .button_states
+            .get(index)
+            .map(|&pressed| if pressed { 1f64 } else { 0f64 }),
+        MappingInput::Axis {
+            index,
+            ref range,
+            invert,
+        } => {
+            let mut value = *axes.get(index)?;
+            if invert {
+                value = -value;
+            }
+            Some(match *range {
+                AxisRange::Full => value,
+                AxisRange::Positive => value.max(0f64),
+                AxisRange::Negative => (-value).max(0f64),
+            })
+        }
+        MappingInput::Hat { hat, mask } => {
+            if hat != 0 {
+                return None;
+            }
+            let hat_mask = joystick.hatswitch.as_ref().map_or(0, hat_mask);
+            Some(if hat_mask & mask != 0 { 1f64 } else { 0f64 })
+        }
+    }
+}
+
+fn hat_mask(hat: &::devices::HatSwitch) -> u8 {
+    use devices::HatSwitch;
+    match *hat {
+        HatSwitch::Center => 0,
+        HatSwitch::Up => 1,
+        HatSwitch::UpRight => 1 | 2,
+        HatSwitch::Right => 2,
+        HatSwitch::DownRight => 4 | 2,
+        HatSwitch::Down => 4,
+        HatSwitch::DownLeft => 4 | 8,
+        HatSwitch::Left => 8,
+        HatSwitch::UpLeft => 1 | 8,
+    }
+}
+
+/// State of a joystick as seen through its gamepad mapping
+#[derive(Clone, Debug, PartialEq)]
+pub struct GamepadState {
+    buttons: [bool; 21],
+    axes: [f64; 6],
+}
+
+impl GamepadState {
+    pub fn new() -> GamepadState {
+        GamepadState {
+            buttons: [false; 21],
+            axes: [0f64; 6],
+        }
+    }
+
+    pub fn button(&self, button: &GamepadButton) -> bool {
+        self.buttons[button.index()]
+    }
+
+    pub fn axis(&self, axis: &GamepadAxis) -> f64 {
+        self.axes[axis.index()]
+    }
+
+    pub fn compare_states(&self, other_state: &GamepadState, id: usize) -> Vec<RawEvent> {
+        let mut output = Vec::new();
+        for (index, button) in BUTTONS.iter().enumerate() {
+            if self.buttons[index] != other_state.buttons[index] {
+                let state = if other_state.buttons[index] {
+                    State::Pressed
+                } else {
+                    State::Released
+                };
+                output.push(RawEvent::GamepadButtonEvent(id, button.clone(), state));
+            }
+        }
+        for (index, axis) in AXES.iter().enumerate() {
+            if self.axes[index] != other_state.axes[index] {
+                output.push(RawEvent::GamepadAxisEvent(
+                    id,
+                    axis.clone(),
+                    other_state.axes[index],
+                ));
+            }
+        }
+        output
+    }
+}
+
+impl Default for GamepadState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Collection of gamepad mappings, searched by vendor and product id
+///
+/// Mappings added later take priority, so user supplied mappings can be layered
+/// over a full gamecontrollerdb.txt.
+#[derive(Clone, Debug)]
+pub struct GamepadMappingDb {
+    mappings: Vec<GamepadMapping>,
+    fallback: GamepadMapping,
+}
+
+impl GamepadMappingDb {
+    pub fn new() -> GamepadMappingDb {
+        GamepadMappingDb {
+            mappings: Vec::new(),
+            fallback: GamepadMapping::fallback(),
+        }
+    }
+
+    /// Adds every Windows (or platform independent) entry in the given gamecontrollerdb.txt contents
+    /// Returns the number of mappings added and the errors of the lines that could
+    /// not be parsed, which are skipped.
+    pub fn add_mappings(&mut self, db: &str) -> (usize, Vec<GamepadMappingError>) {
+        let mut added = 0;
+        let mut errors = Vec::new();
+        for (number, line) in db.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mapping = match GamepadMapping::parse(line) {
+                Ok(mapping) => mapping,
+                Err(message) => {
+                    errors.push(GamepadMappingError {
+                        line: number + 1,
+                        message,
+                    });
+                    continue;
+                }
+            };
+            match mapping.platform {
+                Some(ref platform) if platform != "Windows" => continue,
+                _ => (),
+            }
+            self.mappings.push(mapping);
+            added += 1;
+        }
+        (added, errors)
+    }
+
+    /// Adds a single mapping
+    pub fn add_mapping(&mut self, mapping: GamepadMapping) {
+        self.mappings.push(mapping);
+    }
+
+    /// Replaces the mapping used for devices without an entry
+    pub fn set_fallback(&mut self, mapping: GamepadMapping) {
+        self.fallback = mapping;
+    }
+
+    pub fn find(&self, vendor_id: u16, product_id: u16) -> Option<&GamepadMapping> {
+        self.mappings
+            .iter()
+            .rev()
+            .find(|mapping| mapping.matches(vendor_id, product_id))
+    }
+
+    /// Finds the mapping for a device, using the fallback if there is no entry
+    pub fn find_or_fallback(&self, vendor_id: u16, product_id: u16) -> &GamepadMapping {
+        self.find(vendor_id, product_id).unwrap_or(&self.fallback)
+    }
+}
+
+impl Default for GamepadMappingDb {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use devices::{Axes, HatSwitch, RawAxes};
+
+    const PAD: &str = "030000005e0400008e02000000007801,Test Pad,a:b0,b:b1,back:b6,\
+leftx:a0,lefty:a1,lefttrigger:a2,rightx:a3~,+righty:+a4,-righty:-a4,\
+dpup:h0.1,dpleft:h0.8,misc2:b11,misc6:b12,platform:Windows,";
+
+    fn joystick(x: f64, y: f64, z: f64) -> JoystickState {
+        JoystickState {
+            button_states: vec![false; 8],
+            axis_states: Axes {
+                x: Some(x),
+                y: Some(y),
+                z: Some(z),
+                rx: Some(0.0),
+                ry: Some(0.0),
+                ..Axes::new()
+            },
+            hatswitch: Some(HatSwitch::Center),
+            raw_axis_states: RawAxes::new(),
+        }
+    }
+
+    #[test]
+    fn parses_a_line() {
+        let mapping = GamepadMapping::parse(PAD).unwrap();
+        assert_eq!(mapping.guid, "030000005e0400008e02000000007801");
+        assert_eq!(mapping.name, "Test Pad");
+        assert_eq!(mapping.platform, Some("Windows".to_string()));
+        // misc2 and misc6 are skipped
+        assert_eq!(mapping.bindings.len(), 11);
+        assert!(mapping.bindings.contains(&(
+            MappingInput::Axis {
+                index: 3,
+                range: AxisRange::Full,
+                invert: true,
+            },
+            MappingOutput::Axis(GamepadAxis::RightX, AxisRange::Full),
+        )));
+        assert!(mapping.bindings.contains(&(
+            MappingInput::Hat { hat: 0, mask: 8 },
+            MappingOutput::Button(GamepadButton::DPadLeft),
+        )));
+    }
+
+    #[test]
+    fn rejects_invalid_lines() {
+        assert!(GamepadMapping::parse("0300,Short Guid,a:b0").is_err());
+        assert!(GamepadMapping::parse("030000005e0400008e02000000007801").is_err());
+        let guid = "030000005e0400008e02000000007801,Pad,";
+        assert!(GamepadMapping::parse(&format!("{}a", guid)).is_err());
+        assert!(GamepadMapping::parse(&format!("{}+a:b0", guid)).is_err());
+        assert!(GamepadMapping::parse(&format!("{}a:x0", guid)).is_err());
+        assert!(GamepadMapping::parse(&format!("{}a:b0~", guid)).is_err());
+        assert!(GamepadMapping::parse(&format!("{}dpup:h0", guid)).is_err());
+        assert!(GamepadMapping::parse(&format!("{}a:b0,crc:1234,hint:!SDL_HINT:1,", guid)).is_ok());
+    }
+
+    #[test]
+    fn adds_every_valid_line() {
+        let db = "# Windows
+030000005e0400008e02000000007801,Xbox,a:b0,platform:Windows,
+030000004c050000c405000000000000,Broken,a:q0,platform:Windows,
+
+030000004c050000cc09000000000000,PS4,a:b1,platform:Windows,
+030000004c050000e60c000000000000,Linux Only,a:b1,platform:Linux,
+";
+        let mut db_mappings = GamepadMappingDb::new();
+        let (added, errors) = db_mappings.add_mappings(db);
+        assert_eq!(added, 2);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, 3);
+        assert!(db_mappings.find(0x054c, 0x09cc).is_some());
+        assert!(db_mappings.find(0x054c, 0x0ce6).is_none());
+    }
+
+    #[test]
+    fn matches_guids() {
+        let mapping = GamepadMapping::parse(PAD).unwrap();
+        assert!(mapping.matches(0x045e, 0x028e));
+        assert!(!mapping.matches(0x045e, 0x02ea));
+        assert!(!mapping.matches(0x028e, 0x045e));
+        // The crc and version fields are ignored
+        let mapping = GamepadMapping::parse("0300abcd5e0400008e02000010010000,Pad,a:b0").unwrap();
+        assert!(mapping.matches(0x045e, 0x028e));
+        // Older DirectInput GUIDs
+        let mapping = GamepadMapping::parse("4c05c405000000000000504944564944,Pad,a:b0").unwrap();
+        assert!(mapping.matches(0x054c, 0x05c4));
+        assert!(!mapping.matches(0x05c4, 0x054c));
+    }
+
+    #[test]
+    fn later_mappings_take_priority() {
+        let mut mappings = GamepadMappingDb::new();
+        mappings.add_mapping(GamepadMapping::parse(PAD).unwrap());
+        let mut user = GamepadMapping::parse(PAD).unwrap();
+        user.name = "User".to_string();
+        mappings.add_mapping(user);
+        assert_eq!(mappings.find(0x045e, 0x028e).unwrap().name, "User");
+        assert_eq!(
+            mappings.find_or_fallback(0x1234, 0x5678),
+            &GamepadMapping::fallback()
+        );
+    }
+
+    #[test]
+    fn evaluates_buttons_and_hats() {
+        let mapping = GamepadMapping::parse(PAD).unwrap();
+        let mut state = joystick(0.0, 0.0, 1.0);
+        state.button_states[1] = true;
+        state.button_states[6] = true;
+        state.hatswitch = Some(HatSwitch::UpLeft);
+        let gamepad = mapping.evaluate(&state);
+        assert!(!gamepad.button(&GamepadButton::South));
+        assert!(gamepad.button(&GamepadButton::East));
+        assert!(gamepad.button(&GamepadButton::Back));
+        assert!(gamepad.button(&GamepadButton::DPadUp));
+        assert!(gamepad.button(&GamepadButton::DPadLeft));
+        assert!(!gamepad.button(&GamepadButton::DPadDown));
+    }
+
+    #[test]
+    fn evaluates_sticks_up_positive() {
+        let mapping = GamepadMapping::parse(PAD).unwrap();
+        let gamepad = mapping.evaluate(&joystick(0.5, 0.75, 1.0));
+        assert_eq!(gamepad.axis(&GamepadAxis::LeftX), 0.5);
+        assert_eq!(gamepad.axis(&GamepadAxis::LeftY), 0.75);
+        let mut state = joystick(0.0, 0.0, 1.0);
+        state.axis_states.rx = Some(0.5);
+        state.axis_states.ry = Some(-0.25);
+        let gamepad = mapping.evaluate(&state);
+        // a3~ is inverted, the halves of a4 make up the whole of righty
+        assert_eq!(gamepad.axis(&GamepadAxis::RightX), -0.5);
+        assert_eq!(gamepad.axis(&GamepadAxis::RightY), 0.25);
+    }
+
+    #[test]
+    fn evaluates_triggers_from_zero_at_rest() {
+        let mapping = GamepadMapping::parse(PAD).unwrap();
+        // Triggers rest at the device's -1, which is +1 for the up positive z axis
+        let rest = mapping.evaluate(&joystick(0.0, 0.0, 1.0));
+        assert_eq!(rest.axis(&GamepadAxis::LeftTrigger), 0.0);
+        assert!(GamepadState::new().compare_states(&rest, 0).is_empty());
+        let pressed = mapping.evaluate(&joystick(0.0, 0.0, -1.0));
+        assert_eq!(pressed.axis(&GamepadAxis::LeftTrigger), 1.0);
+        assert_eq!(
+            rest.compare_states(&pressed, 3),
+            vec![RawEvent::GamepadAxisEvent(3, GamepadAxis::LeftTrigger, 1.0)]
+        );
+    }
+
+    #[test]
+    fn ignores_missing_inputs() {
+        let mapping = GamepadMapping::parse(PAD).unwrap();
+        let state = JoystickState {
+            button_states: Vec::new(),
+            axis_states: Axes::new(),
+            hatswitch: None,
+            raw_axis_states: RawAxes::new(),
+        };
+        assert_eq!(mapping.evaluate(&state), GamepadState::new());
+    }
+}
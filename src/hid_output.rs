@@ -0,0 +1,596 @@
+//! Output and feature reports for HID devices
+//!
+//! A `HidDevice` opens its own handle to a device, so it can be used alongside
+//! (and independently of) the `RawInputManager`. Reports are built from
+//! usage/value pairs using the device's report descriptor rather than raw bytes:
+//!
+//! ```no_run
+//! # use multiinput::hid_output::*;
+//! # #[cfg(windows)]
+//! # fn main() -> Result<(), HidError> {
+//! let mut board = HidDevice::open(r"\\?\HID#VID_16C0&PID_05DF#7&1234&0&0000#{4d1e55b2-f16f-11cf-88cb-001111000030}")?;
+//! // Player one light on (LED page, usage "Generic Indicator")
+//! board.send_output_report(0, &[HidReportItem::Button { usage_page: 0x08, usage: 0x4B, pressed: true }])?;
+//! # Ok(())
+//! # }
+//! # #[cfg(not(windows))]
+//! # fn main() {}
+//! ```
+//!
+//! Report items and the rumble report layout (`motor_reports`) are available on
+//! every platform; opening devices needs Windows.
+
+#[cfg(windows)]
+use devices::JoystickInfo;
+#[cfg(windows)]
+use force_feedback::{ForceFeedbackError, ForceFeedbackSink};
+#[cfg(windows)]
+use joystick::garbage_vec;
+#[cfg(windows)]
+use rawinput::raw_name_to_hid_with_access;
+use std::fmt;
+#[cfg(windows)]
+use std::mem::MaybeUninit;
+#[cfg(windows)]
+use std::ptr;
+#[cfg(windows)]
+use winapi::shared::hidpi::{
+    HidP_Feature, HidP_GetButtonCaps, HidP_GetCaps, HidP_GetUsageValue, HidP_GetUsages,
+    HidP_GetValueCaps, HidP_InitializeReportForID, HidP_Output, HidP_SetUsageValue,
+    HidP_SetUsages, HIDP_BUTTON_CAPS, HIDP_CAPS, HIDP_REPORT_TYPE, HIDP_STATUS_BUFFER_TOO_SMALL,
+    HIDP_STATUS_INCOMPATIBLE_REPORT_ID, HIDP_STATUS_INVALID_REPORT_LENGTH,
+    HIDP_STATUS_INVALID_REPORT_TYPE, HIDP_STATUS_REPORT_DOES_NOT_EXIST, HIDP_STATUS_SUCCESS,
+    HIDP_STATUS_USAGE_NOT_FOUND, HIDP_STATUS_VALUE_OUT_OF_RANGE, HIDP_VALUE_CAPS,
+    PHIDP_BUTTON_CAPS, PHIDP_PREPARSED_DATA, PHIDP_VALUE_CAPS,
+};
+#[cfg(windows)]
+use winapi::shared::hidsdi::{
+    HidD_FreePreparsedData, HidD_GetFeature, HidD_GetPreparsedData, HidD_SetFeature,
+    HidD_SetOutputReport,
+};
+#[cfg(windows)]
+use winapi::shared::hidusage::USAGE;
+#[cfg(windows)]
+use winapi::shared::ntdef::{NTSTATUS, PCHAR, ULONG};
+#[cfg(windows)]
+use winapi::um::handleapi::CloseHandle;
+#[cfg(windows)]
+use winapi::um::winnt::{GENERIC_READ, GENERIC_WRITE, HANDLE, PVOID};
+
+/// Usage page of the Physical Interface Device (force feedback) controls
+pub const USAGE_PAGE_PID: u16 = 0x0F;
+
+/// Error produced when talking to a HID device
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HidError(pub String);
+
+impl fmt::Display for HidError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "HID request failed: {}", self.0)
+    }
+}
+
+impl std::error::Error for HidError {}
+
+/// Kinds of report that can be written to a device
+#[derive(Eq, PartialEq, Hash, Clone, Debug)]
+pub enum HidReportType {
+    Output,
+    Feature,
+}
+
+#[cfg(windows)]
+impl HidReportType {
+    fn to_raw(&self) -> HIDP_REPORT_TYPE {
+        match *self {
+            HidReportType::Output => HidP_Output,
+            HidReportType::Feature => HidP_Feature,
+        }
+    }
+}
+
+/// A single control in a report
+#[derive(Eq, PartialEq, Hash, Clone, Debug)]
+pub enum HidReportItem {
+    /// On/off control (e.g. a lamp)
+    Button {
+        usage_page: u16,
+        usage: u16,
+        pressed: bool,
+    },
+    /// Control with a logical value (e.g. an LED brightness)
+    Value {
+        usage_page: u16,
+        usage: u16,
+        value: u32,
+    },
+}
+
+/// Description of a control found in the report descriptor
+#[derive(Eq, PartialEq, Hash, Clone, Debug)]
+pub struct HidControl {
+    pub report_type: HidReportType,
+    pub report_id: u8,
+    pub usage_page: u16,
+    pub usage: u16,
+    /// True for on/off controls, false for controls taking a value
+    pub is_button: bool,
+    pub logical_min: i32,
+    pub logical_max: i32,
+}
+
+#[cfg(windows)]
+fn check_status(status: NTSTATUS, action: &str) -> Result<(), HidError> {
+    let reason = match status {
+        HIDP_STATUS_SUCCESS => return Ok(()),
+        HIDP_STATUS_INVALID_REPORT_LENGTH => "invalid report length",
+        HIDP_STATUS_INVALID_REPORT_TYPE => "invalid report type",
+        HIDP_STATUS_BUFFER_TOO_SMALL => "buffer too small",
+        HIDP_STATUS_INCOMPATIBLE_REPORT_ID => "usage is in a different report",
+        HIDP_STATUS_USAGE_NOT_FOUND => "usage not found",
+        HIDP_STATUS_VALUE_OUT_OF_RANGE => "value out of range",
+        HIDP_STATUS_REPORT_DOES_NOT_EXIST => "report does not exist",
+        _ => "unknown error",
+    };
+    Err(HidError(format!("{} ({})", action, reason)))
+}
+
+/// Handle to a HID device for writing output reports and reading/writing feature reports
+#[cfg(windows)]
+pub struct HidDevice {
+    handle: HANDLE,
+    preparsed_data: PHIDP_PREPARSED_DATA,
+    caps: HIDP_CAPS,
+    controls: Vec<HidControl>,
+}
+
+// The handle and preparsed data are owned by this struct and only used through &mut self
+#[cfg(windows)]
+unsafe impl Send for HidDevice {}
+
+#[cfg(windows)]
+impl HidDevice {
+    /// Opens a device by its path (the `name` in the device list)
+    pub fn open(path: &str) -> Result<HidDevice, HidError> {
+        unsafe {
+            let handle = raw_name_to_hid_with_access(path.to_string(), GENERIC_READ | GENERIC_WRITE)
+                .map_err(HidError)?;
+            let mut preparsed_data: PHIDP_PREPARSED_DATA = ptr::null_mut();
+            if HidD_GetPreparsedData(handle, &mut preparsed_data) == 0 {
+                CloseHandle(handle);
+                return Err(HidError(format!("Could not read report descriptor of {}", path)));
+            }
+            let mut caps: HIDP_CAPS = MaybeUninit::zeroed().assume_init();
+            let status = HidP_GetCaps(preparsed_data, &mut caps);
+            let mut device = HidDevice {
+                handle,
+                preparsed_data,
+                caps,
+                controls: Vec::new(),
+            };
+            check_status(status, "Reading capabilities")?;
+            device.controls = device.read_controls(HidReportType::Output)?;
+            let feature_controls = device.read_controls(HidReportType::Feature)?;
+            device.controls.extend(feature_controls);
+            Ok(device)
+        }
+    }
+
+    /// Opens the device behind a joystick
+    pub fn open_joystick(joystick: &JoystickInfo) -> Result<HidDevice, HidError> {
+        HidDevice::open(&joystick.name)
+    }
+
+    /// All output and feature controls of the device
+    pub fn controls(&self) -> &[HidControl] {
+        &self.controls
+    }
+
+    unsafe fn read_controls(&self, report_type: HidReportType) -> Result<Vec<HidControl>, HidError> {
+        let (button_count, value_count) = match report_type {
+            HidReportType::Output => (self.caps.NumberOutputButtonCaps, self.caps.NumberOutputValueCaps),
+            HidReportType::Feature => (self.caps.NumberFeatureButtonCaps, self.caps.NumberFeatureValueCaps),
+        };
+        let mut controls = Vec::new();
+
+        let mut length = button_count;
+        if length > 0 {
+            let mut button_caps: Vec<HIDP_BUTTON_CAPS> = garbage_vec(length as usize);
+            check_status(
+                HidP_GetButtonCaps(
+                    report_type.to_raw(),
+                    button_caps.as_mut_ptr() as PHIDP_BUTTON_CAPS,
+                    &mut length,
+                    self.preparsed_data,
+                ),
+                "Reading button capabilities",
+            )?;
+            button_caps.truncate(length as usize);
+            for caps in button_caps {
+                let (min, max) = if caps.IsRange != 0 {
+                    (caps.u.Range().UsageMin, caps.u.Range().UsageMax)
+                } else {
+                    (caps.u.NotRange().Usage, caps.u.NotRange().Usage)
+                };
+                for usage in min..=max {
+                    controls.push(HidControl {
+                        report_type: report_type.clone(),
+                        report_id: caps.ReportID,
+                        usage_page: caps.UsagePage,
+                        usage,
+                        is_button: true,
+                        logical_min: 0,
+                        logical_max: 1,
+                    });
+                }
+            }
+        }
+
+        let mut length = value_count;
+        if length > 0 {
+            let mut value_caps: Vec<HIDP_VALUE_CAPS> = garbage_vec(length as usize);
+            check_status(
+                HidP_GetValueCaps(
+                    report_type.to_raw(),
+                    value_caps.as_mut_ptr() as PHIDP_VALUE_CAPS,
+                    &mut length,
+                    self.preparsed_data,
+                ),
+                "Reading value capabilities",
+            )?;
+            value_caps.truncate(length as usize);
+            for caps in value_caps {
+                let (min, max) = if caps.IsRange != 0 {
+                    (caps.u.Range().UsageMin, caps.u.Range().UsageMax)
+                } else {
+                    (caps.u.NotRange().Usage, caps.u.NotRange().Usage)
+                };
+                for usage in min..=max {
+                    controls.push(HidControl {
+                        report_type: report_type.clone(),
+                        report_id: caps.ReportID,
+                        usage_page: caps.UsagePage,
+                        usage,
+                        is_button: false,
+                        logical_min: caps.LogicalMin,
+                        logical_max: caps.LogicalMax,
+                    });
+                }
+            }
+        }
+        Ok(controls)
+    }
+
+    fn report_length(&self, report_type: &HidReportType) -> usize {
+        match *report_type {
+            HidReportType::Output => self.caps.OutputReportByteLength as usize,
+            HidReportType::Feature => self.caps.FeatureReportByteLength as usize,
+        }
+    }
+
+    /// Builds a report from the given items, controls not listed are left at zero
+    pub fn build_report(
+        &self,
+        report_type: HidReportType,
+        report_id: u8,
+        items: &[HidReportItem],
+    ) -> Result<Vec<u8>, HidError> {
+        let mut report = vec![0u8; self.report_length(&report_type)];
+        if report.is_empty() {
+            return Err(HidError(format!("Device has no {:?} reports", report_type)));
+        }
+        let report_pointer = report.as_mut_ptr() as PCHAR;
+        let report_length = report.len() as ULONG;
+        unsafe {
+            check_status(
+                HidP_InitializeReportForID(
+                    report_type.to_raw(),
+                    report_id,
+                    self.preparsed_data,
+                    report_pointer,
+                    report_length,
+                ),
+                "Initialising report",
+            )?;
+            for item in items {
+                match *item {
+                    HidReportItem::Button {
+                        usage_page,
+                        usage,
+                        pressed,
+                    } => {
+                        if !pressed {
+                            continue;
+                        }
+                        let mut usages: [USAGE; 1] = [usage];
+                        let mut usage_count: ULONG = 1;
+                        check_status(
+                            HidP_SetUsages(
+                                report_type.to_raw(),
+                                usage_page,
+                                0,
+                                usages.as_mut_ptr(),
+                                &mut usage_count,
+                                self.preparsed_data,
+                                report_pointer,
+                                report_length,
+                            ),
+                            &format!("Setting usage {:#x}:{:#x}", usage_page, usage),
+                        )?;
+                    }
+                    HidReportItem::Value {
+                        usage_page,
+                        usage,
+                        value,
+                    } => {
+                        check_status(
+                            HidP_SetUsageValue(
+                                report_type.to_raw(),
+                                usage_page,
+                                0,
+                                usage,
+                                value,
+                                self.preparsed_data,
+                                report_pointer,
+                                report_length,
+                            ),
+                            &format!("Setting value {:#x}:{:#x}", usage_page, usage),
+                        )?;
+                    }
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// Sends an output report built from the given items
+    pub fn send_output_report(&mut self, report_id: u8, items: &[HidReportItem]) -> Result<(), HidError> {
+        let report = self.build_report(HidReportType::Output, report_id, items)?;
+        self.send_raw_output_report(&report)
+    }
+
+    /// Sends an already built output report (the first byte is the report id)
+    pub fn send_raw_output_report(&mut self, report: &[u8]) -> Result<(), HidError> {
+        let mut report = report.to_vec();
+        if unsafe { HidD_SetOutputReport(self.handle, report.as_mut_ptr() as PVOID, report.len() as ULONG) } == 0 {
+            return Err(HidError("Could not send output report".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Sets a feature report built from the given items
+    pub fn set_feature_report(&mut self, report_id: u8, items: &[HidReportItem]) -> Result<(), HidError> {
+        let mut report = self.build_report(HidReportType::Feature, report_id, items)?;
+        if unsafe { HidD_SetFeature(self.handle, report.as_mut_ptr() as PVOID, report.len() as ULONG) } == 0 {
+            return Err(HidError(format!("Could not set feature report {}", report_id)));
+        }
+        Ok(())
+    }
+
+    /// Reads a feature report, returning the raw bytes (the first byte is the report id)
+    pub fn get_raw_feature_report(&mut self, report_id: u8) -> Result<Vec<u8>, HidError> {
+        let mut report = vec![0u8; self.report_length(&HidReportType::Feature)];
+        if report.is_empty() {
+            return Err(HidError("Device has no feature reports".to_string()));
+        }
+        report[0] = report_id;
+        if unsafe { HidD_GetFeature(self.handle, report.as_mut_ptr() as PVOID, report.len() as ULONG) } == 0 {
+            return Err(HidError(format!("Could not get feature report {}", report_id)));
+        }
+        Ok(report)
+    }
+
+    /// Reads a feature report and decodes every control it contains
+    pub fn get_feature_report(&mut self, report_id: u8) -> Result<Vec<HidReportItem>, HidError> {
+        let mut report = self.get_raw_feature_report(report_id)?;
+        let report_pointer = report.as_mut_ptr() as PCHAR;
+        let report_length = report.len() as ULONG;
+        let mut items = Vec::new();
+        let controls = self
+            .controls
+            .iter()
+            .filter(|control| control.report_type == HidReportType::Feature && control.report_id == report_id);
+        for control in controls {
+            unsafe {
+                if control.is_button {
+                    let mut usages: Vec<USAGE> = vec![0; 256];
+                    let mut usage_count = usages.len() as ULONG;
+                    check_status(
+                        HidP_GetUsages(
+                            HidP_Feature,
+                            control.usage_page,
+                            0,
+                            usages.as_mut_ptr(),
+                            &mut usage_count,
+                            self.preparsed_data,
+                            report_pointer,
+                            report_length,
+                        ),
+                        "Reading buttons",
+                    )?;
+                    items.push(HidReportItem::Button {
+                        usage_page: control.usage_page,
+                        usage: control.usage,
+                        pressed: usages[..usage_count as usize].contains(&control.usage),
+                    });
+                } else {
+                    let mut value: ULONG = 0;
+                    check_status(
+                        HidP_GetUsageValue(
+                            HidP_Feature,
+                            control.usage_page,
+                            0,
+                            control.usage,
+                            &mut value,
+                            self.preparsed_data,
+                            report_pointer,
+                            report_length,
+                        ),
+                        "Reading value",
+                    )?;
+                    items.push(HidReportItem::Value {
+                        usage_page: control.usage_page,
+                        usage: control.usage,
+                        value,
+                    });
+                }
+            }
+        }
+        Ok(items)
+    }
+}
+
+#[cfg(windows)]
+impl Drop for HidDevice {
+    fn drop(&mut self) {
+        unsafe {
+            HidD_FreePreparsedData(self.preparsed_data);
+            CloseHandle(self.handle);
+        }
+    }
+}
+
+#[cfg(windows)]
+impl JoystickInfo {
+    /// Opens this joystick for output and feature reports
+    pub fn open_hid(&self) -> Result<HidDevice, HidError> {
+        HidDevice::open_joystick(self)
+    }
+}
+
+/// Rumble through HID output reports
+///
+/// The first two output values on the Physical Interface Device usage page are
+/// treated as the low and high frequency motors (sent in one output report per
+/// report id). This covers simple rumble pads;
+/// devices with vendor specific reports need their own sink.
+#[cfg(windows)]
+pub struct HidRumbleSink {
+    device: HidDevice,
+    motors: Vec<HidControl>,
+}
+
+#[cfg(windows)]
+impl HidRumbleSink {
+    pub fn open(joystick: &JoystickInfo) -> Result<HidRumbleSink, ForceFeedbackError> {
+        let device = HidDevice::open_joystick(joystick).map_err(|error| ForceFeedbackError(error.0))?;
+        let motors: Vec<HidControl> = device
+            .controls()
+            .iter()
+            .filter(|control| {
+                control.report_type == HidReportType::Output
+                    && control.usage_page == USAGE_PAGE_PID
+                    && !control.is_button
+            })
+            .take(2)
+            .cloned()
+            .collect();
+        if motors.is_empty() {
+            return Err(ForceFeedbackError(format!("{} has no rumble motors", joystick.name)));
+        }
+        Ok(HidRumbleSink { device, motors })
+    }
+}
+
+/// Output reports setting the motors to the given strengths, one per report id
+pub fn motor_reports(motors: &[HidControl], strengths: &[f64]) -> Vec<(u8, Vec<HidReportItem>)> {
+    let mut reports: Vec<(u8, Vec<HidReportItem>)> = Vec::new();
+    for (motor, &strength) in motors.iter().zip(strengths.iter()) {
+        let range = (motor.logical_max - motor.logical_min) as f64;
+        let item = HidReportItem::Value {
+            usage_page: motor.usage_page,
+            usage: motor.usage,
+            value: (motor.logical_min as f64 + strength.clamp(0f64, 1f64) * range).round() as i32
+                as u32,
+        };
+        match reports.iter_mut().find(|report| report.0 == motor.report_id) {
+            Some(report) => report.1.push(item),
+            None => reports.push((motor.report_id, vec![item])),
+        }
+    }
+    reports
+}
+
+#[cfg(windows)]
+impl ForceFeedbackSink for HidRumbleSink {
+    fn set_rumble(&mut self, low: f64, high: f64) -> Result<(), ForceFeedbackError> {
+        for (report_id, items) in motor_reports(&self.motors, &[low, high]) {
+            self.device
+                .send_output_report(report_id, &items)
+                .map_err(|error| ForceFeedbackError(error.0))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn motor(report_id: u8, usage: u16) -> HidControl {
+        HidControl {
+            report_type: HidReportType::Output,
+            report_id,
+            usage_page: USAGE_PAGE_PID,
+            usage,
+            is_button: false,
+            logical_min: 0,
+            logical_max: 255,
+        }
+    }
+
+    fn value(usage: u16, value: u32) -> HidReportItem {
+        HidReportItem::Value {
+            usage_page: USAGE_PAGE_PID,
+            usage,
+            value,
+        }
+    }
+
+    #[test]
+    fn motors_sharing_a_report_are_sent_together() {
+        let motors = [motor(1, 0x50), motor(1, 0x51)];
+        assert_eq!(
+            motor_reports(&motors, &[1.0, 0.5]),
+            vec![(1, vec![value(0x50, 255), value(0x51, 128)])]
+        );
+    }
+
+    #[test]
+    fn motors_in_different_reports_are_sent_separately() {
+        let motors = [motor(1, 0x50), motor(2, 0x50)];
+        assert_eq!(
+            motor_reports(&motors, &[0.0, 1.0]),
+            vec![(1, vec![value(0x50, 0)]), (2, vec![value(0x50, 255)])]
+        );
+    }
+
+    #[test]
+    fn strengths_are_clamped() {
+        let motors = [motor(1, 0x50), motor(1, 0x51)];
+        assert_eq!(
+            motor_reports(&motors, &[-0.5, 2.0]),
+            vec![(1, vec![value(0x50, 0), value(0x51, 255)])]
+        );
+    }
+
+    #[test]
+    fn negative_logical_values_keep_their_bits() {
+        let mut motors = [motor(1, 0x50), motor(1, 0x51)];
+        for motor in motors.iter_mut() {
+            motor.logical_min = -128;
+            motor.logical_max = 127;
+        }
+        assert_eq!(
+            motor_reports(&motors, &[0.0, 0.25]),
+            vec![(
+                1,
+                vec![value(0x50, -128i32 as u32), value(0x51, -64i32 as u32)]
+            )]
+        );
+        assert_eq!(
+            motor_reports(&motors, &[0.6, 1.0]),
+            vec![(1, vec![value(0x50, 25), value(0x51, 127)])]
+        );
+    }
+}
@@ -1,6 +1,10 @@
-use devices::{HatSwitch, JoystickInfo, JoystickState};
+use capture::{CaptureError, CapturedEvent, CapturedReport, HidDescriptor};
+use devices::{DeviceIds, HatSwitch, JoystickInfo, JoystickState};
 use event::RawEvent;
-use std::mem::{transmute, MaybeUninit};
+use gamepad::GamepadState;
+use rawinput::get_joystick_caps;
+use std::collections::HashMap;
+use std::mem::{self, MaybeUninit};
 use winapi::shared::hidpi::{
     HidP_GetUsageValue, HidP_GetUsages, HidP_Input, HIDP_STATUS_INCOMPATIBLE_REPORT_ID,
     HIDP_STATUS_SUCCESS, HIDP_STATUS_INVALID_REPORT_LENGTH, HIDP_STATUS_INVALID_REPORT_TYPE, PHIDP_PREPARSED_DATA,
@@ -8,7 +12,6 @@ use winapi::shared::hidpi::{
 };
 use winapi::shared::hidusage::USAGE;
 use winapi::shared::ntdef::{LONG, PCHAR, ULONG};
-use winapi::um::winuser::RAWHID;
 
 pub unsafe fn garbage_vec<T>(size: usize) -> Vec<T> {
     let mut v = Vec::with_capacity(size);
@@ -16,8 +19,9 @@ pub unsafe fn garbage_vec<T>(size: usize) -> Vec<T> {
     v
 }
 
+/// Decodes a single input report of a joystick, returning the resulting events
 pub fn process_joystick_data(
-    raw_data: &RAWHID,
+    report: &[u8],
     id: usize,
     hid_info: &mut JoystickInfo,
 ) -> Vec<RawEvent> {
@@ -38,8 +42,8 @@ pub fn process_joystick_data(
                     usage.as_mut_ptr(),
                     &mut number_of_presses,
                     hid_info.preparsed_data.as_mut_ptr() as PHIDP_PREPARSED_DATA,
-                    transmute::<_, PCHAR>(raw_data.bRawData.as_ptr()),
-                    raw_data.dwSizeHid
+                    report.as_ptr() as PCHAR,
+                    report.len() as ULONG
                 );
 
             assert!(status != HIDP_STATUS_INVALID_REPORT_LENGTH, "Invalid Report Length!");
@@ -82,8 +86,8 @@ pub fn process_joystick_data(
                 usage_index,
                 &mut value,
                 hid_info.preparsed_data.as_mut_ptr() as PHIDP_PREPARSED_DATA,
-                transmute::<_, PCHAR>(raw_data.bRawData.as_ptr()),
-                raw_data.dwSizeHid,
+                report.as_ptr() as PCHAR,
+                report.len() as ULONG,
             );
             // If the usage does not match the usage page reported by the device we ignore the result
             // (see https://github.com/Jonesey13/multiinput-rust/issues/3)
@@ -148,9 +152,94 @@ pub fn process_joystick_data(
             hatswitch: hatswitch,
             raw_axis_states: raw_axis_states,
         };
-        let new_events = hid_info.state.compare_states(newstate.clone(), id);
-        output.extend(new_events);
-        hid_info.state = newstate;
+        output.extend(update_joystick_state(hid_info, newstate, id));
     }
     output
 }
+
+/// Stores a newly decoded joystick state, returning the resulting events
+pub fn update_joystick_state(
+    hid_info: &mut JoystickInfo,
+    newstate: JoystickState,
+    id: usize,
+) -> Vec<RawEvent> {
+    let first_report = !hid_info.has_reported;
+    let previous = if first_report {
+        None
+    } else {
+        Some(&hid_info.state)
+    };
+    let mut output = JoystickState::report_events(previous, &newstate, id);
+    hid_info.has_reported = true;
+    hid_info.state = newstate;
+    if let Some(ref mapping) = hid_info.gamepad_mapping {
+        // The first report only sets the state, as the initial state event covers it
+        let gamepad_state = mapping.evaluate(&hid_info.state);
+        if !first_report {
+            output.extend(hid_info.gamepad_state.compare_states(&gamepad_state, id));
+        }
+        hid_info.gamepad_state = gamepad_state;
+    }
+    output
+}
+
+/// Joystick as registered, rebuilt from a captured descriptor
+fn descriptor_joystick_info(descriptor: &HidDescriptor) -> Result<JoystickInfo, CaptureError> {
+    let mut preparsed_data = descriptor.preparsed_data.clone();
+    let (caps, button_caps, value_caps) = unsafe { get_joystick_caps(&mut preparsed_data) }
+        .ok_or_else(|| {
+            CaptureError(format!("Invalid preparsed data for joystick {}", descriptor.device))
+        })?;
+    Ok(JoystickInfo {
+        name: String::new(),
+        handle: ::std::ptr::null_mut(),
+        serial: None,
+        product: None,
+        manufacturer: None,
+        ids: DeviceIds::default(),
+        info: unsafe { mem::zeroed() },
+        caps,
+        button_caps: button_caps.clone(),
+        value_caps: value_caps.clone(),
+        preparsed_data,
+        state: JoystickState::new(button_caps, value_caps),
+        is_360_controller: descriptor.is_360_controller,
+        has_reported: false,
+        gamepad_mapping: None,
+        gamepad_state: GamepadState::new(),
+        xinput_index: None,
+    })
+}
+
+/// Decodes captured reports in time order (see `Capture::decode_hid_reports`)
+pub fn decode_hid_reports(
+    descriptors: &[HidDescriptor],
+    reports: &[CapturedReport],
+) -> Result<Vec<CapturedEvent>, CaptureError> {
+    let mut joysticks: HashMap<usize, JoystickInfo> = HashMap::new();
+    let mut descriptors = descriptors.iter().peekable();
+    let mut events = Vec::new();
+    for report in reports {
+        while let Some(descriptor) = descriptors.next_if(|descriptor| descriptor.time <= report.time) {
+            joysticks.insert(descriptor.device, descriptor_joystick_info(descriptor)?);
+        }
+        let joystick = match joysticks.get_mut(&report.device) {
+            Some(joystick) => joystick,
+            None => {
+                return Err(CaptureError(format!(
+                    "No descriptor for joystick {}",
+                    report.device
+                )))
+            }
+        };
+        events.extend(
+            process_joystick_data(&report.report, report.device, joystick)
+                .into_iter()
+                .map(|event| CapturedEvent {
+                    time: report.time,
+                    event,
+                }),
+        );
+    }
+    Ok(events)
+}
@@ -0,0 +1,500 @@
+//! Minimal JSON values, used by captures, action maps and calibration profiles
+
+use std::fmt;
+use std::fmt::Debug;
+use std::fmt::Write;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    /// Written as null if not finite
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    /// Keys in the order they were written
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn parse(text: &str) -> Result<Json, String> {
+        let mut parser = Parser {
+            text: text.as_bytes(),
+            position: 0,
+        };
+        let value = parser.value()?;
+        parser.whitespace();
+        if parser.position < parser.text.len() {
+            return Err(parser.error("Trailing characters"));
+        }
+        Ok(value)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match *self {
+            Json::Object(ref fields) => fields
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Json::Number(number) => Some(number),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Json::Bool(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Json::String(ref string) => Some(string),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match *self {
+            Json::Array(ref values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+impl<'a> From<&'a str> for Json {
+    fn from(string: &'a str) -> Json {
+        Json::String(string.to_string())
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Json {
+        value.map_or(Json::Null, Into::into)
+    }
+}
+
+impl From<String> for Json {
+    fn from(string: String) -> Json {
+        Json::String(string)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Json {
+        Json::Bool(value)
+    }
+}
+
+impl From<f64> for Json {
+    fn from(number: f64) -> Json {
+        Json::Number(number)
+    }
+}
+
+/// Declares the error of a file format, `$name(pub String)`, along with
+/// `invalid`, the error for a malformed file. Messages about malformed files
+/// (including `JsonError`s) start with `$prefix`.
+macro_rules! format_error {
+    ($(#[$attr:meta])* $name:ident, $prefix:expr) => {
+        $(#[$attr])*
+        #[derive(Clone, Debug, PartialEq, Eq)]
+        pub struct $name(pub String);
+
+        impl ::std::fmt::Display for $name {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                write!(f, "{}", self.0)
+            }
+        }
+
+        impl ::std::error::Error for $name {}
+
+        impl From<::std::io::Error> for $name {
+            fn from(error: ::std::io::Error) -> $name {
+                $name(error.to_string())
+            }
+        }
+
+        impl From<::json::JsonError> for $name {
+            fn from(error: ::json::JsonError) -> $name {
+                $name(format!("{}: {}", $prefix, error.0))
+            }
+        }
+
+        fn invalid<T>(message: &str) -> Result<T, $name> {
+            Err($name(format!("{}: {}", $prefix, message)))
+        }
+    };
+}
+
+/// Missing or malformed value in a document (e.g. "bad version"), converted into
+/// the error of the file format being read
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JsonError(pub String);
+
+/// Field of an object
+pub fn field<T: Into<Json>>(name: &str, value: T) -> (String, Json) {
+    (name.to_string(), value.into())
+}
+
+/// Value written by its Debug name (e.g. an axis as "RX")
+pub fn name<T: Debug>(value: &T) -> Json {
+    Json::String(format!("{:?}", value))
+}
+
+/// The value with the given Debug name
+pub fn from_name<T: Debug + Clone>(values: &[T], name: &str) -> Option<T> {
+    values
+        .iter()
+        .find(|value| format!("{:?}", value) == name)
+        .cloned()
+}
+
+/// The value named by the string at `key` (see `from_name`)
+pub fn named<T: Debug + Clone>(values: &[T], json: &Json, key: &str) -> Result<T, JsonError> {
+    json.get(key)
+        .and_then(Json::as_str)
+        .and_then(|name| from_name(values, name))
+        .ok_or_else(|| JsonError(format!("bad {}", key)))
+}
+
+pub fn json_array<'a>(json: &'a Json, key: &str) -> Result<&'a [Json], JsonError> {
+    json.get(key)
+        .and_then(Json::as_array)
+        .ok_or_else(|| JsonError(format!("bad {}", key)))
+}
+
+fn write_string(f: &mut fmt::Formatter, string: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in string.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(value) => write!(f, "{}", value),
+            Json::Number(number) if number.is_finite() => write!(f, "{}", number),
+            Json::Number(_) => f.write_str("null"),
+            Json::String(ref string) => write_string(f, string),
+            Json::Array(ref values) => {
+                f.write_char('[')?;
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                f.write_char(']')
+            }
+            Json::Object(ref fields) => {
+                f.write_char('{')?;
+                for (index, (name, value)) in fields.iter().enumerate() {
+                    if index > 0 {
+                        f.write_char(',')?;
+                    }
+                    write_string(f, name)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: &str) -> String {
+        format!("{} at column {}", message, self.position + 1)
+    }
+
+    fn whitespace(&mut self) {
+        while self
+            .text
+            .get(self.position)
+            .is_some_and(|c| c.is_ascii_whitespace())
+        {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, literal: &str) -> Result<(), String> {
+        if self.text[self.position..].starts_with(literal.as_bytes()) {
+            self.position += literal.len();
+            Ok(())
+        } else {
+            Err(self.error(&format!("Expected {}", literal)))
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.whitespace();
+        match self.text.get(self.position) {
+            Some(&b'n') => self.expect("null").map(|_| Json::Null),
+            Some(&b't') => self.expect("true").map(|_| Json::Bool(true)),
+            Some(&b'f') => self.expect("false").map(|_| Json::Bool(false)),
+            Some(&b'"') => self.string().map(Json::String),
+            Some(&b'[') => {
+                self.position += 1;
+                let mut values = Vec::new();
+                self.whitespace();
+                if self.text.get(self.position) == Some(&b']') {
+                    self.position += 1;
+                    return Ok(Json::Array(values));
+                }
+                loop {
+                    values.push(self.value()?);
+                    self.whitespace();
+                    match self.text.get(self.position) {
+                        Some(&b',') => self.position += 1,
+                        Some(&b']') => {
+                            self.position += 1;
+                            return Ok(Json::Array(values));
+                        }
+                        _ => return Err(self.error("Expected , or ]")),
+                    }
+                }
+            }
+            Some(&b'{') => {
+                self.position += 1;
+                let mut fields = Vec::new();
+                self.whitespace();
+                if self.text.get(self.position) == Some(&b'}') {
+                    self.position += 1;
+                    return Ok(Json::Object(fields));
+                }
+                loop {
+                    self.whitespace();
+                    let name = self.string()?;
+                    self.whitespace();
+                    self.expect(":")?;
+                    fields.push((name, self.value()?));
+                    self.whitespace();
+                    match self.text.get(self.position) {
+                        Some(&b',') => self.position += 1,
+                        Some(&b'}') => {
+                            self.position += 1;
+                            return Ok(Json::Object(fields));
+                        }
+                        _ => return Err(self.error("Expected , or }")),
+                    }
+                }
+            }
+            Some(_) => self.number(),
+            None => Err(self.error("Unexpected end")),
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.position;
+        while self
+            .text
+            .get(self.position)
+            .is_some_and(|&c| c.is_ascii_digit() || b"+-.eE".contains(&c))
+        {
+            self.position += 1;
+        }
+        ::std::str::from_utf8(&self.text[start..self.position])
+            .ok()
+            .and_then(|number| number.parse().ok())
+            .map(Json::Number)
+            .ok_or_else(|| {
+                self.position = start;
+                self.error("Invalid value")
+            })
+    }
+
+    fn hex_escape(&mut self) -> Result<u32, String> {
+        let digits = self
+            .text
+            .get(self.position..self.position + 4)
+            .and_then(|digits| ::std::str::from_utf8(digits).ok())
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| self.error("Invalid escape"))?;
+        self.position += 4;
+        Ok(digits)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect("\"")?;
+        let mut bytes = Vec::new();
+        loop {
+            let c = *self
+                .text
+                .get(self.position)
+                .ok_or_else(|| self.error("Unterminated string"))?;
+            self.position += 1;
+            match c {
+                b'"' => break,
+                b'\\' => {
+                    let escape = *self
+                        .text
+                        .get(self.position)
+                        .ok_or_else(|| self.error("Unterminated string"))?;
+                    self.position += 1;
+                    let unescaped = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let mut code = self.hex_escape()?;
+                            if (0xD800..0xDC00).contains(&code) {
+                                self.expect("\\u")?;
+                                let low = self.hex_escape()?;
+                                if !(0xDC00..0xE000).contains(&low) {
+                                    return Err(self.error("Invalid escape"));
+                                }
+                                code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                            }
+                            ::std::char::from_u32(code)
+                                .ok_or_else(|| self.error("Invalid escape"))?
+                        }
+                        _ => {
+                            self.position -= 1;
+                            return Err(self.error("Invalid escape"));
+                        }
+                    };
+                    let mut buffer = [0; 4];
+                    bytes.extend_from_slice(unescaped.encode_utf8(&mut buffer).as_bytes());
+                }
+                c => bytes.push(c),
+            }
+        }
+        String::from_utf8(bytes).map_err(|_| self.error("Invalid UTF-8"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use event::Axis;
+
+    #[test]
+    fn escapes() {
+        let parsed = Json::parse(r#""\"\\\/\b\f\n\r\t\u00e9""#).unwrap();
+        assert_eq!(parsed, Json::from("\"\\/\u{8}\u{c}\n\r\té"));
+        assert_eq!(
+            Json::from("\"\\\n\r\t\u{1}é").to_string(),
+            r#""\"\\\n\r\t\u0001é""#
+        );
+    }
+
+    #[test]
+    fn surrogate_pairs() {
+        assert_eq!(Json::parse(r#""\ud83d\ude00""#).unwrap(), Json::from("😀"));
+        assert_eq!(Json::parse("\"😀\"").unwrap(), Json::from("😀"));
+        assert_eq!(
+            Json::parse(r#""\ud83d\u0041""#),
+            Err("Invalid escape at column 14".to_string())
+        );
+        assert_eq!(
+            Json::parse(r#""\ude00""#),
+            Err("Invalid escape at column 8".to_string())
+        );
+        assert_eq!(
+            Json::parse(r#""\ud83d""#),
+            Err("Expected \\u at column 8".to_string())
+        );
+    }
+
+    #[test]
+    fn named_values() {
+        let json = Json::parse(r#"{"axis":"RX","other":"W","list":[1]}"#).unwrap();
+        let axes = [Axis::X, Axis::RX];
+        assert_eq!(name(&Axis::RX), Json::from("RX"));
+        assert_eq!(from_name(&axes, "X"), Some(Axis::X));
+        assert_eq!(named(&axes, &json, "axis"), Ok(Axis::RX));
+        assert_eq!(
+            named(&axes, &json, "other"),
+            Err(JsonError("bad other".to_string()))
+        );
+        assert_eq!(
+            named(&axes, &json, "list"),
+            Err(JsonError("bad list".to_string()))
+        );
+        assert_eq!(json_array(&json, "list"), Ok(&[Json::Number(1.0)][..]));
+        assert_eq!(
+            json_array(&json, "axis"),
+            Err(JsonError("bad axis".to_string()))
+        );
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(Json::parse("-0.5"), Ok(Json::Number(-0.5)));
+        assert_eq!(Json::parse("1e3"), Ok(Json::Number(1000.0)));
+        assert_eq!(Json::parse("2E-2"), Ok(Json::Number(0.02)));
+        assert_eq!(Json::parse(" 7 "), Ok(Json::Number(7.0)));
+        assert_eq!(Json::Number(1.5).to_string(), "1.5");
+        assert_eq!(Json::Number(-3.0).to_string(), "-3");
+        assert_eq!(Json::Number(f64::NAN).to_string(), "null");
+        assert_eq!(Json::Number(f64::INFINITY).to_string(), "null");
+    }
+
+    #[test]
+    fn error_positions() {
+        let error = |text: &str| Json::parse(text).unwrap_err();
+        assert_eq!(error("[1] x"), "Trailing characters at column 5");
+        assert_eq!(error("[1 2]"), "Expected , or ] at column 4");
+        assert_eq!(error(r#"{"a":1 "b":2}"#), "Expected , or } at column 8");
+        assert_eq!(error(r#"{"a" 1}"#), "Expected : at column 6");
+        assert_eq!(error("[-]"), "Invalid value at column 2");
+        assert_eq!(error("[tru]"), "Expected true at column 2");
+        assert_eq!(error(r#""abc"#), "Unterminated string at column 5");
+        assert_eq!(error(r#""\x""#), "Invalid escape at column 3");
+        assert_eq!(error("[1,"), "Unexpected end at column 4");
+    }
+
+    #[test]
+    fn round_trip() {
+        let value = Json::Object(vec![
+            ("null".to_string(), Json::Null),
+            ("bool".to_string(), Json::Bool(true)),
+            ("number".to_string(), Json::Number(-12.25)),
+            ("string".to_string(), Json::from("a \"b\"\n")),
+            (
+                "array".to_string(),
+                Json::Array(vec![Json::Array(vec![]), Json::Object(vec![])]),
+            ),
+        ]);
+        let text = value.to_string();
+        assert_eq!(
+            text,
+            r#"{"null":null,"bool":true,"number":-12.25,"string":"a \"b\"\n","array":[[],{}]}"#
+        );
+        assert_eq!(Json::parse(&text), Ok(value.clone()));
+        assert_eq!(value.get("number").and_then(Json::as_f64), Some(-12.25));
+        assert_eq!(
+            value.get("string").and_then(Json::as_str),
+            Some("a \"b\"\n")
+        );
+        assert_eq!(value.get("missing"), None);
+    }
+}
@@ -0,0 +1,308 @@
+//! Keyboard indicator LEDs
+//!
+//! Each keyboard can have its Num, Caps and Scroll lock LEDs set independently,
+//! e.g. to show which player a keyboard belongs to. Note that the operating
+//! system resynchronises the LEDs of all keyboards when a lock key is pressed.
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// State of the indicator LEDs of a keyboard
+#[derive(Eq, PartialEq, Hash, Clone, Copy, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct KeyboardLeds {
+    pub num_lock: bool,
+    pub caps_lock: bool,
+    pub scroll_lock: bool,
+}
+
+impl KeyboardLeds {
+    pub fn new() -> KeyboardLeds {
+        KeyboardLeds::default()
+    }
+
+    /// All LEDs on
+    pub fn all() -> KeyboardLeds {
+        KeyboardLeds {
+            num_lock: true,
+            caps_lock: true,
+            scroll_lock: true,
+        }
+    }
+}
+
+/// Error produced when setting keyboard LEDs
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LedError(pub String);
+
+impl fmt::Display for LedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Setting keyboard LEDs failed: {}", self.0)
+    }
+}
+
+impl std::error::Error for LedError {}
+
+/// Output of LED states to a keyboard
+pub trait LedSink {
+    fn set_leds(&mut self, leds: KeyboardLeds) -> Result<(), LedError>;
+}
+
+#[cfg(windows)]
+pub mod windows {
+    use super::{KeyboardLeds, LedError, LedSink};
+    use devices::KeyboardInfo;
+    use hid_output::{HidDevice, HidReportItem};
+    use rawinput::raw_name_to_hid_with_access;
+    use std::mem;
+    use std::ptr;
+    use winapi::shared::minwindef::{DWORD, LPVOID};
+    use winapi::um::handleapi::CloseHandle;
+    use winapi::um::ioapiset::DeviceIoControl;
+    use winapi::um::winnt::HANDLE;
+
+    // CTL_CODE(FILE_DEVICE_KEYBOARD, 0x0002, METHOD_BUFFERED, FILE_ANY_ACCESS)
+    const IOCTL_KEYBOARD_SET_INDICATORS: DWORD = 0x000B_0008;
+    const KEYBOARD_SCROLL_LOCK_ON: u16 = 1;
+    const KEYBOARD_NUM_LOCK_ON: u16 = 2;
+    const KEYBOARD_CAPS_LOCK_ON: u16 = 4;
+
+    /// Usage page of LEDs in HID reports
+    const USAGE_PAGE_LED: u16 = 0x08;
+    const USAGE_NUM_LOCK: u16 = 0x01;
+    const USAGE_CAPS_LOCK: u16 = 0x02;
+    const USAGE_SCROLL_LOCK: u16 = 0x03;
+
+    #[repr(C)]
+    struct KeyboardIndicatorParameters {
+        unit_id: u16,
+        led_flags: u16,
+    }
+
+    enum WindowsLedOutput {
+        Ioctl(HANDLE),
+        Hid(HidDevice),
+    }
+
+    /// Sets LEDs through the keyboard driver, or through HID output reports if the driver refuses
+    pub struct WindowsLedSink {
+        name: String,
+        output: WindowsLedOutput,
+    }
+
+    impl WindowsLedSink {
+        pub fn open(keyboard: &KeyboardInfo) -> Result<WindowsLedSink, LedError> {
+            let output = match unsafe { raw_name_to_hid_with_access(keyboard.name.clone(), 0) } {
+                Ok(handle) => WindowsLedOutput::Ioctl(handle),
+                Err(_) => WindowsLedOutput::Hid(open_hid(&keyboard.name)?),
+            };
+            Ok(WindowsLedSink {
+                name: keyboard.name.clone(),
+                output,
+            })
+        }
+    }
+
+    fn open_hid(name: &str) -> Result<HidDevice, LedError> {
+        HidDevice::open(name).map_err(|error| LedError(error.0))
+    }
+
+    fn set_leds_ioctl(handle: HANDLE, leds: KeyboardLeds) -> bool {
+        let mut parameters = KeyboardIndicatorParameters {
+            unit_id: 0,
+            led_flags: 0,
+        };
+        if leds.num_lock {
+            parameters.led_flags |= KEYBOARD_NUM_LOCK_ON;
+        }
+        if leds.caps_lock {
+            parameters.led_flags |= KEYBOARD_CAPS_LOCK_ON;
+        }
+        if leds.scroll_lock {
+            parameters.led_flags |= KEYBOARD_SCROLL_LOCK_ON;
+        }
+        let mut bytes_returned: DWORD = 0;
+        let result = unsafe {
+            DeviceIoControl(
+                handle,
+                IOCTL_KEYBOARD_SET_INDICATORS,
+                &mut parameters as *mut KeyboardIndicatorParameters as LPVOID,
+                mem::size_of::<KeyboardIndicatorParameters>() as DWORD,
+                ptr::null_mut(),
+                0,
+                &mut bytes_returned,
+                ptr::null_mut(),
+            )
+        };
+        result != 0
+    }
+
+    fn set_leds_hid(device: &mut HidDevice, leds: KeyboardLeds) -> Result<(), LedError> {
+        let items = [
+            (USAGE_NUM_LOCK, leds.num_lock),
+            (USAGE_CAPS_LOCK, leds.caps_lock),
+            (USAGE_SCROLL_LOCK, leds.scroll_lock),
+        ];
+        let items: Vec<HidReportItem> = items
+            .iter()
+            .map(|&(usage, pressed)| HidReportItem::Button {
+                usage_page: USAGE_PAGE_LED,
+                usage,
+                pressed,
+            })
+            .collect();
+        let report_id = device
+            .controls()
+            .iter()
+            .find(|control| control.usage_page == USAGE_PAGE_LED)
+            .map(|control| control.report_id)
+            .ok_or_else(|| LedError("Keyboard has no LED output report".to_string()))?;
+        device
+            .send_output_report(report_id, &items)
+            .map_err(|error| LedError(error.0))
+    }
+
+    impl LedSink for WindowsLedSink {
+        fn set_leds(&mut self, leds: KeyboardLeds) -> Result<(), LedError> {
+            if let WindowsLedOutput::Ioctl(handle) = self.output {
+                if set_leds_ioctl(handle, leds) {
+                    return Ok(());
+                }
+                // Driver refused the request, switch to HID output reports for good
+                let device = open_hid(&self.name)?;
+                unsafe {
+                    CloseHandle(handle);
+                }
+                self.output = WindowsLedOutput::Hid(device);
+            }
+            match self.output {
+                WindowsLedOutput::Hid(ref mut device) => set_leds_hid(device, leds),
+                WindowsLedOutput::Ioctl(_) => unreachable!(),
+            }
+        }
+    }
+
+    impl Drop for WindowsLedSink {
+        fn drop(&mut self) {
+            if let WindowsLedOutput::Ioctl(handle) = self.output {
+                unsafe {
+                    CloseHandle(handle);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub mod evdev {
+    use super::{KeyboardLeds, LedError, LedSink};
+    use evdev::write_input_event;
+    use std::fs::File;
+    use std::io::Write;
+
+    const EV_SYN: u16 = 0x00;
+    const EV_LED: u16 = 0x11;
+    const SYN_REPORT: u16 = 0;
+    const LED_NUML: u16 = 0x00;
+    const LED_CAPSL: u16 = 0x01;
+    const LED_SCROLLL: u16 = 0x02;
+
+    /// Sets LEDs by writing EV_LED events to an event node
+    ///
+    /// Any writer can be used in place of the node (e.g. a plain file to inspect
+    /// the events written).
+    pub struct EvdevLedSink<W: Write = File> {
+        device: W,
+    }
+
+    impl<W: Write> EvdevLedSink<W> {
+        /// Uses an event node opened for writing (e.g. /dev/input/event3)
+        pub fn new(device: W) -> EvdevLedSink<W> {
+            EvdevLedSink { device }
+        }
+
+        pub fn into_inner(self) -> W {
+            self.device
+        }
+
+        fn write_event(&mut self, event_type: u16, code: u16, value: i32) -> Result<(), LedError> {
+            write_input_event(&mut self.device, event_type, code, value)
+                .map_err(|error| LedError(error.to_string()))
+        }
+    }
+
+    impl<W: Write> LedSink for EvdevLedSink<W> {
+        fn set_leds(&mut self, leds: KeyboardLeds) -> Result<(), LedError> {
+            self.write_event(EV_LED, LED_NUML, leds.num_lock as i32)?;
+            self.write_event(EV_LED, LED_CAPSL, leds.caps_lock as i32)?;
+            self.write_event(EV_LED, LED_SCROLLL, leds.scroll_lock as i32)?;
+            self.write_event(EV_SYN, SYN_REPORT, 0)?;
+            self.device
+                .flush()
+                .map_err(|error| LedError(error.to_string()))
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use evdev::InputEvent;
+        use std::mem;
+
+        /// Type, code and value of each event written
+        fn events(bytes: &[u8]) -> Vec<(u16, u16, i32)> {
+            let size = mem::size_of::<InputEvent>();
+            assert_eq!(bytes.len() % size, 0);
+            bytes
+                .chunks(size)
+                .map(|event| {
+                    // The event follows the timeval
+                    let event = &event[size - 8..];
+                    (
+                        u16::from_ne_bytes([event[0], event[1]]),
+                        u16::from_ne_bytes([event[2], event[3]]),
+                        i32::from_ne_bytes([event[4], event[5], event[6], event[7]]),
+                    )
+                })
+                .collect()
+        }
+
+        #[test]
+        fn writes_each_led_and_a_sync() {
+            let mut sink = EvdevLedSink::new(Vec::new());
+            let leds = KeyboardLeds {
+                num_lock: true,
+                caps_lock: false,
+                scroll_lock: true,
+            };
+            sink.set_leds(leds).unwrap();
+            assert_eq!(
+                events(&sink.into_inner()),
+                vec![
+                    (EV_LED, LED_NUML, 1),
+                    (EV_LED, LED_CAPSL, 0),
+                    (EV_LED, LED_SCROLLL, 1),
+                    (EV_SYN, SYN_REPORT, 0),
+                ]
+            );
+        }
+
+        #[test]
+        fn events_are_appended() {
+            let mut sink = EvdevLedSink::new(Vec::new());
+            sink.set_leds(KeyboardLeds::all()).unwrap();
+            sink.set_leds(KeyboardLeds::new()).unwrap();
+            let events = events(&sink.into_inner());
+            assert_eq!(events.len(), 8);
+            assert_eq!(
+                events[4..7],
+                [
+                    (EV_LED, LED_NUML, 0),
+                    (EV_LED, LED_CAPSL, 0),
+                    (EV_LED, LED_SCROLLL, 0),
+                ]
+            );
+        }
+    }
+}
@@ -9,35 +9,144 @@ extern crate multiinput;
 use multiinput::*;
 fn main() {
     let mut manager = RawInputManager::new().unwrap();
-    manager.register_devices(DeviceType::Joysticks(XInputInclude::True);
+    manager.register_devices(DeviceType::Joysticks(XInputInclude::True));
     manager.register_devices(DeviceType::Keyboards);
     manager.register_devices(DeviceType::Mice);
     'outer: loop{
-        if let Some(event) = manager.get_event(){
-            match event{
-                RawEvent::KeyboardEvent(_,  KeyId::Escape, State::Pressed)
-                    => break 'outer,
-                _ => (),
-            }
-            println!("{:?}", event);
+        let event = manager.wait_event();
+        match event{
+            RawEvent::KeyboardEvent(_,  KeyId::Escape, State::Pressed)
+                => break 'outer,
+            _ => (),
         }
+        println!("{:?}", event);
     }
     println!("Finishing");
 }
 ```
+
+# Serde
+
+With the `serde` feature, events, devices, joystick states, frames, filters and
+the other plain data types implement `Serialize` and `Deserialize`. Their
+representation is part of the public API, so fields and variants are only ever
+added:
+
+* Enums without data (`KeyId`, `MouseButton`, `Axis`, `HatSwitch`, `State`,
+  `EventKind`, `GamepadButton`, ...) are the variant name, e.g. `"Escape"`
+* `RawEvent` is an object with the variant name as its only key and the fields
+  in order, e.g. `{"KeyboardEvent":[0,"Escape","Pressed"]}`
+* Structs are objects keyed by field name, and `DeviceType::Joysticks` is
+  `{"Joysticks":"True"}`
+* `StableDeviceId` is its 16 digit hex string and durations are `{"secs":..,"nanos":..}`
+
+```
+# extern crate multiinput;
+# extern crate serde_json;
+# #[cfg(feature = "serde")]
+# fn main() {
+use multiinput::*;
+
+let state = JoystickState {
+    button_states: vec![true, false],
+    axis_states: Axes { x: Some(-0.5), ..Axes::new() },
+    hatswitch: Some(HatSwitch::UpLeft),
+    raw_axis_states: RawAxes::new(),
+};
+let events = vec![
+    RawEvent::KeyboardEvent(0, KeyId::Escape, State::Pressed),
+    RawEvent::MouseMoveEvent(1, -3, 4),
+    RawEvent::JoystickAxisEvent(0, Axis::RZ, 0.25),
+    RawEvent::JoystickInitialStateEvent(0, state),
+    RawEvent::GamepadButtonEvent(0, GamepadButton::South, State::Released),
+];
+let json = serde_json::to_string(&events).unwrap();
+assert!(json.starts_with(r#"[{"KeyboardEvent":[0,"Escape","Pressed"]},{"MouseMoveEvent":[1,-3,4]}"#));
+let decoded: Vec<RawEvent> = serde_json::from_str(&json).unwrap();
+assert_eq!(format!("{:?}", decoded), format!("{:?}", events));
+
+let mut devices = DevicesDisplayInfo::default();
+devices.keyboards.push(KeyboardDisplayInfo {
+    name: "\\\\?\\HID#VID_046D&PID_C31C".to_string(),
+    stable_id: StableDeviceId(0x0123_4567_89ab_cdef),
+    ..Default::default()
+});
+let json = serde_json::to_string(&devices).unwrap();
+assert!(json.contains(r#""stable_id":"0123456789abcdef""#));
+let decoded: DevicesDisplayInfo = serde_json::from_str(&json).unwrap();
+assert_eq!(format!("{:?}", decoded), format!("{:?}", devices));
+# }
+# #[cfg(not(feature = "serde"))]
+# fn main() {}
+```
 */
 
+#[cfg(windows)]
 extern crate winapi;
+#[cfg(target_os = "linux")]
+extern crate libc;
+#[cfg(feature = "async")]
+extern crate futures;
+#[cfg(feature = "regex")]
+extern crate regex;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
+#[cfg(all(test, feature = "async"))]
+extern crate tokio;
+
+#[macro_use]
+mod json;
 
+pub mod action;
+pub mod assignment;
+pub mod backend;
+pub mod calibration;
+pub mod capture;
+pub mod device_filter;
 pub mod devices;
+#[cfg(target_os = "linux")]
+mod evdev;
 pub mod event;
+pub mod event_queue;
+pub mod force_feedback;
+pub mod frame;
+pub mod gamepad;
+pub mod hid_output;
+#[cfg(windows)]
 mod joystick;
+#[cfg(windows)]
 mod keyboard;
+pub mod led;
 pub mod manager;
+pub mod mock;
+#[cfg(windows)]
 mod mouse;
+#[cfg(windows)]
 mod rawinput;
+#[cfg(windows)]
+mod rawinput_backend;
+#[cfg(windows)]
 mod registrar;
+pub mod replay;
+#[cfg(all(test, feature = "serde"))]
+mod serde_tests;
+pub mod subscription;
+pub mod xinput;
 
+pub use action::ActionMap;
+pub use device_filter::{DeviceFilter, DeviceStatus, StableDeviceId};
+pub use capture::{Capture, CaptureFormat};
 pub use devices::*;
 pub use event::*;
+pub use event_queue::OverflowPolicy;
+pub use force_feedback::{EffectId, ForceFeedbackEffect, Waveform};
+pub use frame::InputFrame;
+pub use gamepad::{GamepadAxis, GamepadButton, GamepadMapping, GamepadMappingDb};
+#[cfg(windows)]
+pub use hid_output::HidDevice;
+pub use hid_output::{HidError, HidReportItem, HidReportType};
+pub use led::KeyboardLeds;
 pub use manager::*;
+pub use subscription::{EventFilter, EventSubscriber, Subscription};
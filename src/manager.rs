@@ -1,44 +1,69 @@
-use std::sync::mpsc::TryIter;
+use backend::{Backend, Waker};
+use capture::{CaptureError, CaptureFormat, HidReportSink, Recording};
+use device_filter::DeviceFilter;
 use devices::DevicesDisplayInfo;
-use devices::{Devices, JoystickState};
+use devices::JoystickState;
 use event::RawEvent;
+use event_queue::{EventQueue, OverflowPolicy, DEFAULT_CAPACITY};
+#[cfg(feature = "async")]
+use event_queue::EventStream;
+use force_feedback::{EffectId, EffectPlayers, ForceFeedbackEffect, ForceFeedbackError, ForceFeedbackSink};
+use force_feedback::UPDATE_INTERVAL;
+use frame::InputFrame;
+#[cfg(feature = "async")]
+use futures::channel::oneshot;
+#[cfg(feature = "async")]
+use futures::{Future, FutureExt};
+use gamepad::GamepadMappingDb;
+use led::{KeyboardLeds, LedError, LedSink};
+#[cfg(windows)]
+use rawinput_backend::RawInputBackend;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use std::sync::mpsc::TryRecvError;
-use rawinput::{get_event, get_joystick_state};
-use registrar;
-use std::time::{SystemTime, UNIX_EPOCH};
-use winapi::shared::minwindef::UINT;
-use winapi::shared::windef::HWND;
-use winapi::um::libloaderapi::GetModuleHandleW;
-use winapi::um::winuser::{
-    CreateWindowExW, DefWindowProcW, RegisterClassExW, CW_USEDEFAULT, HWND_MESSAGE, WNDCLASSEXW,
-};
+use std::time::{Duration, Instant};
+use subscription::{Broadcast, Dispatcher, EventFilter, EventSubscriber, Subscription};
 
 use std::collections::VecDeque;
-use std::ffi::OsStr;
-use std::mem;
-use std::os::windows::ffi::OsStrExt;
-use std::ptr;
 use std::thread;
 use std::thread::JoinHandle;
-use std::collections::HashSet;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
 use std::iter::FromIterator;
 
 use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
 
 enum Command {
     Register(DeviceType),
     FilterDevices(Vec<String>),
     UnfilterDevices,
-    GetEvent,
+    SetDeviceFilter(Option<DeviceFilter>),
     GetJoystickState(usize),
     Finish,
     PrintDeviceList,
     GetDeviceList,
+    SendDeviceList(Sender<DevicesDisplayInfo>),
     GetDeviceStats,
+    SetGamepadMappings(Option<GamepadMappingDb>),
+    RecordHidReports(HidReportSink),
+    RecordEvents(HidReportSink),
+    StartEffect(usize, EffectId, ForceFeedbackEffect, Option<Duration>),
+    StopEffect(usize, EffectId),
+    StopAllEffects(usize),
+    SetEffectGain(usize, f64),
+    SetForceFeedbackOutput(usize, Box<dyn ForceFeedbackSink + Send>),
+    SetKeyboardLeds(usize, KeyboardLeds),
+    #[cfg(feature = "async")]
+    GetJoystickStateAsync(usize, oneshot::Sender<Option<JoystickState>>),
+    #[cfg(feature = "async")]
+    GetDeviceListAsync(oneshot::Sender<DevicesDisplayInfo>),
 }
 
 /// Types of Raw Input Device
-#[derive(PartialEq, Eq, Clone, Hash)]
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum DeviceType {
     Mice,
     Keyboards,
@@ -51,13 +76,19 @@ pub enum DeviceType {
 /// rumble or the central X button)
 /// Please see https://en.wikipedia.org/wiki/DirectInput#Xbox_360_Controller_support
 /// for more details
-#[derive(PartialEq, Eq, Clone, Hash)]
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum XInputInclude {
     True,
     False,
+    /// Reads Xbox controllers through the XInput API instead of rawinput
+    /// (separate triggers, guide button and rumble; see the xinput module)
+    /// Only controllers connected at registration are picked up.
+    XInputApi,
 }
 
-#[derive(Default)]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct DeviceStats {
     pub number_of_mice: usize,
     pub number_of_keyboards: usize,
@@ -68,211 +99,564 @@ pub struct DeviceStats {
 pub struct RawInputManager {
     joiner: Option<JoinHandle<()>>,
     sender: Sender<Command>,
-    receiver: Receiver<RawEvent>,
+    waker: Arc<Mutex<Waker>>,
+    events: EventQueue,
+    broadcast: Broadcast,
+    dispatcher: Option<Dispatcher>,
+    frame: (EventSubscriber, InputFrame),
     joystick_receiver: Receiver<Option<JoystickState>>,
     device_info_receiver: Receiver<DevicesDisplayInfo>,
     device_stats_receiver: Receiver<DeviceStats>,
+    effect_receiver: Receiver<Result<(), ForceFeedbackError>>,
+    next_effect_id: usize,
+    led_receiver: Receiver<Result<(), LedError>>,
 }
 
 impl RawInputManager {
+    /// Reads input through rawinput (only available on windows)
     pub fn new() -> Result<RawInputManager, &'static str> {
+        RawInputManager::with_event_queue(DEFAULT_CAPACITY, OverflowPolicy::default())
+    }
+
+    /// Creates a manager holding at most capacity unread events
+    /// The policy decides which events are lost when the caller falls behind.
+    #[cfg(windows)]
+    pub fn with_event_queue(
+        capacity: usize,
+        policy: OverflowPolicy,
+    ) -> Result<RawInputManager, &'static str> {
+        Ok(RawInputManager::with_backend(RawInputBackend::new, capacity, policy))
+    }
+
+    /// Creates a manager holding at most capacity unread events
+    /// The policy decides which events are lost when the caller falls behind.
+    #[cfg(not(windows))]
+    pub fn with_event_queue(
+        _capacity: usize,
+        _policy: OverflowPolicy,
+    ) -> Result<RawInputManager, &'static str> {
+        Err("Rawinput is only available on windows")
+    }
+
+    /// Creates a manager reading input from the backend built by make_backend
+    /// The backend is built and used on the manager thread.
+    pub fn with_backend<F, B>(make_backend: F, capacity: usize, policy: OverflowPolicy) -> RawInputManager
+    where
+        F: FnOnce() -> B + Send + 'static,
+        B: Backend + 'static,
+    {
+        let events = EventQueue::new(capacity, policy);
+        let thread_events = events.clone();
+        let broadcast = Broadcast::new();
+        let thread_broadcast = broadcast.clone();
+        // Subscribed before any input is read, so the first frame misses nothing
+        let frame_events = EventSubscriber::new(&broadcast, EventFilter::new(), capacity, policy);
         let (tx, rx) = channel();
-        let (tx2, rx2) = channel();
+        let (tx_waker, rx_waker) = channel();
         let (tx_joy, rx_joy) = channel();
         let (tx_devices, rx_devices) = channel();
         let (tx_stats, rx_stats) = channel();
+        let (tx_effect, rx_effect) = channel();
+        let (tx_led, rx_led) = channel();
 
         let joiner = thread::spawn(move || {
-            let hwnd = setup_message_window();
+            let mut backend = make_backend();
+            tx_waker.send(backend.waker()).unwrap();
             let mut event_queue = VecDeque::new();
-            let mut devices = Devices::new();
             let mut exit = false;
-            let mut registrar = registrar::RawInputRegistrar::new();
+            let mut gamepad_mappings: Option<GamepadMappingDb> = None;
+            let mut effect_players = EffectPlayers::new();
+            let mut led_sinks: HashMap<usize, Box<dyn LedSink>> = HashMap::new();
+            let mut device_names: Option<HashSet<String>> = None;
+            let mut device_filter: Option<DeviceFilter> = None;
+            let mut devices = DevicesDisplayInfo::default();
+            let mut hid_sink: Option<HidReportSink> = None;
+            let mut event_sinks: Vec<HidReportSink> = Vec::new();
             while !exit {
                 match rx.try_recv() {
                     Err(TryRecvError::Disconnected) => {
                         panic!("Multinput Thread Unexpectedly Disconnected!")
                     }
                     Err(TryRecvError::Empty) => {
-                        std::thread::sleep(std::time::Duration::from_nanos(1));
+                        backend.read_events(&mut event_queue);
+                        if hid_sink.as_ref().is_some_and(|sink| !sink.send_events(&event_queue)) {
+                            backend.record_hid_reports(None);
+                            hid_sink = None;
+                        }
+                        if let Some(ref filter) = device_filter {
+                            event_queue.retain(|event| {
+                                devices
+                                    .describe_event(event)
+                                    .is_some_and(|device| filter.matches(&device, event.kind()))
+                            });
+                        }
+                        event_sinks.retain(|sink| sink.send_events(&event_queue));
+                        thread_broadcast.send(&event_queue);
+                        thread_events.extend(event_queue.drain(..));
+                        let mut timeout = None;
+                        if effect_players.is_active() {
+                            effect_players.update(Instant::now());
+                            timeout = Some(UPDATE_INTERVAL);
+                        }
+                        backend.wait(timeout);
                     }
                     Ok(Command::Register(thing)) => {
-                        devices = registrar.register_devices(hwnd, thing).unwrap();
+                        effect_players.clear();
+                        led_sinks.clear();
+                        backend.register_devices(thing).unwrap();
+                        if let Some(ref mappings) = gamepad_mappings {
+                            backend.set_gamepad_mappings(Some(mappings));
+                        }
+                        devices = backend.device_list();
+                        devices.apply_filters(device_names.as_ref(), device_filter.as_ref());
                     }
                     Ok(Command::FilterDevices(strings)) => {
-                        devices.filter_device_map(HashSet::from_iter(strings.into_iter()));
+                        let names = HashSet::from_iter(strings);
+                        backend.filter_devices(names.clone());
+                        device_names = Some(names);
                     }
                     Ok(Command::UnfilterDevices) => {
-                        devices.reset_device_map();
+                        backend.unfilter_devices();
+                        device_names = None;
                     }
-                    Ok(Command::GetEvent) => {
-                        if let Some(event) = get_event(&mut event_queue, &mut devices) {
-                            tx2.send(event).unwrap()
-                        }
+                    Ok(Command::SetDeviceFilter(filter)) => {
+                        device_filter = filter;
+                        devices = backend.device_list();
+                        devices.apply_filters(device_names.as_ref(), device_filter.as_ref());
                     }
                     Ok(Command::Finish) => {
                         exit = true;
                     }
                     Ok(Command::GetJoystickState(id)) => {
-                        tx_joy.send(get_joystick_state(&devices, id)).unwrap()
+                        tx_joy.send(backend.joystick_state(id)).unwrap()
+                    }
+                    Ok(Command::PrintDeviceList) => backend.print_device_list(),
+                    Ok(Command::GetDeviceList) => {
+                        let mut devices = backend.device_list();
+                        devices.apply_filters(device_names.as_ref(), device_filter.as_ref());
+                        tx_devices.send(devices).unwrap()
+                    }
+                    Ok(Command::SendDeviceList(reply)) => {
+                        let mut devices = backend.device_list();
+                        devices.apply_filters(device_names.as_ref(), device_filter.as_ref());
+                        let _ = reply.send(devices);
+                    }
+                    Ok(Command::GetDeviceStats) => tx_stats.send(get_device_stats(&backend.device_list())).unwrap(),
+                    Ok(Command::SetGamepadMappings(mappings)) => {
+                        backend.set_gamepad_mappings(mappings.as_ref());
+                        gamepad_mappings = mappings;
+                    }
+                    Ok(Command::RecordHidReports(sink)) => {
+                        backend.record_hid_reports(Some(sink.clone()));
+                        hid_sink = Some(sink);
+                    }
+                    Ok(Command::RecordEvents(sink)) => event_sinks.push(sink),
+                    Ok(Command::StartEffect(id, effect_id, effect, duration)) => {
+                        let result = effect_players.start(
+                            id,
+                            || backend.open_force_feedback(id),
+                            effect_id,
+                            effect,
+                            duration,
+                        );
+                        tx_effect.send(result).unwrap();
+                    }
+                    Ok(Command::StopEffect(id, effect_id)) => effect_players.stop(id, effect_id),
+                    Ok(Command::StopAllEffects(id)) => effect_players.stop_all(id),
+                    Ok(Command::SetEffectGain(id, gain)) => {
+                        let result =
+                            effect_players.set_gain(id, || backend.open_force_feedback(id), gain);
+                        tx_effect.send(result).unwrap();
+                    }
+                    Ok(Command::SetForceFeedbackOutput(id, sink)) => effect_players.set_output(id, sink),
+                    Ok(Command::SetKeyboardLeds(id, leds)) => {
+                        let result = match led_sinks.entry(id) {
+                            Entry::Occupied(entry) => entry.into_mut().set_leds(leds),
+                            Entry::Vacant(entry) => backend
+                                .open_keyboard_leds(id)
+                                .and_then(|sink| entry.insert(sink).set_leds(leds)),
+                        };
+                        tx_led.send(result).unwrap();
+                    }
+                    #[cfg(feature = "async")]
+                    Ok(Command::GetJoystickStateAsync(id, reply)) => {
+                        let _ = reply.send(backend.joystick_state(id));
+                    }
+                    #[cfg(feature = "async")]
+                    Ok(Command::GetDeviceListAsync(reply)) => {
+                        let mut devices = backend.device_list();
+                        devices.apply_filters(device_names.as_ref(), device_filter.as_ref());
+                        let _ = reply.send(devices);
                     }
-                    Ok(Command::PrintDeviceList) => print_raw_device_list(&devices),
-                    Ok(Command::GetDeviceList) => tx_devices.send(devices.clone().into()).unwrap(),
-                    Ok(Command::GetDeviceStats) => tx_stats.send(get_device_stats(&devices)).unwrap(),
                 };
             }
         });
-        Ok(RawInputManager {
+        RawInputManager {
             joiner: Some(joiner),
             sender: tx,
-            receiver: rx2,
+            waker: Arc::new(Mutex::new(rx_waker.recv().unwrap())),
+            events,
+            broadcast,
+            dispatcher: None,
+            frame: (frame_events, InputFrame::default()),
             joystick_receiver: rx_joy,
             device_stats_receiver: rx_stats,
-            device_info_receiver: rx_devices
-        })
+            device_info_receiver: rx_devices,
+            effect_receiver: rx_effect,
+            next_effect_id: 0,
+            led_receiver: rx_led,
+        }
+    }
+
+    /// Sends a command to the thread, waking it up if it is waiting for input
+    fn send_command(&self, command: Command) {
+        self.sender.send(command).unwrap();
+        (self.waker.lock().unwrap())();
     }
 
     /// Allows Raw Input devices of type device_type to be received from the Input Manager
     pub fn register_devices(&mut self, device_type: DeviceType) {
-        self.sender.send(Command::Register(device_type)).unwrap();
+        self.send_command(Command::Register(device_type));
     }
 
     /// Filters events returned to the list of names provided by the device_names list
     /// Warning: you still need to register the corresponding device types beforehand for this to work!
     pub fn filter_devices(&mut self, device_names: Vec<String>) {
-        self.sender.send(Command::FilterDevices(device_names)).unwrap();
+        self.send_command(Command::FilterDevices(device_names));
     }
 
     /// Undoes the application of filter_devices()
     pub fn unfilter_devices(&mut self) {
-        self.sender.send(Command::UnfilterDevices).unwrap();
+        self.send_command(Command::UnfilterDevices);
+    }
+
+    /// Only reports events passing the filter (in addition to filter_devices)
+    /// Devices are matched as listed at the last registration; whether a device
+    /// passes is shown by the status in get_device_list.
+    pub fn set_device_filter(&mut self, filter: DeviceFilter) {
+        self.send_command(Command::SetDeviceFilter(Some(filter)));
+    }
+
+    /// Undoes the application of set_device_filter()
+    pub fn clear_device_filter(&mut self) {
+        self.send_command(Command::SetDeviceFilter(None));
+    }
+
+    /// Translates joystick input to the standard gamepad layout using the given mappings
+    /// Joysticks without an entry use the database's fallback mapping.
+    /// Gamepad events are sent alongside the raw joystick events.
+    pub fn set_gamepad_mappings(&mut self, mappings: GamepadMappingDb) {
+        self.send_command(Command::SetGamepadMappings(Some(mappings)));
+    }
+
+    /// Stops sending gamepad events
+    pub fn clear_gamepad_mappings(&mut self) {
+        self.send_command(Command::SetGamepadMappings(None));
+    }
+
+    /// Rumbles a joystick for the given duration (motor strengths lie in [0, 1])
+    pub fn set_rumble(
+        &mut self,
+        id: usize,
+        low: f64,
+        high: f64,
+        duration: Duration,
+    ) -> Result<EffectId, ForceFeedbackError> {
+        self.play_effect(id, ForceFeedbackEffect::Rumble { low, high }, Some(duration))
+    }
+
+    /// Starts a force feedback effect on a joystick, playing until stopped if no duration is given
+    /// Effects other than rumble need a device that plays them itself (see the force_feedback module).
+    pub fn play_effect(
+        &mut self,
+        id: usize,
+        effect: ForceFeedbackEffect,
+        duration: Option<Duration>,
+    ) -> Result<EffectId, ForceFeedbackError> {
+        let effect_id = EffectId(self.next_effect_id);
+        self.next_effect_id += 1;
+        self.send_command(Command::StartEffect(id, effect_id, effect, duration));
+        self.effect_receiver.recv().unwrap().map(|_| effect_id)
+    }
+
+    /// Stops a force feedback effect
+    pub fn stop_effect(&mut self, id: usize, effect_id: EffectId) {
+        self.send_command(Command::StopEffect(id, effect_id));
+    }
+
+    /// Stops all force feedback effects on a joystick
+    pub fn stop_all_effects(&mut self, id: usize) {
+        self.send_command(Command::StopAllEffects(id));
+    }
+
+    /// Scales all force feedback effects of a joystick by gain (in [0, 1])
+    pub fn set_effect_gain(&mut self, id: usize, gain: f64) -> Result<(), ForceFeedbackError> {
+        self.send_command(Command::SetEffectGain(id, gain));
+        self.effect_receiver.recv().unwrap()
+    }
+
+    /// Plays the force feedback effects of a joystick on sink instead of the backend's output
+    /// (e.g. an `EvdevForceFeedbackSink` for the joystick's event node on Linux)
+    /// The sink is kept when devices are re-registered.
+    pub fn set_force_feedback_output(&mut self, id: usize, sink: Box<dyn ForceFeedbackSink + Send>) {
+        self.send_command(Command::SetForceFeedbackOutput(id, sink));
+    }
+
+    /// Sets the Num, Caps and Scroll lock LEDs of a keyboard
+    /// The LEDs are reset by the system when any lock key is pressed.
+    pub fn set_keyboard_leds(&mut self, id: usize, leds: KeyboardLeds) -> Result<(), LedError> {
+        self.send_command(Command::SetKeyboardLeds(id, leds));
+        self.led_receiver.recv().unwrap()
     }
 
     /// Get Event from the Input Manager
+    /// Events are read continuously in the background, so this never waits on the thread.
     pub fn get_event(&mut self) -> Option<RawEvent> {
-        self.sender.send(Command::GetEvent).unwrap();
-        match self.receiver.try_recv() {
-            Ok(event) => Some(event),
-            _ => None 
-        }
+        self.events.pop()
+    }
+
+    /// Blocks until an event is available
+    /// This only returns once input arrives from a registered device.
+    pub fn wait_event(&mut self) -> RawEvent {
+        self.events.pop_until(None).unwrap()
+    }
+
+    /// Blocks until an event is available or the timeout has passed
+    pub fn wait_event_timeout(&mut self, timeout: Duration) -> Option<RawEvent> {
+        self.events.pop_until(Some(Instant::now() + timeout))
     }
 
     /// Get All Events from the Input Manager
-    pub fn get_events(&mut self) -> TryIter<RawEvent> {
-        self.sender.send(Command::GetEvent).unwrap();
-        self.receiver.try_iter()
+    pub fn get_events(&mut self) -> Vec<RawEvent> {
+        self.events.drain()
+    }
+
+    /// Number of events lost so far because the event queue was full
+    pub fn dropped_events(&self) -> usize {
+        self.events.dropped()
+    }
+
+    /// Creates a consumer receiving a copy of every event matching filter
+    /// Its queue has the capacity and overflow policy of the manager's.
+    pub fn subscribe(&self, filter: EventFilter) -> EventSubscriber {
+        EventSubscriber::new(&self.broadcast, filter, self.events.capacity(), self.events.policy())
+    }
+
+    /// Creates a consumer receiving a copy of every event matching filter,
+    /// holding at most capacity unread events
+    pub fn subscribe_with_queue(
+        &self,
+        filter: EventFilter,
+        capacity: usize,
+        policy: OverflowPolicy,
+    ) -> EventSubscriber {
+        EventSubscriber::new(&self.broadcast, filter, capacity, policy)
+    }
+
+    /// Calls callback with every event matching filter until the subscription is dropped
+    /// Callbacks run in order on a separate thread (see the subscription module)
+    /// and receive copies, so events are still returned by get_event.
+    /// No events are lost: events wait for slow callbacks without limit.
+    pub fn on_event<F>(&mut self, filter: EventFilter, callback: F) -> Subscription
+    where
+        F: FnMut(&RawEvent) + Send + 'static,
+    {
+        if self.dispatcher.is_none() {
+            let queue = self.broadcast.add(EventFilter::new(), usize::MAX, OverflowPolicy::DropNewest);
+            self.dispatcher = Some(Dispatcher::new(queue));
+        }
+        self.dispatcher.as_ref().unwrap().subscribe(filter, callback)
+    }
+
+    /// Writes the device list and all following events to a capture (see the capture module)
+    /// Register devices first so they are listed in the capture.
+    pub fn start_recording<W: Write + Send + 'static>(
+        &self,
+        writer: W,
+        format: CaptureFormat,
+    ) -> Result<Recording<W>, CaptureError> {
+        let (recording, sink) = Recording::start(&self.get_device_list(), writer, format)?;
+        self.send_command(Command::RecordEvents(sink));
+        Ok(recording)
+    }
+
+    /// Writes the device list, joystick descriptors and raw reports, and the events read
+    /// from them to a capture, so decoding can be repeated without the devices
+    /// Events are recorded before device filters apply. Only the rawinput backend
+    /// provides reports, so other backends record events alone.
+    pub fn start_hid_recording<W: Write + Send + 'static>(
+        &self,
+        writer: W,
+        format: CaptureFormat,
+    ) -> Result<Recording<W>, CaptureError> {
+        let (recording, sink) = Recording::start(&self.get_device_list(), writer, format)?;
+        self.send_command(Command::RecordHidReports(sink));
+        Ok(recording)
+    }
+
+    /// Collects the input since the previous call into a frame
+    /// Frames are built from their own copy of the events (get_event is unaffected),
+    /// the first one holding all input since the manager was created, so call this
+    /// once per iteration of the game loop.
+    pub fn begin_frame(&mut self) -> InputFrame {
+        let (ref subscriber, ref mut frame) = self.frame;
+        frame.next_frame();
+        for event in subscriber.get_events() {
+            frame.apply(&event);
+        }
+        frame.clone()
     }
 
     /// Get Joystick State from the Input Manager
     pub fn get_joystick_state(&mut self, id: usize) -> Option<JoystickState> {
-        self.sender.send(Command::GetJoystickState(id)).unwrap();
+        self.send_command(Command::GetJoystickState(id));
         self.joystick_receiver.recv().unwrap()
     }
 
     /// Print List of Potential Input Devices
     pub fn print_device_list(&self) {
-        self.sender.send(Command::PrintDeviceList).unwrap();
+        self.send_command(Command::PrintDeviceList);
     }
 
     /// Get Device Stats (number of connected devices)
     pub fn get_device_stats(&self) -> DeviceStats {
-        self.sender.send(Command::GetDeviceStats).unwrap();
+        self.send_command(Command::GetDeviceStats);
         self.device_stats_receiver.recv().unwrap()
     }
 
     /// Get Device list
     pub fn get_device_list(&self) -> DevicesDisplayInfo {
-            self.sender.send(Command::GetDeviceList).unwrap();
+            self.send_command(Command::GetDeviceList);
             self.device_info_receiver.recv().unwrap()
     }
+
+    /// Gets the device list for code that does not hold the manager
+    pub fn device_list_source(&self) -> DeviceListSource {
+        DeviceListSource {
+            sender: self.sender.clone(),
+            waker: self.waker.clone(),
+        }
+    }
+
+    /// Stream of events for async executors
+    /// Events taken by the stream are not returned by get_event (and vice versa).
+    /// The stream ends once the manager is dropped.
+    #[cfg(feature = "async")]
+    pub fn event_stream(&self) -> EventStream {
+        self.events.stream()
+    }
+
+    /// Get Joystick State without blocking the calling thread
+    #[cfg(feature = "async")]
+    pub fn get_joystick_state_async(&self, id: usize) -> impl Future<Output = Option<JoystickState>> {
+        let (reply, receiver) = oneshot::channel();
+        self.send_command(Command::GetJoystickStateAsync(id, reply));
+        receiver.map(|state| state.unwrap_or(None))
+    }
+
+    /// Get Device list without blocking the calling thread
+    #[cfg(feature = "async")]
+    pub fn get_device_list_async(&self) -> impl Future<Output = DevicesDisplayInfo> {
+        let (reply, receiver) = oneshot::channel();
+        self.send_command(Command::GetDeviceListAsync(reply));
+        receiver.map(|devices| devices.unwrap_or_default())
+    }
 }
 
 impl Drop for RawInputManager {
     fn drop(&mut self) {
-        self.sender.send(Command::Finish).unwrap();
+        self.send_command(Command::Finish);
         self.joiner.take().unwrap().join().unwrap();
+        self.events.close();
+        self.broadcast.close();
+        self.dispatcher.take();
     }
 }
 
-fn setup_message_window() -> HWND {
-    let hwnd: HWND;
-    unsafe {
-        let hinstance = GetModuleHandleW(ptr::null());
-        if hinstance == ptr::null_mut() {
-            panic!("Instance Generation Failed");
-        }
-
-        let current_time = SystemTime::now();
-        let classname_str = format!(
-            "RawInput Hidden Window - {:?}",
-            current_time.duration_since(UNIX_EPOCH).unwrap()
-        );
-
-        let classname = OsStr::new(&classname_str)
-            .encode_wide()
-            .chain(Some(0).into_iter())
-            .collect::<Vec<_>>();
-
-        let wcex = WNDCLASSEXW {
-            cbSize: (mem::size_of::<WNDCLASSEXW>()) as UINT,
-            cbClsExtra: 0,
-            cbWndExtra: 0,
-            hbrBackground: ptr::null_mut(),
-            hCursor: ptr::null_mut(),
-            hIcon: ptr::null_mut(),
-            hIconSm: ptr::null_mut(),
-            hInstance: hinstance,
-            lpfnWndProc: Some(DefWindowProcW),
-            lpszClassName: classname.as_ptr(),
-            lpszMenuName: ptr::null_mut(),
-            style: 0,
-        };
-        let a = RegisterClassExW(&wcex);
-        if a == 0 {
-            panic!("Registering WindowClass Failed!");
-        }
-
-        hwnd = CreateWindowExW(
-            0,
-            classname.as_ptr(),
-            classname.as_ptr(),
-            0,
-            CW_USEDEFAULT,
-            CW_USEDEFAULT,
-            CW_USEDEFAULT,
-            CW_USEDEFAULT,
-            HWND_MESSAGE,
-            ptr::null_mut(),
-            hinstance,
-            ptr::null_mut(),
-        );
-        if hwnd.is_null() {
-            panic!("Window Creation Failed!");
-        }
-    }
-    hwnd
+/// Gets the device list of a `RawInputManager` (see `device_list_source`)
+#[derive(Clone)]
+pub struct DeviceListSource {
+    sender: Sender<Command>,
+    waker: Arc<Mutex<Waker>>,
 }
 
-/// Prints a list of all available raw input devices
-fn print_raw_device_list(devices: &Devices) {
-    println!("Mice:");
-    for mouse in &devices.mice {
-        println!("{:?}", mouse);
-    }
-    println!("Keyboards:");
-    for keyboard in &devices.keyboards {
-        println!("{:?}", keyboard);
-    }
-    println!("Hids:");
-    for joystick in &devices.joysticks {
-        println!("{:?}", joystick);
+impl DeviceListSource {
+    /// Current device list, or None once the manager is dropped
+    pub fn get(&self) -> Option<DevicesDisplayInfo> {
+        let (reply, receiver) = channel();
+        self.sender.send(Command::SendDeviceList(reply)).ok()?;
+        (self.waker.lock().unwrap())();
+        receiver.recv().ok()
     }
 }
 
-fn get_device_stats(devices: &Devices) -> DeviceStats {
+fn get_device_stats(devices: &DevicesDisplayInfo) -> DeviceStats {
     DeviceStats {
         number_of_mice: devices.mice.len(),
         number_of_keyboards: devices.keyboards.len(),
         number_of_joysticks: devices.joysticks.len(),
     }
 }
+
+#[cfg(all(test, feature = "async"))]
+mod tests {
+    use super::*;
+    use devices::MouseDisplayInfo;
+    use event_queue::DEFAULT_CAPACITY;
+    use mock::{MockBackend, MockHandle};
+
+    fn mock_manager() -> (RawInputManager, MockHandle) {
+        let backend = MockBackend::new();
+        let handle = backend.handle();
+        let mut devices = DevicesDisplayInfo::default();
+        devices.mice.push(MouseDisplayInfo {
+            name: "mouse".to_string(),
+            ..Default::default()
+        });
+        handle.set_devices(devices);
+        let mut manager = RawInputManager::with_backend(move || backend, DEFAULT_CAPACITY, OverflowPolicy::DropOldest);
+        manager.register_devices(DeviceType::Mice);
+        (manager, handle)
+    }
+
+    // async fn is not available in this edition, so the stream is driven on a
+    // tokio runtime through combinators
+    #[test]
+    fn event_stream_on_tokio() {
+        use futures::StreamExt;
+        use std::thread;
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let _context = runtime.enter();
+        let (manager, handle) = mock_manager();
+        let mut stream = manager.event_stream();
+        handle.push_event(RawEvent::MouseMoveEvent(0, 1, 2));
+        // Pushed while the stream is waiting
+        let pusher = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            handle.push_event(RawEvent::MouseWheelEvent(0, 1.0));
+        });
+        let events = runtime
+            .block_on(tokio::time::timeout(
+                Duration::from_secs(5),
+                stream.by_ref().take(2).collect::<Vec<_>>(),
+            ))
+            .unwrap();
+        pusher.join().unwrap();
+        assert_eq!(
+            events,
+            vec![
+                RawEvent::MouseMoveEvent(0, 1, 2),
+                RawEvent::MouseWheelEvent(0, 1.0),
+            ]
+        );
+        drop(manager);
+        let end = runtime
+            .block_on(tokio::time::timeout(Duration::from_secs(5), stream.next()))
+            .unwrap();
+        assert_eq!(end, None);
+    }
+}
@@ -0,0 +1,240 @@
+//! Backend without hardware for driving a `RawInputManager` in tests
+//!
+//! ```
+//! # use multiinput::*;
+//! # use multiinput::mock::MockBackend;
+//! # use std::time::Duration;
+//! let backend = MockBackend::new();
+//! let handle = backend.handle();
+//! let mut devices = DevicesDisplayInfo::default();
+//! devices.mice.push(MouseDisplayInfo {
+//!     name: "mouse".to_string(),
+//!     ..Default::default()
+//! });
+//! handle.set_devices(devices);
+//!
+//! let mut manager = RawInputManager::with_backend(
+//!     move || backend,
+//!     event_queue::DEFAULT_CAPACITY,
+//!     OverflowPolicy::DropOldest,
+//! );
+//! manager.register_devices(DeviceType::Mice);
+//! handle.push_event(RawEvent::MouseMoveEvent(0, 3, -2));
+//! assert!(manager.wait_event_timeout(Duration::from_secs(1)).is_some());
+//! ```
+
+use backend::{Backend, Waker};
+use devices::{DevicesDisplayInfo, JoystickState};
+use event::RawEvent;
+use force_feedback::{ForceFeedbackError, ForceFeedbackSink};
+use gamepad::GamepadMappingDb;
+use led::{KeyboardLeds, LedError, LedSink};
+use manager::DeviceType;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+#[derive(Default)]
+struct MockState {
+    devices: DevicesDisplayInfo,
+    joystick_states: HashMap<usize, JoystickState>,
+    pending: VecDeque<RawEvent>,
+    registered: Vec<DeviceType>,
+    filter: Option<HashSet<String>>,
+    gamepad_mappings: bool,
+    woken: bool,
+    rumble: HashMap<usize, (f64, f64)>,
+    leds: HashMap<usize, KeyboardLeds>,
+}
+
+type Shared = Arc<(Mutex<MockState>, Condvar)>;
+
+/// Backend delivering events pushed through its `MockHandle`
+///
+/// Events pass through device filters like real input, but are delivered whether
+/// or not their device type was registered (registration is only recorded).
+/// Gamepad mappings are also recorded but not applied.
+pub struct MockBackend {
+    shared: Shared,
+}
+
+/// Controls a `MockBackend` from outside the manager thread
+#[derive(Clone)]
+pub struct MockHandle {
+    shared: Shared,
+}
+
+impl MockBackend {
+    pub fn new() -> MockBackend {
+        MockBackend {
+            shared: Arc::new((Mutex::new(MockState::default()), Condvar::new())),
+        }
+    }
+
+    pub fn handle(&self) -> MockHandle {
+        MockHandle {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl Default for MockBackend {
+    fn default() -> MockBackend {
+        MockBackend::new()
+    }
+}
+
+impl MockHandle {
+    /// Sets the devices reported by the backend
+    pub fn set_devices(&self, devices: DevicesDisplayInfo) {
+        self.shared.0.lock().unwrap().devices = devices;
+    }
+
+    pub fn set_joystick_state(&self, id: usize, state: JoystickState) {
+        self.shared.0.lock().unwrap().joystick_states.insert(id, state);
+    }
+
+    /// Queues an event as if it was read from a device
+    /// Joystick states are updated from initial state events.
+    pub fn push_event(&self, event: RawEvent) {
+        let (lock, condvar) = &*self.shared;
+        let mut state = lock.lock().unwrap();
+        if let RawEvent::JoystickInitialStateEvent(id, ref joystick_state) = event {
+            state.joystick_states.insert(id, joystick_state.clone());
+        }
+        state.pending.push_back(event);
+        condvar.notify_all();
+    }
+
+    /// Device types registered so far, in order
+    pub fn registered_device_types(&self) -> Vec<DeviceType> {
+        self.shared.0.lock().unwrap().registered.clone()
+    }
+
+    pub fn has_gamepad_mappings(&self) -> bool {
+        self.shared.0.lock().unwrap().gamepad_mappings
+    }
+
+    /// Last motor strengths sent to a joystick
+    pub fn rumble(&self, id: usize) -> Option<(f64, f64)> {
+        self.shared.0.lock().unwrap().rumble.get(&id).cloned()
+    }
+
+    /// Last LED state sent to a keyboard
+    pub fn leds(&self, id: usize) -> Option<KeyboardLeds> {
+        self.shared.0.lock().unwrap().leds.get(&id).cloned()
+    }
+}
+
+fn device_name(devices: &DevicesDisplayInfo, event: &RawEvent) -> Option<String> {
+    let id = event.device_id();
+    if event.is_from(&DeviceType::Mice) {
+        devices.mice.get(id).map(|mouse| mouse.name.clone())
+    } else if event.is_from(&DeviceType::Keyboards) {
+        devices.keyboards.get(id).map(|keyboard| keyboard.name.clone())
+    } else {
+        devices.joysticks.get(id).map(|joystick| joystick.name.clone())
+    }
+}
+
+impl Backend for MockBackend {
+    fn waker(&self) -> Waker {
+        let shared = self.shared.clone();
+        Box::new(move || {
+            let (lock, condvar) = &*shared;
+            lock.lock().unwrap().woken = true;
+            condvar.notify_all();
+        })
+    }
+
+    fn register_devices(&mut self, device_type: DeviceType) -> Result<(), &'static str> {
+        self.shared.0.lock().unwrap().registered.push(device_type);
+        Ok(())
+    }
+
+    fn filter_devices(&mut self, device_names: HashSet<String>) {
+        self.shared.0.lock().unwrap().filter = Some(device_names);
+    }
+
+    fn unfilter_devices(&mut self) {
+        self.shared.0.lock().unwrap().filter = None;
+    }
+
+    fn set_gamepad_mappings(&mut self, mappings: Option<&GamepadMappingDb>) {
+        self.shared.0.lock().unwrap().gamepad_mappings = mappings.is_some();
+    }
+
+    fn read_events(&mut self, events: &mut VecDeque<RawEvent>) {
+        let mut state = self.shared.0.lock().unwrap();
+        let state = &mut *state;
+        for event in state.pending.drain(..) {
+            if let Some(ref filter) = state.filter {
+                match device_name(&state.devices, &event) {
+                    Some(ref name) if filter.contains(name) => (),
+                    _ => continue,
+                }
+            }
+            events.push_back(event);
+        }
+    }
+
+    fn wait(&mut self, timeout: Option<Duration>) {
+        let (lock, condvar) = &*self.shared;
+        let mut state = lock.lock().unwrap();
+        if state.pending.is_empty() && !state.woken {
+            state = match timeout {
+                Some(timeout) => condvar.wait_timeout(state, timeout).unwrap().0,
+                None => condvar.wait(state).unwrap(),
+            };
+        }
+        state.woken = false;
+    }
+
+    fn joystick_state(&self, id: usize) -> Option<JoystickState> {
+        self.shared.0.lock().unwrap().joystick_states.get(&id).cloned()
+    }
+
+    fn device_list(&self) -> DevicesDisplayInfo {
+        self.shared.0.lock().unwrap().devices.clone()
+    }
+
+    fn open_force_feedback(&mut self, id: usize) -> Result<Box<dyn ForceFeedbackSink>, ForceFeedbackError> {
+        if id >= self.shared.0.lock().unwrap().devices.joysticks.len() {
+            return Err(ForceFeedbackError(format!("No joystick with id {}", id)));
+        }
+        Ok(Box::new(MockOutput {
+            shared: self.shared.clone(),
+            id,
+        }))
+    }
+
+    fn open_keyboard_leds(&mut self, id: usize) -> Result<Box<dyn LedSink>, LedError> {
+        if id >= self.shared.0.lock().unwrap().devices.keyboards.len() {
+            return Err(LedError(format!("No keyboard with id {}", id)));
+        }
+        Ok(Box::new(MockOutput {
+            shared: self.shared.clone(),
+            id,
+        }))
+    }
+}
+
+/// Records output sent to a device
+struct MockOutput {
+    shared: Shared,
+    id: usize,
+}
+
+impl ForceFeedbackSink for MockOutput {
+    fn set_rumble(&mut self, low: f64, high: f64) -> Result<(), ForceFeedbackError> {
+        self.shared.0.lock().unwrap().rumble.insert(self.id, (low, high));
+        Ok(())
+    }
+}
+
+impl LedSink for MockOutput {
+    fn set_leds(&mut self, leds: KeyboardLeds) -> Result<(), LedError> {
+        self.shared.0.lock().unwrap().leds.insert(self.id, leds);
+        Ok(())
+    }
+}
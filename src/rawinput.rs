@@ -1,24 +1,30 @@
-use devices::{DeviceInfo, Devices, JoystickInfo, JoystickState, KeyboardInfo, MouseInfo};
+use capture::HidReportSink;
+use gamepad::GamepadState;
+use devices::{parse_device_path_ids, DeviceIds, DeviceInfo, Devices, JoystickInfo, JoystickState, KeyboardInfo, MouseInfo};
 use event::RawEvent;
 use joystick::{garbage_vec, process_joystick_data};
 use keyboard::process_keyboard_data;
 use mouse::process_mouse_data;
+use xinput::{poll_xinput, XInputProvider};
 use std::collections::VecDeque;
 use std::ffi::OsStr;
 use std::ffi::OsString;
 use std::mem::MaybeUninit;
 use std::os::windows::ffi::OsStrExt;
 use std::os::windows::ffi::OsStringExt;
-use std::{mem, ptr};
+use std::{mem, ptr, slice};
 use winapi::shared::hidpi::{
     HidP_GetButtonCaps, HidP_GetCaps, HidP_GetValueCaps, HidP_Input, HIDP_BUTTON_CAPS, HIDP_CAPS,
     HIDP_STATUS_SUCCESS, HIDP_VALUE_CAPS, PHIDP_BUTTON_CAPS, PHIDP_PREPARSED_DATA,
     PHIDP_VALUE_CAPS,
 };
-use winapi::shared::hidsdi::HidD_GetSerialNumberString;
-use winapi::shared::minwindef::{INT, LPVOID, UINT};
+use winapi::shared::hidsdi::{
+    HidD_GetManufacturerString, HidD_GetProductString, HidD_GetSerialNumberString,
+};
+use winapi::shared::minwindef::{DWORD, INT, LPVOID, UINT};
+use winapi::shared::ntdef::{BOOLEAN, ULONG};
 use winapi::um::fileapi::{CreateFileW, OPEN_EXISTING};
-use winapi::um::handleapi::INVALID_HANDLE_VALUE;
+use winapi::um::handleapi::{CloseHandle, INVALID_HANDLE_VALUE};
 use winapi::um::winnt::{FILE_SHARE_READ, FILE_SHARE_WRITE, HANDLE, PVOID};
 use winapi::um::winuser::{
     GetRawInputBuffer, GetRawInputDeviceInfoW, GetRawInputDeviceList, PRAWINPUT,
@@ -41,7 +47,11 @@ unsafe fn next_raw_input_block(array_ptr: &mut *mut u8) {
     (*array_ptr) = transmute(array_int);
 }
 
-fn read_input_buffer(event_queue: &mut VecDeque<RawEvent>, devices: &mut Devices) {
+fn read_input_buffer(
+    event_queue: &mut VecDeque<RawEvent>,
+    devices: &mut Devices,
+    hid_sink: &mut Option<HidReportSink>,
+) {
     unsafe {
         let mut array_alloc: [u8; 16000] = MaybeUninit::uninit().assume_init();
         let mut buffer_size: UINT = 0;
@@ -69,8 +79,9 @@ fn read_input_buffer(event_queue: &mut VecDeque<RawEvent>, devices: &mut Devices
         let mut array_ptr = array_alloc.as_mut_ptr();
 
         for _ in 0..numberofelements as u32 {
-            let header = (*(array_ptr as *mut RAWINPUT)).header;
-            let raw_input = *(array_ptr as *mut RAWINPUT);
+            // Borrowed from the buffer, as HID reports extend past the end of RAWINPUT
+            let raw_input = &*(array_ptr as *const RAWINPUT);
+            let header = raw_input.header;
             next_raw_input_block(&mut array_ptr);
             let pos = match devices.device_map.get(&header.hDevice) {
                 Some(item) => (*item).clone(),
@@ -84,8 +95,13 @@ fn read_input_buffer(event_queue: &mut VecDeque<RawEvent>, devices: &mut Devices
                     event_queue.extend(process_keyboard_data(&raw_input.data.keyboard(), pos));
                 }
                 RIM_TYPEHID => {
+                    let hid = raw_input.data.hid();
+                    let report = slice::from_raw_parts(hid.bRawData.as_ptr(), hid.dwSizeHid as usize);
+                    if hid_sink.as_ref().is_some_and(|sink| !sink.send_report(pos, report)) {
+                        *hid_sink = None;
+                    }
                     event_queue.extend(process_joystick_data(
-                        &raw_input.data.hid(),
+                        report,
                         pos,
                         &mut devices.joysticks[pos],
                     ));
@@ -96,12 +112,18 @@ fn read_input_buffer(event_queue: &mut VecDeque<RawEvent>, devices: &mut Devices
     }
 }
 
-pub fn get_event(event_queue: &mut VecDeque<RawEvent>, devices: &mut Devices) -> Option<RawEvent> {
-    if event_queue.is_empty() {
-        read_input_buffer(event_queue, devices);
+/// Reads all pending input into event_queue, sending joystick reports to hid_sink
+/// (which is cleared once the recording has stopped)
+pub fn read_events(
+    event_queue: &mut VecDeque<RawEvent>,
+    devices: &mut Devices,
+    xinput: Option<&dyn XInputProvider>,
+    hid_sink: &mut Option<HidReportSink>,
+) {
+    read_input_buffer(event_queue, devices, hid_sink);
+    if let Some(provider) = xinput {
+        poll_xinput(event_queue, devices, provider);
     }
-    let event = event_queue.pop_front();
-    event
 }
 
 pub fn get_joystick_state(devices: &Devices, id: usize) -> Option<JoystickState> {
@@ -143,7 +165,11 @@ pub fn produce_raw_device_list(incl_360_devices: bool) -> Devices {
                 Err(_) => continue,
             };
             let serial = get_serial_number(hid_handle);
-            let device_info_option = get_device_info(device_handle, name, serial);
+            let product = get_product_string(hid_handle);
+            let manufacturer = get_manufacturer_string(hid_handle);
+            CloseHandle(hid_handle);
+            let device_info_option =
+                get_device_info(device_handle, name, serial, product, manufacturer);
             match device_info_option {
                 None => continue,
                 _ => (),
@@ -211,6 +237,11 @@ pub unsafe fn raw_handle_to_name(device_handle: HANDLE) -> String {
 }
 
 pub unsafe fn raw_name_to_hid(name: String) -> Result<HANDLE, String> {
+    raw_name_to_hid_with_access(name, 0)
+}
+
+/// Opens a device by name with the given access rights (e.g. GENERIC_WRITE for output reports)
+pub unsafe fn raw_name_to_hid_with_access(name: String, access: DWORD) -> Result<HANDLE, String> {
     let os_name: &OsStr = name.as_ref();
     let mut classname = os_name
         .encode_wide()
@@ -218,7 +249,7 @@ pub unsafe fn raw_name_to_hid(name: String) -> Result<HANDLE, String> {
         .collect::<Vec<_>>();
     let hid_handle = CreateFileW(
         classname.as_mut_ptr(),
-        0,
+        access,
         FILE_SHARE_READ | FILE_SHARE_WRITE,
         ptr::null_mut(),
         OPEN_EXISTING,
@@ -236,6 +267,8 @@ pub unsafe fn get_device_info(
     handle: HANDLE,
     name: String,
     serial: Option<String>,
+    product: Option<String>,
+    manufacturer: Option<String>,
 ) -> Option<DeviceInfo> {
     let mut data_buffer: [RID_DEVICE_INFO; 1] = MaybeUninit::uninit().assume_init();
     let mut data_buffer_size = mem::size_of::<RID_DEVICE_INFO>() as u32;
@@ -248,18 +281,37 @@ pub unsafe fn get_device_info(
     );
     assert!(result as INT != -1);
     let raw_info = data_buffer[0];
+    let (path_vendor_id, path_product_id) = parse_device_path_ids(&name);
 
     return match raw_info.dwType {
         RIM_TYPEMOUSE => Some(DeviceInfo::Mouse(MouseInfo {
             name: name,
             handle: handle,
             serial: serial,
+            product,
+            manufacturer,
+            ids: DeviceIds {
+                vendor_id: path_vendor_id,
+                product_id: path_product_id,
+                version_number: None,
+                usage_page: 0x01,
+                usage: 0x02,
+            },
             info: raw_info,
         })),
         RIM_TYPEKEYBOARD => Some(DeviceInfo::Keyboard(KeyboardInfo {
             name: name,
             handle: handle,
             serial: serial,
+            product,
+            manufacturer,
+            ids: DeviceIds {
+                vendor_id: path_vendor_id,
+                product_id: path_product_id,
+                version_number: None,
+                usage_page: 0x01,
+                usage: 0x06,
+            },
             info: raw_info,
         })),
         RIM_TYPEHID => {
@@ -288,48 +340,26 @@ pub unsafe fn get_device_info(
                 ) as i32
                     >= 0
             );
-            let mut caps: HIDP_CAPS = MaybeUninit::uninit().assume_init();
-            assert!(
-                HidP_GetCaps(
-                    preparsed_data.as_mut_ptr() as PHIDP_PREPARSED_DATA,
-                    &mut caps
-                ) == HIDP_STATUS_SUCCESS
-            );
-
-            let mut caps_length = caps.NumberInputButtonCaps;
-            let mut p_button_caps: Vec<HIDP_BUTTON_CAPS> = garbage_vec(caps_length as usize);
-
-            if caps_length != 0 {
-                assert!(
-                    HidP_GetButtonCaps(
-                        HidP_Input,
-                        p_button_caps.as_mut_ptr() as PHIDP_BUTTON_CAPS,
-                        &mut caps_length,
-                        preparsed_data.as_mut_ptr() as PHIDP_PREPARSED_DATA
-                    ) == HIDP_STATUS_SUCCESS
-                );
-            }
-
-            caps_length = caps.NumberInputValueCaps;
-            let mut p_value_caps: Vec<HIDP_VALUE_CAPS> = garbage_vec(caps_length as usize);
-
-            if caps_length != 0 {
-                assert!(
-                    HidP_GetValueCaps(
-                        HidP_Input,
-                        p_value_caps.as_mut_ptr() as PHIDP_VALUE_CAPS,
-                        &mut caps_length,
-                        preparsed_data.as_mut_ptr() as PHIDP_PREPARSED_DATA
-                    ) == HIDP_STATUS_SUCCESS
-                );
-            }
+            let (caps, p_button_caps, p_value_caps) =
+                get_joystick_caps(&mut preparsed_data).expect("Invalid Preparsed Data!");
 
             let is_360_controller = name.find("IG_") != None;
+            let hid_info = raw_info.u.hid();
+            let ids = DeviceIds {
+                vendor_id: Some(hid_info.dwVendorId as u16),
+                product_id: Some(hid_info.dwProductId as u16),
+                version_number: Some(hid_info.dwVersionNumber as u16),
+                usage_page: hid_info.usUsagePage,
+                usage: hid_info.usUsage,
+            };
 
             Some(DeviceInfo::Joystick(JoystickInfo {
                 name: name,
                 handle: handle,
                 serial: serial,
+                product,
+                manufacturer,
+                ids,
                 info: raw_info,
                 caps: caps,
                 button_caps: p_button_caps.clone(),
@@ -337,32 +367,92 @@ pub unsafe fn get_device_info(
                 preparsed_data: preparsed_data,
                 state: JoystickState::new(p_button_caps, p_value_caps),
                 is_360_controller: is_360_controller,
+                has_reported: false,
+                gamepad_mapping: None,
+                gamepad_state: GamepadState::new(),
+                xinput_index: None,
             }))
         }
         _ => panic!("Unreachable!"),
     };
 }
 
+/// Reads the input capabilities described by the preparsed data of a joystick
+/// Returns None if the data is invalid.
+pub unsafe fn get_joystick_caps(
+    preparsed_data: &mut [u8],
+) -> Option<(HIDP_CAPS, Vec<HIDP_BUTTON_CAPS>, Vec<HIDP_VALUE_CAPS>)> {
+    let mut caps: HIDP_CAPS = MaybeUninit::uninit().assume_init();
+    if HidP_GetCaps(preparsed_data.as_mut_ptr() as PHIDP_PREPARSED_DATA, &mut caps)
+        != HIDP_STATUS_SUCCESS
+    {
+        return None;
+    }
+
+    let mut caps_length = caps.NumberInputButtonCaps;
+    let mut p_button_caps: Vec<HIDP_BUTTON_CAPS> = garbage_vec(caps_length as usize);
+
+    if caps_length != 0
+        && HidP_GetButtonCaps(
+            HidP_Input,
+            p_button_caps.as_mut_ptr() as PHIDP_BUTTON_CAPS,
+            &mut caps_length,
+            preparsed_data.as_mut_ptr() as PHIDP_PREPARSED_DATA,
+        ) != HIDP_STATUS_SUCCESS
+    {
+        return None;
+    }
+
+    caps_length = caps.NumberInputValueCaps;
+    let mut p_value_caps: Vec<HIDP_VALUE_CAPS> = garbage_vec(caps_length as usize);
+
+    if caps_length != 0
+        && HidP_GetValueCaps(
+            HidP_Input,
+            p_value_caps.as_mut_ptr() as PHIDP_VALUE_CAPS,
+            &mut caps_length,
+            preparsed_data.as_mut_ptr() as PHIDP_PREPARSED_DATA,
+        ) != HIDP_STATUS_SUCCESS
+    {
+        return None;
+    }
+    Some((caps, p_button_caps, p_value_caps))
+}
+
 pub unsafe fn get_serial_number(handle: HANDLE) -> Option<String> {
+    get_hid_string(handle, HidD_GetSerialNumberString)
+}
+
+pub unsafe fn get_product_string(handle: HANDLE) -> Option<String> {
+    get_hid_string(handle, HidD_GetProductString)
+}
+
+pub unsafe fn get_manufacturer_string(handle: HANDLE) -> Option<String> {
+    get_hid_string(handle, HidD_GetManufacturerString)
+}
+
+/// Reads one of the HID string descriptors, returning None if it is missing or blank
+unsafe fn get_hid_string(
+    handle: HANDLE,
+    getter: unsafe extern "system" fn(HANDLE, PVOID, ULONG) -> BOOLEAN,
+) -> Option<String> {
+    // The HID specification limits strings to 126 wide characters plus a terminator
     let mut string_buffer: [u16; 128] = [0u16; 128];
-    let string_buffer_size = 256;
-    let result = HidD_GetSerialNumberString(
-        handle,
-        string_buffer.as_mut_ptr() as PVOID,
-        string_buffer_size,
-    );
-    let serial_string_unparsed = match OsString::from_wide(&string_buffer[0..128]).into_string() {
-        Ok(something) => something,
-        Err(_) => panic!("String Conversion Failed {}", result),
-    };
-    let mut serial_string: Option<String> = None;
-    if result == 1 {
-        let string_front = serial_string_unparsed.find("\0");
-        if let Some(string_index) = string_front {
-            if string_index > 10 {
-                serial_string = Some(String::from(&serial_string_unparsed[0..string_index]));
-            }
-        }
+    let string_buffer_size = mem::size_of_val(&string_buffer) as ULONG;
+    if getter(handle, string_buffer.as_mut_ptr() as PVOID, string_buffer_size) == 0 {
+        return None;
+    }
+    let length = string_buffer
+        .iter()
+        .position(|&c| c == 0)
+        .unwrap_or(string_buffer.len());
+    let string = OsString::from_wide(&string_buffer[0..length])
+        .to_string_lossy()
+        .trim()
+        .to_string();
+    if string.is_empty() {
+        None
+    } else {
+        Some(string)
     }
-    serial_string
 }
@@ -0,0 +1,253 @@
+use backend::{Backend, Waker};
+use capture::HidReportSink;
+use devices::{Devices, DevicesDisplayInfo, JoystickState};
+use event::RawEvent;
+use force_feedback::{ForceFeedbackError, ForceFeedbackSink};
+use gamepad::GamepadMappingDb;
+use led::{LedError, LedSink};
+use manager::DeviceType;
+use rawinput::{get_joystick_state, read_events};
+use registrar::RawInputRegistrar;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use winapi::shared::minwindef::{DWORD, UINT};
+use winapi::shared::windef::HWND;
+use winapi::um::libloaderapi::GetModuleHandleW;
+use winapi::um::winbase::INFINITE;
+use winapi::um::winuser::{
+    CreateWindowExW, DefWindowProcW, MsgWaitForMultipleObjectsEx, PeekMessageW, PostMessageW,
+    RegisterClassExW, CW_USEDEFAULT, HWND_MESSAGE, MSG, MWMO_INPUTAVAILABLE, PM_REMOVE,
+    QS_POSTMESSAGE, QS_RAWINPUT, WM_APP, WNDCLASSEXW,
+};
+
+use std::collections::{HashSet, VecDeque};
+use std::ffi::OsStr;
+use std::mem;
+use std::os::windows::ffi::OsStrExt;
+use std::ptr;
+
+/// Message posted to the hidden window to wake the thread when a command is sent
+const WM_WAKE: UINT = WM_APP;
+
+/// Interval at which XInput controllers are polled
+const XINPUT_POLL_INTERVAL: Duration = Duration::from_millis(8);
+
+/// Reads input through rawinput using a hidden message window
+pub struct RawInputBackend {
+    hwnd: HWND,
+    devices: Devices,
+    registrar: RawInputRegistrar,
+    gamepad_mappings: Option<GamepadMappingDb>,
+    event_queue: VecDeque<RawEvent>,
+    hid_sink: Option<HidReportSink>,
+}
+
+impl RawInputBackend {
+    pub fn new() -> RawInputBackend {
+        RawInputBackend {
+            hwnd: setup_message_window(),
+            devices: Devices::new(),
+            registrar: RawInputRegistrar::new(),
+            gamepad_mappings: None,
+            event_queue: VecDeque::new(),
+            hid_sink: None,
+        }
+    }
+
+    /// Sends the descriptors of all joysticks read through rawinput to the HID sink
+    fn send_hid_descriptors(&mut self) {
+        let sent = match self.hid_sink {
+            Some(ref sink) => self
+                .devices
+                .joysticks
+                .iter()
+                .enumerate()
+                .filter(|&(_, joystick)| joystick.xinput_index.is_none())
+                .all(|(id, joystick)| {
+                    sink.send_descriptor(id, &joystick.preparsed_data, joystick.is_360_controller)
+                }),
+            None => return,
+        };
+        if !sent {
+            self.hid_sink = None;
+        }
+    }
+}
+
+impl Backend for RawInputBackend {
+    fn waker(&self) -> Waker {
+        let window = self.hwnd as usize;
+        Box::new(move || unsafe {
+            PostMessageW(window as HWND, WM_WAKE, 0, 0);
+        })
+    }
+
+    fn register_devices(&mut self, device_type: DeviceType) -> Result<(), &'static str> {
+        self.devices = self.registrar.register_devices(self.hwnd, device_type)?;
+        if let Some(ref mappings) = self.gamepad_mappings {
+            self.devices.apply_gamepad_mappings(mappings);
+        }
+        self.send_hid_descriptors();
+        Ok(())
+    }
+
+    fn filter_devices(&mut self, device_names: HashSet<String>) {
+        self.devices.filter_device_map(device_names);
+    }
+
+    fn unfilter_devices(&mut self) {
+        self.devices.reset_device_map();
+    }
+
+    fn set_gamepad_mappings(&mut self, mappings: Option<&GamepadMappingDb>) {
+        match mappings {
+            Some(mappings) => self.devices.apply_gamepad_mappings(mappings),
+            None => {
+                for joystick in self.devices.joysticks.iter_mut() {
+                    joystick.gamepad_mapping = None;
+                }
+            }
+        }
+        self.gamepad_mappings = mappings.cloned();
+    }
+
+    fn record_hid_reports(&mut self, sink: Option<HidReportSink>) {
+        self.hid_sink = sink;
+        self.send_hid_descriptors();
+    }
+
+    fn read_events(&mut self, events: &mut VecDeque<RawEvent>) {
+        read_events(
+            &mut self.event_queue,
+            &mut self.devices,
+            self.registrar.xinput(),
+            &mut self.hid_sink,
+        );
+        events.extend(self.event_queue.drain(..));
+    }
+
+    fn wait(&mut self, timeout: Option<Duration>) {
+        let timeout = match self.registrar.xinput() {
+            Some(_) => Some(timeout.map_or(XINPUT_POLL_INTERVAL, |timeout| {
+                timeout.min(XINPUT_POLL_INTERVAL)
+            })),
+            None => timeout,
+        };
+        wait_for_messages(self.hwnd, timeout);
+    }
+
+    fn joystick_state(&self, id: usize) -> Option<JoystickState> {
+        get_joystick_state(&self.devices, id)
+    }
+
+    fn device_list(&self) -> DevicesDisplayInfo {
+        self.devices.clone().into()
+    }
+
+    fn print_device_list(&self) {
+        print_raw_device_list(&self.devices);
+    }
+
+    fn open_force_feedback(&mut self, id: usize) -> Result<Box<dyn ForceFeedbackSink>, ForceFeedbackError> {
+        match self.devices.joysticks.get(id) {
+            Some(joystick) => self.registrar.open_force_feedback(joystick),
+            None => Err(ForceFeedbackError(format!("No joystick with id {}", id))),
+        }
+    }
+
+    fn open_keyboard_leds(&mut self, id: usize) -> Result<Box<dyn LedSink>, LedError> {
+        match self.devices.keyboards.get(id) {
+            Some(keyboard) => self.registrar.open_keyboard_leds(keyboard),
+            None => Err(LedError(format!("No keyboard with id {}", id))),
+        }
+    }
+}
+
+fn setup_message_window() -> HWND {
+    let hwnd: HWND;
+    unsafe {
+        let hinstance = GetModuleHandleW(ptr::null());
+        if hinstance.is_null() {
+            panic!("Instance Generation Failed");
+        }
+
+        let current_time = SystemTime::now();
+        let classname_str = format!(
+            "RawInput Hidden Window - {:?}",
+            current_time.duration_since(UNIX_EPOCH).unwrap()
+        );
+
+        let classname = OsStr::new(&classname_str)
+            .encode_wide()
+            .chain(Some(0))
+            .collect::<Vec<_>>();
+
+        let wcex = WNDCLASSEXW {
+            cbSize: (mem::size_of::<WNDCLASSEXW>()) as UINT,
+            cbClsExtra: 0,
+            cbWndExtra: 0,
+            hbrBackground: ptr::null_mut(),
+            hCursor: ptr::null_mut(),
+            hIcon: ptr::null_mut(),
+            hIconSm: ptr::null_mut(),
+            hInstance: hinstance,
+            lpfnWndProc: Some(DefWindowProcW),
+            lpszClassName: classname.as_ptr(),
+            lpszMenuName: ptr::null_mut(),
+            style: 0,
+        };
+        let a = RegisterClassExW(&wcex);
+        if a == 0 {
+            panic!("Registering WindowClass Failed!");
+        }
+
+        hwnd = CreateWindowExW(
+            0,
+            classname.as_ptr(),
+            classname.as_ptr(),
+            0,
+            CW_USEDEFAULT,
+            CW_USEDEFAULT,
+            CW_USEDEFAULT,
+            CW_USEDEFAULT,
+            HWND_MESSAGE,
+            ptr::null_mut(),
+            hinstance,
+            ptr::null_mut(),
+        );
+        if hwnd.is_null() {
+            panic!("Window Creation Failed!");
+        }
+    }
+    hwnd
+}
+
+/// Blocks until a command is posted, raw input arrives or the timeout has passed
+fn wait_for_messages(hwnd: HWND, timeout: Option<Duration>) {
+    let wake_mask = QS_POSTMESSAGE | QS_RAWINPUT;
+    let milliseconds = match timeout {
+        // Round up so a wait never ends just short of its deadline
+        Some(timeout) => timeout.as_micros().div_ceil(1000).min(INFINITE as u128 - 1) as DWORD,
+        None => INFINITE,
+    };
+    unsafe {
+        MsgWaitForMultipleObjectsEx(0, ptr::null(), milliseconds, wake_mask, MWMO_INPUTAVAILABLE);
+        let mut message: MSG = mem::zeroed();
+        while PeekMessageW(&mut message, hwnd, WM_WAKE, WM_WAKE, PM_REMOVE) != 0 {}
+    }
+}
+
+/// Prints a list of all available raw input devices
+fn print_raw_device_list(devices: &Devices) {
+    println!("Mice:");
+    for mouse in &devices.mice {
+        println!("{:?}", mouse);
+    }
+    println!("Keyboards:");
+    for keyboard in &devices.keyboards {
+        println!("{:?}", keyboard);
+    }
+    println!("Hids:");
+    for joystick in &devices.joysticks {
+        println!("{:?}", joystick);
+    }
+}
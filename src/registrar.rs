@@ -1,15 +1,22 @@
-use devices::Devices;
+use devices::{Devices, JoystickInfo, KeyboardInfo};
+use force_feedback::{ForceFeedbackError, ForceFeedbackSink};
+use hid_output::HidRumbleSink;
+use led::windows::WindowsLedSink;
+use led::{LedError, LedSink};
 use manager::{DeviceType, XInputInclude};
 use rawinput::produce_raw_device_list;
+use xinput::{merge_xinput_devices, SystemXInput, XInputProvider, XInputRumbleSink};
 use winapi::shared::minwindef::UINT;
 use winapi::shared::windef::HWND;
 use winapi::um::winuser::{RegisterRawInputDevices, RAWINPUTDEVICE, RIDEV_INPUTSINK};
 
 use std::mem;
+use std::rc::Rc;
 
 #[derive(Default)]
 pub struct RawInputRegistrar {
     include_xinput: bool,
+    xinput: Option<Rc<dyn XInputProvider>>,
 }
 
 impl RawInputRegistrar {
@@ -17,6 +24,29 @@ impl RawInputRegistrar {
         Self::default()
     }
 
+    /// XInput provider in use if joysticks were registered with XInputInclude::XInputApi
+    pub fn xinput(&self) -> Option<&dyn XInputProvider> {
+        self.xinput.as_ref().map(|provider| provider.as_ref())
+    }
+
+    /// Opens the rumble output of a joystick
+    pub fn open_force_feedback(
+        &self,
+        joystick: &JoystickInfo,
+    ) -> Result<Box<dyn ForceFeedbackSink>, ForceFeedbackError> {
+        match (joystick.xinput_index, self.xinput.as_ref()) {
+            (Some(user_index), Some(provider)) => {
+                Ok(Box::new(XInputRumbleSink::new(provider.clone(), user_index)))
+            }
+            _ => Ok(Box::new(HidRumbleSink::open(joystick)?)),
+        }
+    }
+
+    /// Opens the indicator LEDs of a keyboard
+    pub fn open_keyboard_leds(&self, keyboard: &KeyboardInfo) -> Result<Box<dyn LedSink>, LedError> {
+        Ok(Box::new(WindowsLedSink::open(keyboard)?))
+    }
+
     pub fn register_devices(
         &mut self,
         hwnd: HWND,
@@ -37,6 +67,11 @@ impl RawInputRegistrar {
                 self.include_xinput = match include_xinput {
                     XInputInclude::True => true,
                     XInputInclude::False => false,
+                    XInputInclude::XInputApi => false,
+                };
+                self.xinput = match include_xinput {
+                    XInputInclude::XInputApi => Some(Rc::new(SystemXInput::load()?)),
+                    _ => None,
                 };
                 let rid = RAWINPUTDEVICE {
                     usUsagePage: 1,
@@ -73,6 +108,10 @@ impl RawInputRegistrar {
                 return Err("Registration of Controller Failed");
             }
         }
-        Ok(produce_raw_device_list(self.include_xinput))
+        let mut devices = produce_raw_device_list(self.include_xinput);
+        if let Some(ref provider) = self.xinput {
+            merge_xinput_devices(&mut devices, provider.as_ref());
+        }
+        Ok(devices)
     }
 }
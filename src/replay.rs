@@ -0,0 +1,495 @@
+//! Backend playing a capture file through a `RawInputManager`
+//!
+//! ```no_run
+//! # use multiinput::*;
+//! # use multiinput::replay::{ReplayBackend, ReplayMode};
+//! let backend = ReplayBackend::load("bug-report.jsonl", ReplayMode::Accelerated(10.0)).unwrap();
+//! let mut manager = RawInputManager::with_backend(
+//!     move || backend,
+//!     event_queue::DEFAULT_CAPACITY,
+//!     OverflowPolicy::DropNewest,
+//! );
+//! manager.register_devices(DeviceType::Keyboards);
+//! while let Some(event) = manager.wait_event_timeout(std::time::Duration::from_secs(1)) {
+//!     println!("{:?}", event);
+//! }
+//! ```
+
+use backend::{Backend, Waker};
+use capture::{Capture, CaptureError, CapturedEvent};
+use devices::{DevicesDisplayInfo, JoystickState};
+use event::{Axis, RawEvent, State};
+use force_feedback::{EffectId, ForceFeedbackEffect, ForceFeedbackError, ForceFeedbackSink};
+use gamepad::GamepadMappingDb;
+use led::{KeyboardLeds, LedError, LedSink};
+use manager::DeviceType;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::Path;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Pace at which captured events are emitted
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReplayMode {
+    /// At the times they were recorded
+    RealTime,
+    /// Faster by the given factor (`f64::INFINITY` emits everything at once)
+    Accelerated(f64),
+    /// Only when released by `ReplayHandle::step`
+    Stepped,
+}
+
+struct ReplayState {
+    position: usize,
+    /// Events released by steps but not emitted yet
+    steps: usize,
+    woken: bool,
+}
+
+type Shared = Arc<(Mutex<ReplayState>, Condvar)>;
+
+/// Backend emitting the devices and events of a capture
+///
+/// The replay clock starts at the first registration. Events are only emitted
+/// for registered device types and pass through device filters. Like rawinput,
+/// registering joysticks sends their current state; gamepad events
+/// are replayed as captured, so gamepad mappings are ignored. Force feedback
+/// and LED output is accepted and discarded.
+pub struct ReplayBackend {
+    devices: DevicesDisplayInfo,
+    events: Vec<CapturedEvent>,
+    mode: ReplayMode,
+    shared: Shared,
+    start: Option<Instant>,
+    registered: Vec<DeviceType>,
+    filter: Option<HashSet<String>>,
+    joystick_states: HashMap<usize, JoystickState>,
+    /// Initial states of joysticks registered after their state was replayed
+    pending: Vec<RawEvent>,
+}
+
+/// Controls a `ReplayBackend` from outside the manager thread
+#[derive(Clone)]
+pub struct ReplayHandle {
+    shared: Shared,
+    length: usize,
+}
+
+impl ReplayBackend {
+    /// Fails if an accelerated mode is not faster than zero
+    pub fn new(capture: Capture, mode: ReplayMode) -> Result<ReplayBackend, CaptureError> {
+        if let ReplayMode::Accelerated(factor) = mode {
+            if factor.is_nan() || factor <= 0.0 {
+                return Err(CaptureError(format!(
+                    "Invalid replay speed {} (must be greater than 0)",
+                    factor
+                )));
+            }
+        }
+        Ok(ReplayBackend {
+            devices: capture.devices,
+            events: capture.events,
+            mode,
+            shared: Arc::new((
+                Mutex::new(ReplayState {
+                    position: 0,
+                    steps: 0,
+                    woken: false,
+                }),
+                Condvar::new(),
+            )),
+            start: None,
+            registered: Vec::new(),
+            filter: None,
+            joystick_states: HashMap::new(),
+            pending: Vec::new(),
+        })
+    }
+
+    /// Replays a capture file in either format
+    pub fn load<P: AsRef<Path>>(path: P, mode: ReplayMode) -> Result<ReplayBackend, CaptureError> {
+        Capture::load(path).and_then(|capture| ReplayBackend::new(capture, mode))
+    }
+
+    pub fn handle(&self) -> ReplayHandle {
+        ReplayHandle {
+            shared: self.shared.clone(),
+            length: self.events.len(),
+        }
+    }
+
+    /// Time into the replay at which an event is due
+    fn due(&self, start: Instant, event: &CapturedEvent) -> Option<Instant> {
+        match self.mode {
+            ReplayMode::RealTime => Some(start + event.time),
+            ReplayMode::Accelerated(factor) => {
+                Some(start + Duration::from_secs_f64((event.time.as_secs_f64() / factor).min(1e9)))
+            }
+            ReplayMode::Stepped => None,
+        }
+    }
+
+    fn passes_filters(&self, event: &RawEvent) -> bool {
+        if !self
+            .registered
+            .iter()
+            .any(|device_type| event.is_from(device_type))
+        {
+            return false;
+        }
+        match self.filter {
+            Some(ref names) => self
+                .devices
+                .describe_event(event)
+                .is_some_and(|device| names.contains(device.name)),
+            None => true,
+        }
+    }
+
+    fn update_joystick_state(&mut self, event: &RawEvent) {
+        let state = match *event {
+            RawEvent::JoystickInitialStateEvent(id, ref state) => {
+                self.joystick_states.insert(id, state.clone());
+                return;
+            }
+            RawEvent::JoystickButtonEvent(id, ..)
+            | RawEvent::JoystickAxisEvent(id, ..)
+            | RawEvent::JoystickHatSwitchEvent(id, ..) => match self.joystick_states.get_mut(&id) {
+                Some(state) => state,
+                None => return,
+            },
+            _ => return,
+        };
+        match *event {
+            RawEvent::JoystickButtonEvent(_, button, ref pressed) => {
+                if let Some(button_state) = state.button_states.get_mut(button) {
+                    *button_state = *pressed == State::Pressed;
+                }
+            }
+            RawEvent::JoystickAxisEvent(_, ref axis, value) => {
+                let axes = &mut state.axis_states;
+                match *axis {
+                    Axis::X => axes.x = Some(value),
+                    Axis::Y => axes.y = Some(value),
+                    Axis::Z => axes.z = Some(value),
+                    Axis::RX => axes.rx = Some(value),
+                    Axis::RY => axes.ry = Some(value),
+                    Axis::RZ => axes.rz = Some(value),
+                    Axis::SLIDER => axes.slider = Some(value),
+                }
+            }
+            RawEvent::JoystickHatSwitchEvent(_, ref hatswitch) => {
+                state.hatswitch = Some(hatswitch.clone())
+            }
+            _ => (),
+        }
+    }
+}
+
+impl ReplayHandle {
+    /// Releases the next event in `ReplayMode::Stepped`
+    pub fn step(&self) {
+        self.step_by(1);
+    }
+
+    /// Releases the next count events in `ReplayMode::Stepped`
+    pub fn step_by(&self, count: usize) {
+        let (lock, condvar) = &*self.shared;
+        lock.lock().unwrap().steps += count;
+        condvar.notify_all();
+    }
+
+    /// Number of captured events emitted or skipped so far
+    pub fn position(&self) -> usize {
+        self.shared.0.lock().unwrap().position
+    }
+
+    /// Number of events in the capture
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    /// True once every captured event has been emitted or skipped
+    pub fn is_finished(&self) -> bool {
+        self.position() >= self.length
+    }
+}
+
+impl Backend for ReplayBackend {
+    fn waker(&self) -> Waker {
+        let shared = self.shared.clone();
+        Box::new(move || {
+            let (lock, condvar) = &*shared;
+            lock.lock().unwrap().woken = true;
+            condvar.notify_all();
+        })
+    }
+
+    fn register_devices(&mut self, device_type: DeviceType) -> Result<(), &'static str> {
+        if self.start.is_none() {
+            self.start = Some(Instant::now());
+        }
+        if let DeviceType::Joysticks(_) = device_type {
+            let mut states: Vec<_> = self.joystick_states.iter().collect();
+            states.sort_by_key(|&(&id, _)| id);
+            for (&id, state) in states {
+                self.pending
+                    .push(RawEvent::JoystickInitialStateEvent(id, state.clone()));
+            }
+        }
+        self.registered.push(device_type);
+        Ok(())
+    }
+
+    fn filter_devices(&mut self, device_names: HashSet<String>) {
+        self.filter = Some(device_names);
+    }
+
+    fn unfilter_devices(&mut self) {
+        self.filter = None;
+    }
+
+    fn set_gamepad_mappings(&mut self, _mappings: Option<&GamepadMappingDb>) {}
+
+    fn read_events(&mut self, events: &mut VecDeque<RawEvent>) {
+        let start = match self.start {
+            Some(start) => start,
+            None => return,
+        };
+        for event in ::std::mem::take(&mut self.pending) {
+            if self.passes_filters(&event) {
+                events.push_back(event);
+            }
+        }
+        let now = Instant::now();
+        let shared = self.shared.clone();
+        let mut state = shared.0.lock().unwrap();
+        while let Some(captured) = self.events.get(state.position) {
+            match self.due(start, captured) {
+                Some(due) if due > now => break,
+                Some(_) => (),
+                None if state.steps == 0 => break,
+                None => state.steps -= 1,
+            }
+            let event = captured.event.clone();
+            state.position += 1;
+            self.update_joystick_state(&event);
+            if self.passes_filters(&event) {
+                events.push_back(event);
+            }
+        }
+    }
+
+    fn wait(&mut self, timeout: Option<Duration>) {
+        let (lock, condvar) = &*self.shared;
+        let mut state = lock.lock().unwrap();
+        let next = self.events.get(state.position);
+        let due = match (self.start, next) {
+            (Some(start), Some(captured)) => self.due(start, captured),
+            _ => None,
+        };
+        let stepped = self.mode == ReplayMode::Stepped && state.steps > 0;
+        let ready = state.woken || (stepped && self.start.is_some() && next.is_some());
+        if !ready {
+            let until_due = due.map(|due| due.saturating_duration_since(Instant::now()));
+            state = match (timeout, until_due) {
+                (Some(timeout), Some(until_due)) => {
+                    condvar
+                        .wait_timeout(state, timeout.min(until_due))
+                        .unwrap()
+                        .0
+                }
+                (Some(timeout), None) | (None, Some(timeout)) => {
+                    condvar.wait_timeout(state, timeout).unwrap().0
+                }
+                (None, None) => condvar.wait(state).unwrap(),
+            };
+        }
+        state.woken = false;
+    }
+
+    fn joystick_state(&self, id: usize) -> Option<JoystickState> {
+        self.joystick_states.get(&id).cloned()
+    }
+
+    fn device_list(&self) -> DevicesDisplayInfo {
+        self.devices.clone()
+    }
+
+    fn open_force_feedback(
+        &mut self,
+        id: usize,
+    ) -> Result<Box<dyn ForceFeedbackSink>, ForceFeedbackError> {
+        if id >= self.devices.joysticks.len() {
+            return Err(ForceFeedbackError(format!("No joystick with id {}", id)));
+        }
+        Ok(Box::new(DiscardedOutput))
+    }
+
+    fn open_keyboard_leds(&mut self, id: usize) -> Result<Box<dyn LedSink>, LedError> {
+        if id >= self.devices.keyboards.len() {
+            return Err(LedError(format!("No keyboard with id {}", id)));
+        }
+        Ok(Box::new(DiscardedOutput))
+    }
+}
+
+/// Output to replayed devices, which goes nowhere
+struct DiscardedOutput;
+
+impl ForceFeedbackSink for DiscardedOutput {
+    fn set_rumble(&mut self, _low: f64, _high: f64) -> Result<(), ForceFeedbackError> {
+        Ok(())
+    }
+
+    fn play_effect(
+        &mut self,
+        _id: EffectId,
+        _effect: &ForceFeedbackEffect,
+    ) -> Result<(), ForceFeedbackError> {
+        Ok(())
+    }
+}
+
+impl LedSink for DiscardedOutput {
+    fn set_leds(&mut self, _leds: KeyboardLeds) -> Result<(), LedError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use devices::KeyboardDisplayInfo;
+    use event::KeyId;
+    use event_queue::{OverflowPolicy, DEFAULT_CAPACITY};
+    use manager::RawInputManager;
+
+    fn key(state: State) -> RawEvent {
+        RawEvent::KeyboardEvent(0, KeyId::A, state)
+    }
+
+    fn capture(times_ms: &[u64]) -> Capture {
+        let mut devices = DevicesDisplayInfo::default();
+        devices.keyboards.push(KeyboardDisplayInfo::default());
+        Capture {
+            devices,
+            events: times_ms
+                .iter()
+                .enumerate()
+                .map(|(index, &time)| CapturedEvent {
+                    time: Duration::from_millis(time),
+                    event: key(if index % 2 == 0 {
+                        State::Pressed
+                    } else {
+                        State::Released
+                    }),
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn manager(backend: ReplayBackend) -> RawInputManager {
+        let mut manager = RawInputManager::with_backend(
+            move || backend,
+            DEFAULT_CAPACITY,
+            OverflowPolicy::DropNewest,
+        );
+        manager.register_devices(DeviceType::Keyboards);
+        manager
+    }
+
+    #[test]
+    fn rejects_invalid_speeds() {
+        for &factor in [0.0, -1.0, f64::NAN].iter() {
+            assert!(ReplayBackend::new(capture(&[0]), ReplayMode::Accelerated(factor)).is_err());
+        }
+        assert!(ReplayBackend::new(capture(&[0]), ReplayMode::Accelerated(f64::INFINITY)).is_ok());
+    }
+
+    #[test]
+    fn real_time() {
+        let backend = ReplayBackend::new(capture(&[0, 50]), ReplayMode::RealTime).unwrap();
+        let handle = backend.handle();
+        let before = Instant::now();
+        let mut manager = manager(backend);
+        let timeout = Duration::from_secs(5);
+        assert_eq!(
+            manager.wait_event_timeout(timeout),
+            Some(key(State::Pressed))
+        );
+        assert_eq!(
+            manager.wait_event_timeout(timeout),
+            Some(key(State::Released))
+        );
+        assert!(before.elapsed() >= Duration::from_millis(50));
+        assert!(handle.is_finished());
+    }
+
+    #[test]
+    fn accelerated() {
+        let backend =
+            ReplayBackend::new(capture(&[0, 1000, 2000]), ReplayMode::Accelerated(20.0)).unwrap();
+        let before = Instant::now();
+        let mut manager = manager(backend);
+        let timeout = Duration::from_secs(5);
+        for _ in 0..3 {
+            assert!(manager.wait_event_timeout(timeout).is_some());
+        }
+        let elapsed = before.elapsed();
+        assert!(elapsed >= Duration::from_millis(100));
+        assert!(elapsed < Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn stepped() {
+        let backend = ReplayBackend::new(capture(&[0, 10, 20]), ReplayMode::Stepped).unwrap();
+        let handle = backend.handle();
+        assert_eq!(handle.len(), 3);
+        let mut manager = manager(backend);
+        let short = Duration::from_millis(50);
+        assert_eq!(manager.wait_event_timeout(short), None);
+        handle.step();
+        assert_eq!(
+            manager.wait_event_timeout(Duration::from_secs(5)),
+            Some(key(State::Pressed))
+        );
+        assert_eq!(manager.wait_event_timeout(short), None);
+        assert_eq!(handle.position(), 1);
+        handle.step_by(2);
+        assert_eq!(
+            manager.wait_event_timeout(Duration::from_secs(5)),
+            Some(key(State::Released))
+        );
+        assert_eq!(
+            manager.wait_event_timeout(Duration::from_secs(5)),
+            Some(key(State::Pressed))
+        );
+        assert!(handle.is_finished());
+    }
+
+    #[test]
+    fn unregistered_devices_are_skipped() {
+        let mut capture = capture(&[0]);
+        capture.events.insert(
+            0,
+            CapturedEvent {
+                time: Duration::from_millis(0),
+                event: RawEvent::MouseMoveEvent(0, 1, 1),
+            },
+        );
+        let backend = ReplayBackend::new(capture, ReplayMode::RealTime).unwrap();
+        let handle = backend.handle();
+        let mut manager = manager(backend);
+        assert_eq!(
+            manager.wait_event_timeout(Duration::from_secs(5)),
+            Some(key(State::Pressed))
+        );
+        assert_eq!(handle.position(), 2);
+    }
+}
@@ -0,0 +1,484 @@
+//! Round trips of every serde type, pinning the representation documented in
+//! the crate docs (changing one of these strings breaks saved data)
+
+use action::{Action, ActionEvent, ActionKind, Binding, DeviceSet, Input, PlayerEvent};
+use assignment::{AssignedDevice, AssignmentEvent, DeviceKind};
+use calibration::{AxisCalibration, CalibrationProfile};
+use capture::{CaptureFormat, CapturedEvent};
+use device_filter::{DeviceStatus, StableDeviceId};
+use devices::{
+    Axes, DeviceIds, DevicesDisplayInfo, HatSwitch, JoystickCapabilities, JoystickDisplayInfo,
+    JoystickState, KeyboardDisplayInfo, MouseDisplayInfo, RawAxes,
+};
+use event::{Axis, EventKind, KeyId, MouseButton, RawEvent, State};
+use event_queue::OverflowPolicy;
+use force_feedback::{EffectId, ForceFeedbackEffect, Waveform};
+use frame::{ButtonSet, InputFrame, JoystickFrame, KeyboardFrame, MouseFrame};
+use gamepad::{GamepadAxis, GamepadButton};
+use led::KeyboardLeds;
+use manager::{DeviceStats, DeviceType, XInputInclude};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::time::Duration;
+use subscription::EventFilter;
+
+fn check<T: Serialize + DeserializeOwned + PartialEq + Debug>(value: T, json: &str) {
+    assert_eq!(serde_json::to_string(&value).unwrap(), json);
+    assert_eq!(serde_json::from_str::<T>(json).unwrap(), value);
+}
+
+fn set<T: Eq + ::std::hash::Hash>(value: T) -> HashSet<T> {
+    let mut set = HashSet::new();
+    set.insert(value);
+    set
+}
+
+#[test]
+fn unit_enums() {
+    check(State::Pressed, r#""Pressed""#);
+    check(KeyId::Escape, r#""Escape""#);
+    check(MouseButton::Button4, r#""Button4""#);
+    check(Axis::SLIDER, r#""SLIDER""#);
+    check(HatSwitch::UpLeft, r#""UpLeft""#);
+    check(EventKind::JoystickInitialState, r#""JoystickInitialState""#);
+    check(GamepadButton::South, r#""South""#);
+    check(GamepadAxis::LeftTrigger, r#""LeftTrigger""#);
+    check(Waveform::SawtoothDown, r#""SawtoothDown""#);
+    check(DeviceKind::Joystick, r#""Joystick""#);
+    check(DeviceStatus::Filtered, r#""Filtered""#);
+    check(CaptureFormat::JsonLines, r#""JsonLines""#);
+    check(OverflowPolicy::CoalesceMotion, r#""CoalesceMotion""#);
+    check(ActionKind::Analog, r#""Analog""#);
+    check(XInputInclude::XInputApi, r#""XInputApi""#);
+}
+
+#[test]
+fn raw_events() {
+    check(
+        RawEvent::MouseButtonEvent(1, MouseButton::Left, State::Released),
+        r#"{"MouseButtonEvent":[1,"Left","Released"]}"#,
+    );
+    check(
+        RawEvent::MouseMoveEvent(1, -3, 4),
+        r#"{"MouseMoveEvent":[1,-3,4]}"#,
+    );
+    check(
+        RawEvent::MouseWheelEvent(0, -120.0),
+        r#"{"MouseWheelEvent":[0,-120.0]}"#,
+    );
+    check(
+        RawEvent::KeyboardEvent(0, KeyId::Escape, State::Pressed),
+        r#"{"KeyboardEvent":[0,"Escape","Pressed"]}"#,
+    );
+    check(
+        RawEvent::JoystickButtonEvent(2, 5, State::Pressed),
+        r#"{"JoystickButtonEvent":[2,5,"Pressed"]}"#,
+    );
+    check(
+        RawEvent::JoystickAxisEvent(0, Axis::RZ, 0.25),
+        r#"{"JoystickAxisEvent":[0,"RZ",0.25]}"#,
+    );
+    check(
+        RawEvent::JoystickHatSwitchEvent(0, HatSwitch::Down),
+        r#"{"JoystickHatSwitchEvent":[0,"Down"]}"#,
+    );
+    check(
+        RawEvent::GamepadButtonEvent(0, GamepadButton::South, State::Released),
+        r#"{"GamepadButtonEvent":[0,"South","Released"]}"#,
+    );
+    check(
+        RawEvent::GamepadAxisEvent(0, GamepadAxis::LeftX, -1.0),
+        r#"{"GamepadAxisEvent":[0,"LeftX",-1.0]}"#,
+    );
+    check(
+        CapturedEvent {
+            time: Duration::from_micros(1500),
+            event: RawEvent::MouseMoveEvent(0, 1, 0),
+        },
+        r#"{"time":{"secs":0,"nanos":1500000},"event":{"MouseMoveEvent":[0,1,0]}}"#,
+    );
+}
+
+#[test]
+fn joystick_states() {
+    let axes = Axes {
+        x: Some(-0.5),
+        slider: Some(1.0),
+        ..Axes::new()
+    };
+    check(
+        axes.clone(),
+        r#"{"x":-0.5,"y":null,"z":null,"rx":null,"ry":null,"rz":null,"slider":1.0}"#,
+    );
+    let raw_axes = RawAxes {
+        x: 64,
+        ..RawAxes::new()
+    };
+    let raw_json = format!(
+        r#"{{"x":64,"y":{y},"z":{z},"rx":{rx},"ry":{ry},"rz":{rz},"slider":{slider}}}"#,
+        y = raw_axes.y,
+        z = raw_axes.z,
+        rx = raw_axes.rx,
+        ry = raw_axes.ry,
+        rz = raw_axes.rz,
+        slider = raw_axes.slider
+    );
+    check(raw_axes.clone(), &raw_json);
+    check(
+        RawEvent::JoystickInitialStateEvent(
+            0,
+            JoystickState {
+                button_states: vec![true, false],
+                axis_states: axes,
+                hatswitch: Some(HatSwitch::UpLeft),
+                raw_axis_states: raw_axes,
+            },
+        ),
+        &format!(
+            concat!(
+                r#"{{"JoystickInitialStateEvent":[0,{{"button_states":[true,false],"#,
+                r#""axis_states":{{"x":-0.5,"y":null,"z":null,"rx":null,"ry":null,"rz":null,"slider":1.0}},"#,
+                r#""hatswitch":"UpLeft","raw_axis_states":{}}}]}}"#
+            ),
+            raw_json
+        ),
+    );
+}
+
+#[test]
+fn devices() {
+    check(
+        StableDeviceId(0x0123_4567_89ab_cdef),
+        r#""0123456789abcdef""#,
+    );
+    assert!(serde_json::from_str::<StableDeviceId>(r#""xyz""#).is_err());
+    let ids = DeviceIds {
+        vendor_id: Some(0x046d),
+        product_id: None,
+        version_number: Some(2),
+        usage_page: 1,
+        usage: 6,
+    };
+    let ids_json =
+        r#"{"vendor_id":1133,"product_id":null,"version_number":2,"usage_page":1,"usage":6}"#;
+    check(ids.clone(), ids_json);
+    let mouse = MouseDisplayInfo {
+        name: "mouse".to_string(),
+        serial: Some("1234".to_string()),
+        stable_id: StableDeviceId(1),
+        ..Default::default()
+    };
+    let mouse_json = concat!(
+        r#"{"name":"mouse","serial":"1234","product":null,"manufacturer":null,"#,
+        r#""ids":{"vendor_id":null,"product_id":null,"version_number":null,"usage_page":0,"usage":0},"#,
+        r#""stable_id":"0000000000000001","status":"Active"}"#
+    );
+    check(mouse.clone(), mouse_json);
+    let keyboard = KeyboardDisplayInfo {
+        name: "keyboard".to_string(),
+        product: Some("Keys".to_string()),
+        manufacturer: Some("Maker".to_string()),
+        ids: ids.clone(),
+        status: DeviceStatus::Filtered,
+        ..Default::default()
+    };
+    let keyboard_json = format!(
+        concat!(
+            r#"{{"name":"keyboard","serial":null,"product":"Keys","manufacturer":"Maker","#,
+            r#""ids":{},"stable_id":"0000000000000000","status":"Filtered"}}"#
+        ),
+        ids_json
+    );
+    check(keyboard.clone(), &keyboard_json);
+    let capabilities = JoystickCapabilities {
+        buttons: 12,
+        axes: vec![Axis::X, Axis::Y],
+        hatswitch: true,
+    };
+    let capabilities_json = r#"{"buttons":12,"axes":["X","Y"],"hatswitch":true}"#;
+    check(capabilities.clone(), capabilities_json);
+    let joystick = JoystickDisplayInfo {
+        name: "joystick".to_string(),
+        capabilities,
+        ..Default::default()
+    };
+    let joystick_json = format!(
+        concat!(
+            r#"{{"name":"joystick","serial":null,"product":null,"manufacturer":null,"#,
+            r#""ids":{{"vendor_id":null,"product_id":null,"version_number":null,"usage_page":0,"usage":0}},"#,
+            r#""stable_id":"0000000000000000","status":"Active","capabilities":{}}}"#
+        ),
+        capabilities_json
+    );
+    check(joystick.clone(), &joystick_json);
+    check(
+        DevicesDisplayInfo {
+            mice: vec![mouse],
+            keyboards: vec![keyboard],
+            joysticks: vec![joystick],
+        },
+        &format!(
+            r#"{{"mice":[{}],"keyboards":[{}],"joysticks":[{}]}}"#,
+            mouse_json, keyboard_json, joystick_json
+        ),
+    );
+    check(
+        DeviceStats {
+            number_of_mice: 1,
+            number_of_keyboards: 2,
+            number_of_joysticks: 0,
+        },
+        r#"{"number_of_mice":1,"number_of_keyboards":2,"number_of_joysticks":0}"#,
+    );
+    check(DeviceType::Mice, r#""Mice""#);
+    check(
+        DeviceType::Joysticks(XInputInclude::True),
+        r#"{"Joysticks":"True"}"#,
+    );
+    check(
+        KeyboardLeds {
+            num_lock: true,
+            caps_lock: false,
+            scroll_lock: true,
+        },
+        r#"{"num_lock":true,"caps_lock":false,"scroll_lock":true}"#,
+    );
+}
+
+#[test]
+fn filters() {
+    check(
+        EventFilter::new()
+            .device_type(DeviceType::Keyboards)
+            .device(1)
+            .kind(EventKind::Keyboard),
+        r#"{"device_types":["Keyboards"],"devices":[1],"kinds":["Keyboard"]}"#,
+    );
+}
+
+#[test]
+fn force_feedback() {
+    check(EffectId(3), "3");
+    check(
+        ForceFeedbackEffect::Rumble {
+            low: 0.5,
+            high: 1.0,
+        },
+        r#"{"Rumble":{"low":0.5,"high":1.0}}"#,
+    );
+    check(
+        ForceFeedbackEffect::Constant { level: -0.25 },
+        r#"{"Constant":{"level":-0.25}}"#,
+    );
+    check(
+        ForceFeedbackEffect::Periodic {
+            waveform: Waveform::Sine,
+            magnitude: 0.5,
+            period: Duration::from_millis(20),
+            offset: 0.0,
+        },
+        r#"{"Periodic":{"waveform":"Sine","magnitude":0.5,"period":{"secs":0,"nanos":20000000},"offset":0.0}}"#,
+    );
+    check(
+        ForceFeedbackEffect::Spring {
+            axis: Axis::X,
+            center: 0.0,
+            coefficient: 0.75,
+            deadband: 0.1,
+        },
+        r#"{"Spring":{"axis":"X","center":0.0,"coefficient":0.75,"deadband":0.1}}"#,
+    );
+    check(
+        ForceFeedbackEffect::Damper {
+            axis: Axis::Y,
+            coefficient: 1.0,
+        },
+        r#"{"Damper":{"axis":"Y","coefficient":1.0}}"#,
+    );
+}
+
+#[test]
+fn frames() {
+    let mut frame = InputFrame::default();
+    frame.keyboards.insert(
+        0,
+        KeyboardFrame {
+            keys: ButtonSet {
+                pressed: set(KeyId::A),
+                released: HashSet::new(),
+                held: set(KeyId::A),
+            },
+        },
+    );
+    frame.mice.insert(
+        1,
+        MouseFrame {
+            motion: (5, -5),
+            wheel: 0.5,
+            ..Default::default()
+        },
+    );
+    let mut axes = HashMap::new();
+    axes.insert(Axis::X, 0.5);
+    let mut gamepad_axes = HashMap::new();
+    gamepad_axes.insert(GamepadAxis::RightY, -1.0);
+    frame.joysticks.insert(
+        2,
+        JoystickFrame {
+            buttons: ButtonSet {
+                released: set(3),
+                ..Default::default()
+            },
+            axes,
+            hatswitch: Some(HatSwitch::Left),
+            gamepad_buttons: ButtonSet {
+                held: set(GamepadButton::North),
+                ..Default::default()
+            },
+            gamepad_axes,
+        },
+    );
+    check(
+        frame,
+        concat!(
+            r#"{"keyboards":{"0":{"keys":{"pressed":["A"],"released":[],"held":["A"]}}},"#,
+            r#""mice":{"1":{"buttons":{"pressed":[],"released":[],"held":[]},"motion":[5,-5],"wheel":0.5}},"#,
+            r#""joysticks":{"2":{"buttons":{"pressed":[],"released":[3],"held":[]},"axes":{"X":0.5},"#,
+            r#""hatswitch":"Left","gamepad_buttons":{"pressed":[],"released":[],"held":["North"]},"#,
+            r#""gamepad_axes":{"RightY":-1.0}}}}"#
+        ),
+    );
+}
+
+#[test]
+fn actions() {
+    let binding = Binding {
+        input: Input::JoystickAxis(Axis::Y),
+        scale: 2.0,
+        invert: true,
+        deadzone: 0.1,
+    };
+    let binding_json = r#"{"input":{"JoystickAxis":"Y"},"scale":2.0,"invert":true,"deadzone":0.1}"#;
+    check(binding.clone(), binding_json);
+    check(Input::Key(KeyId::Space), r#"{"Key":"Space"}"#);
+    check(
+        Input::MouseButton(MouseButton::Middle),
+        r#"{"MouseButton":"Middle"}"#,
+    );
+    check(Input::MouseWheel, r#""MouseWheel""#);
+    check(Input::JoystickButton(4), r#"{"JoystickButton":4}"#);
+    check(Input::JoystickHat(HatSwitch::Up), r#"{"JoystickHat":"Up"}"#);
+    check(
+        Input::GamepadButton(GamepadButton::East),
+        r#"{"GamepadButton":"East"}"#,
+    );
+    check(
+        Input::GamepadAxis(GamepadAxis::LeftY),
+        r#"{"GamepadAxis":"LeftY"}"#,
+    );
+    check(
+        Action {
+            name: "move".to_string(),
+            kind: ActionKind::Analog,
+            bindings: vec![binding.clone()],
+        },
+        &format!(
+            r#"{{"name":"move","kind":"Analog","bindings":[{}]}}"#,
+            binding_json
+        ),
+    );
+    check(
+        DeviceSet {
+            keyboards: vec![0],
+            mice: vec![],
+            joysticks: vec![1, 2],
+        },
+        r#"{"keyboards":[0],"mice":[],"joysticks":[1,2]}"#,
+    );
+    check(
+        ActionEvent::Pressed("jump".to_string()),
+        r#"{"Pressed":"jump"}"#,
+    );
+    check(
+        ActionEvent::Released("jump".to_string()),
+        r#"{"Released":"jump"}"#,
+    );
+    check(
+        ActionEvent::Changed("move".to_string(), -0.5),
+        r#"{"Changed":["move",-0.5]}"#,
+    );
+    check(
+        ActionEvent::Bound("move".to_string(), binding),
+        &format!(r#"{{"Bound":["move",{}]}}"#, binding_json),
+    );
+    check(
+        PlayerEvent {
+            player: 1,
+            event: ActionEvent::Pressed("jump".to_string()),
+        },
+        r#"{"player":1,"event":{"Pressed":"jump"}}"#,
+    );
+}
+
+#[test]
+fn assignments() {
+    let device = AssignedDevice {
+        kind: DeviceKind::Joystick,
+        id: Some(2),
+        stable_id: StableDeviceId(0xff),
+    };
+    let device_json = r#"{"kind":"Joystick","id":2,"stable_id":"00000000000000ff"}"#;
+    check(device, device_json);
+    check(
+        AssignedDevice { id: None, ..device },
+        r#"{"kind":"Joystick","id":null,"stable_id":"00000000000000ff"}"#,
+    );
+    let event = |name: &str| format!(r#"{{"{}":[0,{}]}}"#, name, device_json);
+    check(AssignmentEvent::Joined(0, device), &event("Joined"));
+    check(AssignmentEvent::Assigned(0, device), &event("Assigned"));
+    check(
+        AssignmentEvent::Disconnected(0, device),
+        &event("Disconnected"),
+    );
+    check(
+        AssignmentEvent::Reconnected(0, device),
+        &event("Reconnected"),
+    );
+    check(
+        AssignmentEvent::Rejected(device),
+        &format!(r#"{{"Rejected":{}}}"#, device_json),
+    );
+    check(
+        AssignmentEvent::Left(1, vec![device]),
+        &format!(r#"{{"Left":[1,[{}]]}}"#, device_json),
+    );
+    check(AssignmentEvent::Swapped(0, 1), r#"{"Swapped":[0,1]}"#);
+}
+
+#[test]
+fn calibration() {
+    let axis = AxisCalibration {
+        min: -0.9,
+        center: 0.05,
+        max: 1.0,
+        deadzone: 0.1,
+    };
+    let axis_json = r#"{"min":-0.9,"center":0.05,"max":1.0,"deadzone":0.1}"#;
+    check(axis, axis_json);
+    let mut axes = HashMap::new();
+    axes.insert(Axis::RZ, axis);
+    check(
+        CalibrationProfile {
+            stable_id: StableDeviceId(0x10),
+            name: "stick".to_string(),
+            axes,
+        },
+        &format!(
+            r#"{{"stable_id":"0000000000000010","name":"stick","axes":{{"RZ":{}}}}}"#,
+            axis_json
+        ),
+    );
+}
@@ -0,0 +1,566 @@
+//! Extra consumers of events
+//!
+//! Besides `get_event`, events can be received by:
+//!
+//! * `EventSubscriber`s (see `RawInputManager::subscribe`), each with its own
+//!   bounded queue so a slow subscriber only loses its own events
+//! * callbacks (see `RawInputManager::on_event`), which run on a single
+//!   dispatcher thread owned by the manager:
+//!     * events reach callbacks in the order they were read
+//!     * for each event, callbacks run in the order they were subscribed
+//!     * a callback never runs concurrently with another callback
+//!     * a callback may drop the manager, after which the dispatcher thread ends
+//!       once the events already queued are dispatched
+//!
+//! Every consumer gets its own copy of the events, so none takes events away from another.
+
+use event::{EventKind, RawEvent};
+use event_queue::{EventQueue, OverflowPolicy};
+#[cfg(feature = "async")]
+use event_queue::EventStream;
+use manager::DeviceType;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::{JoinHandle, ThreadId};
+use std::time::{Duration, Instant};
+
+/// Selects events by device type, device index and event kind
+///
+/// Each criterion left empty matches everything, e.g.
+/// `EventFilter::new().device_type(DeviceType::Keyboards).device(1)`
+/// matches every event of the second keyboard.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct EventFilter {
+    pub device_types: Vec<DeviceType>,
+    pub devices: Vec<usize>,
+    pub kinds: Vec<EventKind>,
+}
+
+impl EventFilter {
+    /// Filter matching every event
+    pub fn new() -> EventFilter {
+        EventFilter::default()
+    }
+
+    pub fn device_type(mut self, device_type: DeviceType) -> EventFilter {
+        self.device_types.push(device_type);
+        self
+    }
+
+    pub fn device(mut self, id: usize) -> EventFilter {
+        self.devices.push(id);
+        self
+    }
+
+    pub fn kind(mut self, kind: EventKind) -> EventFilter {
+        self.kinds.push(kind);
+        self
+    }
+
+    pub fn matches(&self, event: &RawEvent) -> bool {
+        (self.device_types.is_empty()
+            || self.device_types.iter().any(|device_type| event.is_from(device_type)))
+            && (self.devices.is_empty() || self.devices.contains(&event.device_id()))
+            && (self.kinds.is_empty() || self.kinds.contains(&event.kind()))
+    }
+}
+
+#[derive(Default)]
+struct BroadcastState {
+    outputs: Vec<(EventFilter, EventQueue)>,
+    closed: bool,
+}
+
+/// Copies events into the queues of all subscribers
+///
+/// Queues are removed once closed by their subscriber.
+#[derive(Clone, Default)]
+pub struct Broadcast {
+    shared: Arc<Mutex<BroadcastState>>,
+}
+
+impl Broadcast {
+    pub fn new() -> Broadcast {
+        Broadcast::default()
+    }
+
+    /// Adds a queue receiving the events matching filter
+    /// The queue is already closed if the broadcast is.
+    pub fn add(&self, filter: EventFilter, capacity: usize, policy: OverflowPolicy) -> EventQueue {
+        let queue = EventQueue::new(capacity, policy);
+        let mut state = self.shared.lock().unwrap();
+        if state.closed {
+            queue.close();
+        } else {
+            state.outputs.push((filter, queue.clone()));
+        }
+        queue
+    }
+
+    pub fn send<'a, I: IntoIterator<Item = &'a RawEvent>>(&self, events: I) {
+        let mut state = self.shared.lock().unwrap();
+        state.outputs.retain(|(_, queue)| !queue.is_closed());
+        if state.outputs.is_empty() {
+            return;
+        }
+        for event in events {
+            for (filter, queue) in &state.outputs {
+                if filter.matches(event) {
+                    queue.push(event.clone());
+                }
+            }
+        }
+    }
+
+    /// Closes all queues, now and when added later
+    pub fn close(&self) {
+        let mut state = self.shared.lock().unwrap();
+        state.closed = true;
+        for (_, queue) in state.outputs.drain(..) {
+            queue.close();
+        }
+    }
+}
+
+/// Receives a copy of every event matching its filter through its own queue
+///
+/// A subscriber that falls behind loses its oldest events (or others depending on
+/// its `OverflowPolicy`), counted by `lagged`, without affecting other consumers.
+/// Cloning creates a new subscriber with the same filter and an empty queue.
+/// Once the manager is dropped, waits return None as soon as the queue is empty.
+pub struct EventSubscriber {
+    queue: EventQueue,
+    filter: EventFilter,
+    broadcast: Broadcast,
+}
+
+impl EventSubscriber {
+    pub fn new(
+        broadcast: &Broadcast,
+        filter: EventFilter,
+        capacity: usize,
+        policy: OverflowPolicy,
+    ) -> EventSubscriber {
+        EventSubscriber {
+            queue: broadcast.add(filter.clone(), capacity, policy),
+            filter,
+            broadcast: broadcast.clone(),
+        }
+    }
+
+    pub fn filter(&self) -> &EventFilter {
+        &self.filter
+    }
+
+    /// Takes the oldest event without blocking
+    pub fn get_event(&self) -> Option<RawEvent> {
+        self.queue.pop()
+    }
+
+    /// Blocks until an event is available (None once the manager is dropped)
+    pub fn wait_event(&self) -> Option<RawEvent> {
+        self.queue.pop_until(None)
+    }
+
+    /// Blocks until an event is available or the timeout has passed
+    pub fn wait_event_timeout(&self, timeout: Duration) -> Option<RawEvent> {
+        self.queue.pop_until(Some(Instant::now() + timeout))
+    }
+
+    pub fn get_events(&self) -> Vec<RawEvent> {
+        self.queue.drain()
+    }
+
+    /// Stops receiving events, so waits return None once the queue is empty
+    pub fn close(&self) {
+        self.queue.close();
+    }
+
+    /// Number of events this subscriber lost so far by not keeping up
+    pub fn lagged(&self) -> usize {
+        self.queue.dropped()
+    }
+
+    /// Stream of the events of this subscriber for async executors
+    /// The stream ends once the subscriber or the manager is dropped.
+    #[cfg(feature = "async")]
+    pub fn stream(&self) -> EventStream {
+        self.queue.stream()
+    }
+}
+
+impl Clone for EventSubscriber {
+    fn clone(&self) -> EventSubscriber {
+        EventSubscriber::new(
+            &self.broadcast,
+            self.filter.clone(),
+            self.queue.capacity(),
+            self.queue.policy(),
+        )
+    }
+}
+
+impl Drop for EventSubscriber {
+    fn drop(&mut self) {
+        self.queue.close();
+    }
+}
+
+type Callback = Box<dyn FnMut(&RawEvent) + Send>;
+
+struct Subscriber {
+    filter: EventFilter,
+    active: AtomicBool,
+    callback: Mutex<Callback>,
+}
+
+type Subscribers = Arc<Mutex<Vec<Arc<Subscriber>>>>;
+
+/// Thread invoking callbacks for the events of an `EventQueue`
+pub struct Dispatcher {
+    queue: EventQueue,
+    subscribers: Subscribers,
+    thread_id: ThreadId,
+    joiner: Option<JoinHandle<()>>,
+}
+
+impl Dispatcher {
+    /// Starts dispatching the events pushed to queue, until it is closed
+    pub fn new(queue: EventQueue) -> Dispatcher {
+        let subscribers: Subscribers = Arc::new(Mutex::new(Vec::new()));
+        let thread_subscribers = subscribers.clone();
+        let thread_queue = queue.clone();
+        let joiner = thread::spawn(move || {
+            while let Some(event) = thread_queue.pop_until(None) {
+                let subscribers = thread_subscribers.lock().unwrap().clone();
+                for subscriber in subscribers {
+                    if !subscriber.active.load(Ordering::SeqCst) || !subscriber.filter.matches(&event) {
+                        continue;
+                    }
+                    let mut callback = subscriber.callback.lock().unwrap();
+                    // Checked again as unsubscribing waits on the callback lock
+                    if subscriber.active.load(Ordering::SeqCst) {
+                        (*callback)(&event);
+                    }
+                }
+            }
+        });
+        Dispatcher {
+            queue,
+            subscribers,
+            thread_id: joiner.thread().id(),
+            joiner: Some(joiner),
+        }
+    }
+
+    pub fn subscribe<F>(&self, filter: EventFilter, callback: F) -> Subscription
+    where
+        F: FnMut(&RawEvent) + Send + 'static,
+    {
+        let subscriber = Arc::new(Subscriber {
+            filter,
+            active: AtomicBool::new(true),
+            callback: Mutex::new(Box::new(callback)),
+        });
+        self.subscribers.lock().unwrap().push(subscriber.clone());
+        Subscription {
+            subscriber,
+            subscribers: self.subscribers.clone(),
+            dispatcher_thread: self.thread_id,
+        }
+    }
+}
+
+impl Drop for Dispatcher {
+    fn drop(&mut self) {
+        self.queue.close();
+        // A callback dropping the dispatcher can't wait for its own thread, which
+        // then ends on its own
+        if thread::current().id() == self.thread_id {
+            return;
+        }
+        self.joiner.take().unwrap().join().unwrap();
+    }
+}
+
+/// Handle to a callback, which is unsubscribed when the handle is dropped
+#[must_use = "the callback is unsubscribed when the subscription is dropped"]
+pub struct Subscription {
+    subscriber: Arc<Subscriber>,
+    subscribers: Subscribers,
+    dispatcher_thread: ThreadId,
+}
+
+impl Subscription {
+    /// Stops the callback
+    ///
+    /// Once this returns the callback is not running and will not be called
+    /// again. It may be called from within any callback, including its own.
+    pub fn unsubscribe(self) {}
+
+    pub fn filter(&self) -> &EventFilter {
+        &self.subscriber.filter
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.subscriber.active.store(false, Ordering::SeqCst);
+        // Wait for a running call to finish (unless this is that call)
+        if thread::current().id() != self.dispatcher_thread {
+            drop(self.subscriber.callback.lock());
+        }
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| !Arc::ptr_eq(subscriber, &self.subscriber));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use devices::{DevicesDisplayInfo, KeyboardDisplayInfo, MouseDisplayInfo};
+    use event::{KeyId, State};
+    use manager::RawInputManager;
+    use mock::{MockBackend, MockHandle};
+    use std::sync::mpsc::{channel, Receiver};
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn key(id: usize) -> RawEvent {
+        RawEvent::KeyboardEvent(id, KeyId::A, State::Pressed)
+    }
+
+    fn mouse(id: usize) -> RawEvent {
+        RawEvent::MouseMoveEvent(id, 1, 1)
+    }
+
+    fn mock_manager(capacity: usize) -> (RawInputManager, MockHandle) {
+        let backend = MockBackend::new();
+        let handle = backend.handle();
+        let mut devices = DevicesDisplayInfo::default();
+        devices.mice.push(MouseDisplayInfo::default());
+        devices.keyboards.push(KeyboardDisplayInfo::default());
+        handle.set_devices(devices);
+        let manager =
+            RawInputManager::with_backend(move || backend, capacity, OverflowPolicy::DropOldest);
+        (manager, handle)
+    }
+
+    #[test]
+    fn every_subscriber_gets_a_copy() {
+        let broadcast = Broadcast::new();
+        let first = EventSubscriber::new(
+            &broadcast,
+            EventFilter::new(),
+            8,
+            OverflowPolicy::DropOldest,
+        );
+        let second = first.clone();
+        broadcast.send(&[key(0), mouse(0)]);
+        assert_eq!(first.get_events(), vec![key(0), mouse(0)]);
+        assert_eq!(second.get_events(), vec![key(0), mouse(0)]);
+    }
+
+    #[test]
+    fn subscribers_filter_their_own_events() {
+        let broadcast = Broadcast::new();
+        let keys = EventSubscriber::new(
+            &broadcast,
+            EventFilter::new().kind(EventKind::Keyboard),
+            8,
+            OverflowPolicy::DropOldest,
+        );
+        let second_devices = EventSubscriber::new(
+            &broadcast,
+            EventFilter::new().device(1),
+            8,
+            OverflowPolicy::DropOldest,
+        );
+        broadcast.send(&[key(0), mouse(1), key(1)]);
+        assert_eq!(keys.get_events(), vec![key(0), key(1)]);
+        assert_eq!(second_devices.get_events(), vec![mouse(1), key(1)]);
+    }
+
+    #[test]
+    fn slow_subscribers_only_lag_themselves() {
+        let broadcast = Broadcast::new();
+        let slow = EventSubscriber::new(
+            &broadcast,
+            EventFilter::new(),
+            2,
+            OverflowPolicy::DropOldest,
+        );
+        let fast = EventSubscriber::new(
+            &broadcast,
+            EventFilter::new(),
+            8,
+            OverflowPolicy::DropOldest,
+        );
+        broadcast.send(&[key(0), key(1), key(2), key(3)]);
+        assert_eq!(slow.lagged(), 2);
+        assert_eq!(slow.get_events(), vec![key(2), key(3)]);
+        assert_eq!(fast.lagged(), 0);
+        assert_eq!(fast.get_events().len(), 4);
+    }
+
+    #[test]
+    fn closed_subscribers_stop_receiving() {
+        let broadcast = Broadcast::new();
+        let subscriber = EventSubscriber::new(
+            &broadcast,
+            EventFilter::new(),
+            8,
+            OverflowPolicy::DropOldest,
+        );
+        broadcast.send(&[key(0)]);
+        subscriber.close();
+        broadcast.send(&[key(1)]);
+        assert_eq!(subscriber.wait_event(), Some(key(0)));
+        assert_eq!(subscriber.wait_event(), None);
+        assert!(broadcast.shared.lock().unwrap().outputs.is_empty());
+    }
+
+    #[test]
+    fn closing_the_broadcast_ends_waits() {
+        let broadcast = Broadcast::new();
+        let subscriber = EventSubscriber::new(
+            &broadcast,
+            EventFilter::new(),
+            8,
+            OverflowPolicy::DropOldest,
+        );
+        let waiter = thread::spawn(move || subscriber.wait_event());
+        thread::sleep(Duration::from_millis(20));
+        broadcast.close();
+        assert_eq!(waiter.join().unwrap(), None);
+        // Subscribers added later are closed straight away
+        let late = EventSubscriber::new(
+            &broadcast,
+            EventFilter::new(),
+            8,
+            OverflowPolicy::DropOldest,
+        );
+        assert_eq!(late.wait_event_timeout(TIMEOUT), None);
+    }
+
+    #[test]
+    fn manager_subscribers_get_mock_events() {
+        let (manager, handle) = mock_manager(16);
+        let keys = manager.subscribe(EventFilter::new().device_type(DeviceType::Keyboards));
+        let all = manager.subscribe_with_queue(EventFilter::new(), 1, OverflowPolicy::DropNewest);
+        handle.push_event(mouse(0));
+        handle.push_event(key(0));
+        assert_eq!(keys.wait_event_timeout(TIMEOUT), Some(key(0)));
+        // Waits for the manager thread to stop sending
+        drop(manager);
+        assert_eq!(keys.wait_event(), None);
+        assert_eq!(all.get_events(), vec![mouse(0)]);
+        assert_eq!(all.lagged(), 1);
+    }
+
+    /// Dispatcher of an unbounded queue with a callback sending what it gets to the receiver
+    fn dispatcher(
+        filter: EventFilter,
+    ) -> (EventQueue, Dispatcher, Subscription, Receiver<RawEvent>) {
+        let queue = EventQueue::new(usize::MAX, OverflowPolicy::DropNewest);
+        let dispatcher = Dispatcher::new(queue.clone());
+        let (sender, receiver) = channel();
+        let subscription =
+            dispatcher.subscribe(filter, move |event| sender.send(event.clone()).unwrap());
+        (queue, dispatcher, subscription, receiver)
+    }
+
+    #[test]
+    fn callbacks_get_events_in_order() {
+        let (queue, _dispatcher, _subscription, receiver) = dispatcher(EventFilter::new());
+        queue.extend(vec![key(0), mouse(0), key(1)]);
+        let events: Vec<RawEvent> = (0..3)
+            .map(|_| receiver.recv_timeout(TIMEOUT).unwrap())
+            .collect();
+        assert_eq!(events, vec![key(0), mouse(0), key(1)]);
+    }
+
+    #[test]
+    fn callbacks_only_get_matching_events() {
+        let filter = EventFilter::new()
+            .device_type(DeviceType::Keyboards)
+            .device(1);
+        let (queue, _dispatcher, subscription, receiver) = dispatcher(filter.clone());
+        assert_eq!(subscription.filter(), &filter);
+        queue.extend(vec![key(0), mouse(1), key(1), key(2)]);
+        assert_eq!(receiver.recv_timeout(TIMEOUT), Ok(key(1)));
+        assert!(receiver.recv_timeout(Duration::from_millis(50)).is_err());
+    }
+
+    #[test]
+    fn unsubscribed_callbacks_are_not_called() {
+        let (queue, _dispatcher, subscription, receiver) = dispatcher(EventFilter::new());
+        queue.push(key(0));
+        assert_eq!(receiver.recv_timeout(TIMEOUT), Ok(key(0)));
+        subscription.unsubscribe();
+        queue.push(key(1));
+        assert!(receiver.recv_timeout(Duration::from_millis(50)).is_err());
+    }
+
+    #[test]
+    fn callbacks_can_unsubscribe_themselves() {
+        let queue = EventQueue::new(usize::MAX, OverflowPolicy::DropNewest);
+        let dispatcher = Dispatcher::new(queue.clone());
+        let (sender, receiver) = channel();
+        let slot: Arc<Mutex<Option<Subscription>>> = Arc::new(Mutex::new(None));
+        let callback_slot = slot.clone();
+        let subscription = dispatcher.subscribe(EventFilter::new(), move |event| {
+            sender.send(event.clone()).unwrap();
+            callback_slot.lock().unwrap().take();
+        });
+        *slot.lock().unwrap() = Some(subscription);
+        // Callbacks subscribed later still run
+        let (later_sender, later_receiver) = channel();
+        let _later = dispatcher.subscribe(EventFilter::new(), move |event| {
+            later_sender.send(event.clone()).unwrap()
+        });
+        queue.extend(vec![key(0), key(1)]);
+        assert_eq!(later_receiver.recv_timeout(TIMEOUT), Ok(key(0)));
+        assert_eq!(later_receiver.recv_timeout(TIMEOUT), Ok(key(1)));
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec![key(0)]);
+    }
+
+    #[test]
+    fn slow_callbacks_lose_no_events() {
+        let (mut manager, handle) = mock_manager(2);
+        let (sender, receiver) = channel();
+        let _subscription = manager.on_event(EventFilter::new(), move |event| {
+            thread::sleep(Duration::from_millis(1));
+            sender.send(event.clone()).unwrap();
+        });
+        for id in 0..20 {
+            handle.push_event(key(id));
+        }
+        let events: Vec<RawEvent> = (0..20)
+            .map(|_| receiver.recv_timeout(TIMEOUT).unwrap())
+            .collect();
+        assert_eq!(events, (0..20).map(key).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn callbacks_can_drop_the_manager() {
+        let (mut manager, handle) = mock_manager(16);
+        let slot: Arc<Mutex<Option<RawInputManager>>> = Arc::new(Mutex::new(None));
+        let callback_slot = slot.clone();
+        let (sender, receiver) = channel();
+        let _subscription = manager.on_event(EventFilter::new(), move |_| {
+            drop(callback_slot.lock().unwrap().take());
+            sender.send(()).unwrap();
+        });
+        *slot.lock().unwrap() = Some(manager);
+        handle.push_event(key(0));
+        assert_eq!(receiver.recv_timeout(TIMEOUT), Ok(()));
+        assert!(slot.lock().unwrap().is_none());
+    }
+}
@@ -0,0 +1,641 @@
+//! XInput support for Xbox controllers
+//!
+//! Rawinput only sees a reduced view of XInput controllers (combined triggers, no
+//! guide button, no rumble). With `XInputInclude::XInputApi` these controllers are
+//! read through the XInput API instead and reported as ordinary joysticks:
+//!
+//! * Buttons: A, B, X, Y, LB, RB, Back, Start, LS, RS, Guide (in that order)
+//! * Axes: X/Y left stick, RX/RY right stick, Z left trigger, RZ right trigger
+//!   (triggers lie in [0, 1])
+//! * Hatswitch: the DPad
+//!
+//! Axes are oriented as rawinput reports them: Y is up positive, RY down positive.
+//!
+//! Decoding and the `XInputProvider` trait are available on every platform, so
+//! they can be driven by recorded or synthetic controller state.
+
+use devices::{Axes, HatSwitch, JoystickState, RawAxes};
+#[cfg(windows)]
+use devices::{DeviceIds, Devices, JoystickInfo};
+#[cfg(windows)]
+use event::RawEvent;
+use force_feedback::{ForceFeedbackError, ForceFeedbackSink};
+#[cfg(windows)]
+use gamepad::GamepadState;
+#[cfg(windows)]
+use joystick::update_joystick_state;
+use std::collections::HashMap;
+#[cfg(windows)]
+use std::collections::VecDeque;
+#[cfg(windows)]
+use std::ffi::OsStr;
+#[cfg(windows)]
+use std::mem;
+#[cfg(windows)]
+use std::os::windows::ffi::OsStrExt;
+use std::rc::Rc;
+#[cfg(windows)]
+use winapi::shared::minwindef::{DWORD, FARPROC, HMODULE};
+#[cfg(windows)]
+use winapi::shared::ntdef::LPCSTR;
+#[cfg(windows)]
+use winapi::um::libloaderapi::{GetProcAddress, LoadLibraryW};
+
+/// Number of XInput user slots
+pub const XUSER_MAX_COUNT: u32 = 4;
+
+pub const XINPUT_GAMEPAD_DPAD_UP: u16 = 0x0001;
+pub const XINPUT_GAMEPAD_DPAD_DOWN: u16 = 0x0002;
+pub const XINPUT_GAMEPAD_DPAD_LEFT: u16 = 0x0004;
+pub const XINPUT_GAMEPAD_DPAD_RIGHT: u16 = 0x0008;
+pub const XINPUT_GAMEPAD_START: u16 = 0x0010;
+pub const XINPUT_GAMEPAD_BACK: u16 = 0x0020;
+pub const XINPUT_GAMEPAD_LEFT_THUMB: u16 = 0x0040;
+pub const XINPUT_GAMEPAD_RIGHT_THUMB: u16 = 0x0080;
+pub const XINPUT_GAMEPAD_LEFT_SHOULDER: u16 = 0x0100;
+pub const XINPUT_GAMEPAD_RIGHT_SHOULDER: u16 = 0x0200;
+pub const XINPUT_GAMEPAD_GUIDE: u16 = 0x0400;
+pub const XINPUT_GAMEPAD_A: u16 = 0x1000;
+pub const XINPUT_GAMEPAD_B: u16 = 0x2000;
+pub const XINPUT_GAMEPAD_X: u16 = 0x4000;
+pub const XINPUT_GAMEPAD_Y: u16 = 0x8000;
+
+/// Button masks in the order they are reported as joystick buttons
+const BUTTON_ORDER: [u16; 11] = [
+    XINPUT_GAMEPAD_A,
+    XINPUT_GAMEPAD_B,
+    XINPUT_GAMEPAD_X,
+    XINPUT_GAMEPAD_Y,
+    XINPUT_GAMEPAD_LEFT_SHOULDER,
+    XINPUT_GAMEPAD_RIGHT_SHOULDER,
+    XINPUT_GAMEPAD_BACK,
+    XINPUT_GAMEPAD_START,
+    XINPUT_GAMEPAD_LEFT_THUMB,
+    XINPUT_GAMEPAD_RIGHT_THUMB,
+    XINPUT_GAMEPAD_GUIDE,
+];
+
+/// Gamepad mapping matching the joystick layout described in the module docs
+pub const XINPUT_GAMEPAD_MAPPING: &str = "00000000000000000000000000000000,XInput Controller,\
+a:b0,b:b1,x:b2,y:b3,leftshoulder:b4,rightshoulder:b5,back:b6,start:b7,leftstick:b8,\
+rightstick:b9,guide:b10,leftx:a0,lefty:a1,lefttrigger:-a2,rightx:a3,righty:a4,righttrigger:+a5,\
+dpup:h0.1,dpright:h0.2,dpdown:h0.4,dpleft:h0.8,";
+
+/// Mirrors XINPUT_GAMEPAD
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct XInputGamepad {
+    pub buttons: u16,
+    pub left_trigger: u8,
+    pub right_trigger: u8,
+    pub thumb_lx: i16,
+    pub thumb_ly: i16,
+    pub thumb_rx: i16,
+    pub thumb_ry: i16,
+}
+
+/// Mirrors XINPUT_STATE
+#[cfg(windows)]
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+struct XInputState {
+    packet_number: DWORD,
+    gamepad: XInputGamepad,
+}
+
+/// Mirrors XINPUT_VIBRATION (motor speeds from 0 to 65535)
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct XInputVibration {
+    pub left_motor_speed: u16,
+    pub right_motor_speed: u16,
+}
+
+/// Source of XInput controller state
+///
+/// `SystemXInput` talks to the real XInput dll, other implementations can be
+/// used to feed in recorded or synthetic state.
+pub trait XInputProvider {
+    /// State of the controller in the given user slot, None if it is not connected
+    fn get_state(&self, user_index: u32) -> Option<XInputGamepad>;
+    /// Sets the motor speeds, returns false if the controller is not connected
+    fn set_vibration(&self, user_index: u32, vibration: XInputVibration) -> bool;
+}
+
+#[cfg(windows)]
+type XInputGetStateFn = unsafe extern "system" fn(DWORD, *mut XInputState) -> DWORD;
+#[cfg(windows)]
+type XInputSetStateFn = unsafe extern "system" fn(DWORD, *mut XInputVibration) -> DWORD;
+
+#[cfg(windows)]
+const ERROR_SUCCESS: DWORD = 0;
+
+/// XInput provider backed by the system's xinput dll
+#[cfg(windows)]
+pub struct SystemXInput {
+    get_state: XInputGetStateFn,
+    set_state: XInputSetStateFn,
+}
+
+#[cfg(windows)]
+impl SystemXInput {
+    /// Loads the newest available XInput dll
+    /// The undocumented XInputGetStateEx is used when present so that the guide button is reported.
+    pub fn load() -> Result<SystemXInput, &'static str> {
+        for dll_name in &["xinput1_4.dll", "xinput1_3.dll", "xinput9_1_0.dll"] {
+            unsafe {
+                let wide_name = OsStr::new(dll_name)
+                    .encode_wide()
+                    .chain(Some(0))
+                    .collect::<Vec<_>>();
+                let module: HMODULE = LoadLibraryW(wide_name.as_ptr());
+                if module.is_null() {
+                    continue;
+                }
+                // XInputGetStateEx is only exported by ordinal
+                let mut get_state = GetProcAddress(module, 100 as LPCSTR);
+                if get_state.is_null() {
+                    get_state = GetProcAddress(module, b"XInputGetState\0".as_ptr() as LPCSTR);
+                }
+                let set_state = GetProcAddress(module, b"XInputSetState\0".as_ptr() as LPCSTR);
+                if get_state.is_null() || set_state.is_null() {
+                    continue;
+                }
+                return Ok(SystemXInput {
+                    get_state: mem::transmute::<FARPROC, XInputGetStateFn>(get_state),
+                    set_state: mem::transmute::<FARPROC, XInputSetStateFn>(set_state),
+                });
+            }
+        }
+        Err("Could not load an XInput dll")
+    }
+}
+
+#[cfg(windows)]
+impl XInputProvider for SystemXInput {
+    fn get_state(&self, user_index: u32) -> Option<XInputGamepad> {
+        let mut state = XInputState::default();
+        if unsafe { (self.get_state)(user_index, &mut state) } == ERROR_SUCCESS {
+            Some(state.gamepad)
+        } else {
+            None
+        }
+    }
+
+    fn set_vibration(&self, user_index: u32, vibration: XInputVibration) -> bool {
+        let mut vibration = vibration;
+        unsafe { (self.set_state)(user_index, &mut vibration) == ERROR_SUCCESS }
+    }
+}
+
+/// Rumble through XInput vibration
+pub struct XInputRumbleSink {
+    provider: Rc<dyn XInputProvider>,
+    user_index: u32,
+}
+
+impl XInputRumbleSink {
+    pub fn new(provider: Rc<dyn XInputProvider>, user_index: u32) -> XInputRumbleSink {
+        XInputRumbleSink {
+            provider,
+            user_index,
+        }
+    }
+}
+
+impl ForceFeedbackSink for XInputRumbleSink {
+    fn set_rumble(&mut self, low: f64, high: f64) -> Result<(), ForceFeedbackError> {
+        let vibration = XInputVibration {
+            left_motor_speed: (low * 65535f64) as u16,
+            right_motor_speed: (high * 65535f64) as u16,
+        };
+        if self.provider.set_vibration(self.user_index, vibration) {
+            Ok(())
+        } else {
+            Err(ForceFeedbackError(format!(
+                "XInput controller {} is not connected",
+                self.user_index + 1
+            )))
+        }
+    }
+}
+
+fn normalise_thumb(value: i16) -> f64 {
+    (value as f64 / 32767f64).max(-1f64)
+}
+
+/// Converts an XInput gamepad report into a joystick state
+pub fn decode_gamepad(gamepad: &XInputGamepad) -> JoystickState {
+    let button_states = BUTTON_ORDER
+        .iter()
+        .map(|&mask| gamepad.buttons & mask != 0)
+        .collect();
+
+    let mut axis_states = Axes::new();
+    axis_states.x = Some(normalise_thumb(gamepad.thumb_lx));
+    axis_states.y = Some(normalise_thumb(gamepad.thumb_ly));
+    axis_states.rx = Some(normalise_thumb(gamepad.thumb_rx));
+    axis_states.ry = Some(-normalise_thumb(gamepad.thumb_ry));
+    axis_states.z = Some(gamepad.left_trigger as f64 / 255f64);
+    axis_states.rz = Some(gamepad.right_trigger as f64 / 255f64);
+
+    let mut raw_axis_states = RawAxes::new();
+    raw_axis_states.x = gamepad.thumb_lx as u16 as u32;
+    raw_axis_states.y = gamepad.thumb_ly as u16 as u32;
+    raw_axis_states.rx = gamepad.thumb_rx as u16 as u32;
+    raw_axis_states.ry = gamepad.thumb_ry as u16 as u32;
+    raw_axis_states.z = gamepad.left_trigger as u32;
+    raw_axis_states.rz = gamepad.right_trigger as u32;
+
+    let up = gamepad.buttons & XINPUT_GAMEPAD_DPAD_UP != 0;
+    let down = gamepad.buttons & XINPUT_GAMEPAD_DPAD_DOWN != 0;
+    let left = gamepad.buttons & XINPUT_GAMEPAD_DPAD_LEFT != 0;
+    let right = gamepad.buttons & XINPUT_GAMEPAD_DPAD_RIGHT != 0;
+    let hatswitch = match (up && !down, down && !up, left && !right, right && !left) {
+        (true, _, true, _) => HatSwitch::UpLeft,
+        (true, _, _, true) => HatSwitch::UpRight,
+        (true, _, _, _) => HatSwitch::Up,
+        (_, true, true, _) => HatSwitch::DownLeft,
+        (_, true, _, true) => HatSwitch::DownRight,
+        (_, true, _, _) => HatSwitch::Down,
+        (_, _, true, _) => HatSwitch::Left,
+        (_, _, _, true) => HatSwitch::Right,
+        _ => HatSwitch::Center,
+    };
+
+    JoystickState {
+        button_states,
+        axis_states,
+        hatswitch: Some(hatswitch),
+        raw_axis_states,
+    }
+}
+
+/// Builds the joystick entry for an XInput user slot
+#[cfg(windows)]
+pub fn xinput_joystick_info(user_index: u32, gamepad: &XInputGamepad) -> JoystickInfo {
+    JoystickInfo {
+        name: format!("XInput Controller {}", user_index + 1),
+        handle: ::std::ptr::null_mut(),
+        serial: None,
+        product: Some("XInput Controller".to_string()),
+        manufacturer: None,
+        ids: DeviceIds {
+            vendor_id: None,
+            product_id: None,
+            version_number: None,
+            usage_page: 0x01,
+            usage: 0x05,
+        },
+        info: unsafe { mem::zeroed() },
+        caps: unsafe { mem::zeroed() },
+        button_caps: Vec::new(),
+        value_caps: Vec::new(),
+        preparsed_data: Vec::new(),
+        state: decode_gamepad(gamepad),
+        is_360_controller: true,
+        has_reported: false,
+        gamepad_mapping: None,
+        gamepad_state: GamepadState::new(),
+        xinput_index: Some(user_index),
+    }
+}
+
+/// Joysticks kept and XInput controllers added by `merge_xinput_devices`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct XInputMerge {
+    /// Indices of the rawinput joysticks kept, in their new order
+    pub kept: Vec<usize>,
+    /// Indices of the rawinput joysticks of XInput controllers
+    pub removed: Vec<usize>,
+    /// Connected controllers in the order they are added after the kept joysticks
+    pub added: Vec<(u32, XInputGamepad)>,
+    /// Joystick id of each connected controller's user slot
+    pub xinput_map: HashMap<u32, usize>,
+}
+
+/// Plans the merge of XInput controllers into the joysticks, given as
+/// `(is_360_controller, xinput_index)` of each
+///
+/// Rawinput entries for XInput controllers (those with "IG_" in their path) are
+/// removed so that no controller is reported twice, and entries of an earlier
+/// merge are replaced.
+pub fn plan_xinput_merge(
+    joysticks: &[(bool, Option<u32>)],
+    provider: &dyn XInputProvider,
+) -> XInputMerge {
+    let mut merge = XInputMerge::default();
+    for (index, &(is_360_controller, xinput_index)) in joysticks.iter().enumerate() {
+        if xinput_index.is_some() {
+            continue;
+        }
+        if is_360_controller {
+            merge.removed.push(index);
+        } else {
+            merge.kept.push(index);
+        }
+    }
+    for user_index in 0..XUSER_MAX_COUNT {
+        if let Some(gamepad) = provider.get_state(user_index) {
+            merge
+                .xinput_map
+                .insert(user_index, merge.kept.len() + merge.added.len());
+            merge.added.push((user_index, gamepad));
+        }
+    }
+    merge
+}
+
+/// Adds a joystick for each connected XInput controller (see `plan_xinput_merge`)
+#[cfg(windows)]
+pub fn merge_xinput_devices(devices: &mut Devices, provider: &dyn XInputProvider) {
+    let entries: Vec<(bool, Option<u32>)> = devices
+        .joysticks
+        .iter()
+        .map(|joystick| (joystick.is_360_controller, joystick.xinput_index))
+        .collect();
+    let merge = plan_xinput_merge(&entries, provider);
+    let mut joysticks: Vec<Option<JoystickInfo>> =
+        devices.joysticks.drain(..).map(Some).collect();
+    for &index in &merge.removed {
+        if let Some(joystick) = joysticks[index].take() {
+            devices.device_map.remove(&joystick.handle);
+        }
+    }
+    for (pos, &index) in merge.kept.iter().enumerate() {
+        let joystick = joysticks[index].take().unwrap();
+        if let Some(old_pos) = devices.device_map.get_mut(&joystick.handle) {
+            *old_pos = pos;
+        }
+        devices.joysticks.push(joystick);
+    }
+    for &(user_index, ref gamepad) in &merge.added {
+        devices
+            .joysticks
+            .push(xinput_joystick_info(user_index, gamepad));
+    }
+    devices.xinput_map = merge.xinput_map;
+    devices.original_device_map = devices.device_map.clone();
+    devices.original_xinput_map = devices.xinput_map.clone();
+}
+
+/// Reads the current state of every active XInput joystick
+#[cfg(windows)]
+pub fn poll_xinput(
+    event_queue: &mut VecDeque<RawEvent>,
+    devices: &mut Devices,
+    provider: &dyn XInputProvider,
+) {
+    for user_index in 0..XUSER_MAX_COUNT {
+        let pos = match devices.xinput_map.get(&user_index) {
+            Some(&pos) => pos,
+            None => continue,
+        };
+        if let Some(gamepad) = provider.get_state(user_index) {
+            event_queue.extend(update_joystick_state(
+                &mut devices.joysticks[pos],
+                decode_gamepad(&gamepad),
+                pos,
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use devices::HatSwitch;
+    use gamepad::{GamepadAxis, GamepadButton, GamepadMapping};
+    use std::cell::RefCell;
+
+    /// Controllers in user slots, with the last vibration sent to each
+    #[derive(Default)]
+    struct StubXInput {
+        gamepads: RefCell<HashMap<u32, XInputGamepad>>,
+        vibrations: RefCell<HashMap<u32, XInputVibration>>,
+    }
+
+    impl XInputProvider for StubXInput {
+        fn get_state(&self, user_index: u32) -> Option<XInputGamepad> {
+            self.gamepads.borrow().get(&user_index).cloned()
+        }
+
+        fn set_vibration(&self, user_index: u32, vibration: XInputVibration) -> bool {
+            if !self.gamepads.borrow().contains_key(&user_index) {
+                return false;
+            }
+            self.vibrations.borrow_mut().insert(user_index, vibration);
+            true
+        }
+    }
+
+    #[test]
+    fn decodes_buttons_and_dpad() {
+        let state = decode_gamepad(&XInputGamepad {
+            buttons: XINPUT_GAMEPAD_A
+                | XINPUT_GAMEPAD_START
+                | XINPUT_GAMEPAD_GUIDE
+                | XINPUT_GAMEPAD_DPAD_DOWN
+                | XINPUT_GAMEPAD_DPAD_LEFT,
+            ..Default::default()
+        });
+        let pressed: Vec<usize> = (0..state.button_states.len())
+            .filter(|&button| state.button_states[button])
+            .collect();
+        assert_eq!(pressed, vec![0, 7, 10]);
+        assert_eq!(state.hatswitch, Some(HatSwitch::DownLeft));
+        // Opposite directions cancel out
+        let state = decode_gamepad(&XInputGamepad {
+            buttons: XINPUT_GAMEPAD_DPAD_UP | XINPUT_GAMEPAD_DPAD_DOWN | XINPUT_GAMEPAD_DPAD_RIGHT,
+            ..Default::default()
+        });
+        assert_eq!(state.hatswitch, Some(HatSwitch::Right));
+    }
+
+    #[test]
+    fn decodes_sticks_in_rawinput_orientation() {
+        let state = decode_gamepad(&XInputGamepad {
+            thumb_lx: 32767,
+            thumb_ly: 32767,
+            thumb_rx: -32768,
+            thumb_ry: 32767,
+            left_trigger: 255,
+            ..Default::default()
+        });
+        let axes = &state.axis_states;
+        assert_eq!(axes.x, Some(1.0));
+        assert_eq!(axes.y, Some(1.0));
+        assert_eq!(axes.rx, Some(-1.0));
+        assert_eq!(axes.ry, Some(-1.0));
+        assert_eq!(axes.z, Some(1.0));
+        assert_eq!(axes.rz, Some(0.0));
+        assert_eq!(state.raw_axis_states.ry, 32767);
+    }
+
+    #[test]
+    fn both_sticks_are_up_positive_through_the_mapping() {
+        let mapping = GamepadMapping::parse(XINPUT_GAMEPAD_MAPPING).unwrap();
+        let up = mapping.evaluate(&decode_gamepad(&XInputGamepad {
+            thumb_ly: 32767,
+            thumb_ry: 32767,
+            ..Default::default()
+        }));
+        assert_eq!(up.axis(&GamepadAxis::LeftY), 1.0);
+        assert_eq!(up.axis(&GamepadAxis::RightY), 1.0);
+        let down = mapping.evaluate(&decode_gamepad(&XInputGamepad {
+            thumb_ly: -32768,
+            thumb_ry: -32768,
+            ..Default::default()
+        }));
+        assert_eq!(down.axis(&GamepadAxis::LeftY), -1.0);
+        assert_eq!(down.axis(&GamepadAxis::RightY), -1.0);
+    }
+
+    #[test]
+    fn triggers_and_buttons_through_the_mapping() {
+        let mapping = GamepadMapping::parse(XINPUT_GAMEPAD_MAPPING).unwrap();
+        let rest = mapping.evaluate(&decode_gamepad(&XInputGamepad::default()));
+        assert_eq!(rest.axis(&GamepadAxis::LeftTrigger), 0.0);
+        assert_eq!(rest.axis(&GamepadAxis::RightTrigger), 0.0);
+        let pressed = mapping.evaluate(&decode_gamepad(&XInputGamepad {
+            buttons: XINPUT_GAMEPAD_Y | XINPUT_GAMEPAD_GUIDE | XINPUT_GAMEPAD_DPAD_UP,
+            left_trigger: 255,
+            right_trigger: 255,
+            ..Default::default()
+        }));
+        assert_eq!(pressed.axis(&GamepadAxis::LeftTrigger), 1.0);
+        assert_eq!(pressed.axis(&GamepadAxis::RightTrigger), 1.0);
+        assert!(pressed.button(&GamepadButton::North));
+        assert!(pressed.button(&GamepadButton::Guide));
+        assert!(pressed.button(&GamepadButton::DPadUp));
+    }
+
+    #[test]
+    fn rumbles_through_the_provider() {
+        let provider = Rc::new(StubXInput::default());
+        provider
+            .gamepads
+            .borrow_mut()
+            .insert(1, XInputGamepad::default());
+        let mut sink = XInputRumbleSink::new(provider.clone(), 1);
+        sink.set_rumble(1.0, 0.5).unwrap();
+        assert_eq!(
+            provider.vibrations.borrow()[&1],
+            XInputVibration {
+                left_motor_speed: 65535,
+                right_motor_speed: 32767,
+            }
+        );
+        let mut sink = XInputRumbleSink::new(provider.clone(), 2);
+        assert!(sink.set_rumble(1.0, 1.0).is_err());
+    }
+
+    #[test]
+    fn plans_xinput_merge_over_rawinput_joysticks() {
+        let provider = StubXInput::default();
+        let pressed = XInputGamepad {
+            buttons: XINPUT_GAMEPAD_A,
+            ..Default::default()
+        };
+        provider.gamepads.borrow_mut().insert(0, pressed);
+        provider
+            .gamepads
+            .borrow_mut()
+            .insert(2, XInputGamepad::default());
+
+        // A rawinput 360 controller, two other joysticks and a controller of an
+        // earlier merge
+        let joysticks = [(true, None), (false, None), (true, Some(0)), (false, None)];
+        let merge = plan_xinput_merge(&joysticks, &provider);
+        assert_eq!(merge.kept, vec![1, 3]);
+        assert_eq!(merge.removed, vec![0]);
+        assert_eq!(
+            merge.added,
+            vec![(0, pressed), (2, XInputGamepad::default())]
+        );
+        let mut expected = HashMap::new();
+        expected.insert(0, 2);
+        expected.insert(2, 3);
+        assert_eq!(merge.xinput_map, expected);
+
+        // Merging again without controllers only drops the earlier ones
+        provider.gamepads.borrow_mut().clear();
+        let joysticks = [
+            (false, None),
+            (false, None),
+            (true, Some(0)),
+            (true, Some(2)),
+        ];
+        let merge = plan_xinput_merge(&joysticks, &provider);
+        assert_eq!(merge.kept, vec![0, 1]);
+        assert!(merge.removed.is_empty());
+        assert!(merge.added.is_empty());
+        assert!(merge.xinput_map.is_empty());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn merges_xinput_controllers_with_rawinput_joysticks() {
+        use devices::Devices;
+        use winapi::um::winnt::HANDLE;
+
+        let rawinput_joystick = |handle: usize, is_360_controller: bool| {
+            let mut joystick = xinput_joystick_info(0, &XInputGamepad::default());
+            joystick.name = format!("Joystick {}", handle);
+            joystick.handle = handle as HANDLE;
+            joystick.is_360_controller = is_360_controller;
+            joystick.xinput_index = None;
+            joystick
+        };
+        let mut devices = Devices::new();
+        devices.joysticks.push(rawinput_joystick(1, true));
+        devices.joysticks.push(rawinput_joystick(2, false));
+        devices.device_map.insert(1 as HANDLE, 0);
+        devices.device_map.insert(2 as HANDLE, 1);
+        let provider = StubXInput::default();
+        provider
+            .gamepads
+            .borrow_mut()
+            .insert(0, XInputGamepad::default());
+        provider
+            .gamepads
+            .borrow_mut()
+            .insert(2, XInputGamepad::default());
+
+        merge_xinput_devices(&mut devices, &provider);
+        let names: Vec<&str> = devices
+            .joysticks
+            .iter()
+            .map(|joystick| joystick.name.as_str())
+            .collect();
+        assert_eq!(
+            names,
+            vec!["Joystick 2", "XInput Controller 1", "XInput Controller 3"]
+        );
+        assert_eq!(devices.device_map.get(&(1 as HANDLE)), None);
+        assert_eq!(devices.device_map.get(&(2 as HANDLE)), Some(&0));
+        assert_eq!(devices.xinput_map.get(&0), Some(&1));
+        assert_eq!(devices.xinput_map.get(&2), Some(&2));
+
+        // The first poll sends the initial states, later polls the changes
+        let mut events = VecDeque::new();
+        poll_xinput(&mut events, &mut devices, &provider);
+        assert_eq!(events.len(), 2);
+        assert!(events
+            .iter()
+            .all(|event| matches!(*event, RawEvent::JoystickInitialStateEvent(..))));
+        events.clear();
+        provider.gamepads.borrow_mut().insert(
+            2,
+            XInputGamepad {
+                thumb_ry: 32767,
+                ..Default::default()
+            },
+        );
+        poll_xinput(&mut events, &mut devices, &provider);
+        assert_eq!(
+            events.into_iter().collect::<Vec<_>>(),
+            vec![RawEvent::JoystickAxisEvent(2, ::event::Axis::RY, -1.0)]
+        );
+    }
+}